@@ -1,9 +1,14 @@
 pub mod mutation_root;
 pub mod query_root;
+pub mod subscription_root;
 
-use async_graphql::{http::GraphiQLSource, EmptySubscription, Schema, Value};
+use async_graphql::{
+    http::{GraphiQLSource, ALL_WEBSOCKET_PROTOCOLS},
+    Data, Schema, Value,
+};
+use async_graphql_axum::{GraphQLProtocol, GraphQLWebSocket};
 use axum::{
-    extract::Extension,
+    extract::{Extension, WebSocketUpgrade},
     response::{self, IntoResponse},
 };
 use common::{
@@ -13,11 +18,17 @@ use common::{
 };
 use mutation_root::MutationRoot;
 use query_root::QueryRoot;
+use subscription_root::SubscriptionRoot;
 
-pub type ApiSchema = Schema<QueryRoot, MutationRoot, EmptySubscription>;
+pub type ApiSchema = Schema<QueryRoot, MutationRoot, SubscriptionRoot>;
 
 pub async fn graphiql() -> impl IntoResponse {
-    response::Html(GraphiQLSource::build().endpoint("/").finish())
+    response::Html(
+        GraphiQLSource::build()
+            .endpoint("/")
+            .subscription_endpoint("/ws")
+            .finish(),
+    )
 }
 
 pub async fn graphql_handler(
@@ -48,3 +59,22 @@ pub async fn graphql_handler(
     }
     response
 }
+
+/// Subscriptions over WebSocket. Ctx is taken from the upgrade request,
+/// so the connection is authenticated by the same cookie as queries
+pub async fn graphql_ws_handler(
+    Extension(schema): Extension<ApiSchema>,
+    ctx: CtxStruct,
+    protocol: GraphQLProtocol,
+    upgrade: WebSocketUpgrade,
+) -> axum::response::Response {
+    upgrade
+        .protocols(ALL_WEBSOCKET_PROTOCOLS)
+        .on_upgrade(move |stream| {
+            let mut data = Data::default();
+            data.insert(ctx);
+            GraphQLWebSocket::new(stream, schema, protocol)
+                .with_data(data)
+                .serve()
+        })
+}
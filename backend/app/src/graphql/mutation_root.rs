@@ -1,19 +1,41 @@
+mod batch_mutation;
+mod downtime_mutation;
+mod machinery_mutation;
+mod machinery_stats_mutation;
+mod maintenance_order_mutation;
 mod measure_units_mutation;
+mod pipe_from_mutation;
 mod pipe_mutation;
 mod pipe_stats_mutation;
+mod pipe_to_mutation;
+mod production_info_mutation;
 mod production_plan_per_day_mutation;
+mod quality_check_mutation;
+mod quality_spec_mutation;
+mod recipe_mutation;
 mod sales_plan_per_day_mutation;
+mod stock_movement_mutation;
 mod user_mutation;
-mod production_info_mutation;
 
 use async_graphql::Object;
+use batch_mutation::BatchMutation;
+use downtime_mutation::DowntimeMutation;
+use machinery_mutation::MachineryMutation;
+use machinery_stats_mutation::MachineryStatsMutation;
+use maintenance_order_mutation::MaintenanceOrderMutation;
 use measure_units_mutation::MeasureUnitsMutation;
+use pipe_from_mutation::PipeFromMutation;
 use pipe_mutation::PipeMutation;
 use pipe_stats_mutation::PipeStatsMutation;
+use pipe_to_mutation::PipeToMutation;
+use production_info_mutation::ProductionInfoMutation;
 use production_plan_per_day_mutation::ProductionPlanPerDayMutation;
+use quality_check_mutation::QualityCheckMutation;
+use quality_spec_mutation::QualitySpecMutation;
+use recipe_mutation::RecipeMutation;
 use sales_plan_per_day_mutation::SalesPlanPerDayMutation;
+use stock_movement_mutation::StockMovementMutation;
 use user_mutation::UserMutation;
-use production_info_mutation::ProductionInfoMutation;
 
 pub struct MutationRoot;
 #[Object]
@@ -30,6 +52,10 @@ impl MutationRoot {
         PipeStatsMutation
     }
 
+    async fn machinery_stats(&self) -> MachineryStatsMutation {
+        MachineryStatsMutation
+    }
+
     async fn sales_plan_per_day(&self) -> SalesPlanPerDayMutation {
         SalesPlanPerDayMutation
     }
@@ -45,4 +71,44 @@ impl MutationRoot {
     async fn production_info(&self) -> ProductionInfoMutation {
         ProductionInfoMutation
     }
+
+    async fn machinery(&self) -> MachineryMutation {
+        MachineryMutation
+    }
+
+    async fn pipe_to(&self) -> PipeToMutation {
+        PipeToMutation
+    }
+
+    async fn pipe_from(&self) -> PipeFromMutation {
+        PipeFromMutation
+    }
+
+    async fn maintenance_order(&self) -> MaintenanceOrderMutation {
+        MaintenanceOrderMutation
+    }
+
+    async fn downtime(&self) -> DowntimeMutation {
+        DowntimeMutation
+    }
+
+    async fn recipe(&self) -> RecipeMutation {
+        RecipeMutation
+    }
+
+    async fn stock_movement(&self) -> StockMovementMutation {
+        StockMovementMutation
+    }
+
+    async fn batch(&self) -> BatchMutation {
+        BatchMutation
+    }
+
+    async fn quality_spec(&self) -> QualitySpecMutation {
+        QualitySpecMutation
+    }
+
+    async fn quality_check(&self) -> QualityCheckMutation {
+        QualityCheckMutation
+    }
 }
@@ -0,0 +1,32 @@
+use async_graphql::{Context, Object, Result};
+use common::ctx::CtxStruct;
+use db::Db;
+
+use service::{
+    batch::{Batch, BatchUseCases, CreateLotInput, ProduceBatchInput},
+    thing_derived::ThingDerived,
+};
+
+pub struct BatchMutation;
+#[Object]
+impl BatchMutation {
+    /// Raw material lot entering an input pipe
+    async fn create_lot(&self, ctx: &Context<'_>, ct_input: CreateLotInput) -> Result<Batch> {
+        let db = ctx.data::<Db>()?;
+        let ctx = ctx.data::<CtxStruct>()?;
+        Ok(BatchUseCases::create_lot(ct_input, db, ctx).await?)
+    }
+
+    /// Batch produced by the machinery from the batches of its input pipes
+    async fn produce(&self, ctx: &Context<'_>, ct_input: ProduceBatchInput) -> Result<Batch> {
+        let db = ctx.data::<Db>()?;
+        let ctx = ctx.data::<CtxStruct>()?;
+        Ok(BatchUseCases::produce(ct_input, db, ctx).await?)
+    }
+
+    async fn delete(&self, ctx: &Context<'_>, id: ThingDerived) -> Result<Batch> {
+        let db = ctx.data::<Db>()?;
+        let ctx = ctx.data::<CtxStruct>()?;
+        Ok(BatchUseCases::delete(&id, db, ctx).await?)
+    }
+}
@@ -0,0 +1,48 @@
+use async_graphql::{Context, Object, Result};
+use common::ctx::CtxStruct;
+use db::Db;
+
+use service::{
+    datetime::DateTimeDerived,
+    downtime::{CreateDowntimeInput, Downtime, DowntimeUseCases},
+    thing_derived::ThingDerived,
+};
+
+pub struct DowntimeMutation;
+#[Object]
+impl DowntimeMutation {
+    async fn create(&self, ctx: &Context<'_>, ct_input: CreateDowntimeInput) -> Result<Downtime> {
+        let db = ctx.data::<Db>()?;
+        let ctx = ctx.data::<CtxStruct>()?;
+        Ok(DowntimeUseCases::create(ct_input, db, ctx).await?)
+    }
+
+    async fn update(
+        &self,
+        ctx: &Context<'_>,
+        ct_input: CreateDowntimeInput,
+        id: ThingDerived,
+    ) -> Result<Downtime> {
+        let db = ctx.data::<Db>()?;
+        let ctx = ctx.data::<CtxStruct>()?;
+        Ok(DowntimeUseCases::update(ct_input, &id, db, ctx).await?)
+    }
+
+    /// Ends the open downtime, now by default
+    async fn end(
+        &self,
+        ctx: &Context<'_>,
+        id: ThingDerived,
+        ended_at: Option<DateTimeDerived>,
+    ) -> Result<Downtime> {
+        let db = ctx.data::<Db>()?;
+        let ctx = ctx.data::<CtxStruct>()?;
+        Ok(DowntimeUseCases::end(&id, ended_at, db, ctx).await?)
+    }
+
+    async fn delete(&self, ctx: &Context<'_>, id: ThingDerived) -> Result<Downtime> {
+        let db = ctx.data::<Db>()?;
+        let ctx = ctx.data::<CtxStruct>()?;
+        Ok(DowntimeUseCases::delete(&id, db, ctx).await?)
+    }
+}
@@ -0,0 +1,29 @@
+use async_graphql::{Context, Object, Result};
+use common::ctx::CtxStruct;
+use db::Db;
+
+use service::{
+    machinery::{CreateMachineryInput, Machinery, MachineryUseCases},
+    thing_derived::ThingDerived,
+};
+
+pub struct MachineryMutation;
+#[Object]
+impl MachineryMutation {
+    async fn create(&self, ctx: &Context<'_>, ct_input: CreateMachineryInput) -> Result<Machinery> {
+        let db = ctx.data::<Db>()?;
+        let ctx = ctx.data::<CtxStruct>()?;
+        Ok(MachineryUseCases::create(ct_input, db, ctx).await?)
+    }
+
+    async fn update(
+        &self,
+        ctx: &Context<'_>,
+        ct_input: CreateMachineryInput,
+        id: ThingDerived,
+    ) -> Result<Machinery> {
+        let db = ctx.data::<Db>()?;
+        let ctx = ctx.data::<CtxStruct>()?;
+        Ok(MachineryUseCases::update(ct_input, &id, db, ctx).await?)
+    }
+}
@@ -0,0 +1,39 @@
+use async_graphql::{Context, Object, Result};
+use common::ctx::CtxStruct;
+use db::Db;
+
+use service::{
+    machinery_stats::{CreateMachineryStatsInput, MachineryStats, MachineryStatsUseCases},
+    thing_derived::ThingDerived,
+};
+
+pub struct MachineryStatsMutation;
+#[Object]
+impl MachineryStatsMutation {
+    async fn create(
+        &self,
+        ctx: &Context<'_>,
+        ct_input: CreateMachineryStatsInput,
+    ) -> Result<MachineryStats> {
+        let db = ctx.data::<Db>()?;
+        let ctx = ctx.data::<CtxStruct>()?;
+        Ok(MachineryStatsUseCases::create(ct_input, db, ctx).await?)
+    }
+
+    async fn update(
+        &self,
+        ctx: &Context<'_>,
+        ct_input: CreateMachineryStatsInput,
+        id: ThingDerived,
+    ) -> Result<MachineryStats> {
+        let db = ctx.data::<Db>()?;
+        let ctx = ctx.data::<CtxStruct>()?;
+        Ok(MachineryStatsUseCases::update(ct_input, &id, db, ctx).await?)
+    }
+
+    async fn delete(&self, ctx: &Context<'_>, id: ThingDerived) -> Result<MachineryStats> {
+        let db = ctx.data::<Db>()?;
+        let ctx = ctx.data::<CtxStruct>()?;
+        Ok(MachineryStatsUseCases::delete(&id, db, ctx).await?)
+    }
+}
@@ -0,0 +1,59 @@
+use async_graphql::{Context, Object, Result};
+use common::ctx::CtxStruct;
+use db::Db;
+
+use service::{
+    datetime::DateTimeDerived,
+    maintenance_order::{CreateMaintenanceOrderInput, MaintenanceOrder, MaintenanceOrderUseCases},
+    thing_derived::ThingDerived,
+};
+
+pub struct MaintenanceOrderMutation;
+#[Object]
+impl MaintenanceOrderMutation {
+    async fn create(
+        &self,
+        ctx: &Context<'_>,
+        ct_input: CreateMaintenanceOrderInput,
+    ) -> Result<MaintenanceOrder> {
+        let db = ctx.data::<Db>()?;
+        let ctx = ctx.data::<CtxStruct>()?;
+        Ok(MaintenanceOrderUseCases::create(ct_input, db, ctx).await?)
+    }
+
+    async fn update(
+        &self,
+        ctx: &Context<'_>,
+        ct_input: CreateMaintenanceOrderInput,
+        id: ThingDerived,
+    ) -> Result<MaintenanceOrder> {
+        let db = ctx.data::<Db>()?;
+        let ctx = ctx.data::<CtxStruct>()?;
+        Ok(MaintenanceOrderUseCases::update(ct_input, &id, db, ctx).await?)
+    }
+
+    /// Resets wear of the object. Now and the current user by default
+    async fn complete(
+        &self,
+        ctx: &Context<'_>,
+        id: ThingDerived,
+        completed_at: Option<DateTimeDerived>,
+        performed_by: Option<ThingDerived>,
+    ) -> Result<MaintenanceOrder> {
+        let db = ctx.data::<Db>()?;
+        let ctx = ctx.data::<CtxStruct>()?;
+        Ok(MaintenanceOrderUseCases::complete(&id, completed_at, performed_by, db, ctx).await?)
+    }
+
+    async fn cancel(&self, ctx: &Context<'_>, id: ThingDerived) -> Result<MaintenanceOrder> {
+        let db = ctx.data::<Db>()?;
+        let ctx = ctx.data::<CtxStruct>()?;
+        Ok(MaintenanceOrderUseCases::cancel(&id, db, ctx).await?)
+    }
+
+    async fn delete(&self, ctx: &Context<'_>, id: ThingDerived) -> Result<MaintenanceOrder> {
+        let db = ctx.data::<Db>()?;
+        let ctx = ctx.data::<CtxStruct>()?;
+        Ok(MaintenanceOrderUseCases::delete(&id, db, ctx).await?)
+    }
+}
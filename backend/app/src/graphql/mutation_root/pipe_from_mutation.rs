@@ -0,0 +1,24 @@
+use async_graphql::{Context, Object, Result};
+use common::ctx::CtxStruct;
+use db::Db;
+
+use service::{
+    pipe_from::{CreatePipeFromInput, PipeFrom, PipeFromUseCases},
+    thing_derived::ThingDerived,
+};
+
+pub struct PipeFromMutation;
+#[Object]
+impl PipeFromMutation {
+    async fn create(&self, ctx: &Context<'_>, ct_input: CreatePipeFromInput) -> Result<PipeFrom> {
+        let db = ctx.data::<Db>()?;
+        let ctx = ctx.data::<CtxStruct>()?;
+        Ok(PipeFromUseCases::create(ct_input, db, ctx).await?)
+    }
+
+    async fn delete(&self, ctx: &Context<'_>, id: ThingDerived) -> Result<PipeFrom> {
+        let db = ctx.data::<Db>()?;
+        let ctx = ctx.data::<CtxStruct>()?;
+        Ok(PipeFromUseCases::delete(&id, db, ctx).await?)
+    }
+}
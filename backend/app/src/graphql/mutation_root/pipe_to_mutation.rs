@@ -0,0 +1,24 @@
+use async_graphql::{Context, Object, Result};
+use common::ctx::CtxStruct;
+use db::Db;
+
+use service::{
+    pipe_to::{CreatePipeToInput, PipeTo, PipeToUseCases},
+    thing_derived::ThingDerived,
+};
+
+pub struct PipeToMutation;
+#[Object]
+impl PipeToMutation {
+    async fn create(&self, ctx: &Context<'_>, ct_input: CreatePipeToInput) -> Result<PipeTo> {
+        let db = ctx.data::<Db>()?;
+        let ctx = ctx.data::<CtxStruct>()?;
+        Ok(PipeToUseCases::create(ct_input, db, ctx).await?)
+    }
+
+    async fn delete(&self, ctx: &Context<'_>, id: ThingDerived) -> Result<PipeTo> {
+        let db = ctx.data::<Db>()?;
+        let ctx = ctx.data::<CtxStruct>()?;
+        Ok(PipeToUseCases::delete(&id, db, ctx).await?)
+    }
+}
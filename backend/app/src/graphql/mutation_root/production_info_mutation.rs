@@ -3,7 +3,11 @@ use common::ctx::CtxStruct;
 use db::Db;
 
 use service::{
-    production_info::{CreateProductionInfoInput, ProductionInfo, ProductionInfoUseCases},
+    datetime::DateTimeDerived,
+    flow_integration::FlowIntegrationOptions,
+    production_info::{
+        CreateProductionInfoInput, MaterializationSummary, ProductionInfo, ProductionInfoUseCases,
+    },
     thing_derived::ThingDerived,
 };
 
@@ -30,4 +34,26 @@ impl ProductionInfoMutation {
         let ctx = ctx.data::<CtxStruct>()?;
         Ok(ProductionInfoUseCases::update(ct_input, &id, db, ctx).await?)
     }
+
+    /// Same as the scheduled job. Materializes closed days
+    /// in [until - lookback_days, until), `until` is now by default
+    async fn materialize(
+        &self,
+        ctx: &Context<'_>,
+        until: Option<DateTimeDerived>,
+        #[graphql(validator(minimum = 1, maximum = 366))] lookback_days: Option<i64>,
+        options: Option<FlowIntegrationOptions>,
+    ) -> Result<MaterializationSummary> {
+        let db = ctx.data::<Db>()?;
+        let ctx = ctx.data::<CtxStruct>()?;
+        let until = until.unwrap_or_else(|| chrono::Utc::now().into());
+        Ok(ProductionInfoUseCases::materialize(
+            until,
+            lookback_days.unwrap_or(31),
+            &options.unwrap_or_default(),
+            db,
+            ctx,
+        )
+        .await?)
+    }
 }
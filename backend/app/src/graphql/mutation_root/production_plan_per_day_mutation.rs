@@ -3,20 +3,20 @@ use common::ctx::CtxStruct;
 use db::Db;
 
 use service::{
-    production_per_day::{
-        CreateProductionPlanPerDayTypeInput, ProductionPlanPerDay, ProductionPlanPerDayUseCases,
-    },
+    plan_feasibility::CheckedProductionPlanPerDay,
+    production_per_day::{CreateProductionPlanPerDayTypeInput, ProductionPlanPerDayUseCases},
     thing_derived::ThingDerived,
 };
 
 pub struct ProductionPlanPerDayMutation;
 #[Object]
 impl ProductionPlanPerDayMutation {
+    /// Saves the plan and returns warnings if it exceeds capacity of the line or misses the sales plan
     async fn create(
         &self,
         ctx: &Context<'_>,
         ct_input: CreateProductionPlanPerDayTypeInput,
-    ) -> Result<ProductionPlanPerDay> {
+    ) -> Result<CheckedProductionPlanPerDay> {
         let db = ctx.data::<Db>()?;
         let ctx = ctx.data::<CtxStruct>()?;
         Ok(ProductionPlanPerDayUseCases::create(ct_input, db, ctx).await?)
@@ -27,7 +27,7 @@ impl ProductionPlanPerDayMutation {
         ctx: &Context<'_>,
         ct_input: CreateProductionPlanPerDayTypeInput,
         id: ThingDerived,
-    ) -> Result<ProductionPlanPerDay> {
+    ) -> Result<CheckedProductionPlanPerDay> {
         let db = ctx.data::<Db>()?;
         let ctx = ctx.data::<CtxStruct>()?;
         Ok(ProductionPlanPerDayUseCases::update(ct_input, &id, db, ctx).await?)
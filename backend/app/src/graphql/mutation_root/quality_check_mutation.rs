@@ -0,0 +1,39 @@
+use async_graphql::{Context, Object, Result};
+use common::ctx::CtxStruct;
+use db::Db;
+
+use service::{
+    quality_check::{CreateQualityCheckInput, QualityCheck, QualityCheckUseCases},
+    thing_derived::ThingDerived,
+};
+
+pub struct QualityCheckMutation;
+#[Object]
+impl QualityCheckMutation {
+    async fn create(
+        &self,
+        ctx: &Context<'_>,
+        ct_input: CreateQualityCheckInput,
+    ) -> Result<QualityCheck> {
+        let db = ctx.data::<Db>()?;
+        let ctx = ctx.data::<CtxStruct>()?;
+        Ok(QualityCheckUseCases::create(ct_input, db, ctx).await?)
+    }
+
+    async fn update(
+        &self,
+        ctx: &Context<'_>,
+        ct_input: CreateQualityCheckInput,
+        id: ThingDerived,
+    ) -> Result<QualityCheck> {
+        let db = ctx.data::<Db>()?;
+        let ctx = ctx.data::<CtxStruct>()?;
+        Ok(QualityCheckUseCases::update(ct_input, &id, db, ctx).await?)
+    }
+
+    async fn delete(&self, ctx: &Context<'_>, id: ThingDerived) -> Result<QualityCheck> {
+        let db = ctx.data::<Db>()?;
+        let ctx = ctx.data::<CtxStruct>()?;
+        Ok(QualityCheckUseCases::delete(&id, db, ctx).await?)
+    }
+}
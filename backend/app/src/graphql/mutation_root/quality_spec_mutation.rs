@@ -0,0 +1,39 @@
+use async_graphql::{Context, Object, Result};
+use common::ctx::CtxStruct;
+use db::Db;
+
+use service::{
+    quality_spec::{CreateQualitySpecInput, QualitySpec, QualitySpecUseCases},
+    thing_derived::ThingDerived,
+};
+
+pub struct QualitySpecMutation;
+#[Object]
+impl QualitySpecMutation {
+    async fn create(
+        &self,
+        ctx: &Context<'_>,
+        ct_input: CreateQualitySpecInput,
+    ) -> Result<QualitySpec> {
+        let db = ctx.data::<Db>()?;
+        let ctx = ctx.data::<CtxStruct>()?;
+        Ok(QualitySpecUseCases::create(ct_input, db, ctx).await?)
+    }
+
+    async fn update(
+        &self,
+        ctx: &Context<'_>,
+        ct_input: CreateQualitySpecInput,
+        id: ThingDerived,
+    ) -> Result<QualitySpec> {
+        let db = ctx.data::<Db>()?;
+        let ctx = ctx.data::<CtxStruct>()?;
+        Ok(QualitySpecUseCases::update(ct_input, &id, db, ctx).await?)
+    }
+
+    async fn delete(&self, ctx: &Context<'_>, id: ThingDerived) -> Result<QualitySpec> {
+        let db = ctx.data::<Db>()?;
+        let ctx = ctx.data::<CtxStruct>()?;
+        Ok(QualitySpecUseCases::delete(&id, db, ctx).await?)
+    }
+}
@@ -0,0 +1,36 @@
+use async_graphql::{Context, Object, Result};
+use common::ctx::CtxStruct;
+use db::Db;
+
+use service::{
+    recipe::{CreateRecipeInput, Recipe, RecipeUseCases},
+    thing_derived::ThingDerived,
+};
+
+pub struct RecipeMutation;
+#[Object]
+impl RecipeMutation {
+    /// Fails if the recipe makes a cycle or its units don't convert into units of other recipes
+    async fn create(&self, ctx: &Context<'_>, ct_input: CreateRecipeInput) -> Result<Recipe> {
+        let db = ctx.data::<Db>()?;
+        let ctx = ctx.data::<CtxStruct>()?;
+        Ok(RecipeUseCases::create(ct_input, db, ctx).await?)
+    }
+
+    async fn update(
+        &self,
+        ctx: &Context<'_>,
+        ct_input: CreateRecipeInput,
+        id: ThingDerived,
+    ) -> Result<Recipe> {
+        let db = ctx.data::<Db>()?;
+        let ctx = ctx.data::<CtxStruct>()?;
+        Ok(RecipeUseCases::update(ct_input, &id, db, ctx).await?)
+    }
+
+    async fn delete(&self, ctx: &Context<'_>, id: ThingDerived) -> Result<Recipe> {
+        let db = ctx.data::<Db>()?;
+        let ctx = ctx.data::<CtxStruct>()?;
+        Ok(RecipeUseCases::delete(&id, db, ctx).await?)
+    }
+}
@@ -0,0 +1,39 @@
+use async_graphql::{Context, Object, Result};
+use common::ctx::CtxStruct;
+use db::Db;
+
+use service::{
+    stock_movement::{CreateStockMovementInput, StockMovement, StockMovementUseCases},
+    thing_derived::ThingDerived,
+};
+
+pub struct StockMovementMutation;
+#[Object]
+impl StockMovementMutation {
+    async fn create(
+        &self,
+        ctx: &Context<'_>,
+        ct_input: CreateStockMovementInput,
+    ) -> Result<StockMovement> {
+        let db = ctx.data::<Db>()?;
+        let ctx = ctx.data::<CtxStruct>()?;
+        Ok(StockMovementUseCases::create(ct_input, db, ctx).await?)
+    }
+
+    async fn update(
+        &self,
+        ctx: &Context<'_>,
+        ct_input: CreateStockMovementInput,
+        id: ThingDerived,
+    ) -> Result<StockMovement> {
+        let db = ctx.data::<Db>()?;
+        let ctx = ctx.data::<CtxStruct>()?;
+        Ok(StockMovementUseCases::update(ct_input, &id, db, ctx).await?)
+    }
+
+    async fn delete(&self, ctx: &Context<'_>, id: ThingDerived) -> Result<StockMovement> {
+        let db = ctx.data::<Db>()?;
+        let ctx = ctx.data::<CtxStruct>()?;
+        Ok(StockMovementUseCases::delete(&id, db, ctx).await?)
+    }
+}
@@ -1,16 +1,64 @@
+mod audit_log_query;
+mod batch_query;
+mod bottleneck_query;
+mod capacity_query;
+mod downtime_query;
+mod flow_integration_query;
+mod hold_time_query;
+mod machinery_query;
+mod machinery_stats_query;
+mod maintenance_order_query;
+mod maintenance_query;
+mod mass_balance_query;
 mod measure_units_query;
+mod mrp_query;
+mod pipe_from_query;
 mod pipe_query;
 mod pipe_stats_query;
+mod pipe_to_query;
+mod plan_fact_query;
+mod plan_feasibility_query;
+mod plant_topology_query;
+mod production_info_failure_query;
 mod production_info_query;
 mod production_plan_per_day_query;
+mod quality_check_query;
+mod quality_spec_query;
+mod recipe_query;
 mod sales_plan_per_day_query;
+mod stock_movement_query;
+mod stock_query;
 mod user_query;
 
 use async_graphql::Object;
-use production_info_query::ProductionInfoQuery;
-use user_query::UserQuery;
+use audit_log_query::AuditLogQuery;
+use batch_query::BatchQuery;
+use bottleneck_query::BottleneckQuery;
+use capacity_query::CapacityQuery;
+use downtime_query::DowntimeQuery;
+use flow_integration_query::FlowIntegrationQuery;
+use hold_time_query::HoldTimeQuery;
+use machinery_query::MachineryQuery;
+use machinery_stats_query::MachineryStatsQuery;
+use maintenance_order_query::MaintenanceOrderQuery;
+use maintenance_query::MaintenanceQuery;
+use mass_balance_query::MassBalanceQuery;
 use measure_units_query::MeasureUnitsQuery;
+use mrp_query::MrpQuery;
+use pipe_from_query::PipeFromQuery;
 use pipe_query::PipeQuery;
+use pipe_to_query::PipeToQuery;
+use plan_fact_query::PlanFactQuery;
+use plan_feasibility_query::PlanFeasibilityQuery;
+use plant_topology_query::PlantTopologyQuery;
+use production_info_failure_query::ProductionInfoFailureQuery;
+use production_info_query::ProductionInfoQuery;
+use quality_check_query::QualityCheckQuery;
+use quality_spec_query::QualitySpecQuery;
+use recipe_query::RecipeQuery;
+use stock_movement_query::StockMovementQuery;
+use stock_query::StockQuery;
+use user_query::UserQuery;
 
 use pipe_stats_query::PipeStatsQuery;
 use production_plan_per_day_query::ProductionPlanPerDayQuery;
@@ -51,4 +99,100 @@ impl QueryRoot {
     async fn pipe_stats(&self) -> PipeStatsQuery {
         PipeStatsQuery
     }
+
+    async fn machinery_stats(&self) -> MachineryStatsQuery {
+        MachineryStatsQuery
+    }
+
+    async fn machinery(&self) -> MachineryQuery {
+        MachineryQuery
+    }
+
+    async fn pipe_to(&self) -> PipeToQuery {
+        PipeToQuery
+    }
+
+    async fn pipe_from(&self) -> PipeFromQuery {
+        PipeFromQuery
+    }
+
+    async fn plant_topology(&self) -> PlantTopologyQuery {
+        PlantTopologyQuery
+    }
+
+    async fn mass_balance(&self) -> MassBalanceQuery {
+        MassBalanceQuery
+    }
+
+    async fn flow_integration(&self) -> FlowIntegrationQuery {
+        FlowIntegrationQuery
+    }
+
+    async fn plan_fact(&self) -> PlanFactQuery {
+        PlanFactQuery
+    }
+
+    async fn production_info_failure(&self) -> ProductionInfoFailureQuery {
+        ProductionInfoFailureQuery
+    }
+
+    async fn maintenance(&self) -> MaintenanceQuery {
+        MaintenanceQuery
+    }
+
+    async fn maintenance_order(&self) -> MaintenanceOrderQuery {
+        MaintenanceOrderQuery
+    }
+
+    async fn downtime(&self) -> DowntimeQuery {
+        DowntimeQuery
+    }
+
+    async fn capacity(&self) -> CapacityQuery {
+        CapacityQuery
+    }
+
+    async fn bottleneck(&self) -> BottleneckQuery {
+        BottleneckQuery
+    }
+
+    async fn plan_feasibility(&self) -> PlanFeasibilityQuery {
+        PlanFeasibilityQuery
+    }
+
+    async fn recipe(&self) -> RecipeQuery {
+        RecipeQuery
+    }
+
+    async fn stock_movement(&self) -> StockMovementQuery {
+        StockMovementQuery
+    }
+
+    async fn stock(&self) -> StockQuery {
+        StockQuery
+    }
+
+    async fn mrp(&self) -> MrpQuery {
+        MrpQuery
+    }
+
+    async fn batch(&self) -> BatchQuery {
+        BatchQuery
+    }
+
+    async fn quality_spec(&self) -> QualitySpecQuery {
+        QualitySpecQuery
+    }
+
+    async fn quality_check(&self) -> QualityCheckQuery {
+        QualityCheckQuery
+    }
+
+    async fn hold_time(&self) -> HoldTimeQuery {
+        HoldTimeQuery
+    }
+
+    async fn audit_log(&self) -> AuditLogQuery {
+        AuditLogQuery
+    }
 }
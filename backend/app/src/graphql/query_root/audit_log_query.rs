@@ -0,0 +1,27 @@
+use async_graphql::{Context, Object, Result};
+use common::ctx::CtxStruct;
+use db::Db;
+
+use service::{
+    audit_log::{AuditLogPage, AuditLogUseCases},
+    thing_derived::ThingDerived,
+};
+
+pub struct AuditLogQuery;
+#[Object]
+impl AuditLogQuery {
+    /// Who changed the record and how, oldest first. Reads the change feed of its table
+    /// from the versionstamp `since` by `limit` change sets (1000 by default, 10000 at most)
+    /// until changes of the record are found. Continue from `nextSince` until it is null
+    async fn select_by_record(
+        &self,
+        ctx: &Context<'_>,
+        record: ThingDerived,
+        since: Option<u64>,
+        limit: Option<usize>,
+    ) -> Result<AuditLogPage> {
+        let db = ctx.data::<Db>()?;
+        let ctx = ctx.data::<CtxStruct>()?;
+        Ok(AuditLogUseCases::select_by_record(&record, since, limit, db, ctx).await?)
+    }
+}
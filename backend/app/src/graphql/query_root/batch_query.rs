@@ -0,0 +1,56 @@
+use async_graphql::{Context, Object, Result};
+use common::ctx::CtxStruct;
+use db::Db;
+
+use service::{
+    batch::{Batch, BatchTrace, BatchUseCases, TraceDirection},
+    thing_derived::ThingDerived,
+};
+
+pub struct BatchQuery;
+#[Object]
+impl BatchQuery {
+    /// Newest first. Only of the pipe if `pipe` is set
+    async fn list(
+        &self,
+        ctx: &Context<'_>,
+        offset: Option<usize>,
+        limit: Option<usize>,
+        pipe: Option<ThingDerived>,
+    ) -> Result<Vec<Batch>> {
+        let db = ctx.data::<Db>()?;
+        let ctx = ctx.data::<CtxStruct>()?;
+        Ok(BatchUseCases::list(offset, limit, pipe, db, ctx).await?)
+    }
+
+    async fn count(&self, ctx: &Context<'_>) -> Result<usize> {
+        let db = ctx.data::<Db>()?;
+        let ctx = ctx.data::<CtxStruct>()?;
+        Ok(BatchUseCases::count(db, ctx).await?)
+    }
+
+    async fn select_by_id(&self, ctx: &Context<'_>, id: ThingDerived) -> Result<Batch> {
+        let db = ctx.data::<Db>()?;
+        let ctx = ctx.data::<CtxStruct>()?;
+        Ok(BatchUseCases::select_by_id(&id, db, ctx).await?)
+    }
+
+    async fn select_by_code(&self, ctx: &Context<'_>, code: String) -> Result<Batch> {
+        let db = ctx.data::<Db>()?;
+        let ctx = ctx.data::<CtxStruct>()?;
+        Ok(BatchUseCases::select_by_code(&code, db, ctx).await?)
+    }
+
+    /// Backward: lots and batches the batch was made of.
+    /// Forward: batches, down to the pallets, made of the batch
+    async fn trace(
+        &self,
+        ctx: &Context<'_>,
+        id: ThingDerived,
+        direction: TraceDirection,
+    ) -> Result<BatchTrace> {
+        let db = ctx.data::<Db>()?;
+        let ctx = ctx.data::<CtxStruct>()?;
+        Ok(BatchUseCases::trace(&id, direction, db, ctx).await?)
+    }
+}
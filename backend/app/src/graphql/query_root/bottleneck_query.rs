@@ -0,0 +1,24 @@
+use async_graphql::{Context, Object, Result};
+use common::ctx::CtxStruct;
+use db::Db;
+
+use service::{
+    bottleneck::{BottleneckAnalysis, BottleneckUseCases},
+    thing_derived::ThingDerived,
+};
+
+pub struct BottleneckQuery;
+#[Object]
+impl BottleneckQuery {
+    /// Maximum throughput of the line into the final pipe and the pipes and machinery limiting it.
+    /// The only final pipe of the plant by default
+    async fn analyze(
+        &self,
+        ctx: &Context<'_>,
+        final_pipe: Option<ThingDerived>,
+    ) -> Result<BottleneckAnalysis> {
+        let db = ctx.data::<Db>()?;
+        let ctx = ctx.data::<CtxStruct>()?;
+        Ok(BottleneckUseCases::analyze(final_pipe, db, ctx).await?)
+    }
+}
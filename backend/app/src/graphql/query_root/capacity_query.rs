@@ -0,0 +1,39 @@
+use async_graphql::{Context, Object, Result};
+use common::ctx::CtxStruct;
+use db::Db;
+
+use service::{
+    capacity::{CapacityUseCases, PipeUtilization},
+    datetime::DateTimeDerived,
+    flow_integration::{Bucket, FlowIntegrationOptions},
+    thing_derived::ThingDerived,
+};
+
+pub struct CapacityQuery;
+#[Object]
+impl CapacityQuery {
+    /// Actual flow against max_flow per pipe in buckets over [from, to).
+    /// All pipes if `pipe` is not set, the most utilized first
+    async fn utilization(
+        &self,
+        ctx: &Context<'_>,
+        from: DateTimeDerived,
+        to: DateTimeDerived,
+        bucket: Bucket,
+        pipe: Option<ThingDerived>,
+        options: Option<FlowIntegrationOptions>,
+    ) -> Result<Vec<PipeUtilization>> {
+        let db = ctx.data::<Db>()?;
+        let ctx = ctx.data::<CtxStruct>()?;
+        Ok(CapacityUseCases::utilization(
+            from,
+            to,
+            bucket,
+            pipe,
+            &options.unwrap_or_default(),
+            db,
+            ctx,
+        )
+        .await?)
+    }
+}
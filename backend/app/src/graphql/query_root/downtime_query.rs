@@ -0,0 +1,37 @@
+use async_graphql::{Context, Object, Result};
+use common::ctx::CtxStruct;
+use db::Db;
+
+use service::{
+    downtime::{Downtime, DowntimeUseCases},
+    thing_derived::ThingDerived,
+};
+
+pub struct DowntimeQuery;
+#[Object]
+impl DowntimeQuery {
+    /// Newest first. Only of the pipe or machinery if `object` is set
+    async fn list(
+        &self,
+        ctx: &Context<'_>,
+        offset: Option<usize>,
+        limit: Option<usize>,
+        object: Option<ThingDerived>,
+    ) -> Result<Vec<Downtime>> {
+        let db = ctx.data::<Db>()?;
+        let ctx = ctx.data::<CtxStruct>()?;
+        Ok(DowntimeUseCases::list(offset, limit, object, db, ctx).await?)
+    }
+
+    async fn count(&self, ctx: &Context<'_>) -> Result<usize> {
+        let db = ctx.data::<Db>()?;
+        let ctx = ctx.data::<CtxStruct>()?;
+        Ok(DowntimeUseCases::count(db, ctx).await?)
+    }
+
+    async fn select_by_id(&self, ctx: &Context<'_>, id: ThingDerived) -> Result<Downtime> {
+        let db = ctx.data::<Db>()?;
+        let ctx = ctx.data::<CtxStruct>()?;
+        Ok(DowntimeUseCases::select_by_id(&id, db, ctx).await?)
+    }
+}
@@ -0,0 +1,77 @@
+use async_graphql::{Context, Object, Result};
+use common::ctx::CtxStruct;
+use db::Db;
+
+use service::{
+    datetime::DateTimeDerived,
+    flow_integration::{FlowIntegral, FlowIntegrationOptions, FlowIntegrationUseCases},
+    thing_derived::ThingDerived,
+};
+
+pub struct FlowIntegrationQuery;
+#[Object]
+impl FlowIntegrationQuery {
+    /// Pipe flow integrated over [from, to)
+    async fn pipe(
+        &self,
+        ctx: &Context<'_>,
+        pipe: ThingDerived,
+        from: DateTimeDerived,
+        to: DateTimeDerived,
+        options: Option<FlowIntegrationOptions>,
+    ) -> Result<FlowIntegral> {
+        let db = ctx.data::<Db>()?;
+        let ctx = ctx.data::<CtxStruct>()?;
+        Ok(FlowIntegrationUseCases::integrate_pipe(
+            &pipe,
+            from,
+            to,
+            &options.unwrap_or_default(),
+            db,
+            ctx,
+        )
+        .await?)
+    }
+
+    /// Machinery flow integrated over [from, to)
+    async fn machinery(
+        &self,
+        ctx: &Context<'_>,
+        machinery: ThingDerived,
+        from: DateTimeDerived,
+        to: DateTimeDerived,
+        options: Option<FlowIntegrationOptions>,
+    ) -> Result<FlowIntegral> {
+        let db = ctx.data::<Db>()?;
+        let ctx = ctx.data::<CtxStruct>()?;
+        Ok(FlowIntegrationUseCases::integrate_machinery(
+            &machinery,
+            from,
+            to,
+            &options.unwrap_or_default(),
+            db,
+            ctx,
+        )
+        .await?)
+    }
+
+    /// Pipe flow integrated over the plant local day containing the date
+    async fn pipe_day(
+        &self,
+        ctx: &Context<'_>,
+        pipe: ThingDerived,
+        date: DateTimeDerived,
+        options: Option<FlowIntegrationOptions>,
+    ) -> Result<FlowIntegral> {
+        let db = ctx.data::<Db>()?;
+        let ctx = ctx.data::<CtxStruct>()?;
+        Ok(FlowIntegrationUseCases::integrate_pipe_day(
+            &pipe,
+            date,
+            &options.unwrap_or_default(),
+            db,
+            ctx,
+        )
+        .await?)
+    }
+}
@@ -0,0 +1,49 @@
+use async_graphql::{Context, Object, Result};
+use common::ctx::CtxStruct;
+use db::Db;
+
+use service::{
+    datetime::DateTimeDerived,
+    flow_integration::FlowIntegrationOptions,
+    hold_time::{HoldTimeReport, HoldTimeUseCases},
+    thing_derived::ThingDerived,
+};
+
+pub struct HoldTimeQuery;
+#[Object]
+impl HoldTimeQuery {
+    /// Hold time compliance for every day from the day of `from` to the day of `to` inclusive.
+    /// All machinery with minimum hold time if `machinery` is not set
+    async fn report(
+        &self,
+        ctx: &Context<'_>,
+        from: DateTimeDerived,
+        to: DateTimeDerived,
+        machinery: Option<ThingDerived>,
+        options: Option<FlowIntegrationOptions>,
+    ) -> Result<HoldTimeReport> {
+        let db = ctx.data::<Db>()?;
+        let ctx = ctx.data::<CtxStruct>()?;
+        Ok(
+            HoldTimeUseCases::report(from, to, machinery, &options.unwrap_or_default(), db, ctx)
+                .await?,
+        )
+    }
+
+    /// Same report as CSV, one row per record
+    async fn export(
+        &self,
+        ctx: &Context<'_>,
+        from: DateTimeDerived,
+        to: DateTimeDerived,
+        machinery: Option<ThingDerived>,
+        options: Option<FlowIntegrationOptions>,
+    ) -> Result<String> {
+        let db = ctx.data::<Db>()?;
+        let ctx = ctx.data::<CtxStruct>()?;
+        Ok(
+            HoldTimeUseCases::export(from, to, machinery, &options.unwrap_or_default(), db, ctx)
+                .await?,
+        )
+    }
+}
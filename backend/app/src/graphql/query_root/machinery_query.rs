@@ -0,0 +1,30 @@
+use async_graphql::{Context, Object, Result};
+use common::ctx::CtxStruct;
+use db::Db;
+
+use service::{
+    machinery::{Machinery, MachineryUseCases},
+    thing_derived::ThingDerived,
+};
+
+pub struct MachineryQuery;
+#[Object]
+impl MachineryQuery {
+    async fn select_by_id(&self, ctx: &Context<'_>, id: ThingDerived) -> Result<Machinery> {
+        let db = ctx.data::<Db>()?;
+        let ctx = ctx.data::<CtxStruct>()?;
+        Ok(MachineryUseCases::select_by_id(&id, db, ctx).await?)
+    }
+
+    async fn list(
+        &self,
+        ctx: &Context<'_>,
+        offset: Option<usize>,
+        limit: Option<usize>,
+        name: Option<String>,
+    ) -> Result<Vec<Machinery>> {
+        let db = ctx.data::<Db>()?;
+        let ctx = ctx.data::<CtxStruct>()?;
+        Ok(MachineryUseCases::list(offset, limit, name, db, ctx).await?)
+    }
+}
@@ -0,0 +1,41 @@
+use async_graphql::{Context, Object, Result};
+use common::ctx::CtxStruct;
+use db::Db;
+
+use service::{
+    datetime::DateTimeDerived,
+    machinery_stats::{MachineryStats, MachineryStatsUseCases},
+    thing_derived::ThingDerived,
+};
+
+pub struct MachineryStatsQuery;
+#[Object]
+impl MachineryStatsQuery {
+    async fn select_by_id(&self, ctx: &Context<'_>, id: ThingDerived) -> Result<MachineryStats> {
+        let db = ctx.data::<Db>()?;
+        let ctx = ctx.data::<CtxStruct>()?;
+        Ok(MachineryStatsUseCases::select_by_id(&id, db, ctx).await?)
+    }
+
+    async fn select_by_machinery_and_date(
+        &self,
+        ctx: &Context<'_>,
+        machinery: ThingDerived,
+        date: DateTimeDerived,
+    ) -> Result<Vec<MachineryStats>> {
+        let db = ctx.data::<Db>()?;
+        let ctx = ctx.data::<CtxStruct>()?;
+        Ok(MachineryStatsUseCases::select_by_machinery_and_date(&machinery, date, db, ctx).await?)
+    }
+
+    async fn list(
+        &self,
+        ctx: &Context<'_>,
+        offset: Option<usize>,
+        limit: Option<usize>,
+    ) -> Result<Vec<MachineryStats>> {
+        let db = ctx.data::<Db>()?;
+        let ctx = ctx.data::<CtxStruct>()?;
+        Ok(MachineryStatsUseCases::list(offset, limit, db, ctx).await?)
+    }
+}
@@ -0,0 +1,37 @@
+use async_graphql::{Context, Object, Result};
+use common::ctx::CtxStruct;
+use db::Db;
+
+use service::{
+    maintenance_order::{MaintenanceOrder, MaintenanceOrderUseCases},
+    thing_derived::ThingDerived,
+};
+
+pub struct MaintenanceOrderQuery;
+#[Object]
+impl MaintenanceOrderQuery {
+    /// Newest first. Only of the pipe or machinery if `object` is set
+    async fn list(
+        &self,
+        ctx: &Context<'_>,
+        offset: Option<usize>,
+        limit: Option<usize>,
+        object: Option<ThingDerived>,
+    ) -> Result<Vec<MaintenanceOrder>> {
+        let db = ctx.data::<Db>()?;
+        let ctx = ctx.data::<CtxStruct>()?;
+        Ok(MaintenanceOrderUseCases::list(offset, limit, object, db, ctx).await?)
+    }
+
+    async fn count(&self, ctx: &Context<'_>) -> Result<usize> {
+        let db = ctx.data::<Db>()?;
+        let ctx = ctx.data::<CtxStruct>()?;
+        Ok(MaintenanceOrderUseCases::count(db, ctx).await?)
+    }
+
+    async fn select_by_id(&self, ctx: &Context<'_>, id: ThingDerived) -> Result<MaintenanceOrder> {
+        let db = ctx.data::<Db>()?;
+        let ctx = ctx.data::<CtxStruct>()?;
+        Ok(MaintenanceOrderUseCases::select_by_id(&id, db, ctx).await?)
+    }
+}
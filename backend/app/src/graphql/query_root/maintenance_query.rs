@@ -0,0 +1,56 @@
+use async_graphql::{Context, Object, Result};
+use common::ctx::CtxStruct;
+use db::Db;
+
+use service::{
+    maintenance::{MaintenanceAlert, MaintenanceOptions, MaintenanceUseCases, WearStatus},
+    thing_derived::ThingDerived,
+};
+
+pub struct MaintenanceQuery;
+#[Object]
+impl MaintenanceQuery {
+    /// Wear of every pipe and machinery
+    async fn statuses(
+        &self,
+        ctx: &Context<'_>,
+        options: Option<MaintenanceOptions>,
+    ) -> Result<Vec<WearStatus>> {
+        let db = ctx.data::<Db>()?;
+        let ctx = ctx.data::<CtxStruct>()?;
+        Ok(MaintenanceUseCases::statuses(&options.unwrap_or_default(), db, ctx).await?)
+    }
+
+    /// Wear of one pipe or machinery
+    async fn status(
+        &self,
+        ctx: &Context<'_>,
+        object: ThingDerived,
+        options: Option<MaintenanceOptions>,
+    ) -> Result<WearStatus> {
+        let db = ctx.data::<Db>()?;
+        let ctx = ctx.data::<CtxStruct>()?;
+        Ok(MaintenanceUseCases::status(&object, &options.unwrap_or_default(), db, ctx).await?)
+    }
+
+    /// Objects due for maintenance, the closest to their wearout limit first
+    async fn due(
+        &self,
+        ctx: &Context<'_>,
+        options: Option<MaintenanceOptions>,
+    ) -> Result<Vec<WearStatus>> {
+        let db = ctx.data::<Db>()?;
+        let ctx = ctx.data::<CtxStruct>()?;
+        Ok(MaintenanceUseCases::due(&options.unwrap_or_default(), db, ctx).await?)
+    }
+
+    async fn alerts(
+        &self,
+        ctx: &Context<'_>,
+        options: Option<MaintenanceOptions>,
+    ) -> Result<Vec<MaintenanceAlert>> {
+        let db = ctx.data::<Db>()?;
+        let ctx = ctx.data::<CtxStruct>()?;
+        Ok(MaintenanceUseCases::alerts(&options.unwrap_or_default(), db, ctx).await?)
+    }
+}
@@ -0,0 +1,29 @@
+use async_graphql::{Context, Object, Result};
+use common::ctx::CtxStruct;
+use db::Db;
+use rust_decimal::Decimal;
+
+use service::{
+    datetime::DateTimeDerived,
+    flow_integration::FlowIntegrationOptions,
+    mass_balance::{MassBalanceReport, MassBalanceUseCases},
+};
+
+pub struct MassBalanceQuery;
+#[Object]
+impl MassBalanceQuery {
+    /// Inbound vs outbound volume of every machinery over [from, to).
+    /// Tolerance is a share of inbound volume, 0.05 by default
+    async fn report(
+        &self,
+        ctx: &Context<'_>,
+        from: DateTimeDerived,
+        to: DateTimeDerived,
+        tolerance: Option<Decimal>,
+        options: Option<FlowIntegrationOptions>,
+    ) -> Result<MassBalanceReport> {
+        let db = ctx.data::<Db>()?;
+        let ctx = ctx.data::<CtxStruct>()?;
+        Ok(MassBalanceUseCases::get(from, to, tolerance, options, db, ctx).await?)
+    }
+}
@@ -1,6 +1,7 @@
 use async_graphql::{Context, Object, Result};
 use common::ctx::CtxStruct;
 use db::Db;
+use rust_decimal::Decimal;
 
 use service::{
     datetime::DateTimeDerived,
@@ -28,4 +29,17 @@ impl MeasureUnitsQuery {
         let ctx = ctx.data::<CtxStruct>()?;
         Ok(MeasureUnitsUseCases::list(offset, limit, name, db, ctx).await?)
     }
+
+    /// Amount in `to` units. Error if the units are of different dimensions
+    async fn convert(
+        &self,
+        ctx: &Context<'_>,
+        amount: Decimal,
+        from: ThingDerived,
+        to: ThingDerived,
+    ) -> Result<Decimal> {
+        let db = ctx.data::<Db>()?;
+        let ctx = ctx.data::<CtxStruct>()?;
+        Ok(MeasureUnitsUseCases::convert(amount, &from, &to, db, ctx).await?)
+    }
 }
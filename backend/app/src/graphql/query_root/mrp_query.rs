@@ -0,0 +1,28 @@
+use async_graphql::{Context, Object, Result};
+use common::ctx::CtxStruct;
+use db::Db;
+
+use service::{
+    datetime::DateTimeDerived,
+    flow_integration::FlowIntegrationOptions,
+    mrp::{MrpReport, MrpScenarioInput, MrpUseCases},
+};
+
+pub struct MrpQuery;
+#[Object]
+impl MrpQuery {
+    /// Raw material requirements and shortfalls for every day from the day of `from`
+    /// to the day of `to` inclusive. `scenario` changes plans and receipts without saving them
+    async fn report(
+        &self,
+        ctx: &Context<'_>,
+        from: DateTimeDerived,
+        to: DateTimeDerived,
+        scenario: Option<MrpScenarioInput>,
+        options: Option<FlowIntegrationOptions>,
+    ) -> Result<MrpReport> {
+        let db = ctx.data::<Db>()?;
+        let ctx = ctx.data::<CtxStruct>()?;
+        Ok(MrpUseCases::report(from, to, scenario, &options.unwrap_or_default(), db, ctx).await?)
+    }
+}
@@ -0,0 +1,55 @@
+use async_graphql::{Context, Object, Result};
+use common::ctx::CtxStruct;
+use db::Db;
+
+use service::{
+    machinery::Machinery,
+    pipe::Pipe,
+    pipe_from::{PipeFrom, PipeFromUseCases},
+    thing_derived::ThingDerived,
+};
+
+pub struct PipeFromQuery;
+#[Object]
+impl PipeFromQuery {
+    async fn select_by_id(&self, ctx: &Context<'_>, id: ThingDerived) -> Result<PipeFrom> {
+        let db = ctx.data::<Db>()?;
+        let ctx = ctx.data::<CtxStruct>()?;
+        Ok(PipeFromUseCases::select_by_id(&id, db, ctx).await?)
+    }
+
+    async fn list(
+        &self,
+        ctx: &Context<'_>,
+        offset: Option<usize>,
+        limit: Option<usize>,
+    ) -> Result<Vec<PipeFrom>> {
+        let db = ctx.data::<Db>()?;
+        let ctx = ctx.data::<CtxStruct>()?;
+        Ok(PipeFromUseCases::list(offset, limit, db, ctx).await?)
+    }
+
+    async fn count(&self, ctx: &Context<'_>) -> Result<usize> {
+        let db = ctx.data::<Db>()?;
+        let ctx = ctx.data::<CtxStruct>()?;
+        Ok(PipeFromUseCases::count(db, ctx).await?)
+    }
+
+    /// Pipes feeding the machinery
+    async fn input_pipes(&self, ctx: &Context<'_>, machinery: ThingDerived) -> Result<Vec<Pipe>> {
+        let db = ctx.data::<Db>()?;
+        let ctx = ctx.data::<CtxStruct>()?;
+        Ok(PipeFromUseCases::select_input_pipes(&machinery, db, ctx).await?)
+    }
+
+    /// Downstream machinery, fed by the pipe
+    async fn target_machinery(
+        &self,
+        ctx: &Context<'_>,
+        pipe: ThingDerived,
+    ) -> Result<Vec<Machinery>> {
+        let db = ctx.data::<Db>()?;
+        let ctx = ctx.data::<CtxStruct>()?;
+        Ok(PipeFromUseCases::select_target_machinery(&pipe, db, ctx).await?)
+    }
+}
@@ -4,6 +4,7 @@ use db::Db;
 
 use service::{
     datetime::DateTimeDerived,
+    flow_integration::{Bucket, FlowBucket, FlowIntegrationOptions},
     pipe_stats::{PipeStats, PipeStatsUseCases},
     thing_derived::ThingDerived,
 };
@@ -27,4 +28,41 @@ impl PipeStatsQuery {
         let ctx = ctx.data::<CtxStruct>()?;
         Ok(PipeStatsUseCases::list(offset, limit, db, ctx).await?)
     }
+
+    /// Readings of the pipe in [from, to)
+    async fn select_by_pipe_and_range(
+        &self,
+        ctx: &Context<'_>,
+        pipe: ThingDerived,
+        from: DateTimeDerived,
+        to: DateTimeDerived,
+    ) -> Result<Vec<PipeStats>> {
+        let db = ctx.data::<Db>()?;
+        let ctx = ctx.data::<CtxStruct>()?;
+        Ok(PipeStatsUseCases::select_by_pipe_and_range(&pipe, from, to, db, ctx).await?)
+    }
+
+    /// Integrated volume, average, min and max flow of the pipe in buckets over [from, to)
+    async fn aggregate(
+        &self,
+        ctx: &Context<'_>,
+        pipe: ThingDerived,
+        from: DateTimeDerived,
+        to: DateTimeDerived,
+        bucket: Bucket,
+        options: Option<FlowIntegrationOptions>,
+    ) -> Result<Vec<FlowBucket>> {
+        let db = ctx.data::<Db>()?;
+        let ctx = ctx.data::<CtxStruct>()?;
+        Ok(PipeStatsUseCases::aggregate_by_pipe(
+            &pipe,
+            from,
+            to,
+            bucket,
+            &options.unwrap_or_default(),
+            db,
+            ctx,
+        )
+        .await?)
+    }
 }
@@ -0,0 +1,55 @@
+use async_graphql::{Context, Object, Result};
+use common::ctx::CtxStruct;
+use db::Db;
+
+use service::{
+    machinery::Machinery,
+    pipe::Pipe,
+    pipe_to::{PipeTo, PipeToUseCases},
+    thing_derived::ThingDerived,
+};
+
+pub struct PipeToQuery;
+#[Object]
+impl PipeToQuery {
+    async fn select_by_id(&self, ctx: &Context<'_>, id: ThingDerived) -> Result<PipeTo> {
+        let db = ctx.data::<Db>()?;
+        let ctx = ctx.data::<CtxStruct>()?;
+        Ok(PipeToUseCases::select_by_id(&id, db, ctx).await?)
+    }
+
+    async fn list(
+        &self,
+        ctx: &Context<'_>,
+        offset: Option<usize>,
+        limit: Option<usize>,
+    ) -> Result<Vec<PipeTo>> {
+        let db = ctx.data::<Db>()?;
+        let ctx = ctx.data::<CtxStruct>()?;
+        Ok(PipeToUseCases::list(offset, limit, db, ctx).await?)
+    }
+
+    async fn count(&self, ctx: &Context<'_>) -> Result<usize> {
+        let db = ctx.data::<Db>()?;
+        let ctx = ctx.data::<CtxStruct>()?;
+        Ok(PipeToUseCases::count(db, ctx).await?)
+    }
+
+    /// Pipes the machinery outputs into
+    async fn output_pipes(&self, ctx: &Context<'_>, machinery: ThingDerived) -> Result<Vec<Pipe>> {
+        let db = ctx.data::<Db>()?;
+        let ctx = ctx.data::<CtxStruct>()?;
+        Ok(PipeToUseCases::select_output_pipes(&machinery, db, ctx).await?)
+    }
+
+    /// Machinery that outputs into the pipe
+    async fn source_machinery(
+        &self,
+        ctx: &Context<'_>,
+        pipe: ThingDerived,
+    ) -> Result<Vec<Machinery>> {
+        let db = ctx.data::<Db>()?;
+        let ctx = ctx.data::<CtxStruct>()?;
+        Ok(PipeToUseCases::select_source_machinery(&pipe, db, ctx).await?)
+    }
+}
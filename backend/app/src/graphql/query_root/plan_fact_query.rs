@@ -0,0 +1,32 @@
+use async_graphql::{Context, Object, Result};
+use common::ctx::CtxStruct;
+use db::Db;
+
+use service::{
+    datetime::DateTimeDerived,
+    flow_integration::FlowIntegrationOptions,
+    plan_fact::{PlanFactReport, PlanFactUseCases},
+    thing_derived::ThingDerived,
+};
+
+pub struct PlanFactQuery;
+#[Object]
+impl PlanFactQuery {
+    /// Production plan, sales plan and fact for every day from the day of `from`
+    /// to the day of `to` inclusive. Uses the only final pipe of the line if `final_pipe` is not set
+    async fn report(
+        &self,
+        ctx: &Context<'_>,
+        from: DateTimeDerived,
+        to: DateTimeDerived,
+        final_pipe: Option<ThingDerived>,
+        options: Option<FlowIntegrationOptions>,
+    ) -> Result<PlanFactReport> {
+        let db = ctx.data::<Db>()?;
+        let ctx = ctx.data::<CtxStruct>()?;
+        Ok(
+            PlanFactUseCases::report(from, to, final_pipe, &options.unwrap_or_default(), db, ctx)
+                .await?,
+        )
+    }
+}
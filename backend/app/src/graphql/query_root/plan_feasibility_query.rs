@@ -0,0 +1,34 @@
+use async_graphql::{Context, Object, Result};
+use common::ctx::CtxStruct;
+use db::Db;
+use rust_decimal::Decimal;
+
+use service::{
+    datetime::DateTimeDerived,
+    plan_feasibility::{PlanFeasibility, PlanFeasibilityUseCases},
+    plant_topology::PlantTopologyUseCases,
+    thing_derived::ThingDerived,
+};
+
+pub struct PlanFeasibilityQuery;
+#[Object]
+impl PlanFeasibilityQuery {
+    /// Checks a production plan amount for the day without saving it.
+    /// The only final pipe of the plant by default
+    async fn check(
+        &self,
+        ctx: &Context<'_>,
+        amount: Decimal,
+        units: ThingDerived,
+        date: DateTimeDerived,
+        line: Option<ThingDerived>,
+    ) -> Result<PlanFeasibility> {
+        let db = ctx.data::<Db>()?;
+        let ctx = ctx.data::<CtxStruct>()?;
+        let line = match line {
+            Some(line) => line,
+            None => PlantTopologyUseCases::select_final_pipe(db, ctx).await?,
+        };
+        Ok(PlanFeasibilityUseCases::check(amount, &units, date, &line, db, ctx).await?)
+    }
+}
@@ -0,0 +1,16 @@
+use async_graphql::{Context, Object, Result};
+use common::ctx::CtxStruct;
+use db::Db;
+
+use service::plant_topology::{PlantTopology, PlantTopologyUseCases};
+
+pub struct PlantTopologyQuery;
+#[Object]
+impl PlantTopologyQuery {
+    /// All machinery, pipes and edges between them in one graph
+    async fn graph(&self, ctx: &Context<'_>) -> Result<PlantTopology> {
+        let db = ctx.data::<Db>()?;
+        let ctx = ctx.data::<CtxStruct>()?;
+        Ok(PlantTopologyUseCases::get(db, ctx).await?)
+    }
+}
@@ -0,0 +1,27 @@
+use async_graphql::{Context, Object, Result};
+use common::ctx::CtxStruct;
+use db::Db;
+
+use service::production_info_failure::{ProductionInfoFailure, ProductionInfoFailureUseCases};
+
+pub struct ProductionInfoFailureQuery;
+#[Object]
+impl ProductionInfoFailureQuery {
+    /// Newest first
+    async fn list(
+        &self,
+        ctx: &Context<'_>,
+        offset: Option<usize>,
+        limit: Option<usize>,
+    ) -> Result<Vec<ProductionInfoFailure>> {
+        let db = ctx.data::<Db>()?;
+        let ctx = ctx.data::<CtxStruct>()?;
+        Ok(ProductionInfoFailureUseCases::list(offset, limit, db, ctx).await?)
+    }
+
+    async fn count(&self, ctx: &Context<'_>) -> Result<usize> {
+        let db = ctx.data::<Db>()?;
+        let ctx = ctx.data::<CtxStruct>()?;
+        Ok(ProductionInfoFailureUseCases::count(db, ctx).await?)
+    }
+}
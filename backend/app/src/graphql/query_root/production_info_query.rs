@@ -17,13 +17,15 @@ impl ProductionInfoQuery {
         Ok(ProductionInfoUseCases::select_by_id(&id, db, ctx).await?)
     }
 
+    /// Uses the only final pipe of the plant if `final_pipe` is not set
     async fn select_by_date(
         &self,
         ctx: &Context<'_>,
         date: DateTimeDerived,
+        final_pipe: Option<ThingDerived>,
     ) -> Result<Option<ProductionInfo>> {
         let db = ctx.data::<Db>()?;
         let ctx = ctx.data::<CtxStruct>()?;
-        Ok(ProductionInfoUseCases::select_by_date(date, db, ctx).await?)
+        Ok(ProductionInfoUseCases::select_by_date(final_pipe, date, db, ctx).await?)
     }
 }
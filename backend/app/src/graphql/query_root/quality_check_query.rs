@@ -0,0 +1,53 @@
+use async_graphql::{Context, Object, Result};
+use common::ctx::CtxStruct;
+use db::Db;
+
+use service::{
+    datetime::DateTimeDerived,
+    quality_check::{QualityCheck, QualityCheckUseCases},
+    thing_derived::ThingDerived,
+};
+
+pub struct QualityCheckQuery;
+#[Object]
+impl QualityCheckQuery {
+    /// Newest first. Only of the pipe or machinery if `object` is set
+    async fn list(
+        &self,
+        ctx: &Context<'_>,
+        offset: Option<usize>,
+        limit: Option<usize>,
+        object: Option<ThingDerived>,
+        out_of_spec: Option<bool>,
+    ) -> Result<Vec<QualityCheck>> {
+        let db = ctx.data::<Db>()?;
+        let ctx = ctx.data::<CtxStruct>()?;
+        Ok(QualityCheckUseCases::list(offset, limit, object, out_of_spec, db, ctx).await?)
+    }
+
+    async fn count(&self, ctx: &Context<'_>) -> Result<usize> {
+        let db = ctx.data::<Db>()?;
+        let ctx = ctx.data::<CtxStruct>()?;
+        Ok(QualityCheckUseCases::count(db, ctx).await?)
+    }
+
+    async fn select_by_id(&self, ctx: &Context<'_>, id: ThingDerived) -> Result<QualityCheck> {
+        let db = ctx.data::<Db>()?;
+        let ctx = ctx.data::<CtxStruct>()?;
+        Ok(QualityCheckUseCases::select_by_id(&id, db, ctx).await?)
+    }
+
+    /// Checks affecting the line on the plant local day of the date.
+    /// Uses the only final pipe of the plant if `final_pipe` is not set
+    async fn select_by_date(
+        &self,
+        ctx: &Context<'_>,
+        date: DateTimeDerived,
+        final_pipe: Option<ThingDerived>,
+        out_of_spec: Option<bool>,
+    ) -> Result<Vec<QualityCheck>> {
+        let db = ctx.data::<Db>()?;
+        let ctx = ctx.data::<CtxStruct>()?;
+        Ok(QualityCheckUseCases::select_by_date(final_pipe, date, out_of_spec, db, ctx).await?)
+    }
+}
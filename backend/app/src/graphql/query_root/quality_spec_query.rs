@@ -0,0 +1,37 @@
+use async_graphql::{Context, Object, Result};
+use common::ctx::CtxStruct;
+use db::Db;
+
+use service::{
+    quality_spec::{QualitySpec, QualitySpecUseCases},
+    thing_derived::ThingDerived,
+};
+
+pub struct QualitySpecQuery;
+#[Object]
+impl QualitySpecQuery {
+    /// Only of the raw material if `material` is set
+    async fn list(
+        &self,
+        ctx: &Context<'_>,
+        offset: Option<usize>,
+        limit: Option<usize>,
+        material: Option<ThingDerived>,
+    ) -> Result<Vec<QualitySpec>> {
+        let db = ctx.data::<Db>()?;
+        let ctx = ctx.data::<CtxStruct>()?;
+        Ok(QualitySpecUseCases::list(offset, limit, material, db, ctx).await?)
+    }
+
+    async fn count(&self, ctx: &Context<'_>) -> Result<usize> {
+        let db = ctx.data::<Db>()?;
+        let ctx = ctx.data::<CtxStruct>()?;
+        Ok(QualitySpecUseCases::count(db, ctx).await?)
+    }
+
+    async fn select_by_id(&self, ctx: &Context<'_>, id: ThingDerived) -> Result<QualitySpec> {
+        let db = ctx.data::<Db>()?;
+        let ctx = ctx.data::<CtxStruct>()?;
+        Ok(QualitySpecUseCases::select_by_id(&id, db, ctx).await?)
+    }
+}
@@ -0,0 +1,71 @@
+use async_graphql::{Context, Object, Result};
+use common::ctx::CtxStruct;
+use db::Db;
+use rust_decimal::Decimal;
+
+use service::{
+    recipe::{Recipe, RecipeUseCases, RequirementExplosion},
+    thing_derived::ThingDerived,
+};
+
+pub struct RecipeQuery;
+#[Object]
+impl RecipeQuery {
+    async fn list(
+        &self,
+        ctx: &Context<'_>,
+        offset: Option<usize>,
+        limit: Option<usize>,
+    ) -> Result<Vec<Recipe>> {
+        let db = ctx.data::<Db>()?;
+        let ctx = ctx.data::<CtxStruct>()?;
+        Ok(RecipeUseCases::list(offset, limit, db, ctx).await?)
+    }
+
+    async fn count(&self, ctx: &Context<'_>) -> Result<usize> {
+        let db = ctx.data::<Db>()?;
+        let ctx = ctx.data::<CtxStruct>()?;
+        Ok(RecipeUseCases::count(db, ctx).await?)
+    }
+
+    async fn select_by_id(&self, ctx: &Context<'_>, id: ThingDerived) -> Result<Recipe> {
+        let db = ctx.data::<Db>()?;
+        let ctx = ctx.data::<CtxStruct>()?;
+        Ok(RecipeUseCases::select_by_id(&id, db, ctx).await?)
+    }
+
+    /// None if the material is raw
+    async fn select_by_product(
+        &self,
+        ctx: &Context<'_>,
+        product: ThingDerived,
+    ) -> Result<Option<Recipe>> {
+        let db = ctx.data::<Db>()?;
+        let ctx = ctx.data::<CtxStruct>()?;
+        Ok(RecipeUseCases::select_by_product(&product, db, ctx).await?)
+    }
+
+    /// Raw materials and intermediate products needed to make `amount` of the product
+    async fn requirements(
+        &self,
+        ctx: &Context<'_>,
+        product: ThingDerived,
+        amount: Decimal,
+        units: ThingDerived,
+    ) -> Result<RequirementExplosion> {
+        let db = ctx.data::<Db>()?;
+        let ctx = ctx.data::<CtxStruct>()?;
+        Ok(RecipeUseCases::requirements(&product, amount, &units, db, ctx).await?)
+    }
+
+    /// Raw materials and intermediate products needed to fulfil the production plan
+    async fn plan_requirements(
+        &self,
+        ctx: &Context<'_>,
+        plan: ThingDerived,
+    ) -> Result<RequirementExplosion> {
+        let db = ctx.data::<Db>()?;
+        let ctx = ctx.data::<CtxStruct>()?;
+        Ok(RecipeUseCases::plan_requirements(&plan, db, ctx).await?)
+    }
+}
@@ -4,6 +4,7 @@ use db::Db;
 
 use service::{
     datetime::DateTimeDerived,
+    plan_revision::PlanRevision,
     sales_per_day::{SalesPlanPerDay, SalesPlanPerDayUnitsUseCases},
     thing_derived::ThingDerived,
 };
@@ -17,14 +18,48 @@ impl SalesPlanPerDayQuery {
         Ok(SalesPlanPerDayUnitsUseCases::select_by_id(&id, db, ctx).await?)
     }
 
+    /// All lines if `line` is not set
     async fn list(
         &self,
         ctx: &Context<'_>,
         offset: Option<usize>,
         limit: Option<usize>,
+        line: Option<ThingDerived>,
     ) -> Result<Vec<SalesPlanPerDay>> {
         let db = ctx.data::<Db>()?;
         let ctx = ctx.data::<CtxStruct>()?;
-        Ok(SalesPlanPerDayUnitsUseCases::list(offset, limit, db, ctx).await?)
+        Ok(SalesPlanPerDayUnitsUseCases::list(offset, limit, line, db, ctx).await?)
+    }
+
+    /// Plan of the line for the plant local day containing the date
+    async fn select_by_date(
+        &self,
+        ctx: &Context<'_>,
+        line: ThingDerived,
+        date: DateTimeDerived,
+    ) -> Result<Option<SalesPlanPerDay>> {
+        let db = ctx.data::<Db>()?;
+        let ctx = ctx.data::<CtxStruct>()?;
+        Ok(SalesPlanPerDayUnitsUseCases::select_by_date(&line, date, db, ctx).await?)
+    }
+
+    /// Plan of the line for the plant local day containing the date as it was at `as_of`
+    async fn select_by_date_as_of(
+        &self,
+        ctx: &Context<'_>,
+        line: ThingDerived,
+        date: DateTimeDerived,
+        as_of: DateTimeDerived,
+    ) -> Result<Option<PlanRevision>> {
+        let db = ctx.data::<Db>()?;
+        let ctx = ctx.data::<CtxStruct>()?;
+        Ok(SalesPlanPerDayUnitsUseCases::select_by_date_as_of(&line, date, as_of, db, ctx).await?)
+    }
+
+    /// Every change of the plan with its author, newest first
+    async fn revisions(&self, ctx: &Context<'_>, id: ThingDerived) -> Result<Vec<PlanRevision>> {
+        let db = ctx.data::<Db>()?;
+        let ctx = ctx.data::<CtxStruct>()?;
+        Ok(SalesPlanPerDayUnitsUseCases::revisions(&id, db, ctx).await?)
     }
 }
@@ -0,0 +1,39 @@
+use async_graphql::{Context, Object, Result};
+use common::ctx::CtxStruct;
+use db::Db;
+
+use service::{
+    datetime::DateTimeDerived,
+    flow_integration::FlowIntegrationOptions,
+    stock::{StockReport, StockUseCases},
+    thing_derived::ThingDerived,
+};
+
+pub struct StockQuery;
+#[Object]
+impl StockQuery {
+    /// On-hand balance of the raw material for every day from the day of `from`
+    /// to the day of `to` inclusive. Days from today on are projected with production plans
+    async fn report(
+        &self,
+        ctx: &Context<'_>,
+        material: ThingDerived,
+        from: DateTimeDerived,
+        to: DateTimeDerived,
+        units: Option<ThingDerived>,
+        options: Option<FlowIntegrationOptions>,
+    ) -> Result<StockReport> {
+        let db = ctx.data::<Db>()?;
+        let ctx = ctx.data::<CtxStruct>()?;
+        Ok(StockUseCases::report(
+            &material,
+            from,
+            to,
+            units,
+            &options.unwrap_or_default(),
+            db,
+            ctx,
+        )
+        .await?)
+    }
+}
@@ -0,0 +1,61 @@
+use async_graphql::{Context, Result, Subscription};
+use common::ctx::CtxStruct;
+use db::Db;
+use futures::{Stream, StreamExt};
+
+use service::{
+    flow_integration::FlowIntegrationOptions,
+    live::{LiveProductionFact, LiveUseCases},
+    maintenance::{MaintenanceAlert, MaintenanceOptions},
+    pipe_stats::PipeStats,
+    thing_derived::ThingDerived,
+};
+
+pub struct SubscriptionRoot;
+#[Subscription]
+impl SubscriptionRoot {
+    /// Readings of the pipe as they are created
+    async fn pipe_stats<'ctx>(
+        &self,
+        ctx: &Context<'ctx>,
+        pipe: ThingDerived,
+    ) -> Result<impl Stream<Item = Result<PipeStats>> + 'ctx> {
+        let db = ctx.data::<Db>()?;
+        let ctx = ctx.data::<CtxStruct>()?;
+        Ok(LiveUseCases::pipe_stats(&pipe, db, ctx)
+            .await?
+            .map(|stats| Ok(stats?)))
+    }
+
+    /// Fact of the current plant local day, updated on every reading of the final pipe.
+    /// Uses the only final pipe of the plant if `final_pipe` is not set
+    async fn production_fact<'ctx>(
+        &self,
+        ctx: &Context<'ctx>,
+        final_pipe: Option<ThingDerived>,
+        options: Option<FlowIntegrationOptions>,
+    ) -> Result<impl Stream<Item = Result<LiveProductionFact>> + 'ctx> {
+        let db = ctx.data::<Db>()?;
+        let ctx = ctx.data::<CtxStruct>()?;
+        Ok(
+            LiveUseCases::production_facts(final_pipe, options.unwrap_or_default(), db, ctx)
+                .await?
+                .map(|fact| Ok(fact?)),
+        )
+    }
+
+    /// Critical alerts first. Sent again only when they change
+    async fn maintenance_alerts<'ctx>(
+        &self,
+        ctx: &Context<'ctx>,
+        options: Option<MaintenanceOptions>,
+    ) -> Result<impl Stream<Item = Result<Vec<MaintenanceAlert>>> + 'ctx> {
+        let db = ctx.data::<Db>()?;
+        let ctx = ctx.data::<CtxStruct>()?;
+        Ok(
+            LiveUseCases::maintenance_alerts(options.unwrap_or_default(), db, ctx)
+                .await?
+                .map(|alerts| Ok(alerts?)),
+        )
+    }
+}
@@ -1,4 +1,5 @@
 mod graphql;
+mod scheduler;
 
 pub use common::mw_req_logger;
 pub use common::{error, mw_ctx};
@@ -8,16 +9,17 @@ pub use db::db;
 pub use db::set_database;
 pub use db::Db;
 
-use async_graphql::{EmptySubscription, Schema};
+use async_graphql::Schema;
 use axum::{
     extract::Extension,
     middleware,
-    routing::{get_service, post},
+    routing::{get, get_service, post},
     Router,
 };
 use error::Result;
 use graphql::{
-    graphiql, graphql_handler, mutation_root::MutationRoot, query_root::QueryRoot, ApiSchema,
+    graphiql, graphql_handler, graphql_ws_handler, mutation_root::MutationRoot,
+    query_root::QueryRoot, subscription_root::SubscriptionRoot, ApiSchema,
 };
 use http::HeaderValue;
 use http::Method;
@@ -69,9 +71,10 @@ async fn main() -> Result<()> {
     //#[cfg(not(debug_assertions))]
     service::prod_populate::check_and_recreate_admin_user().await;
     service::prod_populate::seed_data().await;
+    scheduler::spawn_production_info_job(db::DB.clone());
 
     // GQL
-    let schema: ApiSchema = Schema::build(QueryRoot, MutationRoot, EmptySubscription)
+    let schema: ApiSchema = Schema::build(QueryRoot, MutationRoot, SubscriptionRoot)
         //.data(mc.clone())
         .data(db::DB.clone())
         .data(key_enc.clone())
@@ -101,6 +104,7 @@ async fn main() -> Result<()> {
         gql = gql.route("/", post(graphql_handler));
     }
     gql = gql
+        .route("/ws", get(graphql_ws_handler))
         .layer(Extension(schema))
         // Require auth to access gql
         .route_layer(middleware::from_fn(mw_ctx::mw_require_auth));
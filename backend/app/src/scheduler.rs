@@ -0,0 +1,77 @@
+use chrono::{Duration, Utc};
+use common::ctx::SystemCtx;
+use db::Db;
+use service::{
+    flow_integration::{day_bounds, FlowIntegrationOptions},
+    production_info::ProductionInfoUseCases,
+};
+
+lazy_static::lazy_static! {
+    /// Minutes after the plant local midnight when the previous days get materialized
+    static ref PRODUCTION_INFO_JOB_DELAY_MINUTES: i64 =
+        std::env::var("PRODUCTION_INFO_JOB_DELAY_MINUTES")
+            .ok()
+            .and_then(|delay| delay.parse().ok())
+            .unwrap_or(60);
+    /// How many closed days are checked on every run, same range as the mutation accepts
+    static ref PRODUCTION_INFO_JOB_LOOKBACK_DAYS: i64 =
+        std::env::var("PRODUCTION_INFO_JOB_LOOKBACK_DAYS")
+            .ok()
+            .and_then(|days| days.parse().ok())
+            .filter(|days| (1..=366).contains(days))
+            .unwrap_or(31);
+}
+
+async fn run_production_info_job(db: &Db, options: &FlowIntegrationOptions) {
+    // The job writes without a user
+    let ctx = SystemCtx::new();
+    match ProductionInfoUseCases::materialize(
+        Utc::now().into(),
+        *PRODUCTION_INFO_JOB_LOOKBACK_DAYS,
+        options,
+        db,
+        &ctx,
+    )
+    .await
+    {
+        Ok(summary) => println!(
+            "->> {:<12} - production info: created {}, completed {}, skipped {}, failed {}",
+            "SCHEDULER", summary.created, summary.completed, summary.skipped, summary.failed
+        ),
+        Err(err) => println!(
+            "->> {:<12} - production info failed: {:?}",
+            "SCHEDULER", err.error
+        ),
+    }
+}
+
+/// Materializes ProductionInfo of closed days on start and then
+/// every day PRODUCTION_INFO_JOB_DELAY_MINUTES after the plant local midnight
+pub fn spawn_production_info_job(db: Db) {
+    tokio::spawn(async move {
+        let options = FlowIntegrationOptions::default();
+        let ctx = SystemCtx::new();
+        let offset = match options.utc_offset(&ctx) {
+            Ok(offset) => offset,
+            Err(err) => {
+                println!(
+                    "->> {:<12} - production info job stopped: {:?}",
+                    "SCHEDULER", err.error
+                );
+                return;
+            }
+        };
+        let delay = Duration::minutes(*PRODUCTION_INFO_JOB_DELAY_MINUTES);
+        loop {
+            run_production_info_job(&db, &options).await;
+
+            let now = Utc::now();
+            let mut next_run = day_bounds(now, offset).0 + delay;
+            while next_run <= now {
+                next_run += Duration::days(1);
+            }
+            let sleep = (next_run - now).to_std().unwrap_or_default();
+            tokio::time::sleep(sleep).await;
+        }
+    });
+}
@@ -140,6 +140,60 @@ impl Ctx for CtxStruct {
     //}
 }
 
+/// Ctx of the work done outside of requests, e.g. the scheduled jobs.
+/// There is no user, so claims and cookies are errors
+#[derive(Clone, Debug)]
+pub struct SystemCtx {
+    req_id: Uuid,
+}
+
+impl SystemCtx {
+    pub fn new() -> Self {
+        Self {
+            req_id: Uuid::new_v4(),
+        }
+    }
+
+    fn no_user(&self) -> ApiError {
+        ApiError {
+            error: Error::AuthFailNoJwtCookie,
+            req_id: self.req_id,
+        }
+    }
+}
+
+impl Default for SystemCtx {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Ctx for SystemCtx {
+    fn user_id(&self) -> ApiResult<String> {
+        Err(self.no_user())
+    }
+
+    fn user_id_thing(&self) -> ApiResult<Thing> {
+        Err(self.no_user())
+    }
+
+    fn roles(&self) -> ApiResult<Roles> {
+        Err(self.no_user())
+    }
+
+    fn cookies(&self) -> Cookies {
+        Cookies::default()
+    }
+
+    fn cookies_add(&self, _cookie: Cookie<'static>) -> ApiResult<()> {
+        Err(self.no_user())
+    }
+
+    fn req_id(&self) -> Uuid {
+        self.req_id
+    }
+}
+
 // ugly but direct implementation from axum, until "async trait fn" are in stable rust, instead of importing some 3rd party macro
 // Extractor - makes it possible to specify Ctx as a param - fetches the result from the header parts extension
 impl<S: Send + Sync> FromRequestParts<S> for CtxStruct {
@@ -26,6 +26,7 @@ pub enum Error {
     SurrealDb { source: String },
     SurrealDbNoResult { source: String, id: String },
     SurrealDbParse { source: String, id: String },
+    IncompatibleUnits { from: String, to: String },
 }
 
 /// ApiError has to have the req_id to report to the client and implements IntoResponse.
@@ -74,6 +75,9 @@ impl fmt::Display for Error {
             Self::SurrealDb { .. } => write!(f, "{INTERNAL}"),
             Self::SurrealDbNoResult { id, .. } => write!(f, "No result for id {id}"),
             Self::SurrealDbParse { id, .. } => write!(f, "Couldn't parse id {id}"),
+            Self::IncompatibleUnits { from, to } => {
+                write!(f, "Units {from} can't be converted into {to}")
+            }
             Self::Forbidden => write!(f, "Forbidden! You do not have needed priveledges"),
         }
     }
@@ -87,7 +91,8 @@ impl IntoResponse for ApiError {
             Error::TicketDeleteFailIdNotFound { .. }
             | Error::Serde { .. }
             | Error::SurrealDbNoResult { .. }
-            | Error::SurrealDbParse { .. } => StatusCode::BAD_REQUEST,
+            | Error::SurrealDbParse { .. }
+            | Error::IncompatibleUnits { .. } => StatusCode::BAD_REQUEST,
             Error::Generic { .. }
             | Error::LoginFail
             | Error::AuthFailNoJwtCookie
@@ -2,6 +2,7 @@ use assert_fs::TempDir;
 use include_dir::{include_dir, Dir};
 use once_cell::sync::Lazy;
 use std::fs;
+use std::path::PathBuf;
 use surrealdb::opt::auth::Root;
 use surrealdb::{
     dbs::Capabilities,
@@ -43,7 +44,7 @@ pub type Db = Surreal<Any>;
 
 pub static DB: Lazy<Db> = Lazy::new(Surreal::init);
 
-pub async fn set_test_db() -> Db {
+async fn connect_test_db() -> Db {
     //use tracing_log::LogTracer;
     //let _ = LogTracer::init();
     static ONCE_LOG: std::sync::Once = std::sync::Once::new();
@@ -73,9 +74,12 @@ pub async fn set_test_db() -> Db {
         .expect("Problem with namespace or database");
     let version = db.version().await.expect("Could not get db version!");
     println!("->> DB version: {version}");
-    println!("DIR:{:?}\n\n", DB_DIR);
+    db
+}
 
-    let temp_dir = TempDir::new().unwrap();
+/// Extracts the db files into the temp dir and writes the runner config for them
+fn test_config_file(temp_dir: &TempDir) -> PathBuf {
+    println!("DIR:{:?}\n\n", DB_DIR);
     DB_DIR.extract(temp_dir.path()).unwrap();
 
     let config_file_path = temp_dir.join(".surrealdb");
@@ -95,6 +99,18 @@ pub async fn set_test_db() -> Db {
         DB_DATABASE.as_str()
     );
     fs::write(config_file_path.clone(), content).unwrap();
+    config_file_path
+}
+
+pub async fn set_test_db() -> Db {
+    let db = connect_test_db().await;
+
+    let temp_dir = TempDir::new().unwrap();
+    let config_file_path = test_config_file(&temp_dir);
+    // _initial.json is not commited, so the runner recreates it from current schemas.
+    // Diffs of older migrations can't be applied on top of it, so test db
+    // is always created from current schemas. The chain itself is checked by migrations_test
+    fs::remove_dir_all(temp_dir.join("migrations/definitions")).unwrap();
 
     let runner = MigrationRunner::new(&db);
     runner
@@ -225,3 +241,162 @@ pub async fn set_database() {
 
     set_migrations().await;
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+    use std::path::Path;
+
+    const FIRST_MIGRATION: &str = "20240425_164504_initial";
+
+    fn sorted_files(dir: &Path) -> Vec<PathBuf> {
+        let mut files: Vec<PathBuf> = fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .collect();
+        files.sort();
+        files
+    }
+
+    /// Unified diff from the modified text back to the original one
+    fn reverse(diff: &str) -> String {
+        diff.lines()
+            .enumerate()
+            .map(|(index, line)| match line.chars().next() {
+                // File headers, removed lines may start with "---" too
+                _ if index < 2 => line.to_string(),
+                Some('-') => format!("+{}", &line[1..]),
+                Some('+') => format!("-{}", &line[1..]),
+                Some('@') => {
+                    let ranges: Vec<&str> = line.split_whitespace().collect();
+                    format!("@@ -{} +{} @@", &ranges[2][1..], &ranges[1][1..])
+                }
+                _ => line.to_string(),
+            })
+            .map(|line| line + "\n")
+            .collect()
+    }
+
+    /// Schemas the first migration starts from. Current schemas with the diffs
+    /// of all migrations reverted, joined the same way the runner joins them
+    fn initial_definition(dir: &Path) -> String {
+        let mut schemas = sorted_files(&dir.join("schemas"))
+            .iter()
+            .map(|file| fs::read_to_string(file).unwrap())
+            .collect::<Vec<_>>()
+            .join("\n");
+        for file in sorted_files(&dir.join("migrations/definitions"))
+            .iter()
+            .rev()
+        {
+            let definition: serde_json::Value =
+                serde_json::from_str(&fs::read_to_string(file).unwrap()).unwrap();
+            if let Some(diff) = definition["schemas"].as_str() {
+                let reversed = reverse(diff);
+                let patch = diffy::Patch::from_str(&reversed).unwrap();
+                schemas = diffy::apply(&schemas, &patch)
+                    .unwrap_or_else(|_| panic!("Can't revert {}", file.display()));
+            }
+        }
+        serde_json::json!({ "schemas": schemas, "events": "" }).to_string()
+    }
+
+    #[tokio::test]
+    async fn migrations_test() {
+        let db = connect_test_db().await;
+        let temp_dir = TempDir::new().unwrap();
+        let config_file_path = test_config_file(&temp_dir);
+        fs::write(
+            temp_dir.join("migrations/definitions/_initial.json"),
+            initial_definition(temp_dir.path()),
+        )
+        .unwrap();
+        let runner = MigrationRunner::new(&db).use_config_file(&config_file_path);
+        runner
+            .up_to(FIRST_MIGRATION)
+            .await
+            .expect("Failed to apply the first migration");
+
+        // Rows written while the production tables were schemaless
+        db.query(
+            r#"
+            CREATE MeasureUnits:kg SET name = "kg";
+            CREATE RawMaterial:sugar SET name = "Sugar";
+            CREATE PipeType:pipe SET name = "Pipe", max_flow = "100", wearout_max = "1000",
+                units = MeasureUnits:kg;
+            CREATE Pipe:line SET name = "Line", pipe_type = PipeType:pipe, material = RawMaterial:sugar;
+            CREATE PipeStats:reading SET date = d"2024-01-01T08:00:00Z", pipe = Pipe:line,
+                units = MeasureUnits:kg, flow = "150", wearout = "10";
+            CREATE MachineryType:mixer SET name = "Mixer", max_flow = "50", wearout_max = "500",
+                units = MeasureUnits:kg;
+            CREATE Machinery:mixer SET name = "Mixer", machinery_type = MachineryType:mixer;
+            CREATE MachineryStats:reading SET date = d"2024-01-01T08:00:00Z",
+                machinery = Machinery:mixer, units = MeasureUnits:kg, flow = "60", wearout = "5";
+            CREATE SalesPlanPerDay:plan SET date = d"2024-01-01T08:00:00Z",
+                units = MeasureUnits:kg, amount = "80";
+            CREATE ProductionPlanPerDay:plan SET date = d"2024-01-01T08:00:00Z",
+                units = MeasureUnits:kg, amount = "90";
+            CREATE ProductionInfo:info SET date = d"2024-01-01T08:00:00Z",
+                sales_plan = SalesPlanPerDay:plan, production_plan = ProductionPlanPerDay:plan,
+                final_pipe = Pipe:line, measure_units = MeasureUnits:kg;
+            "#,
+        )
+        .await
+        .unwrap()
+        .check()
+        .unwrap();
+
+        runner.up().await.expect("Failed to apply migrations");
+
+        let mut response = db
+            .query(
+                r#"
+                RETURN {
+                    factor: MeasureUnits:kg.factor = 1,
+                    max_flow: type::is::decimal(PipeType:pipe.max_flow),
+                    flow: type::is::decimal(PipeStats:reading.flow),
+                    over_capacity: PipeStats:reading.over_capacity,
+                    machinery_over_capacity: MachineryStats:reading.over_capacity,
+                    line: SalesPlanPerDay:plan.line = Pipe:line,
+                    production_line: ProductionPlanPerDay:plan.line = Pipe:line,
+                    day: SalesPlanPerDay:plan.day = d"2024-01-01T00:00:00Z",
+                    info_day: ProductionInfo:info.day = d"2024-01-01T00:00:00Z",
+                    revisions: (SELECT VALUE plan FROM PlanRevision WHERE revision = 1 ORDER BY plan)
+                        = [ProductionPlanPerDay:plan, SalesPlanPerDay:plan],
+                };
+                SELECT VALUE script_name FROM script_migration ORDER BY script_name;
+                "#,
+            )
+            .await
+            .unwrap();
+        let checks: Option<BTreeMap<String, bool>> = response.take(0).unwrap();
+        assert_eq!(
+            checks,
+            Some(
+                [
+                    "day",
+                    "factor",
+                    "flow",
+                    "info_day",
+                    "line",
+                    "machinery_over_capacity",
+                    "max_flow",
+                    "over_capacity",
+                    "production_line",
+                    "revisions",
+                ]
+                .into_iter()
+                .map(|check| (check.to_string(), true))
+                .collect()
+            )
+        );
+        let applied: Vec<String> = response.take(1).unwrap();
+        let migrations: Vec<String> = sorted_files(&temp_dir.join("migrations"))
+            .iter()
+            .filter(|file| file.is_file())
+            .map(|file| file.file_stem().unwrap().to_string_lossy().to_string())
+            .collect();
+        assert_eq!(applied, migrations);
+    }
+}
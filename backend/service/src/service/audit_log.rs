@@ -0,0 +1,691 @@
+use crate::common::Unwrapper;
+use crate::datetime::DateTimeDerived;
+use crate::service::guard::RoleGuard;
+use crate::thing_derived::ThingDerived;
+use crate::thing_wrapper::ObjectWithThing;
+use async_graphql::{Enum, Json, SimpleObject};
+use common::{
+    ctx::Ctx,
+    error::{ApiError, Error},
+    role::Role,
+    ApiResult,
+};
+
+use db::Db;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::collections::HashMap;
+use surrealdb::sql::{Id, Thing, Value};
+
+/// Tables defined with CHANGEFEED
+pub const AUDITED_TABLES: [&str; 21] = [
+    "Batch",
+    "BatchLink",
+    "Downtime",
+    "Machinery",
+    "MachineryStats",
+    "MachineryType",
+    "MaintenanceOrder",
+    "MeasureUnits",
+    "Pipe",
+    "PipeFrom",
+    "PipeStats",
+    "PipeTo",
+    "PipeType",
+    "ProductionInfo",
+    "ProductionPlanPerDay",
+    "QualityCheck",
+    "QualitySpec",
+    "RawMaterial",
+    "Recipe",
+    "SalesPlanPerDay",
+    "StockMovement",
+];
+
+/// Changes scanned by one call if no limit is given
+const CHANGES_PAGE: usize = 1000;
+/// Changes scanned by one call at most
+const CHANGES_PAGE_MAX: usize = 10000;
+/// The feed keeps one change set per table with a change feed and transaction, User and
+/// Deletion included, whatever the number of records written. A page of that many change
+/// sets spans more than one transaction
+const CHANGES_PAGE_MIN: usize = AUDITED_TABLES.len() + 2;
+
+const DELETION_RESOURCE: &str = "Deletion";
+
+/// Fields maintained for the audit log, not reported as changed
+const AUDIT_FIELDS: [&str; 2] = ["changed_by", "changed_at"];
+
+/// Content of a write together with its author.
+/// Every audited table defines `changed_by` and `changed_at`, which are read back from
+/// the change feed. The author is NONE for changes made by the system. `changed_at` is
+/// set by the schema and optional only because writes with CONTENT are type checked before VALUE
+#[derive(Serialize)]
+pub struct Audited<T> {
+    #[serde(flatten)]
+    content: T,
+    changed_by: Option<Thing>,
+}
+
+impl<T> Audited<T> {
+    pub fn new(content: T, ctx: &dyn Ctx) -> Self {
+        Self {
+            content,
+            changed_by: ctx.user_id_thing().ok(),
+        }
+    }
+}
+
+#[derive(Enum, Copy, Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub enum AuditAction {
+    Create,
+    Update,
+    Delete,
+}
+
+#[derive(Clone, Debug, SimpleObject)]
+#[graphql(guard = "RoleGuard::new(Role::User)")]
+pub struct AuditEntry {
+    /// Order of the changes. Changes of one transaction share it
+    pub versionstamp: u64,
+    pub record: ThingDerived,
+    pub action: AuditAction,
+    /// None for deletions made without the service
+    pub changed_at: Option<DateTimeDerived>,
+    /// None for changes made by the system
+    pub changed_by: Option<ThingDerived>,
+    pub changed_by_email: Option<String>,
+    /// Top level fields that differ between `before` and `after`
+    pub fields: Vec<String>,
+    /// None for created records
+    pub before: Option<Json<serde_json::Value>>,
+    /// None for deleted records
+    pub after: Option<Json<serde_json::Value>>,
+}
+
+/// Changes of a record found in one page of the change feed of its table
+#[derive(Clone, Debug, SimpleObject)]
+#[graphql(guard = "RoleGuard::new(Role::User)")]
+pub struct AuditLogPage {
+    /// Oldest first
+    pub entries: Vec<AuditEntry>,
+    /// Versionstamp the next page starts from. None once the end of the feed is reached
+    pub next_since: Option<u64>,
+}
+
+/// Applies one operation of a patch from the change feed. Strings are patched
+/// by diff-match-patch text, other values are replaced
+fn patch(json: &mut Option<serde_json::Value>, op: &str, path: &str, value: Value) {
+    let value = match (op, value) {
+        ("remove", _) | (_, Value::None | Value::Null) => None,
+        ("change", Value::Strand(text)) => {
+            let Some(current) = json
+                .as_ref()
+                .and_then(|json| json.pointer(path))
+                .and_then(serde_json::Value::as_str)
+            else {
+                return;
+            };
+            let dmp = dmp::new();
+            let Ok((text, _)) = dmp
+                .patch_from_text(text.0)
+                .and_then(|patches| dmp.patch_apply(&patches, current))
+            else {
+                return;
+            };
+            Some(serde_json::Value::String(text.into_iter().collect()))
+        }
+        (_, value) => Some(value.into_json()),
+    };
+    let Some((parent, key)) = path.rsplit_once('/') else {
+        return;
+    };
+    if key.is_empty() {
+        *json = value;
+        return;
+    }
+    let Some(object) = json
+        .as_mut()
+        .and_then(|json| json.pointer_mut(parent))
+        .and_then(serde_json::Value::as_object_mut)
+    else {
+        return;
+    };
+    match value {
+        Some(value) => object.insert(key.to_string(), value),
+        None => object.remove(key),
+    };
+}
+
+fn changed_fields(
+    before: Option<&serde_json::Value>,
+    after: Option<&serde_json::Value>,
+) -> Vec<String> {
+    let field =
+        |json: Option<&serde_json::Value>, key: &str| json.and_then(|json| json.get(key)).cloned();
+    let mut fields: Vec<String> = [before, after]
+        .into_iter()
+        .flatten()
+        .filter_map(serde_json::Value::as_object)
+        .flat_map(|object| object.keys().cloned())
+        .filter(|key| !AUDIT_FIELDS.contains(&key.as_str()))
+        .filter(|key| field(before, key) != field(after, key))
+        .collect();
+    fields.sort();
+    fields.dedup();
+    fields
+}
+
+fn versionstamp(changeset: &Value) -> Option<u64> {
+    match changeset.pick(&["versionstamp".into()]) {
+        Value::Number(versionstamp) => Some(versionstamp.as_int() as u64),
+        _ => None,
+    }
+}
+
+/// Entries of the record in the output of `SHOW CHANGES`, oldest first.
+/// Change feeds keep the current state with the patch back to the original one for writes,
+/// and only the id for deletions
+pub fn entries(changesets: Value, record: &Thing) -> Vec<AuditEntry> {
+    let mut entries: Vec<AuditEntry> = vec![];
+    let Value::Array(changesets) = changesets else {
+        return entries;
+    };
+    for changeset in changesets {
+        let Some(versionstamp) = versionstamp(&changeset) else {
+            continue;
+        };
+        let Value::Array(changes) = changeset.pick(&["changes".into()]) else {
+            continue;
+        };
+        for change in changes {
+            let (current, deleted) = match (
+                change.pick(&["current".into()]),
+                change.pick(&["delete".into()]),
+            ) {
+                (Value::Object(current), _) => (current, false),
+                (_, Value::Object(deleted)) => (deleted, true),
+                _ => continue,
+            };
+            if current.get("id") != Some(&Value::Thing(record.clone())) {
+                continue;
+            }
+            if deleted {
+                // Author and content of the deleted record are kept by Deletion
+                entries.push(AuditEntry {
+                    versionstamp,
+                    record: record.clone().into(),
+                    action: AuditAction::Delete,
+                    changed_at: None,
+                    changed_by: None,
+                    changed_by_email: None,
+                    fields: vec![],
+                    before: None,
+                    after: None,
+                });
+                continue;
+            }
+            let changed_at = match current.get("changed_at") {
+                Some(Value::Datetime(changed_at)) => Some(changed_at.0.into()),
+                _ => None,
+            };
+            let changed_by = match current.get("changed_by") {
+                Some(Value::Thing(changed_by)) => Some(changed_by.clone().into()),
+                _ => None,
+            };
+            let after = Value::Object(current).into_json();
+            let mut before = Some(after.clone());
+            if let Value::Array(ops) = change.pick(&["update".into()]) {
+                for op in ops {
+                    if let (Value::Strand(name), Value::Strand(path)) =
+                        (op.pick(&["op".into()]), op.pick(&["path".into()]))
+                    {
+                        patch(&mut before, &name, &path, op.pick(&["value".into()]));
+                    }
+                }
+            }
+            entries.push(AuditEntry {
+                versionstamp,
+                record: record.clone().into(),
+                action: match before {
+                    None => AuditAction::Create,
+                    Some(_) => AuditAction::Update,
+                },
+                changed_at,
+                changed_by,
+                changed_by_email: None,
+                fields: changed_fields(before.as_ref(), Some(&after)),
+                before: before.map(Json),
+                after: Some(Json(after)),
+            });
+        }
+    }
+    entries
+}
+
+#[derive(Deserialize)]
+struct UserEmail {
+    id: ThingDerived,
+    email: String,
+}
+
+pub struct AuditLogRepository {}
+
+impl AuditLogRepository {
+    /// Deletes the record. Its author and content are written to Deletion in the same
+    /// transaction, the record itself is not written, so its asserts are not checked again
+    pub async fn delete<R>(table: &str, id: Id, db: &Db, ctx: &dyn Ctx) -> ApiResult<Option<R>>
+    where
+        R: DeserializeOwned,
+    {
+        Self::delete_with(table, id, &[], db, ctx).await
+    }
+
+    /// Same as `delete`, the statements run first in the same transaction with the record
+    /// bound to `$thing`. A THROW among them keeps the record and its message is the error
+    pub async fn delete_with<R>(
+        table: &str,
+        id: Id,
+        statements: &[&str],
+        db: &Db,
+        ctx: &dyn Ctx,
+    ) -> ApiResult<Option<R>>
+    where
+        R: DeserializeOwned,
+    {
+        let query = db
+            .query(format!(
+                "BEGIN TRANSACTION; {} \
+                 LET $before = (SELECT * FROM $thing)[0]; \
+                 DELETE $thing RETURN BEFORE; \
+                 IF $before {{ \
+                 CREATE {DELETION_RESOURCE} \
+                 SET record = $thing, before = $before, changed_by = $changed_by; \
+                 }}; \
+                 COMMIT TRANSACTION;",
+                statements.join(" ")
+            ))
+            .bind(("thing", Thing::from((table, id))))
+            .bind(("changed_by", ctx.user_id_thing().ok()));
+        Unwrapper::unwrapper_checked(query, ctx)
+            .await?
+            .take::<Option<R>>(statements.len() + 1)
+            .map_err(ApiError::from(ctx))
+    }
+
+    /// Deletion of the record written by the transaction that deleted it at the versionstamp,
+    /// if it was deleted by the service
+    pub async fn select_deletion(
+        record: &Thing,
+        versionstamp: u64,
+        db: &Db,
+        ctx: &dyn Ctx,
+    ) -> ApiResult<Option<Value>> {
+        // Every table written by a transaction gets its own versionstamp on commit, in no
+        // particular order. The Deletion is the one of the record nearest to the versionstamp
+        let since = versionstamp.saturating_sub(CHANGES_PAGE_MIN as u64);
+        let changesets =
+            Self::changes(DELETION_RESOURCE, since, 2 * CHANGES_PAGE_MIN + 1, db, ctx).await?;
+        Ok(changesets
+            .into_iter()
+            .filter_map(|changeset| {
+                let distance = self::versionstamp(&changeset)?.abs_diff(versionstamp);
+                let Value::Array(changes) = changeset.pick(&["changes".into()]) else {
+                    return None;
+                };
+                changes
+                    .into_iter()
+                    .filter_map(|change| match change {
+                        Value::Object(change) => change.0.into_values().next(),
+                        _ => None,
+                    })
+                    .find(|deletion| {
+                        deletion.pick(&["record".into()]) == Value::Thing(record.clone())
+                    })
+                    .map(|deletion| (distance, deletion))
+            })
+            .min_by_key(|(distance, _)| *distance)
+            .map(|(_, deletion)| deletion))
+    }
+
+    /// Change sets of the table since the versionstamp. The limit counts change sets of
+    /// all tables scanned to find them, a change set of one table is never cut
+    pub async fn changes(
+        table: &str,
+        since: u64,
+        limit: usize,
+        db: &Db,
+        ctx: &dyn Ctx,
+    ) -> ApiResult<Vec<Value>> {
+        let changesets = db
+            .query(format!(
+                "SHOW CHANGES FOR TABLE {table} SINCE {since} LIMIT {limit};"
+            ))
+            .await
+            .map_err(ApiError::from(ctx))?
+            .take::<Value>(0)
+            .map_err(ApiError::from(ctx))?;
+        Ok(match changesets {
+            Value::Array(changesets) => changesets.0,
+            _ => vec![],
+        })
+    }
+
+    /// Versionstamp of the last of `limit` change sets of the database since the one given
+    pub async fn last_versionstamp(
+        since: u64,
+        limit: usize,
+        db: &Db,
+        ctx: &dyn Ctx,
+    ) -> ApiResult<Option<u64>> {
+        let changesets = db
+            .query(format!(
+                "SHOW CHANGES FOR DATABASE SINCE {since} LIMIT {limit};"
+            ))
+            .await
+            .map_err(ApiError::from(ctx))?
+            .take::<Value>(0)
+            .map_err(ApiError::from(ctx))?;
+        Ok(match changesets {
+            Value::Array(changesets) => changesets.last().and_then(versionstamp),
+            _ => None,
+        })
+    }
+
+    pub async fn select_emails(
+        users: Vec<Thing>,
+        db: &Db,
+        ctx: &dyn Ctx,
+    ) -> ApiResult<HashMap<String, String>> {
+        let query = db
+            .query("SELECT id, email FROM $users;")
+            .bind(("users", users));
+        Ok(Unwrapper::unwrapper_vec::<UserEmail, _>(query, 0, ctx)
+            .await?
+            .into_iter()
+            .map(|user| (user.id.to_string(), user.email))
+            .collect())
+    }
+}
+
+pub struct AuditLogUseCases {}
+
+impl AuditLogUseCases {
+    /// Who changed the record and how. Reads the change feed of its table by `limit` change
+    /// sets starting from the versionstamp `since`, until changes of the record are found
+    pub async fn select_by_record(
+        record: &dyn ObjectWithThing,
+        since: Option<u64>,
+        limit: Option<usize>,
+        db: &Db,
+        ctx: &dyn Ctx,
+    ) -> ApiResult<AuditLogPage> {
+        let record = record.thing(ctx)?;
+        if !AUDITED_TABLES.contains(&record.tb.as_str()) {
+            return Err(ApiError {
+                req_id: ctx.req_id(),
+                error: Error::Generic {
+                    description: format!("Audit log: {} has no change feed", record.tb),
+                },
+            });
+        }
+        let limit = limit
+            .unwrap_or(CHANGES_PAGE)
+            .clamp(CHANGES_PAGE_MIN, CHANGES_PAGE_MAX);
+        let mut since = since.unwrap_or(0);
+        let (mut entries, next_since) = loop {
+            let changesets = AuditLogRepository::changes(&record.tb, since, limit, db, ctx).await?;
+            let next_since = match changesets.last().and_then(versionstamp) {
+                Some(last) => Some(last + 1),
+                // Either the feed has ended or the page was filled by other tables. The last
+                // change set of the database may be cut, the next page reads it again
+                None => AuditLogRepository::last_versionstamp(since, limit, db, ctx)
+                    .await?
+                    .filter(|last| *last > since),
+            };
+            let entries = entries(Value::Array(changesets.into()), &record);
+            match next_since {
+                Some(next_since) if entries.is_empty() => since = next_since,
+                _ => break (entries, next_since),
+            }
+        };
+
+        for entry in entries
+            .iter_mut()
+            .filter(|entry| entry.action == AuditAction::Delete)
+        {
+            let Some(deletion) =
+                AuditLogRepository::select_deletion(&record, entry.versionstamp, db, ctx).await?
+            else {
+                continue;
+            };
+            if let Value::Datetime(changed_at) = deletion.pick(&["changed_at".into()]) {
+                entry.changed_at = Some(changed_at.0.into());
+            }
+            if let Value::Thing(changed_by) = deletion.pick(&["changed_by".into()]) {
+                entry.changed_by = Some(changed_by.into());
+            }
+            let before = deletion.pick(&["before".into()]).into_json();
+            entry.fields = changed_fields(Some(&before), None);
+            entry.before = Some(Json(before));
+        }
+
+        let mut users: Vec<Thing> = vec![];
+        for entry in &entries {
+            if let Some(user) = &entry.changed_by {
+                let user = user.thing(ctx)?;
+                if !users.contains(&user) {
+                    users.push(user);
+                }
+            }
+        }
+        if !users.is_empty() {
+            let emails = AuditLogRepository::select_emails(users, db, ctx).await?;
+            for entry in &mut entries {
+                entry.changed_by_email = entry
+                    .changed_by
+                    .as_ref()
+                    .and_then(|user| emails.get(&user.to_string()).cloned());
+            }
+        }
+        Ok(AuditLogPage {
+            entries,
+            next_since,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pipe_stats::PipeStatsUseCases;
+    use crate::pipe_type::{CreatePipeTypeInput, PipeTypeUseCases};
+    use crate::prod_populate::{
+        measure_units_shortcut, pipe_shortcut, pipe_stats_shortcut, pipe_type_shortcut,
+    };
+    use crate::raw_material::{CreateRawMaterialInput, RawMaterialUseCases};
+    use crate::user::tests::create_user;
+    use common::ctx::MockCtx;
+    use db::set_test_db;
+    use rstest::*;
+
+    fn ctx(user: Option<Thing>) -> MockCtx {
+        let mut ctx = MockCtx::new();
+        ctx.expect_req_id().return_const(uuid::Uuid::new_v4());
+        ctx.expect_user_id_thing().returning(move || {
+            user.clone().ok_or(ApiError {
+                req_id: uuid::Uuid::new_v4(),
+                error: Error::Generic {
+                    description: "No user".to_string(),
+                },
+            })
+        });
+        ctx
+    }
+
+    #[fixture]
+    async fn tdb() -> Db {
+        set_test_db().await
+    }
+
+    fn input(name: &str) -> CreateRawMaterialInput {
+        CreateRawMaterialInput {
+            name: name.to_string(),
+        }
+    }
+
+    #[rstest]
+    #[tokio::test]
+    #[awt]
+    async fn audit_log_test(#[future] tdb: Db) {
+        let system = ctx(None);
+        let (user, _) = create_user(&system, &tdb).await.unwrap();
+        let author = ctx(Some(user.thing(&system).unwrap()));
+
+        let material = RawMaterialUseCases::create(input("Сахар"), &tdb, &system)
+            .await
+            .unwrap();
+        // More changes than one page holds, in the table of the record and in another one
+        for name in 0..CHANGES_PAGE_MIN {
+            RawMaterialUseCases::create(input(&name.to_string()), &tdb, &system)
+                .await
+                .unwrap();
+            measure_units_shortcut(&format!("ед. {name}"), &tdb, &system)
+                .await
+                .unwrap();
+        }
+        RawMaterialUseCases::update(input("Сахар-песок"), &material, &tdb, &author)
+            .await
+            .unwrap();
+        RawMaterialUseCases::delete(&material, &tdb, &author)
+            .await
+            .unwrap();
+        // Changes of other records are skipped
+        RawMaterialUseCases::create(input("Молоко"), &tdb, &author)
+            .await
+            .unwrap();
+
+        let page = AuditLogUseCases::select_by_record(&material, None, None, &tdb, &system)
+            .await
+            .unwrap();
+        let entries = page.entries;
+        assert_eq!(
+            entries.iter().map(|entry| entry.action).collect::<Vec<_>>(),
+            vec![
+                AuditAction::Create,
+                AuditAction::Update,
+                AuditAction::Delete
+            ]
+        );
+        let (created, updated, deleted) = (&entries[0], &entries[1], &entries[2]);
+
+        assert_eq!(created.changed_by, None);
+        assert_eq!(created.changed_by_email, None);
+        assert!(created.before.is_none());
+        assert_eq!(created.fields, vec!["id", "name"]);
+
+        assert_eq!(updated.changed_by, user.id);
+        assert_eq!(updated.changed_by_email, Some(user.email.clone()));
+        assert_eq!(updated.fields, vec!["name"]);
+        assert_eq!(updated.before.as_ref().unwrap().0["name"], "Сахар");
+        assert_eq!(updated.after.as_ref().unwrap().0["name"], "Сахар-песок");
+        assert!(updated.changed_at.is_some());
+
+        // Author of the deletion is kept by Deletion
+        assert_eq!(deleted.changed_by, user.id);
+        assert_eq!(deleted.changed_by_email, Some(user.email.clone()));
+        assert_eq!(deleted.before.as_ref().unwrap().0["name"], "Сахар-песок");
+        assert!(deleted.after.is_none());
+        assert!(deleted.versionstamp > updated.versionstamp);
+
+        // Same entries page by page
+        let mut paged = vec![];
+        let mut since = None;
+        let mut pages = 0;
+        loop {
+            pages += 1;
+            let page = AuditLogUseCases::select_by_record(&material, since, Some(1), &tdb, &system)
+                .await
+                .unwrap();
+            // Pages without changes of the record are read through
+            assert!(!page.entries.is_empty() || page.next_since.is_none());
+            paged.extend(page.entries.into_iter().map(|entry| entry.versionstamp));
+            since = page.next_since;
+            if since.is_none() {
+                break;
+            }
+        }
+        assert_eq!(
+            paged,
+            entries
+                .iter()
+                .map(|entry| entry.versionstamp)
+                .collect::<Vec<_>>()
+        );
+        assert!(pages > 2);
+
+        // Users are not audited
+        assert!(
+            AuditLogUseCases::select_by_record(&user, None, None, &tdb, &system)
+                .await
+                .is_err()
+        );
+    }
+
+    #[rstest]
+    #[tokio::test]
+    #[awt]
+    async fn delete_invalid_record_test(#[future] tdb: Db) {
+        let system = ctx(None);
+        let (user, _) = create_user(&system, &tdb).await.unwrap();
+        let author = ctx(Some(user.thing(&system).unwrap()));
+
+        let units = measure_units_shortcut("кг", &tdb, &system).await.unwrap();
+        let pipe_type = pipe_type_shortcut("Трубопровод", &units, &tdb, &system)
+            .await
+            .unwrap();
+        let material = RawMaterialUseCases::create(input("Сахар"), &tdb, &system)
+            .await
+            .unwrap();
+        let pipe = pipe_shortcut("Трубопровод № 1", &pipe_type, &material, &tdb, &system)
+            .await
+            .unwrap();
+        let stats = pipe_stats_shortcut(
+            chrono::Utc::now().into(),
+            rust_decimal::Decimal::ONE,
+            rust_decimal::Decimal::from(10),
+            &units,
+            &pipe,
+            &tdb,
+            &system,
+        )
+        .await
+        .unwrap();
+        // The reading doesn't pass the wearout assert anymore
+        PipeTypeUseCases::update(
+            CreatePipeTypeInput {
+                name: pipe_type.name.clone(),
+                max_flow: pipe_type.max_flow,
+                wearout_max: rust_decimal::Decimal::from(5),
+                units: units.id.clone().unwrap(),
+            },
+            &pipe_type,
+            &tdb,
+            &system,
+        )
+        .await
+        .unwrap();
+
+        PipeStatsUseCases::delete(&stats, &tdb, &author)
+            .await
+            .unwrap();
+        let entries = AuditLogUseCases::select_by_record(&stats, None, None, &tdb, &system)
+            .await
+            .unwrap()
+            .entries;
+        let deleted = entries.last().unwrap();
+        assert_eq!(deleted.action, AuditAction::Delete);
+        assert_eq!(deleted.changed_by, user.id);
+        assert!(deleted.changed_at.is_some());
+        assert_eq!(deleted.before.as_ref().unwrap().0["wearout"], "10");
+    }
+}
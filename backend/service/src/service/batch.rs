@@ -0,0 +1,1045 @@
+use crate::audit_log::{AuditLogRepository, Audited};
+use crate::common::Unwrapper;
+use crate::datetime::DateTimeDerived;
+use crate::flow_integration::hours;
+use crate::measure_units::MeasureUnitsUseCases;
+use crate::pipe::PipeUseCases;
+use crate::pipe_from::PipeFromUseCases;
+use crate::pipe_to::PipeToUseCases;
+use crate::service::guard::RoleGuard;
+use crate::thing_derived::ThingDerived;
+use crate::thing_wrapper::ObjectWithThing;
+use async_graphql::{Enum, InputObject, SimpleObject};
+use common::{
+    ctx::Ctx,
+    error::{ApiError, Error},
+    role::Role,
+    ApiResult,
+};
+
+use db::Db;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use surrealdb::sql::{Id, Thing};
+
+#[allow(dead_code)]
+const RESOURCE: &str = "Batch";
+const LINK_RESOURCE: &str = "BatchLink";
+
+/// Quantity of material that went through a pipe during [started_at, ended_at].
+/// Lots enter the line through input pipes, the rest are produced by machinery from other batches
+#[derive(Clone, Debug, Serialize, Deserialize, SimpleObject)]
+#[graphql(guard = "RoleGuard::new(Role::User)")]
+pub struct Batch {
+    pub id: Option<ThingDerived>,
+    pub code: String,
+    pub pipe: ThingDerived,
+    pub material: ThingDerived,
+    /// None for raw material lots
+    pub machinery: Option<ThingDerived>,
+    pub supplier_lot: Option<String>,
+    pub amount: Decimal,
+    pub units: ThingDerived,
+    pub started_at: DateTimeDerived,
+    pub ended_at: DateTimeDerived,
+}
+
+impl ObjectWithThing for Batch {
+    fn thing(&self, ctx: &dyn Ctx) -> ApiResult<Thing> {
+        self.id
+            .as_ref()
+            .ok_or(ApiError {
+                req_id: ctx.req_id(),
+                error: Error::Generic {
+                    description: "Can't get thing. Get none instead".to_string(),
+                },
+            })?
+            .thing(ctx)
+    }
+}
+
+/// Graph edge: Batch -> BatchLink -> Batch
+/// Amount of the consumed batch (in) in its units that went into the produced one (out)
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct BatchLink {
+    pub id: Option<ThingDerived>,
+    pub r#in: ThingDerived,
+    pub out: ThingDerived,
+    pub amount: Decimal,
+}
+
+#[derive(Enum, Copy, Clone, Debug, Eq, PartialEq)]
+pub enum TraceDirection {
+    /// To the batches the traced one was produced from
+    Backward,
+    /// To the batches produced from the traced one
+    Forward,
+}
+
+#[derive(Clone, Debug, SimpleObject)]
+#[graphql(guard = "RoleGuard::new(Role::User)")]
+pub struct TracedBatch {
+    pub batch: Batch,
+    /// Links from the traced batch on the shortest path
+    pub depth: usize,
+    /// Amount of the upstream batch that ended up in the downstream one,
+    /// in units of the upstream batch. For backward trace it is the amount of this batch
+    /// in the traced one, for forward trace it is the amount of the traced batch in this one
+    pub amount: Decimal,
+    /// True if the trace ends here: a raw material lot for backward trace,
+    /// a batch nothing was produced from yet for forward trace
+    pub end: bool,
+}
+
+#[derive(Clone, Debug, SimpleObject)]
+#[graphql(guard = "RoleGuard::new(Role::User)")]
+pub struct BatchTrace {
+    pub batch: Batch,
+    pub direction: TraceDirection,
+    /// Ordered by depth
+    pub batches: Vec<TracedBatch>,
+}
+
+/// Follows links from the batch. `batches` has every batch reachable from it by `links`
+pub fn trace(
+    batch: &Batch,
+    batches: &HashMap<String, Batch>,
+    links: &[BatchLink],
+    direction: TraceDirection,
+) -> Vec<TracedBatch> {
+    struct Tracer<'a> {
+        batches: &'a HashMap<String, Batch>,
+        links: &'a [BatchLink],
+        direction: TraceDirection,
+        result: Vec<TracedBatch>,
+        indexes: HashMap<String, usize>,
+        path: Vec<String>,
+    }
+
+    impl Tracer<'_> {
+        /// `amount` is the part of the traced batch in `batch` for forward trace and
+        /// the part of `batch` in the traced one for backward trace
+        fn visit(&mut self, batch: &Batch, amount: Decimal, depth: usize) {
+            let id = batch
+                .id
+                .as_ref()
+                .map(ToString::to_string)
+                .unwrap_or_default();
+            if self.path.contains(&id) {
+                return;
+            }
+            self.path.push(id.clone());
+            let next: Vec<(&Batch, Decimal)> = self
+                .links
+                .iter()
+                .filter_map(|link| {
+                    let (from, to) = match self.direction {
+                        TraceDirection::Backward => (&link.out, &link.r#in),
+                        TraceDirection::Forward => (&link.r#in, &link.out),
+                    };
+                    if from.to_string() != id {
+                        return None;
+                    }
+                    let next = self.batches.get(&to.to_string())?;
+                    // Backward: `amount` of `batch` is in the traced one, so is the same part
+                    // of the parent's link.amount. Forward: `amount` of the traced batch
+                    // is split as `batch` is
+                    Some((next, link.amount * amount / batch.amount))
+                })
+                .collect();
+            if depth > 0 {
+                let end = next.is_empty();
+                match self.indexes.get(&id) {
+                    Some(&index) => {
+                        let traced = &mut self.result[index];
+                        traced.amount += amount;
+                        traced.depth = traced.depth.min(depth);
+                    }
+                    None => {
+                        self.indexes.insert(id, self.result.len());
+                        self.result.push(TracedBatch {
+                            batch: batch.clone(),
+                            depth,
+                            amount,
+                            end,
+                        });
+                    }
+                }
+            }
+            for (next, next_amount) in next {
+                self.visit(next, next_amount, depth + 1);
+            }
+            self.path.pop();
+        }
+    }
+
+    let mut tracer = Tracer {
+        batches,
+        links,
+        direction,
+        result: vec![],
+        indexes: HashMap::new(),
+        path: vec![],
+    };
+    // Backward trace starts with the whole batch being in itself, forward one with
+    // the whole batch to be split
+    tracer.visit(batch, batch.amount, 0);
+    let mut result = tracer.result;
+    result.sort_by_key(|traced| traced.depth);
+    result
+}
+
+pub struct BatchRepository {}
+
+impl BatchRepository {
+    pub async fn list(
+        offset: usize,
+        limit: usize,
+        pipe: Option<ThingDerived>,
+        db: &Db,
+        ctx: &dyn Ctx,
+    ) -> ApiResult<Vec<Batch>> {
+        let query = db
+            .query(format!(
+                "SELECT * FROM {RESOURCE} WHERE $pipe = NONE OR pipe = $pipe \
+                 ORDER BY started_at DESC LIMIT {limit} START {offset};"
+            ))
+            .bind(("pipe", pipe.map(|pipe| pipe.thing(ctx)).transpose()?));
+        Unwrapper::unwrapper_vec(query, 0, ctx).await
+    }
+
+    pub async fn count(db: &Db, ctx: &dyn Ctx) -> ApiResult<usize> {
+        let query = db.query(format!("(SELECT count() FROM {RESOURCE} GROUP ALL).count"));
+        Unwrapper::unwrapper_option_without_error(query, 0, ctx)
+            .await
+            .map(|count| count.unwrap_or(0))
+    }
+
+    pub async fn select_by_id(
+        id: &dyn ObjectWithThing,
+        db: &Db,
+        ctx: &dyn Ctx,
+    ) -> ApiResult<Batch> {
+        let thing_id = id.thing(ctx)?;
+        if thing_id.tb != RESOURCE {
+            return Err(ApiError {
+                error: Error::Generic {
+                    description: "Wrong table name in select_by_id".to_string(),
+                },
+                req_id: ctx.req_id(),
+            });
+        }
+        let query = db
+            .query("SELECT * FROM $thing_id".to_string())
+            .bind(("thing_id", thing_id));
+
+        Unwrapper::unwrapper_option(query, 0, "Can't get batch by id", ctx).await
+    }
+
+    pub async fn select_by_code(code: &str, db: &Db, ctx: &dyn Ctx) -> ApiResult<Batch> {
+        let query = db
+            .query(format!("SELECT * FROM {RESOURCE} WHERE code = $code;"))
+            .bind(("code", code));
+        Unwrapper::unwrapper_option(query, 0, "Can't get batch by code", ctx).await
+    }
+
+    pub async fn select_by_ids(ids: Vec<Thing>, db: &Db, ctx: &dyn Ctx) -> ApiResult<Vec<Batch>> {
+        let query = db
+            .query(format!("SELECT * FROM {RESOURCE} WHERE id INSIDE $ids;"))
+            .bind(("ids", ids));
+        Unwrapper::unwrapper_vec(query, 0, ctx).await
+    }
+
+    /// Batches of the pipe overlapping [from, to]
+    pub async fn select_by_pipe_and_range(
+        pipe: &dyn ObjectWithThing,
+        from: DateTimeDerived,
+        to: DateTimeDerived,
+        db: &Db,
+        ctx: &dyn Ctx,
+    ) -> ApiResult<Vec<Batch>> {
+        let query = db
+            .query(format!(
+                "SELECT * FROM {RESOURCE} WHERE pipe = $pipe \
+                 AND started_at <= $to AND ended_at >= $from ORDER BY started_at ASC;"
+            ))
+            .bind(("pipe", pipe.thing(ctx)?))
+            .bind(("from", from.0))
+            .bind(("to", to.0));
+        Unwrapper::unwrapper_vec(query, 0, ctx).await
+    }
+
+    /// Batches produced by the machinery that started in [from, to)
+    pub async fn select_by_machinery_and_range(
+        machinery: &dyn ObjectWithThing,
+        from: DateTimeDerived,
+        to: DateTimeDerived,
+        db: &Db,
+        ctx: &dyn Ctx,
+    ) -> ApiResult<Vec<Batch>> {
+        let query = db
+            .query(format!(
+                "SELECT * FROM {RESOURCE} WHERE machinery = $machinery \
+                 AND started_at >= $from AND started_at < $to ORDER BY started_at ASC;"
+            ))
+            .bind(("machinery", machinery.thing(ctx)?))
+            .bind(("from", from.0))
+            .bind(("to", to.0));
+        Unwrapper::unwrapper_vec(query, 0, ctx).await
+    }
+
+    /// Links into (Backward) or out of (Forward) the batches
+    pub async fn select_links(
+        batches: Vec<Thing>,
+        direction: TraceDirection,
+        db: &Db,
+        ctx: &dyn Ctx,
+    ) -> ApiResult<Vec<BatchLink>> {
+        let field = match direction {
+            TraceDirection::Backward => "out",
+            TraceDirection::Forward => "in",
+        };
+        let query = db
+            .query(format!(
+                "SELECT * FROM {LINK_RESOURCE} WHERE {field} INSIDE $batches;"
+            ))
+            .bind(("batches", batches));
+        Unwrapper::unwrapper_vec(query, 0, ctx).await
+    }
+
+    /// Amount of the batch consumed by other batches already
+    pub async fn consumed(
+        batch: &dyn ObjectWithThing,
+        db: &Db,
+        ctx: &dyn Ctx,
+    ) -> ApiResult<Decimal> {
+        let links =
+            Self::select_links(vec![batch.thing(ctx)?], TraceDirection::Forward, db, ctx).await?;
+        Ok(links.iter().map(|link| link.amount).sum())
+    }
+
+    pub async fn create(batch: Batch, db: &Db, ctx: &dyn Ctx) -> ApiResult<Batch> {
+        db.create(RESOURCE)
+            .content(Audited::new(batch, ctx))
+            .await
+            .map_err(ApiError::from(ctx))
+            .map(|v: Vec<Batch>| {
+                v.into_iter().next().ok_or(ApiError {
+                    req_id: ctx.req_id(),
+                    error: Error::SurrealDbNoResult {
+                        source: "internal".to_string(),
+                        id: "Error while creating ".to_string(),
+                    },
+                })
+            })?
+    }
+
+    /// Creates the batch with its links in one transaction,
+    /// so a link that fails doesn't leave the batch without its inputs.
+    /// Fails if an input would be consumed by more than its amount, the links written
+    /// by other transactions meanwhile included
+    pub async fn create_with_links(
+        mut batch: Batch,
+        inputs: &[(Batch, Decimal)],
+        db: &Db,
+        ctx: &dyn Ctx,
+    ) -> ApiResult<Batch> {
+        let thing = Thing::from((RESOURCE, Id::rand()));
+        batch.id = Some(thing.clone().into());
+        let mut statements = "BEGIN TRANSACTION; CREATE $batch CONTENT $content;".to_string();
+        for index in 0..inputs.len() {
+            statements.push_str(&format!(
+                " RELATE $in{index}->{LINK_RESOURCE}->$batch \
+                 SET amount = $amount{index}, changed_by = $changed_by;"
+            ));
+        }
+        for index in 0..inputs.len() {
+            statements.push_str(&format!(
+                " LET $consumed = math::sum((SELECT VALUE amount FROM {LINK_RESOURCE} \
+                 WHERE in = $in{index})); \
+                 IF $consumed > $in{index}.amount {{ THROW $error{index}; }};"
+            ));
+        }
+        statements.push_str(" COMMIT TRANSACTION;");
+        let mut query = db
+            .query(statements)
+            .bind(("batch", thing))
+            .bind(("content", Audited::new(batch, ctx)))
+            .bind(("changed_by", ctx.user_id_thing().ok()));
+        for (index, (input, amount)) in inputs.iter().enumerate() {
+            query = query
+                .bind((format!("in{index}"), input.thing(ctx)?))
+                .bind((format!("amount{index}"), *amount))
+                .bind((
+                    format!("error{index}"),
+                    format!("Batch: less than {amount} of {} is left", input.code),
+                ));
+        }
+        Unwrapper::unwrapper_checked(query, ctx)
+            .await?
+            .take::<Option<Batch>>(0)
+            .map_err(ApiError::from(ctx))?
+            .ok_or(ApiError {
+                req_id: ctx.req_id(),
+                error: Error::SurrealDbNoResult {
+                    source: "internal".to_string(),
+                    id: "Error while creating ".to_string(),
+                },
+            })
+    }
+
+    /// Deletes the batch with the links to the batches it was produced from.
+    /// A batch consumed by other batches is kept, it is a part of their trace
+    pub async fn delete(id: Thing, db: &Db, ctx: &dyn Ctx) -> ApiResult<Batch> {
+        AuditLogRepository::delete_with(
+            RESOURCE,
+            id.id,
+            &[
+                &format!(
+                    "IF (SELECT VALUE id FROM {LINK_RESOURCE} WHERE in = $thing) {{ \
+                     THROW 'Batch: it is consumed by other batches, the trace needs it'; \
+                     }};"
+                ),
+                &format!("DELETE {LINK_RESOURCE} WHERE out = $thing;"),
+            ],
+            db,
+            ctx,
+        )
+        .await?
+        .ok_or(ApiError {
+            req_id: ctx.req_id(),
+            error: Error::SurrealDbNoResult {
+                source: "internal".to_string(),
+                id: "Error while deleting ".to_string(),
+            },
+        })
+    }
+}
+
+/// Raw material lot entering the line through an input pipe
+#[derive(Deserialize, InputObject, Clone, Serialize, Debug)]
+pub struct CreateLotInput {
+    #[graphql(validator(min_length = 1))]
+    pub code: String,
+    pub pipe: ThingDerived,
+    pub supplier_lot: Option<String>,
+    pub amount: Decimal,
+    pub units: ThingDerived,
+    pub started_at: DateTimeDerived,
+    pub ended_at: DateTimeDerived,
+}
+
+#[derive(Deserialize, InputObject, Clone, Serialize, Debug)]
+pub struct BatchInputInput {
+    pub batch: ThingDerived,
+    pub amount: Decimal,
+    pub units: ThingDerived,
+}
+
+/// Batch produced by the machinery into one of its output pipes
+#[derive(Deserialize, InputObject, Clone, Serialize, Debug)]
+pub struct ProduceBatchInput {
+    #[graphql(validator(min_length = 1))]
+    pub code: String,
+    pub machinery: ThingDerived,
+    pub pipe: ThingDerived,
+    pub amount: Decimal,
+    pub units: ThingDerived,
+    pub started_at: DateTimeDerived,
+    pub ended_at: DateTimeDerived,
+    /// Consumed batches. By default the batches of the input pipes of the machinery
+    /// overlapping [started_at, ended_at], in proportion to the overlap
+    pub inputs: Option<Vec<BatchInputInput>>,
+}
+
+pub struct BatchUseCases {}
+
+impl BatchUseCases {
+    fn error(description: String, ctx: &dyn Ctx) -> ApiError {
+        ApiError {
+            req_id: ctx.req_id(),
+            error: Error::Generic { description },
+        }
+    }
+
+    fn check(
+        amount: Decimal,
+        from: &DateTimeDerived,
+        to: &DateTimeDerived,
+        ctx: &dyn Ctx,
+    ) -> ApiResult<()> {
+        if amount <= Decimal::ZERO {
+            return Err(Self::error(
+                "Batch: amount should be positive".to_string(),
+                ctx,
+            ));
+        }
+        if from.0 > to.0 {
+            return Err(Self::error(
+                "Batch: `started_at` should not be after `ended_at`".to_string(),
+                ctx,
+            ));
+        }
+        Ok(())
+    }
+
+    pub async fn create_lot(ct_input: CreateLotInput, db: &Db, ctx: &dyn Ctx) -> ApiResult<Batch> {
+        Self::check(
+            ct_input.amount,
+            &ct_input.started_at,
+            &ct_input.ended_at,
+            ctx,
+        )?;
+        let pipe = PipeUseCases::select_by_id(&ct_input.pipe, db, ctx).await?;
+        if !PipeToUseCases::select_source_machinery(&pipe, db, ctx)
+            .await?
+            .is_empty()
+        {
+            return Err(Self::error(
+                format!(
+                    "Batch: lots enter the line through input pipes only, {} is not one",
+                    pipe.name
+                ),
+                ctx,
+            ));
+        }
+        BatchRepository::create(
+            Batch {
+                id: None,
+                code: ct_input.code,
+                pipe: ct_input.pipe,
+                material: pipe.material,
+                machinery: None,
+                supplier_lot: ct_input.supplier_lot,
+                amount: ct_input.amount,
+                units: ct_input.units,
+                started_at: ct_input.started_at,
+                ended_at: ct_input.ended_at,
+            },
+            db,
+            ctx,
+        )
+        .await
+    }
+
+    /// Consumed batches of the input pipes overlapping the interval, in proportion to the overlap
+    async fn overlapping_inputs(
+        input_pipes: &[ThingDerived],
+        started_at: &DateTimeDerived,
+        ended_at: &DateTimeDerived,
+        db: &Db,
+        ctx: &dyn Ctx,
+    ) -> ApiResult<Vec<(Batch, Decimal)>> {
+        let mut inputs = vec![];
+        for pipe in input_pipes {
+            for batch in BatchRepository::select_by_pipe_and_range(
+                pipe,
+                started_at.clone(),
+                ended_at.clone(),
+                db,
+                ctx,
+            )
+            .await?
+            {
+                let (start, end) = (batch.started_at.0 .0, batch.ended_at.0 .0);
+                let duration = hours(start, end);
+                let overlap = hours(start.max(started_at.0 .0), end.min(ended_at.0 .0));
+                let amount = if duration.is_zero() {
+                    batch.amount
+                } else {
+                    batch.amount * overlap / duration
+                };
+                if amount > Decimal::ZERO {
+                    inputs.push((batch, amount));
+                }
+            }
+        }
+        Ok(inputs)
+    }
+
+    pub async fn produce(ct_input: ProduceBatchInput, db: &Db, ctx: &dyn Ctx) -> ApiResult<Batch> {
+        Self::check(
+            ct_input.amount,
+            &ct_input.started_at,
+            &ct_input.ended_at,
+            ctx,
+        )?;
+        let outputs = PipeToUseCases::select_output_pipes(&ct_input.machinery, db, ctx).await?;
+        let pipe = outputs
+            .into_iter()
+            .find(|pipe| pipe.id.as_ref() == Some(&ct_input.pipe))
+            .ok_or(Self::error(
+                format!("Batch: machinery doesn't output into {}", ct_input.pipe),
+                ctx,
+            ))?;
+        let input_pipes: Vec<ThingDerived> =
+            PipeFromUseCases::select_input_pipes(&ct_input.machinery, db, ctx)
+                .await?
+                .into_iter()
+                .filter_map(|pipe| pipe.id)
+                .collect();
+
+        let converter = MeasureUnitsUseCases::converter(db, ctx).await?;
+        let inputs = match ct_input.inputs {
+            Some(inputs) => {
+                let mut result = vec![];
+                for input in inputs {
+                    let batch = BatchRepository::select_by_id(&input.batch, db, ctx).await?;
+                    let amount =
+                        converter.convert(input.amount, &input.units, &batch.units, ctx)?;
+                    // The same batch given twice is consumed by the sum of its amounts
+                    match result
+                        .iter_mut()
+                        .find(|(other, _): &&mut (Batch, Decimal)| other.id == batch.id)
+                    {
+                        Some((_, total)) => *total += amount,
+                        None => result.push((batch, amount)),
+                    }
+                }
+                result
+            }
+            None => {
+                Self::overlapping_inputs(
+                    &input_pipes,
+                    &ct_input.started_at,
+                    &ct_input.ended_at,
+                    db,
+                    ctx,
+                )
+                .await?
+            }
+        };
+        for (batch, amount) in inputs.iter() {
+            if !input_pipes.contains(&batch.pipe) {
+                return Err(Self::error(
+                    format!(
+                        "Batch: {} is not in an input pipe of the machinery",
+                        batch.code
+                    ),
+                    ctx,
+                ));
+            }
+            if *amount <= Decimal::ZERO {
+                return Err(Self::error(
+                    format!(
+                        "Batch: consumed amount of {} should be positive",
+                        batch.code
+                    ),
+                    ctx,
+                ));
+            }
+        }
+
+        BatchRepository::create_with_links(
+            Batch {
+                id: None,
+                code: ct_input.code,
+                pipe: ct_input.pipe,
+                material: pipe.material,
+                machinery: Some(ct_input.machinery),
+                supplier_lot: None,
+                amount: ct_input.amount,
+                units: ct_input.units,
+                started_at: ct_input.started_at,
+                ended_at: ct_input.ended_at,
+            },
+            &inputs,
+            db,
+            ctx,
+        )
+        .await
+    }
+
+    /// Batches the batch was produced from or batches produced from it
+    /// with the amounts that went from one into the other
+    pub async fn trace(
+        batch: &dyn ObjectWithThing,
+        direction: TraceDirection,
+        db: &Db,
+        ctx: &dyn Ctx,
+    ) -> ApiResult<BatchTrace> {
+        let batch = BatchRepository::select_by_id(batch, db, ctx).await?;
+        let mut batches: HashMap<String, Batch> = HashMap::new();
+        let mut visited: HashSet<String> = HashSet::from([batch.thing(ctx)?.to_string()]);
+        let mut links: Vec<BatchLink> = vec![];
+        // Level by level, every batch is followed once
+        let mut front = vec![batch.thing(ctx)?];
+        while !front.is_empty() {
+            let level = BatchRepository::select_links(front, direction, db, ctx).await?;
+            front = level
+                .iter()
+                .map(|link| match direction {
+                    TraceDirection::Backward => &link.r#in,
+                    TraceDirection::Forward => &link.out,
+                })
+                .filter(|id| visited.insert(id.to_string()))
+                .map(|id| id.thing(ctx))
+                .collect::<ApiResult<_>>()?;
+            for next in BatchRepository::select_by_ids(front.clone(), db, ctx).await? {
+                batches.insert(next.thing(ctx)?.to_string(), next);
+            }
+            links.extend(level);
+        }
+        Ok(BatchTrace {
+            batches: trace(&batch, &batches, &links, direction),
+            batch,
+            direction,
+        })
+    }
+
+    /// Batches consumed by other batches can't be deleted, the trace of those needs them
+    pub async fn delete(id: &dyn ObjectWithThing, db: &Db, ctx: &dyn Ctx) -> ApiResult<Batch> {
+        let batch = BatchRepository::select_by_id(id, db, ctx).await?;
+        if BatchRepository::consumed(&batch, db, ctx).await? > Decimal::ZERO {
+            return Err(Self::error(
+                format!(
+                    "Batch: {} is consumed by other batches, the trace needs it",
+                    batch.code
+                ),
+                ctx,
+            ));
+        }
+        BatchRepository::delete(batch.thing(ctx)?, db, ctx).await
+    }
+
+    pub async fn select_by_id(
+        id: &dyn ObjectWithThing,
+        db: &Db,
+        ctx: &dyn Ctx,
+    ) -> ApiResult<Batch> {
+        BatchRepository::select_by_id(id, db, ctx).await
+    }
+
+    pub async fn select_by_code(code: &str, db: &Db, ctx: &dyn Ctx) -> ApiResult<Batch> {
+        BatchRepository::select_by_code(code, db, ctx).await
+    }
+
+    pub async fn count(db: &Db, ctx: &dyn Ctx) -> ApiResult<usize> {
+        BatchRepository::count(db, ctx).await
+    }
+
+    pub async fn list(
+        offset: Option<usize>,
+        limit: Option<usize>,
+        pipe: Option<ThingDerived>,
+        db: &Db,
+        ctx: &dyn Ctx,
+    ) -> ApiResult<Vec<Batch>> {
+        let limit = limit.unwrap_or(10);
+        let offset = offset.unwrap_or(0);
+
+        BatchRepository::list(offset, limit, pipe, db, ctx).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::machinery::Machinery;
+    use crate::prod_populate::{
+        connect_shortcut, machinery_shortcut, machinery_type_shortcut, measure_units_shortcut,
+        pipe_shortcut, pipe_type_shortcut,
+    };
+    use crate::raw_material::{CreateRawMaterialInput, RawMaterialUseCases};
+    use chrono::{DateTime, TimeZone, Utc};
+    use common::ctx::MockCtx;
+    use db::set_test_db;
+    use rstest::*;
+
+    #[fixture]
+    fn ctx() -> MockCtx {
+        let mut ctx = MockCtx::new();
+        ctx.expect_req_id().return_const(uuid::Uuid::new_v4());
+        ctx.expect_user_id_thing().returning(|| {
+            Err(ApiError {
+                req_id: uuid::Uuid::new_v4(),
+                error: Error::Generic {
+                    description: "No user".to_string(),
+                },
+            })
+        });
+        ctx
+    }
+
+    #[fixture]
+    async fn tdb() -> Db {
+        set_test_db().await
+    }
+
+    fn at(hour: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2024, 1, 1, hour, 0, 0).unwrap()
+    }
+
+    fn batch(id: &str, amount: i64) -> Batch {
+        Batch {
+            id: Some(Thing::from(("Batch", id)).into()),
+            code: id.to_string(),
+            pipe: Thing::from(("Pipe", "pipe")).into(),
+            material: Thing::from(("RawMaterial", "material")).into(),
+            machinery: None,
+            supplier_lot: None,
+            amount: Decimal::from(amount),
+            units: Thing::from(("MeasureUnits", "kg")).into(),
+            started_at: at(0).into(),
+            ended_at: at(1).into(),
+        }
+    }
+
+    fn link(from: &str, to: &str, amount: i64) -> BatchLink {
+        BatchLink {
+            id: None,
+            r#in: Thing::from(("Batch", from)).into(),
+            out: Thing::from(("Batch", to)).into(),
+            amount: Decimal::from(amount),
+        }
+    }
+
+    #[test]
+    fn trace_test() {
+        let batches: HashMap<String, Batch> = [
+            batch("sugar", 500),
+            batch("milk", 1000),
+            batch("mix", 1000),
+            batch("pallet1", 10),
+            batch("pallet2", 20),
+        ]
+        .into_iter()
+        .map(|batch| (batch.id.clone().unwrap().to_string(), batch))
+        .collect();
+        let links = [
+            link("sugar", "mix", 500),
+            link("milk", "mix", 500),
+            link("mix", "pallet1", 300),
+            link("mix", "pallet2", 700),
+        ];
+        let amounts = |traced: &[TracedBatch]| -> Vec<(String, usize, Decimal, bool)> {
+            traced
+                .iter()
+                .map(|traced| {
+                    (
+                        traced.batch.code.clone(),
+                        traced.depth,
+                        traced.amount,
+                        traced.end,
+                    )
+                })
+                .collect()
+        };
+
+        let backward = trace(
+            &batches["Batch:pallet1"],
+            &batches,
+            &links,
+            TraceDirection::Backward,
+        );
+        assert_eq!(
+            amounts(&backward),
+            vec![
+                ("mix".to_string(), 1, Decimal::from(300), false),
+                ("sugar".to_string(), 2, Decimal::from(150), true),
+                ("milk".to_string(), 2, Decimal::from(150), true),
+            ]
+        );
+
+        let forward = trace(
+            &batches["Batch:sugar"],
+            &batches,
+            &links,
+            TraceDirection::Forward,
+        );
+        assert_eq!(
+            amounts(&forward),
+            vec![
+                ("mix".to_string(), 1, Decimal::from(500), false),
+                ("pallet1".to_string(), 2, Decimal::from(150), true),
+                ("pallet2".to_string(), 2, Decimal::from(350), true),
+            ]
+        );
+    }
+
+    #[rstest]
+    #[tokio::test]
+    #[awt]
+    async fn produce_and_trace_test(ctx: MockCtx, #[future] tdb: Db) {
+        let units = measure_units_shortcut("кг", &tdb, &ctx).await.unwrap();
+        let pipe_type = pipe_type_shortcut("Трубопровод", &units, &tdb, &ctx)
+            .await
+            .unwrap();
+        let machinery_type = machinery_type_shortcut("Смеситель", &units, &tdb, &ctx)
+            .await
+            .unwrap();
+        let mut pipes = vec![];
+        for name in ["Просеянный сахар", "Изначальная смесь", "Упакованная смесь"]
+        {
+            let material = RawMaterialUseCases::create(
+                CreateRawMaterialInput {
+                    name: name.to_string(),
+                },
+                &tdb,
+                &ctx,
+            )
+            .await
+            .unwrap();
+            pipes.push(
+                pipe_shortcut(name, &pipe_type, &material, &tdb, &ctx)
+                    .await
+                    .unwrap(),
+            );
+        }
+        let mixer = machinery_shortcut("Смеситель № 1", &machinery_type, &tdb, &ctx)
+            .await
+            .unwrap();
+        let packer = machinery_shortcut("Упаковщик № 1", &machinery_type, &tdb, &ctx)
+            .await
+            .unwrap();
+        connect_shortcut(&[&pipes[0]], &mixer, &[&pipes[1]], &tdb, &ctx)
+            .await
+            .unwrap();
+        connect_shortcut(&[&pipes[1]], &packer, &[&pipes[2]], &tdb, &ctx)
+            .await
+            .unwrap();
+        let pipe = |index: usize| pipes[index].id.clone().unwrap();
+
+        let lot = |code: &str, pipe: ThingDerived, from: u32, to: u32| CreateLotInput {
+            code: code.to_string(),
+            pipe,
+            supplier_lot: Some(format!("supplier-{code}")),
+            amount: Decimal::from(500),
+            units: units.id.clone().unwrap(),
+            started_at: at(from).into(),
+            ended_at: at(to).into(),
+        };
+        BatchUseCases::create_lot(lot("S1", pipe(0), 0, 10), &tdb, &ctx)
+            .await
+            .unwrap();
+        let second = BatchUseCases::create_lot(lot("S2", pipe(0), 10, 20), &tdb, &ctx)
+            .await
+            .unwrap();
+        // Mix pipe is fed by the mixer
+        assert!(
+            BatchUseCases::create_lot(lot("S3", pipe(1), 0, 1), &tdb, &ctx)
+                .await
+                .is_err()
+        );
+
+        let produce = |code: &str, machinery: &Machinery, pipe: ThingDerived, amount: i64| {
+            ProduceBatchInput {
+                code: code.to_string(),
+                machinery: machinery.id.clone().unwrap(),
+                pipe,
+                amount: Decimal::from(amount),
+                units: units.id.clone().unwrap(),
+                started_at: at(5).into(),
+                ended_at: at(15).into(),
+                inputs: None,
+            }
+        };
+        // Half of every lot overlaps
+        let mix = BatchUseCases::produce(produce("M1", &mixer, pipe(1), 400), &tdb, &ctx)
+            .await
+            .unwrap();
+        let consumed = |amount: i64| {
+            Some(vec![BatchInputInput {
+                batch: mix.id.clone().unwrap(),
+                amount: Decimal::from(amount),
+                units: units.id.clone().unwrap(),
+            }])
+        };
+        let pallet = BatchUseCases::produce(
+            ProduceBatchInput {
+                inputs: consumed(100),
+                ..produce("P1", &packer, pipe(2), 10)
+            },
+            &tdb,
+            &ctx,
+        )
+        .await
+        .unwrap();
+        // Only 300 of the mix is left
+        let error = BatchUseCases::produce(
+            ProduceBatchInput {
+                inputs: consumed(400),
+                ..produce("P2", &packer, pipe(2), 10)
+            },
+            &tdb,
+            &ctx,
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(
+            error.error.to_string(),
+            "Batch: less than 400 of M1 is left"
+        );
+        assert!(BatchUseCases::select_by_code("P2", &tdb, &ctx)
+            .await
+            .is_err());
+        // Amounts of the same batch are consumed together
+        assert!(BatchUseCases::produce(
+            ProduceBatchInput {
+                inputs: Some([consumed(200).unwrap(), consumed(200).unwrap()].concat()),
+                ..produce("P2", &packer, pipe(2), 10)
+            },
+            &tdb,
+            &ctx,
+        )
+        .await
+        .is_err());
+        assert_eq!(
+            BatchRepository::consumed(&mix, &tdb, &ctx).await.unwrap(),
+            Decimal::from(100)
+        );
+        // Packer doesn't output into the mix pipe
+        assert!(
+            BatchUseCases::produce(produce("M2", &packer, pipe(1), 10), &tdb, &ctx)
+                .await
+                .is_err()
+        );
+
+        let backward = BatchUseCases::trace(&pallet, TraceDirection::Backward, &tdb, &ctx)
+            .await
+            .unwrap();
+        let lots: Vec<_> = backward
+            .batches
+            .iter()
+            .filter(|traced| traced.end)
+            .map(|traced| (traced.batch.code.as_str(), traced.amount))
+            .collect();
+        assert_eq!(backward.batches[0].amount, Decimal::from(100));
+        assert_eq!(lots.len(), 2);
+        assert!(lots.contains(&("S1", Decimal::new(625, 1))));
+        assert!(lots.contains(&("S2", Decimal::new(625, 1))));
+
+        let forward = BatchUseCases::trace(&second, TraceDirection::Forward, &tdb, &ctx)
+            .await
+            .unwrap();
+        assert_eq!(forward.batches.len(), 2);
+        assert_eq!(forward.batches[0].amount, Decimal::from(250));
+        assert_eq!(forward.batches[1].batch.code, "P1");
+        assert_eq!(forward.batches[1].amount, Decimal::new(625, 1));
+
+        // Consumed batches are a part of the trace
+        assert!(BatchUseCases::delete(&second, &tdb, &ctx).await.is_err());
+        let error = BatchRepository::delete(mix.thing(&ctx).unwrap(), &tdb, &ctx)
+            .await
+            .unwrap_err();
+        assert!(matches!(error.error, Error::Generic { .. }));
+        assert_eq!(
+            BatchRepository::consumed(&mix, &tdb, &ctx).await.unwrap(),
+            Decimal::from(100)
+        );
+        // Deleting the pallet gives its input back
+        BatchUseCases::delete(&pallet, &tdb, &ctx).await.unwrap();
+        assert_eq!(
+            BatchRepository::consumed(&mix, &tdb, &ctx).await.unwrap(),
+            Decimal::ZERO
+        );
+        BatchUseCases::delete(&mix, &tdb, &ctx).await.unwrap();
+        assert_eq!(
+            BatchRepository::consumed(&second, &tdb, &ctx)
+                .await
+                .unwrap(),
+            Decimal::ZERO
+        );
+    }
+}
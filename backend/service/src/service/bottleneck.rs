@@ -0,0 +1,408 @@
+use crate::capacity::utilization;
+use crate::plant_topology::{PlantTopology, PlantTopologyUseCases, TopologyNode};
+use crate::service::guard::RoleGuard;
+use crate::thing_derived::ThingDerived;
+use async_graphql::SimpleObject;
+use common::{
+    ctx::Ctx,
+    error::{ApiError, Error},
+    role::Role,
+    ApiResult,
+};
+
+use db::Db;
+use rust_decimal::Decimal;
+use std::collections::{HashMap, VecDeque};
+
+/// Flow through the node when the line runs at its maximum throughput
+#[derive(Clone, Debug, SimpleObject)]
+#[graphql(guard = "RoleGuard::new(Role::User)")]
+pub struct NodeFlow {
+    pub node: TopologyNode,
+    pub flow: Decimal,
+    /// flow / max_flow. None if the node is not limited
+    pub utilization: Option<Decimal>,
+}
+
+#[derive(Clone, Debug, SimpleObject)]
+#[graphql(guard = "RoleGuard::new(Role::User)")]
+pub struct BottleneckAnalysis {
+    pub final_pipe: ThingDerived,
+    /// Units of the final pipe
+    pub units: ThingDerived,
+    /// Maximum flow per hour into the final pipe. None if nothing on the way limits it
+    pub max_throughput: Option<Decimal>,
+    /// max_throughput over a day
+    pub max_daily_output: Option<Decimal>,
+    /// Pipes and machinery of the minimum cut. Extending any of them raises the throughput
+    pub limiting: Vec<TopologyNode>,
+    /// Every node that feeds the final pipe, from raw inputs to the final pipe.
+    /// Empty if the throughput is not limited
+    pub flows: Vec<NodeFlow>,
+}
+
+/// Edge of the residual graph. None capacity is unlimited
+struct Arc {
+    to: usize,
+    capacity: Option<Decimal>,
+    flow: Decimal,
+    reverse: usize,
+}
+
+impl Arc {
+    fn residual(&self) -> Option<Decimal> {
+        self.capacity.map(|capacity| capacity - self.flow)
+    }
+
+    fn has_residual(&self) -> bool {
+        self.residual()
+            .is_none_or(|residual| residual > Decimal::ZERO)
+    }
+}
+
+/// Max flow solved by Edmonds-Karp over nodes split into `in` and `out` halves,
+/// so capacity of pipes and machinery is capacity of the edge between the halves
+struct FlowNetwork {
+    arcs: Vec<Vec<Arc>>,
+}
+
+impl FlowNetwork {
+    fn new(size: usize) -> Self {
+        Self {
+            arcs: (0..size).map(|_| vec![]).collect(),
+        }
+    }
+
+    fn add(&mut self, from: usize, to: usize, capacity: Option<Decimal>) {
+        let reverse = self.arcs[to].len();
+        let forward = self.arcs[from].len();
+        self.arcs[from].push(Arc {
+            to,
+            capacity,
+            flow: Decimal::ZERO,
+            reverse,
+        });
+        self.arcs[to].push(Arc {
+            to: from,
+            capacity: Some(Decimal::ZERO),
+            flow: Decimal::ZERO,
+            reverse: forward,
+        });
+    }
+
+    /// Nodes reachable from `source` over arcs with residual capacity.
+    /// Each reached node keeps the arc it was reached by
+    fn reach(&self, source: usize) -> Vec<Option<(usize, usize)>> {
+        let mut parent = vec![None; self.arcs.len()];
+        let mut visited = vec![false; self.arcs.len()];
+        visited[source] = true;
+        let mut queue = VecDeque::from([source]);
+        while let Some(node) = queue.pop_front() {
+            for (i, arc) in self.arcs[node].iter().enumerate() {
+                if !visited[arc.to] && arc.has_residual() {
+                    visited[arc.to] = true;
+                    parent[arc.to] = Some((node, i));
+                    queue.push_back(arc.to);
+                }
+            }
+        }
+        parent[source] = Some((source, usize::MAX));
+        parent
+    }
+
+    /// Total flow from `source` to `sink`. None if it is unlimited
+    fn max_flow(&mut self, source: usize, sink: usize) -> Option<Decimal> {
+        let mut total = Decimal::ZERO;
+        loop {
+            let parent = self.reach(source);
+            if parent[sink].is_none() {
+                return Some(total);
+            }
+            let mut path = vec![];
+            let mut node = sink;
+            while node != source {
+                let (from, arc) = parent[node]?;
+                path.push((from, arc));
+                node = from;
+            }
+            let bottleneck = path
+                .iter()
+                .filter_map(|&(from, arc)| self.arcs[from][arc].residual())
+                .min()?;
+            for (from, arc) in path {
+                self.arcs[from][arc].flow += bottleneck;
+                let (to, reverse) = (self.arcs[from][arc].to, self.arcs[from][arc].reverse);
+                self.arcs[to][reverse].flow -= bottleneck;
+            }
+            total += bottleneck;
+        }
+    }
+}
+
+impl BottleneckAnalysis {
+    /// Max flow from the input pipes to `final_pipe`. Zero max_flow is not limited.
+    /// Capacities are taken as is, so they are expected in comparable units
+    pub fn new(
+        topology: &PlantTopology,
+        final_pipe: &ThingDerived,
+        ctx: &dyn Ctx,
+    ) -> ApiResult<Self> {
+        let index: HashMap<String, usize> = topology
+            .nodes
+            .iter()
+            .enumerate()
+            .map(|(i, node)| (node.id.to_string(), i))
+            .collect();
+        let not_found = |id: &ThingDerived| ApiError {
+            req_id: ctx.req_id(),
+            error: Error::Generic {
+                description: format!("Bottleneck analysis: {id} is not in the topology"),
+            },
+        };
+        let node_index = |id: &ThingDerived| {
+            index
+                .get(&id.to_string())
+                .copied()
+                .ok_or_else(|| not_found(id))
+        };
+        let final_index = node_index(final_pipe)?;
+
+        // Node i is split into 2 * i and 2 * i + 1
+        let size = topology.nodes.len();
+        let (source, sink) = (2 * size, 2 * size + 1);
+        let mut network = FlowNetwork::new(2 * size + 2);
+        for (i, node) in topology.nodes.iter().enumerate() {
+            let capacity = (!node.max_flow.is_zero()).then_some(node.max_flow);
+            network.add(2 * i, 2 * i + 1, capacity);
+        }
+        for edge in topology.edges.iter() {
+            network.add(
+                2 * node_index(&edge.from)? + 1,
+                2 * node_index(&edge.to)?,
+                None,
+            );
+        }
+        for input in topology.input_pipes.iter() {
+            network.add(source, 2 * node_index(input)?, None);
+        }
+        network.add(2 * final_index + 1, sink, None);
+
+        let max_throughput = network.max_flow(source, sink);
+        let reached = network.reach(source);
+        // Nodes the final pipe can be reached from
+        let mut feeds = vec![false; size];
+        feeds[final_index] = true;
+        let mut stack = vec![final_index];
+        while let Some(node) = stack.pop() {
+            for edge in topology.edges.iter() {
+                let (from, to) = (node_index(&edge.from)?, node_index(&edge.to)?);
+                if to == node && !feeds[from] {
+                    feeds[from] = true;
+                    stack.push(from);
+                }
+            }
+        }
+        let order: Vec<usize> = match &topology.topological_order {
+            Some(order) => order
+                .iter()
+                .filter_map(|id| index.get(&id.to_string()).copied())
+                .collect(),
+            None => (0..size).collect(),
+        };
+
+        let mut limiting = vec![];
+        let mut flows = vec![];
+        for i in order.into_iter().filter(|&i| feeds[i]) {
+            let node = &topology.nodes[i];
+            // The split arc is the first one added to the `in` half
+            let arc = &network.arcs[2 * i][0];
+            if max_throughput.is_some() && reached[2 * i].is_some() && reached[2 * i + 1].is_none()
+            {
+                limiting.push(node.clone());
+            }
+            flows.push(NodeFlow {
+                node: node.clone(),
+                flow: arc.flow,
+                utilization: utilization(arc.flow, node.max_flow),
+            });
+        }
+        Ok(Self {
+            final_pipe: final_pipe.clone(),
+            units: topology.nodes[final_index].units.clone(),
+            max_throughput,
+            max_daily_output: max_throughput.map(|throughput| throughput * Decimal::from(24)),
+            limiting,
+            flows: if max_throughput.is_some() {
+                flows
+            } else {
+                vec![]
+            },
+        })
+    }
+}
+
+/// Topology without the nodes and their edges
+pub fn without_nodes(topology: &PlantTopology, removed: &[String]) -> PlantTopology {
+    let kept = |id: &ThingDerived| !removed.contains(&id.to_string());
+    let kept_all = |ids: &[ThingDerived]| ids.iter().filter(|id| kept(id)).cloned().collect();
+    PlantTopology {
+        nodes: topology
+            .nodes
+            .iter()
+            .filter(|node| kept(&node.id))
+            .cloned()
+            .collect(),
+        edges: topology
+            .edges
+            .iter()
+            .filter(|edge| kept(&edge.from) && kept(&edge.to))
+            .cloned()
+            .collect(),
+        has_cycles: topology.has_cycles,
+        cycles: topology.cycles.clone(),
+        topological_order: topology
+            .topological_order
+            .as_ref()
+            .map(|order| kept_all(order)),
+        input_pipes: kept_all(&topology.input_pipes),
+        final_pipes: kept_all(&topology.final_pipes),
+    }
+}
+
+pub struct BottleneckUseCases {}
+
+impl BottleneckUseCases {
+    /// Analysis of the line of `final_pipe`. The only final pipe of the plant by default
+    pub async fn analyze(
+        final_pipe: Option<ThingDerived>,
+        db: &Db,
+        ctx: &dyn Ctx,
+    ) -> ApiResult<BottleneckAnalysis> {
+        let topology = PlantTopologyUseCases::get(db, ctx).await?;
+        let final_pipe = match final_pipe {
+            Some(final_pipe) => final_pipe,
+            None => PlantTopologyUseCases::select_final_pipe(db, ctx).await?,
+        };
+        BottleneckAnalysis::new(&topology, &final_pipe, ctx)
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::plant_topology::{TopologyEdge, TopologyEdgeKind, TopologyNodeKind};
+    use common::ctx::MockCtx;
+    use rstest::*;
+    use surrealdb::sql::Thing;
+
+    #[fixture]
+    fn ctx() -> MockCtx {
+        let mut ctx = MockCtx::new();
+        ctx.expect_req_id().return_const(uuid::Uuid::new_v4());
+        ctx.expect_user_id_thing().returning(|| {
+            Err(ApiError {
+                req_id: uuid::Uuid::new_v4(),
+                error: Error::Generic {
+                    description: "No user".to_string(),
+                },
+            })
+        });
+        ctx
+    }
+
+    pub fn thing(tb: &str, id: &str) -> ThingDerived {
+        Thing::from((tb, id)).into()
+    }
+
+    fn node(kind: TopologyNodeKind, id: &str, max_flow: i64) -> TopologyNode {
+        let tb = match kind {
+            TopologyNodeKind::Pipe => "Pipe",
+            TopologyNodeKind::Machinery => "Machinery",
+        };
+        TopologyNode {
+            id: thing(tb, id),
+            kind,
+            name: id.to_string(),
+            node_type: thing("PipeType", "pt"),
+            type_name: "Конвейер".to_string(),
+            max_flow: Decimal::from(max_flow),
+            wearout_max: Decimal::ZERO,
+            units: thing("MeasureUnits", "kg"),
+            units_name: "кг".to_string(),
+            material: None,
+        }
+    }
+
+    fn edge(from: &TopologyNode, to: &TopologyNode) -> TopologyEdge {
+        TopologyEdge {
+            id: thing("PipeTo", &format!("{}_{}", from.name, to.name)),
+            kind: match from.kind {
+                TopologyNodeKind::Pipe => TopologyEdgeKind::PipeFrom,
+                TopologyNodeKind::Machinery => TopologyEdgeKind::PipeTo,
+            },
+            from: from.id.clone(),
+            to: to.id.clone(),
+        }
+    }
+
+    /// in_1 -> mixer_1 \
+    ///                   mid -> packer -> final
+    /// in_2 -> mixer_2 /
+    pub fn line(packer_max_flow: i64) -> PlantTopology {
+        use TopologyNodeKind::*;
+        let nodes = vec![
+            node(Pipe, "in_1", 0),
+            node(Pipe, "in_2", 0),
+            node(Machinery, "mixer_1", 4),
+            node(Machinery, "mixer_2", 3),
+            node(Pipe, "mid", 10),
+            node(Machinery, "packer", packer_max_flow),
+            node(Pipe, "final", 0),
+        ];
+        let edges = [(0, 2), (1, 3), (2, 4), (3, 4), (4, 5), (5, 6)]
+            .iter()
+            .map(|&(from, to)| edge(&nodes[from], &nodes[to]))
+            .collect();
+        PlantTopology {
+            topological_order: Some(nodes.iter().map(|node| node.id.clone()).collect()),
+            input_pipes: vec![nodes[0].id.clone(), nodes[1].id.clone()],
+            final_pipes: vec![nodes[6].id.clone()],
+            nodes,
+            edges,
+            has_cycles: false,
+            cycles: vec![],
+        }
+    }
+
+    fn names(nodes: &[TopologyNode]) -> Vec<&str> {
+        nodes.iter().map(|node| node.name.as_str()).collect()
+    }
+
+    #[rstest]
+    fn bottleneck_test(ctx: MockCtx) {
+        let final_pipe = thing("Pipe", "final");
+        let analysis = BottleneckAnalysis::new(&line(6), &final_pipe, &ctx).unwrap();
+        assert_eq!(analysis.max_throughput, Some(Decimal::from(6)));
+        assert_eq!(analysis.max_daily_output, Some(Decimal::from(144)));
+        assert_eq!(names(&analysis.limiting), vec!["packer"]);
+        assert_eq!(analysis.flows.len(), 7);
+        assert_eq!(analysis.flows[5].utilization, Some(Decimal::ONE));
+
+        // Both mixers together are the bottleneck now
+        let analysis = BottleneckAnalysis::new(&line(20), &final_pipe, &ctx).unwrap();
+        assert_eq!(analysis.max_throughput, Some(Decimal::from(7)));
+        assert_eq!(names(&analysis.limiting), vec!["mixer_1", "mixer_2"]);
+        assert_eq!(analysis.flows[2].flow, Decimal::from(4));
+
+        let mut unlimited = line(0);
+        unlimited
+            .nodes
+            .iter_mut()
+            .for_each(|node| node.max_flow = Decimal::ZERO);
+        let analysis = BottleneckAnalysis::new(&unlimited, &final_pipe, &ctx).unwrap();
+        assert_eq!(analysis.max_throughput, None);
+        assert!(analysis.limiting.is_empty());
+
+        assert!(BottleneckAnalysis::new(&line(6), &thing("Pipe", "unknown"), &ctx).is_err());
+    }
+}
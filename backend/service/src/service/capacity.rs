@@ -0,0 +1,239 @@
+use crate::datetime::DateTimeDerived;
+use crate::flow_integration::{Bucket, FlowBucket, FlowIntegrationOptions};
+use crate::pipe_stats::PipeStatsUseCases;
+use crate::plant_topology::{PlantTopologyUseCases, TopologyNodeKind};
+use crate::service::guard::RoleGuard;
+use crate::thing_derived::ThingDerived;
+use async_graphql::SimpleObject;
+use common::{
+    ctx::Ctx,
+    error::{ApiError, Error},
+    role::Role,
+    ApiResult,
+};
+
+use db::Db;
+use rust_decimal::Decimal;
+
+lazy_static::lazy_static! {
+    /// Readings above max_flow of the pipe or machinery type are rejected instead of flagged
+    pub static ref REJECT_OVER_CAPACITY: bool =
+        std::env::var("REJECT_OVER_CAPACITY")
+            .ok()
+            .and_then(|reject| reject.parse().ok())
+            .unwrap_or(false);
+}
+
+/// Zero max_flow means the capacity is not limited
+pub fn over_capacity(flow: Decimal, max_flow: Decimal) -> bool {
+    !max_flow.is_zero() && flow > max_flow
+}
+
+/// Share of max_flow. None if the capacity is not limited
+pub fn utilization(flow: Decimal, max_flow: Decimal) -> Option<Decimal> {
+    (!max_flow.is_zero()).then(|| flow / max_flow)
+}
+
+/// Whether the reading of `object` is over capacity.
+/// Error if it is and such readings are rejected
+pub fn check_capacity(
+    object: &ThingDerived,
+    flow: Decimal,
+    max_flow: Decimal,
+    ctx: &dyn Ctx,
+) -> ApiResult<bool> {
+    let over = over_capacity(flow, max_flow);
+    if over && *REJECT_OVER_CAPACITY {
+        return Err(ApiError {
+            req_id: ctx.req_id(),
+            error: Error::Generic {
+                description: format!("Flow {flow} of {object} is above max flow {max_flow}"),
+            },
+        });
+    }
+    Ok(over)
+}
+
+#[derive(Clone, Debug, SimpleObject)]
+#[graphql(guard = "RoleGuard::new(Role::User)")]
+pub struct UtilizationBucket {
+    pub from: DateTimeDerived,
+    pub to: DateTimeDerived,
+    /// Time weighted flow over the covered part of the bucket
+    pub average_flow: Option<Decimal>,
+    /// Highest reading inside the bucket
+    pub peak_flow: Option<Decimal>,
+    /// average_flow / max_flow
+    pub utilization: Option<Decimal>,
+    /// peak_flow / max_flow
+    pub peak_utilization: Option<Decimal>,
+    pub over_capacity: bool,
+}
+
+#[derive(Clone, Debug, SimpleObject)]
+#[graphql(guard = "RoleGuard::new(Role::User)")]
+pub struct PipeUtilization {
+    pub pipe: ThingDerived,
+    pub name: String,
+    /// Of the pipe type. Zero if not limited
+    pub max_flow: Decimal,
+    pub units: ThingDerived,
+    pub buckets: Vec<UtilizationBucket>,
+    /// Time weighted over the whole period
+    pub average_utilization: Option<Decimal>,
+    pub peak_utilization: Option<Decimal>,
+    /// Buckets with readings above max_flow
+    pub over_capacity_buckets: usize,
+}
+
+impl PipeUtilization {
+    pub fn new(
+        pipe: ThingDerived,
+        name: String,
+        max_flow: Decimal,
+        units: ThingDerived,
+        buckets: &[FlowBucket],
+    ) -> Self {
+        let volume: Decimal = buckets.iter().map(|bucket| bucket.volume).sum();
+        let covered_hours: Decimal = buckets.iter().map(|bucket| bucket.covered_hours).sum();
+        let buckets: Vec<UtilizationBucket> = buckets
+            .iter()
+            .map(|bucket| UtilizationBucket {
+                from: bucket.from.clone(),
+                to: bucket.to.clone(),
+                average_flow: bucket.average_flow,
+                peak_flow: bucket.max_flow,
+                utilization: bucket
+                    .average_flow
+                    .and_then(|flow| utilization(flow, max_flow)),
+                peak_utilization: bucket.max_flow.and_then(|flow| utilization(flow, max_flow)),
+                over_capacity: bucket
+                    .max_flow
+                    .is_some_and(|flow| over_capacity(flow, max_flow)),
+            })
+            .collect();
+        Self {
+            pipe,
+            name,
+            max_flow,
+            units,
+            average_utilization: (!covered_hours.is_zero())
+                .then(|| volume / covered_hours)
+                .and_then(|flow| utilization(flow, max_flow)),
+            peak_utilization: buckets
+                .iter()
+                .filter_map(|bucket| bucket.peak_utilization)
+                .max(),
+            over_capacity_buckets: buckets.iter().filter(|bucket| bucket.over_capacity).count(),
+            buckets,
+        }
+    }
+}
+
+pub struct CapacityUseCases {}
+
+impl CapacityUseCases {
+    /// Actual flow against max_flow of every pipe, or only of `pipe`, in buckets over [from, to).
+    /// The most utilized pipes first
+    pub async fn utilization(
+        from: DateTimeDerived,
+        to: DateTimeDerived,
+        bucket: Bucket,
+        pipe: Option<ThingDerived>,
+        options: &FlowIntegrationOptions,
+        db: &Db,
+        ctx: &dyn Ctx,
+    ) -> ApiResult<Vec<PipeUtilization>> {
+        if from.0 >= to.0 {
+            return Err(ApiError {
+                req_id: ctx.req_id(),
+                error: Error::Generic {
+                    description: "Capacity utilization: `from` should be before `to`".to_string(),
+                },
+            });
+        }
+        let topology = PlantTopologyUseCases::get(db, ctx).await?;
+        let mut result = vec![];
+        for node in topology.nodes.iter().filter(|node| {
+            node.kind == TopologyNodeKind::Pipe && pipe.as_ref().is_none_or(|pipe| *pipe == node.id)
+        }) {
+            let buckets = PipeStatsUseCases::aggregate_by_pipe(
+                &node.id,
+                from.clone(),
+                to.clone(),
+                bucket,
+                options,
+                db,
+                ctx,
+            )
+            .await?;
+            result.push(PipeUtilization::new(
+                node.id.clone(),
+                node.name.clone(),
+                node.max_flow,
+                node.units.clone(),
+                &buckets,
+            ));
+        }
+        result.sort_by_key(|pipe| std::cmp::Reverse(pipe.average_utilization));
+        Ok(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{TimeZone, Utc};
+    use surrealdb::sql::Thing;
+
+    fn bucket(hour: u32, volume: i64, max_flow: Option<i64>) -> FlowBucket {
+        let at = |hour| Utc.with_ymd_and_hms(2024, 1, 1, hour, 0, 0).unwrap();
+        FlowBucket {
+            from: at(hour).into(),
+            to: at(hour + 1).into(),
+            volume: Decimal::from(volume),
+            average_flow: max_flow.map(|_| Decimal::from(volume)),
+            min_flow: max_flow.map(Decimal::from),
+            max_flow: max_flow.map(Decimal::from),
+            readings: max_flow.map_or(0, |_| 1),
+            covered_hours: Decimal::from(max_flow.map_or(0, |_| 1)),
+            downtime_hours: Decimal::ZERO,
+        }
+    }
+
+    #[test]
+    fn pipe_utilization_test() {
+        let thing = |tb: &str| -> ThingDerived { Thing::from((tb, "1")).into() };
+        let buckets = [
+            bucket(0, 5, Some(5)),
+            bucket(1, 9, Some(12)),
+            bucket(2, 0, None),
+        ];
+        let pipe = PipeUtilization::new(
+            thing("Pipe"),
+            "Трубопровод № 1".to_string(),
+            Decimal::from(10),
+            thing("MeasureUnits"),
+            &buckets,
+        );
+        assert_eq!(pipe.buckets[0].utilization, Some(Decimal::new(5, 1)));
+        assert!(!pipe.buckets[0].over_capacity);
+        assert_eq!(pipe.buckets[1].peak_utilization, Some(Decimal::new(12, 1)));
+        assert!(pipe.buckets[1].over_capacity);
+        assert_eq!(pipe.buckets[2].utilization, None);
+        // 14 over 2 covered hours
+        assert_eq!(pipe.average_utilization, Some(Decimal::new(7, 1)));
+        assert_eq!(pipe.peak_utilization, Some(Decimal::new(12, 1)));
+        assert_eq!(pipe.over_capacity_buckets, 1);
+
+        let unlimited = PipeUtilization::new(
+            thing("Pipe"),
+            "Трубопровод № 2".to_string(),
+            Decimal::ZERO,
+            thing("MeasureUnits"),
+            &buckets,
+        );
+        assert_eq!(unlimited.average_utilization, None);
+        assert_eq!(unlimited.over_capacity_buckets, 0);
+    }
+}
@@ -51,6 +51,34 @@ impl Unwrapper {
                 },
             })
     }
+    /// Runs the query and fails on the first failed statement. A THROW fails the whole
+    /// transaction, but only its own statement tells why, so its message is the error
+    pub async fn unwrapper_checked<'a, C: surrealdb::Connection>(
+        query: surrealdb::method::Query<'a, C>,
+        ctx: &dyn Ctx,
+    ) -> ApiResult<surrealdb::Response> {
+        let mut response = query.await.map_err(ApiError::from(ctx))?;
+        let mut errors: Vec<(usize, surrealdb::Error)> =
+            response.take_errors().into_iter().collect();
+        errors.sort_by_key(|(index, _)| *index);
+        let thrown = errors.iter().find_map(|(_, error)| match error {
+            surrealdb::Error::Db(surrealdb::error::Db::Thrown(description)) => {
+                Some(description.clone())
+            }
+            _ => None,
+        });
+        if let Some(description) = thrown {
+            return Err(ApiError {
+                req_id: ctx.req_id(),
+                error: Error::Generic { description },
+            });
+        }
+        match errors.into_iter().next() {
+            Some((_, error)) => Err(ApiError::from(ctx)(error)),
+            None => Ok(response),
+        }
+    }
+
     pub async fn unwrapper_vec<'a, R, C: surrealdb::Connection>(
         query: surrealdb::method::Query<'a, C>,
         index: usize,
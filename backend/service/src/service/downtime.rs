@@ -0,0 +1,342 @@
+use crate::audit_log::{AuditLogRepository, Audited};
+use crate::common::Unwrapper;
+use crate::datetime::DateTimeDerived;
+use crate::maintenance_order::MaintenanceKind;
+use crate::service::guard::RoleGuard;
+use crate::thing_derived::ThingDerived;
+use crate::thing_wrapper::ObjectWithThing;
+use async_graphql::{InputObject, SimpleObject};
+use chrono::{DateTime, Utc};
+use common::{
+    ctx::Ctx,
+    error::{ApiError, Error},
+    role::Role,
+    ApiResult,
+};
+
+use db::Db;
+use serde::{Deserialize, Serialize};
+use surrealdb::sql::Thing;
+
+#[allow(dead_code)]
+const RESOURCE: &str = "Downtime";
+
+/// Period when the object was stopped. Its flow is integrated as zero
+#[derive(Clone, Debug, Serialize, Deserialize, SimpleObject)]
+#[graphql(guard = "RoleGuard::new(Role::User)")]
+pub struct Downtime {
+    pub id: Option<ThingDerived>,
+    /// Pipe or Machinery
+    pub object: ThingDerived,
+    pub kind: MaintenanceKind,
+    pub started_at: DateTimeDerived,
+    /// None while the object is still stopped
+    pub ended_at: Option<DateTimeDerived>,
+    pub reason: String,
+    pub maintenance_order: Option<ThingDerived>,
+    pub reported_by: Option<ThingDerived>,
+}
+
+impl ObjectWithThing for Downtime {
+    fn thing(&self, ctx: &dyn Ctx) -> ApiResult<Thing> {
+        self.id
+            .as_ref()
+            .ok_or(ApiError {
+                req_id: ctx.req_id(),
+                error: Error::Generic {
+                    description: "Can't get thing. Get none instead".to_string(),
+                },
+            })?
+            .thing(ctx)
+    }
+}
+
+impl Downtime {
+    /// Part of the downtime inside [from, to). Open downtime lasts until `to`
+    pub fn interval(
+        &self,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> Option<(DateTime<Utc>, DateTime<Utc>)> {
+        let start = self.started_at.0 .0.max(from);
+        let end = self.ended_at.as_ref().map_or(to, |end| end.0 .0.min(to));
+        (start < end).then_some((start, end))
+    }
+}
+
+pub struct DowntimeRepository {}
+
+impl DowntimeRepository {
+    pub async fn list(
+        offset: usize,
+        limit: usize,
+        object: Option<ThingDerived>,
+        db: &Db,
+        ctx: &dyn Ctx,
+    ) -> ApiResult<Vec<Downtime>> {
+        let query = db
+            .query(format!(
+                "SELECT * FROM {RESOURCE} WHERE $object = NONE OR object = $object \
+                 ORDER BY started_at DESC LIMIT {limit} START {offset};"
+            ))
+            .bind((
+                "object",
+                object.map(|object| object.thing(ctx)).transpose()?,
+            ));
+        Unwrapper::unwrapper_vec(query, 0, ctx).await
+    }
+
+    pub async fn count(db: &Db, ctx: &dyn Ctx) -> ApiResult<usize> {
+        let query = db.query(format!("(SELECT count() FROM {RESOURCE} GROUP ALL).count"));
+        Unwrapper::unwrapper_option_without_error(query, 0, ctx)
+            .await
+            .map(|count| count.unwrap_or(0))
+    }
+
+    pub async fn select_by_id(
+        id: &dyn ObjectWithThing,
+        db: &Db,
+        ctx: &dyn Ctx,
+    ) -> ApiResult<Downtime> {
+        let thing_id = id.thing(ctx)?;
+        if thing_id.tb != RESOURCE {
+            return Err(ApiError {
+                error: Error::Generic {
+                    description: "Wrong table name in select_by_id".to_string(),
+                },
+                req_id: ctx.req_id(),
+            });
+        }
+        let query = db
+            .query("SELECT * FROM $thing_id".to_string())
+            .bind(("thing_id", thing_id));
+
+        Unwrapper::unwrapper_option(query, 0, "Can't get downtime by id", ctx).await
+    }
+
+    /// Downtime overlapping [from, to) of the object and of the machinery outputting into it
+    pub async fn select_stopping(
+        object: &dyn ObjectWithThing,
+        from: DateTimeDerived,
+        to: DateTimeDerived,
+        db: &Db,
+        ctx: &dyn Ctx,
+    ) -> ApiResult<Vec<Downtime>> {
+        let query = db
+            .query(format!(
+                "SELECT * FROM {RESOURCE} \
+                 WHERE object INSIDE array::concat([$object], (SELECT VALUE in FROM PipeTo WHERE out = $object)) \
+                 AND started_at < $to AND (ended_at = NONE OR ended_at > $from) \
+                 ORDER BY started_at ASC;"
+            ))
+            .bind(("object", object.thing(ctx)?))
+            .bind(("from", from.0))
+            .bind(("to", to.0));
+        Unwrapper::unwrapper_vec(query, 0, ctx).await
+    }
+
+    /// Downtime of any object overlapping [from, to). Of all kinds if `kind` is None
+    pub async fn select_by_range(
+        from: DateTimeDerived,
+        to: DateTimeDerived,
+        kind: Option<MaintenanceKind>,
+        db: &Db,
+        ctx: &dyn Ctx,
+    ) -> ApiResult<Vec<Downtime>> {
+        let query = db
+            .query(format!(
+                "SELECT * FROM {RESOURCE} WHERE ($kind = NONE OR kind = $kind) \
+                 AND started_at < $to AND (ended_at = NONE OR ended_at > $from) \
+                 ORDER BY started_at ASC;"
+            ))
+            .bind(("kind", kind))
+            .bind(("from", from.0))
+            .bind(("to", to.0));
+        Unwrapper::unwrapper_vec(query, 0, ctx).await
+    }
+
+    pub async fn create(downtime: Downtime, db: &Db, ctx: &dyn Ctx) -> ApiResult<Downtime> {
+        db.create(RESOURCE)
+            .content(Audited::new(downtime, ctx))
+            .await
+            .map_err(ApiError::from(ctx))
+            .map(|v: Vec<Downtime>| {
+                v.into_iter().next().ok_or(ApiError {
+                    req_id: ctx.req_id(),
+                    error: Error::SurrealDbNoResult {
+                        source: "internal".to_string(),
+                        id: "Error while creating ".to_string(),
+                    },
+                })
+            })?
+    }
+
+    pub async fn update(
+        downtime: Downtime,
+        id: Thing,
+        db: &Db,
+        ctx: &dyn Ctx,
+    ) -> ApiResult<Downtime> {
+        db.update((RESOURCE, id.id.to_string()))
+            .content(Audited::new(
+                Downtime {
+                    id: None,
+                    ..downtime
+                },
+                ctx,
+            ))
+            .await
+            .map_err(ApiError::from(ctx))?
+            .ok_or(ApiError {
+                req_id: ctx.req_id(),
+                error: Error::SurrealDbNoResult {
+                    source: "internal".to_string(),
+                    id: id.to_string(),
+                },
+            })
+    }
+
+    pub async fn delete(id: Thing, db: &Db, ctx: &dyn Ctx) -> ApiResult<Downtime> {
+        AuditLogRepository::delete(RESOURCE, id.id, db, ctx)
+            .await?
+            .ok_or(ApiError {
+                req_id: ctx.req_id(),
+                error: Error::SurrealDbNoResult {
+                    source: "internal".to_string(),
+                    id: "Error while deleting ".to_string(),
+                },
+            })
+    }
+}
+
+#[derive(Deserialize, InputObject, Clone, Serialize, Debug)]
+pub struct CreateDowntimeInput {
+    /// Pipe or Machinery
+    pub object: ThingDerived,
+    pub kind: MaintenanceKind,
+    pub started_at: DateTimeDerived,
+    /// None while the object is still stopped
+    pub ended_at: Option<DateTimeDerived>,
+    pub reason: String,
+    pub maintenance_order: Option<ThingDerived>,
+}
+
+pub struct DowntimeUseCases {}
+
+impl DowntimeUseCases {
+    fn downtime(ct_input: CreateDowntimeInput, ctx: &dyn Ctx) -> Downtime {
+        Downtime {
+            id: None,
+            object: ct_input.object,
+            kind: ct_input.kind,
+            started_at: ct_input.started_at,
+            ended_at: ct_input.ended_at,
+            reason: ct_input.reason,
+            maintenance_order: ct_input.maintenance_order,
+            reported_by: ctx.user_id_thing().ok().map(Into::into),
+        }
+    }
+
+    pub async fn create(
+        ct_input: CreateDowntimeInput,
+        db: &Db,
+        ctx: &dyn Ctx,
+    ) -> ApiResult<Downtime> {
+        DowntimeRepository::create(Self::downtime(ct_input, ctx), db, ctx).await
+    }
+
+    pub async fn update(
+        ct_input: CreateDowntimeInput,
+        id: &dyn ObjectWithThing,
+        db: &Db,
+        ctx: &dyn Ctx,
+    ) -> ApiResult<Downtime> {
+        let downtime = DowntimeRepository::select_by_id(id, db, ctx).await?;
+        // The editor is kept in changed_by, the downtime stays reported by its author
+        let downtime = Downtime {
+            reported_by: downtime.reported_by,
+            ..Self::downtime(ct_input, ctx)
+        };
+        DowntimeRepository::update(downtime, id.thing(ctx)?, db, ctx).await
+    }
+
+    /// Ends the open downtime, now by default
+    pub async fn end(
+        id: &dyn ObjectWithThing,
+        ended_at: Option<DateTimeDerived>,
+        db: &Db,
+        ctx: &dyn Ctx,
+    ) -> ApiResult<Downtime> {
+        let downtime = DowntimeRepository::select_by_id(id, db, ctx).await?;
+        if downtime.ended_at.is_some() {
+            return Err(ApiError {
+                req_id: ctx.req_id(),
+                error: Error::Generic {
+                    description: "Downtime is ended already".to_string(),
+                },
+            });
+        }
+        let downtime = Downtime {
+            ended_at: Some(ended_at.unwrap_or_else(|| Utc::now().into())),
+            ..downtime
+        };
+        DowntimeRepository::update(downtime, id.thing(ctx)?, db, ctx).await
+    }
+
+    pub async fn delete(id: &dyn ObjectWithThing, db: &Db, ctx: &dyn Ctx) -> ApiResult<Downtime> {
+        DowntimeRepository::delete(id.thing(ctx)?, db, ctx).await
+    }
+
+    pub async fn select_by_id(
+        id: &dyn ObjectWithThing,
+        db: &Db,
+        ctx: &dyn Ctx,
+    ) -> ApiResult<Downtime> {
+        DowntimeRepository::select_by_id(id, db, ctx).await
+    }
+
+    /// Parts of [from, to) when the object or the machinery outputting into it was stopped
+    pub async fn intervals(
+        object: &dyn ObjectWithThing,
+        from: DateTimeDerived,
+        to: DateTimeDerived,
+        db: &Db,
+        ctx: &dyn Ctx,
+    ) -> ApiResult<Vec<(DateTime<Utc>, DateTime<Utc>)>> {
+        let (start, end) = (from.0 .0, to.0 .0);
+        Ok(
+            DowntimeRepository::select_stopping(object, from, to, db, ctx)
+                .await?
+                .iter()
+                .filter_map(|downtime| downtime.interval(start, end))
+                .collect(),
+        )
+    }
+
+    pub async fn select_by_range(
+        from: DateTimeDerived,
+        to: DateTimeDerived,
+        kind: Option<MaintenanceKind>,
+        db: &Db,
+        ctx: &dyn Ctx,
+    ) -> ApiResult<Vec<Downtime>> {
+        DowntimeRepository::select_by_range(from, to, kind, db, ctx).await
+    }
+
+    pub async fn count(db: &Db, ctx: &dyn Ctx) -> ApiResult<usize> {
+        DowntimeRepository::count(db, ctx).await
+    }
+
+    pub async fn list(
+        offset: Option<usize>,
+        limit: Option<usize>,
+        object: Option<ThingDerived>,
+        db: &Db,
+        ctx: &dyn Ctx,
+    ) -> ApiResult<Vec<Downtime>> {
+        let limit = limit.unwrap_or(10);
+        let offset = offset.unwrap_or(0);
+
+        DowntimeRepository::list(offset, limit, object, db, ctx).await
+    }
+}
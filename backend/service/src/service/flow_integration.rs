@@ -0,0 +1,985 @@
+use crate::datetime::DateTimeDerived;
+use crate::downtime::DowntimeUseCases;
+use crate::machinery::MachineryUseCases;
+use crate::machinery_stats::{MachineryStats, MachineryStatsUseCases};
+use crate::measure_units::{MeasureUnitsUseCases, UnitConverter};
+use crate::pipe::PipeUseCases;
+use crate::pipe_stats::{PipeStats, PipeStatsUseCases};
+use crate::service::guard::RoleGuard;
+use crate::thing_derived::ThingDerived;
+use crate::thing_wrapper::ObjectWithThing;
+use async_graphql::{Enum, InputObject, SimpleObject};
+use chrono::{
+    DateTime, Datelike, Duration, FixedOffset, NaiveDate, NaiveDateTime, NaiveTime, TimeZone,
+    Timelike, Utc,
+};
+use common::{
+    ctx::Ctx,
+    error::{ApiError, Error},
+    role::Role,
+    ApiResult,
+};
+
+use db::Db;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+
+lazy_static::lazy_static! {
+    /// Offset of the plant local time from UTC in minutes.
+    /// Used for day boundaries when no offset is requested explicitly
+    pub static ref PLANT_UTC_OFFSET_MINUTES: i32 =
+        std::env::var("PLANT_UTC_OFFSET_MINUTES")
+            .ok()
+            .and_then(|offset| offset.parse().ok())
+            .unwrap_or(0);
+}
+
+/// How flow behaves between two readings
+#[derive(Enum, Copy, Clone, Debug, Default, Eq, PartialEq, Serialize, Deserialize)]
+pub enum Interpolation {
+    /// Flow holds the previous reading value until the next reading
+    #[default]
+    Step,
+    /// Flow changes linearly between two readings.
+    /// After the last known reading it holds its value
+    Linear,
+}
+
+#[derive(InputObject, Clone, Debug, Default, Serialize, Deserialize)]
+pub struct FlowIntegrationOptions {
+    /// Step by default
+    pub interpolation: Option<Interpolation>,
+    /// Reading is not extended further than this. The rest is reported as a gap.
+    /// Unlimited by default
+    pub max_gap_hours: Option<Decimal>,
+    /// Offset from UTC in minutes used for day boundaries.
+    /// PLANT_UTC_OFFSET_MINUTES by default
+    pub utc_offset_minutes: Option<i32>,
+    /// Readings are extended across downtime as if there was none. False by default
+    pub ignore_downtime: Option<bool>,
+}
+
+impl FlowIntegrationOptions {
+    pub fn interpolation(&self) -> Interpolation {
+        self.interpolation.unwrap_or_default()
+    }
+
+    pub fn max_gap(&self) -> Option<Duration> {
+        self.max_gap_hours.map(|hours| {
+            Duration::seconds(
+                (hours * Decimal::new(3600, 0))
+                    .trunc()
+                    .try_into()
+                    .unwrap_or(0),
+            )
+        })
+    }
+
+    pub fn utc_offset(&self, ctx: &dyn Ctx) -> ApiResult<FixedOffset> {
+        let minutes = self.utc_offset_minutes.unwrap_or(*PLANT_UTC_OFFSET_MINUTES);
+        FixedOffset::east_opt(minutes * 60).ok_or(ApiError {
+            req_id: ctx.req_id(),
+            error: Error::Generic {
+                description: format!("Wrong UTC offset: {minutes} minutes"),
+            },
+        })
+    }
+
+    pub fn ignore_downtime(&self) -> bool {
+        self.ignore_downtime.unwrap_or(false)
+    }
+}
+
+/// Period without readings
+#[derive(Clone, Debug, SimpleObject, PartialEq, Eq)]
+#[graphql(guard = "RoleGuard::new(Role::User)")]
+pub struct FlowGap {
+    pub from: DateTimeDerived,
+    pub to: DateTimeDerived,
+}
+
+#[derive(Clone, Debug, SimpleObject)]
+#[graphql(guard = "RoleGuard::new(Role::User)")]
+pub struct FlowIntegral {
+    pub from: DateTimeDerived,
+    pub to: DateTimeDerived,
+    /// Integrated flow over the covered part of [from, to)
+    pub volume: Decimal,
+    pub covered_hours: Decimal,
+    /// Part of covered_hours when the flow was stopped by downtime
+    pub downtime_hours: Decimal,
+    /// Number of readings inside [from, to)
+    pub readings: usize,
+    pub gaps: Vec<FlowGap>,
+}
+
+impl FlowIntegral {
+    /// False if there is nothing known about the flow in the interval
+    pub fn has_data(&self) -> bool {
+        !self.covered_hours.is_zero()
+    }
+}
+
+#[derive(Clone, Debug, SimpleObject)]
+#[graphql(guard = "RoleGuard::new(Role::User)")]
+pub struct FlowBucket {
+    pub from: DateTimeDerived,
+    pub to: DateTimeDerived,
+    pub volume: Decimal,
+    /// Time weighted flow over the covered part of the bucket
+    pub average_flow: Option<Decimal>,
+    /// Over readings inside the bucket
+    pub min_flow: Option<Decimal>,
+    pub max_flow: Option<Decimal>,
+    pub readings: usize,
+    pub covered_hours: Decimal,
+    pub downtime_hours: Decimal,
+}
+
+/// Single flow reading, flow is measured in units per hour
+#[derive(Clone, Copy, Debug)]
+pub struct FlowPoint {
+    pub date: DateTime<Utc>,
+    pub flow: Decimal,
+}
+
+impl FlowPoint {
+    /// Flow of the reading measured in `from` converted into `to`
+    pub fn convert(
+        self,
+        from: &ThingDerived,
+        to: &ThingDerived,
+        converter: &UnitConverter,
+        ctx: &dyn Ctx,
+    ) -> ApiResult<Self> {
+        Ok(Self {
+            flow: converter.convert(self.flow, from, to, ctx)?,
+            ..self
+        })
+    }
+}
+
+impl From<&PipeStats> for FlowPoint {
+    fn from(value: &PipeStats) -> Self {
+        Self {
+            date: value.date.0 .0,
+            flow: value.flow,
+        }
+    }
+}
+
+impl From<&MachineryStats> for FlowPoint {
+    fn from(value: &MachineryStats) -> Self {
+        Self {
+            date: value.date.0 .0,
+            flow: value.flow,
+        }
+    }
+}
+
+pub fn hours(from: DateTime<Utc>, to: DateTime<Utc>) -> Decimal {
+    Decimal::new(to.signed_duration_since(from).num_seconds(), 0) / Decimal::new(3600, 0)
+}
+
+/// Flow at the moment on the line between two readings
+fn linear_flow(left: &FlowPoint, right: &FlowPoint, at: DateTime<Utc>) -> Decimal {
+    let total = hours(left.date, right.date);
+    if total.is_zero() {
+        return left.flow;
+    }
+    left.flow + (right.flow - left.flow) * hours(left.date, at) / total
+}
+
+fn local_to_utc(local: NaiveDateTime, offset: FixedOffset) -> DateTime<Utc> {
+    Utc.from_utc_datetime(&(local - Duration::seconds(offset.local_minus_utc() as i64)))
+}
+
+/// Start of the local day containing the moment and start of the next one
+pub fn day_bounds(date: DateTime<Utc>, offset: FixedOffset) -> (DateTime<Utc>, DateTime<Utc>) {
+    let local_day = date.with_timezone(&offset).date_naive();
+    let start = local_to_utc(local_day.and_time(NaiveTime::MIN), offset);
+    (start, start + Duration::days(1))
+}
+
+/// Start of the plant local day containing the date. Plans and ProductionInfo are keyed by it
+pub fn plant_day(date: &DateTimeDerived, ctx: &dyn Ctx) -> ApiResult<DateTimeDerived> {
+    let offset = FlowIntegrationOptions::default().utc_offset(ctx)?;
+    Ok(day_bounds(date.0 .0, offset).0.into())
+}
+
+/// Size of aggregation bucket. Boundaries are taken in plant local time
+#[derive(Enum, Copy, Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub enum Bucket {
+    Hour,
+    Day,
+    /// Starts on Monday
+    Week,
+    Month,
+}
+
+impl Bucket {
+    /// Start of the bucket containing the moment
+    pub fn start(&self, date: DateTime<Utc>, offset: FixedOffset) -> DateTime<Utc> {
+        let local = date.with_timezone(&offset).naive_local();
+        let day = local.date();
+        let start = match self {
+            Bucket::Hour => day.and_hms_opt(local.hour(), 0, 0).unwrap_or(local),
+            Bucket::Day => day.and_time(NaiveTime::MIN),
+            Bucket::Week => (day - Duration::days(day.weekday().num_days_from_monday() as i64))
+                .and_time(NaiveTime::MIN),
+            Bucket::Month => day.with_day(1).unwrap_or(day).and_time(NaiveTime::MIN),
+        };
+        local_to_utc(start, offset)
+    }
+
+    /// Start of the bucket following the one that starts at `start`
+    pub fn next(&self, start: DateTime<Utc>, offset: FixedOffset) -> DateTime<Utc> {
+        match self {
+            Bucket::Hour => start + Duration::hours(1),
+            Bucket::Day => start + Duration::days(1),
+            Bucket::Week => start + Duration::weeks(1),
+            Bucket::Month => {
+                let local = start.with_timezone(&offset).date_naive();
+                let (year, month) = match local.month() {
+                    12 => (local.year() + 1, 1),
+                    month => (local.year(), month + 1),
+                };
+                NaiveDate::from_ymd_opt(year, month, 1)
+                    .map(|day| local_to_utc(day.and_time(NaiveTime::MIN), offset))
+                    .unwrap_or(start + Duration::days(31))
+            }
+        }
+    }
+
+    /// Buckets covering [from, to). First and last ones are cut by the interval.
+    /// None if there are more than `limit` buckets
+    pub fn bounds(
+        &self,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+        offset: FixedOffset,
+        limit: usize,
+    ) -> Option<Vec<(DateTime<Utc>, DateTime<Utc>)>> {
+        let mut result = vec![];
+        let mut start = self.start(from, offset);
+        while start < to {
+            if result.len() == limit {
+                return None;
+            }
+            let end = self.next(start, offset);
+            result.push((start.max(from), end.min(to)));
+            start = end;
+        }
+        Some(result)
+    }
+}
+
+/// Integrates flow over [from, to).
+/// `previous` is the last reading before `from`, `next` is the first reading at `to` or later,
+/// `readings` are sorted readings inside the interval
+pub fn integrate(
+    previous: Option<FlowPoint>,
+    readings: &[FlowPoint],
+    next: Option<FlowPoint>,
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+    options: &FlowIntegrationOptions,
+) -> FlowIntegral {
+    let readings: Vec<FlowPoint> = readings
+        .iter()
+        .filter(|point| point.date >= from && point.date < to)
+        .copied()
+        .collect();
+    let interpolation = options.interpolation();
+    let max_gap = options.max_gap();
+
+    let mut volume = Decimal::ZERO;
+    let mut covered_hours = Decimal::ZERO;
+    let mut gaps: Vec<FlowGap> = vec![];
+    let mut push_gap = |gap_from: DateTime<Utc>, gap_to: DateTime<Utc>| {
+        if gap_from >= gap_to {
+            return;
+        }
+        match gaps.last_mut() {
+            Some(last) if last.to.0 .0 == gap_from => last.to = gap_to.into(),
+            _ => gaps.push(FlowGap {
+                from: gap_from.into(),
+                to: gap_to.into(),
+            }),
+        }
+    };
+
+    let mut anchor = previous;
+    let mut since = from;
+    for i in 0..=readings.len() {
+        let (until, right) = match readings.get(i) {
+            Some(reading) => (reading.date, Some(*reading)),
+            None => (to, next),
+        };
+        match anchor {
+            None => push_gap(since, until),
+            Some(left) => {
+                let covered_until = match max_gap {
+                    Some(max_gap) => until.min((left.date + max_gap).max(since)),
+                    None => until,
+                };
+                if since < covered_until {
+                    let segment_hours = hours(since, covered_until);
+                    volume += match (interpolation, right) {
+                        (Interpolation::Linear, Some(right)) => {
+                            (linear_flow(&left, &right, since)
+                                + linear_flow(&left, &right, covered_until))
+                                / Decimal::new(2, 0)
+                                * segment_hours
+                        }
+                        _ => left.flow * segment_hours,
+                    };
+                    covered_hours += segment_hours;
+                }
+                push_gap(covered_until, until);
+            }
+        }
+        if let Some(reading) = readings.get(i) {
+            anchor = Some(*reading);
+            since = reading.date;
+        }
+    }
+
+    FlowIntegral {
+        from: from.into(),
+        to: to.into(),
+        volume,
+        covered_hours,
+        downtime_hours: Decimal::ZERO,
+        readings: readings.len(),
+        gaps,
+    }
+}
+
+/// Sorts intervals, clips them to [from, to) and joins the overlapping ones
+pub fn merge_intervals(
+    intervals: &[(DateTime<Utc>, DateTime<Utc>)],
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+) -> Vec<(DateTime<Utc>, DateTime<Utc>)> {
+    let mut intervals: Vec<_> = intervals
+        .iter()
+        .map(|&(start, end)| (start.max(from), end.min(to)))
+        .filter(|(start, end)| start < end)
+        .collect();
+    intervals.sort();
+    let mut result: Vec<(DateTime<Utc>, DateTime<Utc>)> = vec![];
+    for (start, end) in intervals {
+        match result.last_mut() {
+            Some(last) if last.1 >= start => last.1 = last.1.max(end),
+            _ => result.push((start, end)),
+        }
+    }
+    result
+}
+
+/// Removes [from, to) from the gaps
+fn cut_gaps(gaps: Vec<FlowGap>, from: DateTime<Utc>, to: DateTime<Utc>) -> Vec<FlowGap> {
+    gaps.into_iter()
+        .flat_map(|gap| {
+            [
+                (gap.from.0 .0, from.min(gap.to.0 .0)),
+                (to.max(gap.from.0 .0), gap.to.0 .0),
+            ]
+        })
+        .filter(|(gap_from, gap_to)| gap_from < gap_to)
+        .map(|(gap_from, gap_to)| FlowGap {
+            from: gap_from.into(),
+            to: gap_to.into(),
+        })
+        .collect()
+}
+
+/// Integrates [from, to) taking `readings` around it as previous and next ones
+fn integrate_slice(
+    previous: Option<FlowPoint>,
+    readings: &[FlowPoint],
+    next: Option<FlowPoint>,
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+    options: &FlowIntegrationOptions,
+) -> FlowIntegral {
+    let first = readings.partition_point(|point| point.date < from);
+    let last = readings.partition_point(|point| point.date < to);
+    integrate(
+        first.checked_sub(1).map(|i| readings[i]).or(previous),
+        &readings[first..last],
+        readings.get(last).copied().or(next),
+        from,
+        to,
+        options,
+    )
+}
+
+/// Integrates flow over [from, to) like `integrate`, but the flow is zero during `downtimes`.
+/// Downtime counts as covered, readings are not extended across it
+pub fn integrate_with_downtime(
+    previous: Option<FlowPoint>,
+    readings: &[FlowPoint],
+    next: Option<FlowPoint>,
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+    downtimes: &[(DateTime<Utc>, DateTime<Utc>)],
+    options: &FlowIntegrationOptions,
+) -> FlowIntegral {
+    let mut integral = integrate_slice(previous, readings, next, from, to, options);
+    for (start, end) in merge_intervals(downtimes, from, to) {
+        let stopped = integrate_slice(previous, readings, next, start, end, options);
+        let downtime_hours = hours(start, end);
+        integral.volume -= stopped.volume;
+        integral.covered_hours += downtime_hours - stopped.covered_hours;
+        integral.downtime_hours += downtime_hours;
+        integral.gaps = cut_gaps(integral.gaps, start, end);
+    }
+    integral
+}
+
+/// Integrates every bucket separately.
+/// `previous` and `next` are readings around the whole interval,
+/// `readings` are sorted readings inside it, flow is zero during `downtimes`
+pub fn aggregate(
+    previous: Option<FlowPoint>,
+    readings: &[FlowPoint],
+    next: Option<FlowPoint>,
+    buckets: &[(DateTime<Utc>, DateTime<Utc>)],
+    downtimes: &[(DateTime<Utc>, DateTime<Utc>)],
+    options: &FlowIntegrationOptions,
+) -> Vec<FlowBucket> {
+    buckets
+        .iter()
+        .map(|&(from, to)| {
+            let first = readings.partition_point(|point| point.date < from);
+            let last = readings.partition_point(|point| point.date < to);
+            let inside = &readings[first..last];
+            let integral =
+                integrate_with_downtime(previous, readings, next, from, to, downtimes, options);
+            FlowBucket {
+                from: from.into(),
+                to: to.into(),
+                volume: integral.volume,
+                average_flow: integral
+                    .has_data()
+                    .then(|| integral.volume / integral.covered_hours),
+                min_flow: inside.iter().map(|point| point.flow).min(),
+                max_flow: inside.iter().map(|point| point.flow).max(),
+                readings: inside.len(),
+                covered_hours: integral.covered_hours,
+                downtime_hours: integral.downtime_hours,
+            }
+        })
+        .collect()
+}
+
+pub struct FlowIntegrationUseCases {}
+
+impl FlowIntegrationUseCases {
+    fn check_interval(
+        from: &DateTimeDerived,
+        to: &DateTimeDerived,
+        ctx: &dyn Ctx,
+    ) -> ApiResult<()> {
+        if from.0 >= to.0 {
+            return Err(ApiError {
+                req_id: ctx.req_id(),
+                error: Error::Generic {
+                    description: "Flow integration: `from` should be before `to`".to_string(),
+                },
+            });
+        }
+        Ok(())
+    }
+
+    /// Downtime intervals stopping the flow of the object unless the options ignore them
+    pub async fn downtime(
+        object: &dyn ObjectWithThing,
+        from: DateTimeDerived,
+        to: DateTimeDerived,
+        options: &FlowIntegrationOptions,
+        db: &Db,
+        ctx: &dyn Ctx,
+    ) -> ApiResult<Vec<(DateTime<Utc>, DateTime<Utc>)>> {
+        if options.ignore_downtime() {
+            return Ok(vec![]);
+        }
+        DowntimeUseCases::intervals(object, from, to, db, ctx).await
+    }
+
+    pub async fn integrate_pipe(
+        pipe: &dyn ObjectWithThing,
+        from: DateTimeDerived,
+        to: DateTimeDerived,
+        options: &FlowIntegrationOptions,
+        db: &Db,
+        ctx: &dyn Ctx,
+    ) -> ApiResult<FlowIntegral> {
+        Self::check_interval(&from, &to, ctx)?;
+        let previous =
+            PipeStatsUseCases::select_last_reading_before(pipe, from.clone(), db, ctx).await?;
+        let readings =
+            PipeStatsUseCases::select_by_pipe_and_range(pipe, from.clone(), to.clone(), db, ctx)
+                .await?;
+        let next = match options.interpolation() {
+            Interpolation::Linear => {
+                PipeStatsUseCases::select_first_reading_from(pipe, to.clone(), db, ctx).await?
+            }
+            Interpolation::Step => None,
+        };
+        let downtimes = Self::downtime(pipe, from.clone(), to.clone(), options, db, ctx).await?;
+        let units = PipeUseCases::units(pipe, db, ctx).await?;
+        let converter = MeasureUnitsUseCases::converter(db, ctx).await?;
+        let point = |reading: &PipeStats| {
+            FlowPoint::from(reading).convert(&reading.units, &units, &converter, ctx)
+        };
+        Ok(integrate_with_downtime(
+            previous.as_ref().map(point).transpose()?,
+            &readings.iter().map(point).collect::<ApiResult<Vec<_>>>()?,
+            next.as_ref().map(point).transpose()?,
+            from.0 .0,
+            to.0 .0,
+            &downtimes,
+            options,
+        ))
+    }
+
+    pub async fn integrate_machinery(
+        machinery: &dyn ObjectWithThing,
+        from: DateTimeDerived,
+        to: DateTimeDerived,
+        options: &FlowIntegrationOptions,
+        db: &Db,
+        ctx: &dyn Ctx,
+    ) -> ApiResult<FlowIntegral> {
+        Self::check_interval(&from, &to, ctx)?;
+        let previous =
+            MachineryStatsUseCases::select_last_reading_before(machinery, from.clone(), db, ctx)
+                .await?;
+        let readings = MachineryStatsUseCases::select_by_machinery_and_range(
+            machinery,
+            from.clone(),
+            to.clone(),
+            db,
+            ctx,
+        )
+        .await?;
+        let next = match options.interpolation() {
+            Interpolation::Linear => {
+                MachineryStatsUseCases::select_first_reading_from(machinery, to.clone(), db, ctx)
+                    .await?
+            }
+            Interpolation::Step => None,
+        };
+        let downtimes =
+            Self::downtime(machinery, from.clone(), to.clone(), options, db, ctx).await?;
+        let units = MachineryUseCases::units(machinery, db, ctx).await?;
+        let converter = MeasureUnitsUseCases::converter(db, ctx).await?;
+        let point = |reading: &MachineryStats| {
+            FlowPoint::from(reading).convert(&reading.units, &units, &converter, ctx)
+        };
+        Ok(integrate_with_downtime(
+            previous.as_ref().map(point).transpose()?,
+            &readings.iter().map(point).collect::<ApiResult<Vec<_>>>()?,
+            next.as_ref().map(point).transpose()?,
+            from.0 .0,
+            to.0 .0,
+            &downtimes,
+            options,
+        ))
+    }
+
+    /// Integrates pipe flow over the local day containing the date
+    pub async fn integrate_pipe_day(
+        pipe: &dyn ObjectWithThing,
+        date: DateTimeDerived,
+        options: &FlowIntegrationOptions,
+        db: &Db,
+        ctx: &dyn Ctx,
+    ) -> ApiResult<FlowIntegral> {
+        let (from, to) = day_bounds(date.0 .0, options.utc_offset(ctx)?);
+        Self::integrate_pipe(pipe, from.into(), to.into(), options, db, ctx).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::downtime::CreateDowntimeInput;
+    use crate::maintenance_order::MaintenanceKind;
+    use crate::pipe_stats::tests::create_pipe;
+    use crate::pipe_stats::CreatePipeStatsInput;
+    use common::ctx::MockCtx;
+    use db::set_test_db;
+    use rstest::*;
+
+    #[fixture]
+    fn ctx() -> MockCtx {
+        let mut ctx = MockCtx::new();
+        ctx.expect_req_id().return_const(uuid::Uuid::new_v4());
+        ctx.expect_user_id_thing().returning(|| {
+            Err(ApiError {
+                req_id: uuid::Uuid::new_v4(),
+                error: Error::Generic {
+                    description: "No user".to_string(),
+                },
+            })
+        });
+        ctx
+    }
+
+    #[fixture]
+    async fn tdb() -> Db {
+        set_test_db().await
+    }
+
+    fn at(day: u32, hour: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2024, 1, day, hour, 0, 0).unwrap()
+    }
+
+    fn point(day: u32, hour: u32, flow: i64) -> FlowPoint {
+        FlowPoint {
+            date: at(day, hour),
+            flow: Decimal::from(flow),
+        }
+    }
+
+    fn options(interpolation: Interpolation, max_gap_hours: Option<i64>) -> FlowIntegrationOptions {
+        FlowIntegrationOptions {
+            interpolation: Some(interpolation),
+            max_gap_hours: max_gap_hours.map(Decimal::from),
+            utc_offset_minutes: Some(0),
+            ignore_downtime: None,
+        }
+    }
+
+    #[test]
+    fn step_integration_test() {
+        let readings = [point(1, 6, 10), point(1, 18, 0)];
+        let result = integrate(
+            Some(point(1, 0, 2)),
+            &readings,
+            None,
+            at(1, 0),
+            at(2, 0),
+            &options(Interpolation::Step, None),
+        );
+        // 6h * 2 + 12h * 10 + 6h * 0
+        assert_eq!(result.volume, Decimal::from(132));
+        assert_eq!(result.covered_hours, Decimal::from(24));
+        assert!(result.gaps.is_empty());
+
+        // Nothing known before the first reading
+        let result = integrate(
+            None,
+            &readings,
+            None,
+            at(1, 0),
+            at(2, 0),
+            &options(Interpolation::Step, None),
+        );
+        assert_eq!(result.volume, Decimal::from(120));
+        assert_eq!(
+            result.gaps,
+            vec![FlowGap {
+                from: at(1, 0).into(),
+                to: at(1, 6).into()
+            }]
+        );
+
+        let result = integrate(
+            None,
+            &[],
+            None,
+            at(1, 0),
+            at(2, 0),
+            &options(Interpolation::Step, None),
+        );
+        assert!(!result.has_data());
+        assert_eq!(result.gaps.len(), 1);
+    }
+
+    #[test]
+    fn linear_integration_test() {
+        // Flow grows from 0 at 00:00 to 12 at 12:00 and falls to 0 at 00:00 of the next day
+        let result = integrate(
+            Some(point(1, 0, 0)),
+            &[point(1, 12, 12)],
+            Some(point(2, 0, 0)),
+            at(1, 0),
+            at(2, 0),
+            &options(Interpolation::Linear, None),
+        );
+        assert_eq!(result.volume, Decimal::from(144));
+
+        // Window lies between two readings
+        let result = integrate(
+            Some(point(1, 0, 0)),
+            &[],
+            Some(point(1, 12, 12)),
+            at(1, 6),
+            at(1, 12),
+            &options(Interpolation::Linear, None),
+        );
+        // (6 + 12) / 2 * 6h
+        assert_eq!(result.volume, Decimal::from(54));
+    }
+
+    #[test]
+    fn max_gap_test() {
+        let result = integrate(
+            Some(point(1, 0, 10)),
+            &[point(1, 20, 1)],
+            None,
+            at(1, 0),
+            at(2, 0),
+            &options(Interpolation::Step, Some(8)),
+        );
+        // 8h * 10 + 4h * 1
+        assert_eq!(result.volume, Decimal::from(84));
+        assert_eq!(result.covered_hours, Decimal::from(12));
+        assert_eq!(
+            result.gaps,
+            vec![FlowGap {
+                from: at(1, 8).into(),
+                to: at(1, 20).into()
+            }]
+        );
+    }
+
+    #[test]
+    fn day_bounds_test() {
+        let moscow = FixedOffset::east_opt(3 * 3600).unwrap();
+        // 22:00 UTC is already the next day in Moscow
+        let (from, to) = day_bounds(at(1, 22), moscow);
+        assert_eq!(from, at(1, 21));
+        assert_eq!(to, at(2, 21));
+
+        let (from, to) = day_bounds(at(1, 20), moscow);
+        assert_eq!(from, Utc.with_ymd_and_hms(2023, 12, 31, 21, 0, 0).unwrap());
+        assert_eq!(to, at(1, 21));
+    }
+
+    #[test]
+    fn bucket_bounds_test() {
+        let utc = FixedOffset::east_opt(0).unwrap();
+        let months = Bucket::Month
+            .bounds(
+                at(15, 0),
+                Utc.with_ymd_and_hms(2024, 3, 10, 0, 0, 0).unwrap(),
+                utc,
+                100,
+            )
+            .unwrap();
+        assert_eq!(months.len(), 3);
+        assert_eq!(
+            months[0],
+            (
+                at(15, 0),
+                Utc.with_ymd_and_hms(2024, 2, 1, 0, 0, 0).unwrap()
+            )
+        );
+        assert_eq!(
+            months[1].1,
+            Utc.with_ymd_and_hms(2024, 3, 1, 0, 0, 0).unwrap()
+        );
+
+        // 2024-01-03 is Wednesday
+        let weeks = Bucket::Week.bounds(at(3, 0), at(20, 0), utc, 100).unwrap();
+        assert_eq!(weeks[0], (at(3, 0), at(8, 0)));
+        assert_eq!(weeks.len(), 3);
+
+        // Days start at 21:00 UTC in Moscow
+        let moscow = FixedOffset::east_opt(3 * 3600).unwrap();
+        let days = Bucket::Day.bounds(at(1, 0), at(3, 0), moscow, 100).unwrap();
+        assert_eq!(
+            days,
+            vec![
+                (at(1, 0), at(1, 21)),
+                (at(1, 21), at(2, 21)),
+                (at(2, 21), at(3, 0)),
+            ]
+        );
+
+        assert!(Bucket::Hour.bounds(at(1, 0), at(3, 0), utc, 47).is_none());
+    }
+
+    #[test]
+    fn aggregate_test() {
+        let utc = FixedOffset::east_opt(0).unwrap();
+        let buckets = Bucket::Day.bounds(at(1, 0), at(3, 0), utc, 100).unwrap();
+        let readings = [point(1, 6, 10), point(1, 18, 4), point(2, 12, 2)];
+        let result = aggregate(
+            None,
+            &readings,
+            None,
+            &buckets,
+            &[],
+            &options(Interpolation::Step, None),
+        );
+        assert_eq!(result.len(), 2);
+        // 12h * 10 + 6h * 4
+        assert_eq!(result[0].volume, Decimal::from(144));
+        assert_eq!(result[0].average_flow, Some(Decimal::from(8)));
+        assert_eq!(result[0].min_flow, Some(Decimal::from(4)));
+        assert_eq!(result[0].max_flow, Some(Decimal::from(10)));
+        assert_eq!(result[0].readings, 2);
+        // Second day continues with the last reading of the first one
+        // 12h * 4 + 12h * 2
+        assert_eq!(result[1].volume, Decimal::from(72));
+        assert_eq!(result[1].readings, 1);
+    }
+
+    #[test]
+    fn downtime_integration_test() {
+        // Overlapping downtimes are joined, the one crossing the interval end is cut
+        let downtimes = [
+            (at(1, 6), at(1, 8)),
+            (at(1, 7), at(1, 10)),
+            (at(1, 20), at(2, 2)),
+        ];
+        let result = integrate_with_downtime(
+            Some(point(1, 0, 10)),
+            &[point(1, 12, 4)],
+            None,
+            at(1, 0),
+            at(2, 0),
+            &downtimes,
+            &options(Interpolation::Step, None),
+        );
+        // 12h * 10 + 12h * 4 - 4h * 10 - 4h * 4
+        assert_eq!(result.volume, Decimal::from(112));
+        assert_eq!(result.covered_hours, Decimal::from(24));
+        assert_eq!(result.downtime_hours, Decimal::from(8));
+
+        // Downtime is known, so it is not a gap
+        let result = integrate_with_downtime(
+            None,
+            &[point(1, 6, 10)],
+            None,
+            at(1, 0),
+            at(2, 0),
+            &[(at(1, 0), at(1, 3))],
+            &options(Interpolation::Step, None),
+        );
+        assert_eq!(result.volume, Decimal::from(180));
+        assert_eq!(result.covered_hours, Decimal::from(21));
+        assert_eq!(
+            result.gaps,
+            vec![FlowGap {
+                from: at(1, 3).into(),
+                to: at(1, 6).into()
+            }]
+        );
+
+        // Flow grows from 0 to 12 at 12:00 and falls back, stopped from 06:00 till 18:00
+        let result = integrate_with_downtime(
+            Some(point(1, 0, 0)),
+            &[point(1, 12, 12)],
+            Some(point(2, 0, 0)),
+            at(1, 0),
+            at(2, 0),
+            &[(at(1, 6), at(1, 18))],
+            &options(Interpolation::Linear, None),
+        );
+        assert_eq!(result.volume, Decimal::from(36));
+    }
+
+    #[rstest]
+    #[tokio::test]
+    #[awt]
+    async fn integrate_pipe_day_test(ctx: MockCtx, #[future] tdb: Db) {
+        let (pipe, units) = create_pipe(&ctx, &tdb).await.unwrap();
+        for (date, flow) in [
+            (at(1, 1), Decimal::new(5, 1)),
+            (at(1, 10), Decimal::new(6, 0)),
+            (at(1, 20), Decimal::new(8, 0)),
+            (at(2, 10), Decimal::new(1, 0)),
+            (at(2, 22), Decimal::new(20, 0)),
+        ] {
+            PipeStatsUseCases::create(
+                CreatePipeStatsInput {
+                    date: date.into(),
+                    flow,
+                    units: units.id.clone().unwrap(),
+                    wearout: Decimal::ZERO,
+                    pipe: pipe.id.clone().unwrap(),
+                },
+                &tdb,
+                &ctx,
+            )
+            .await
+            .unwrap();
+        }
+        let options = options(Interpolation::Step, None);
+        let first_day = FlowIntegrationUseCases::integrate_pipe_day(
+            &pipe,
+            at(1, 12).into(),
+            &options,
+            &tdb,
+            &ctx,
+        )
+        .await
+        .unwrap();
+        // 0.5 * 9 + 6 * 10 + 8 * 4
+        assert_eq!(first_day.volume, Decimal::new(965, 1));
+        assert_eq!(first_day.readings, 3);
+        assert_eq!(first_day.gaps.len(), 1);
+
+        let second_day = FlowIntegrationUseCases::integrate_pipe_day(
+            &pipe,
+            at(2, 0).into(),
+            &options,
+            &tdb,
+            &ctx,
+        )
+        .await
+        .unwrap();
+        // 8 * 10 + 1 * 12 + 20 * 2
+        assert_eq!(second_day.volume, Decimal::new(132, 0));
+        assert!(second_day.gaps.is_empty());
+
+        // Pipe is stopped from 20:00 and not started yet
+        DowntimeUseCases::create(
+            CreateDowntimeInput {
+                object: pipe.id.clone().unwrap(),
+                kind: MaintenanceKind::Unplanned,
+                started_at: at(2, 20).into(),
+                ended_at: None,
+                reason: "Leak".to_string(),
+                maintenance_order: None,
+            },
+            &tdb,
+            &ctx,
+        )
+        .await
+        .unwrap();
+        let stopped = FlowIntegrationUseCases::integrate_pipe_day(
+            &pipe,
+            at(2, 0).into(),
+            &options,
+            &tdb,
+            &ctx,
+        )
+        .await
+        .unwrap();
+        // 8 * 10 + 1 * 10
+        assert_eq!(stopped.volume, Decimal::new(90, 0));
+        assert_eq!(stopped.downtime_hours, Decimal::new(4, 0));
+        let ignored = FlowIntegrationUseCases::integrate_pipe_day(
+            &pipe,
+            at(2, 0).into(),
+            &FlowIntegrationOptions {
+                ignore_downtime: Some(true),
+                ..options
+            },
+            &tdb,
+            &ctx,
+        )
+        .await
+        .unwrap();
+        assert_eq!(ignored.volume, Decimal::new(132, 0));
+    }
+}
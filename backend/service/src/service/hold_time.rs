@@ -0,0 +1,893 @@
+use crate::batch::{Batch, BatchRepository, TraceDirection};
+use crate::datetime::DateTimeDerived;
+use crate::flow_integration::{day_bounds, Bucket, FlowIntegrationOptions, FlowPoint};
+use crate::machinery::{Machinery, MachineryUseCases};
+use crate::measure_units::{MeasureUnitsUseCases, UnitConverter};
+use crate::pipe::Pipe;
+use crate::pipe_from::PipeFromUseCases;
+use crate::pipe_stats::{PipeStats, PipeStatsUseCases};
+use crate::pipe_to::PipeToUseCases;
+use crate::quality_check::{QualityCheck, QualityCheckRepository};
+use crate::quality_spec::QualityParameter;
+use crate::service::guard::RoleGuard;
+use crate::thing_derived::ThingDerived;
+use crate::thing_wrapper::ObjectWithThing;
+use async_graphql::{Enum, SimpleObject};
+use chrono::{DateTime, Duration, FixedOffset, Utc};
+use common::{
+    ctx::Ctx,
+    error::{ApiError, Error},
+    role::Role,
+    ApiResult,
+};
+
+use db::Db;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+
+const MAX_DAYS: usize = 366;
+
+/// Ordered from the best to the worst
+#[derive(Enum, Copy, Clone, Debug, Eq, PartialEq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum HoldTimeStatus {
+    /// Held long enough and every temperature reading is within spec
+    Compliant,
+    /// No temperature readings, no flow readings or the product is still inside
+    Unverified,
+    /// Held shorter than required or a temperature reading is out of spec
+    Violated,
+}
+
+/// Product that went through a process step during an hour or as one batch
+#[derive(Clone, Debug, SimpleObject)]
+#[graphql(guard = "RoleGuard::new(Role::User)")]
+pub struct HoldTimeRecord {
+    pub machinery: ThingDerived,
+    pub machinery_name: String,
+    /// Set for batch records. Interval records cover the product entering during [from, to)
+    pub batch: Option<ThingDerived>,
+    pub batch_code: Option<String>,
+    pub from: DateTimeDerived,
+    pub to: DateTimeDerived,
+    /// Amount that entered during the interval or amount of the batch
+    pub amount: Decimal,
+    pub units: ThingDerived,
+    pub required_seconds: i64,
+    /// Shortest residence of the product. None if it is unknown
+    pub hold_seconds: Option<i64>,
+    /// Part of the product has not left by the last reading, `hold_seconds` is a lower bound then
+    pub still_inside: bool,
+    /// Checks of the machinery and its output pipes while the product was held
+    pub temperature_readings: usize,
+    pub temperature_violations: usize,
+    pub status: HoldTimeStatus,
+}
+
+#[derive(Clone, Debug, SimpleObject)]
+#[graphql(guard = "RoleGuard::new(Role::User)")]
+pub struct HoldTimeDay {
+    pub date: DateTimeDerived,
+    /// Worst status of the records, compliant if there are none
+    pub status: HoldTimeStatus,
+    pub records: Vec<HoldTimeRecord>,
+}
+
+#[derive(Clone, Debug, SimpleObject)]
+#[graphql(guard = "RoleGuard::new(Role::User)")]
+pub struct HoldTimeReport {
+    pub from: DateTimeDerived,
+    pub to: DateTimeDerived,
+    pub status: HoldTimeStatus,
+    pub days: Vec<HoldTimeDay>,
+}
+
+/// Shortest residence of the product found
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Residence {
+    pub seconds: i64,
+    /// False if the product is still inside at the end of the readings.
+    /// `seconds` is the time it has spent inside so far then
+    pub left: bool,
+}
+
+fn seconds(from: DateTime<Utc>, to: DateTime<Utc>) -> i64 {
+    to.signed_duration_since(from).num_seconds()
+}
+
+/// Cumulative volume of the summed flow of several pipes over [from, to]
+/// as (moment, volume since `from`) points. Every series is sorted and holds
+/// its last reading, flow before the first reading is zero
+pub fn cumulative(
+    series: &[Vec<FlowPoint>],
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+) -> Vec<(DateTime<Utc>, Decimal)> {
+    let mut moments: Vec<DateTime<Utc>> = series
+        .iter()
+        .flatten()
+        .map(|point| point.date)
+        .filter(|date| *date > from && *date < to)
+        .chain([from, to])
+        .collect();
+    moments.sort();
+    moments.dedup();
+    let flow_at = |at: DateTime<Utc>| -> Decimal {
+        series
+            .iter()
+            .map(|points| {
+                let next = points.partition_point(|point| point.date <= at);
+                next.checked_sub(1)
+                    .map_or(Decimal::ZERO, |last| points[last].flow)
+            })
+            .sum()
+    };
+    let mut volume = Decimal::ZERO;
+    let mut result = vec![(from, volume)];
+    for pair in moments.windows(2) {
+        volume +=
+            flow_at(pair[0]) * Decimal::from(seconds(pair[0], pair[1])) / Decimal::new(3600, 0);
+        result.push((pair[1], volume));
+    }
+    result
+}
+
+/// Volume of the cumulative curve at the moment
+fn volume_at(curve: &[(DateTime<Utc>, Decimal)], at: DateTime<Utc>) -> Decimal {
+    let next = curve.partition_point(|(date, _)| *date <= at);
+    match (next.checked_sub(1).map(|last| curve[last]), curve.get(next)) {
+        (Some((from, start)), Some((to, end))) => {
+            start
+                + (*end - start) * Decimal::from(seconds(from, at))
+                    / Decimal::from(seconds(from, *to))
+        }
+        (Some((_, volume)), None) => volume,
+        (None, Some((_, volume))) => *volume,
+        (None, None) => Decimal::ZERO,
+    }
+}
+
+/// First moment the cumulative curve reaches the volume, or gets above it if `above` is set.
+/// None if it does not by its end
+fn reached_at(
+    curve: &[(DateTime<Utc>, Decimal)],
+    volume: Decimal,
+    above: bool,
+) -> Option<DateTime<Utc>> {
+    let reached = curve
+        .iter()
+        .position(|(_, value)| *value > volume || (!above && *value == volume))?;
+    let Some(before) = reached.checked_sub(1) else {
+        return Some(curve[0].0);
+    };
+    let (from, start) = curve[before];
+    let (to, end) = curve[reached];
+    // Rounded down so the hold time is never overstated
+    let offset = Decimal::from(seconds(from, to)) * (volume - start) / (end - start);
+    Some(from + Duration::seconds(offset.trunc().try_into().unwrap_or(0)))
+}
+
+/// Smallest content of the machinery at the start of the curves that keeps it non-negative.
+/// The real content is unknown, taking the smallest one never overstates the hold time
+pub fn initial_content(
+    inflow: &[(DateTime<Utc>, Decimal)],
+    outflow: &[(DateTime<Utc>, Decimal)],
+) -> Decimal {
+    inflow
+        .iter()
+        .chain(outflow.iter())
+        .map(|(date, _)| volume_at(outflow, *date) - volume_at(inflow, *date))
+        .fold(Decimal::ZERO, Decimal::max)
+}
+
+/// Shortest residence of the product entering during [from, to], first in first out.
+/// None if nothing entered
+pub fn min_residence(
+    inflow: &[(DateTime<Utc>, Decimal)],
+    outflow: &[(DateTime<Utc>, Decimal)],
+    content: Decimal,
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+) -> Option<Residence> {
+    let end = outflow.last()?.0;
+    // Residence changes linearly while the product enters, except for the moments
+    // it meets a change of the outflow. Its minimum is at one of them or at the ends
+    let mut entries = vec![];
+    for pair in inflow.windows(2) {
+        let ((start, start_volume), (stop, stop_volume)) = (pair[0], pair[1]);
+        let (start, stop) = (start.max(from), stop.min(to));
+        if stop_volume <= start_volume || start >= stop {
+            continue;
+        }
+        let (start_volume, stop_volume) = (volume_at(inflow, start), volume_at(inflow, stop));
+        entries.extend([(start, false), (stop, true)]);
+        entries.extend(
+            outflow
+                .iter()
+                .map(|(_, volume)| *volume - content)
+                .filter(|volume| *volume > start_volume && *volume < stop_volume)
+                .filter_map(|volume| reached_at(inflow, volume, false))
+                .map(|entry| (entry, false)),
+        );
+    }
+    entries
+        .into_iter()
+        .map(|(entry, last)| {
+            let volume = volume_at(inflow, entry) + content;
+            // The product leaves once the outflow gets above its volume. The last one
+            // entering the segment is behind its volume and leaves once the outflow
+            // reaches it, unless the outflow stood at the volume before it came in
+            let exit = if last {
+                reached_at(outflow, volume, false)
+                    .filter(|exit| *exit >= entry)
+                    .or_else(|| reached_at(outflow, volume, true))
+            } else {
+                reached_at(outflow, volume, true)
+            };
+            match exit {
+                Some(exit) => Residence {
+                    seconds: seconds(entry, exit),
+                    left: true,
+                },
+                None => Residence {
+                    seconds: seconds(entry, end),
+                    left: false,
+                },
+            }
+        })
+        .min_by_key(|residence| residence.seconds)
+}
+
+pub fn hold_status(
+    hold: Option<Residence>,
+    required_seconds: i64,
+    temperature_readings: usize,
+    temperature_violations: usize,
+) -> HoldTimeStatus {
+    if temperature_violations > 0 {
+        return HoldTimeStatus::Violated;
+    }
+    match hold {
+        Some(hold) if hold.seconds < required_seconds && hold.left => HoldTimeStatus::Violated,
+        Some(hold) if hold.seconds >= required_seconds && temperature_readings > 0 => {
+            HoldTimeStatus::Compliant
+        }
+        _ => HoldTimeStatus::Unverified,
+    }
+}
+
+fn csv_field(value: String) -> String {
+    if value.contains([',', '"', '\n']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value
+    }
+}
+
+/// Report as CSV with a header and one row per record
+pub fn hold_time_csv(report: &HoldTimeReport) -> String {
+    let mut result = "day,machinery,batch,from,to,amount,units,required_seconds,hold_seconds,\
+                      still_inside,temperature_readings,temperature_violations,status\n"
+        .to_string();
+    for day in report.days.iter() {
+        for record in day.records.iter() {
+            let row = [
+                day.date.0 .0.to_rfc3339(),
+                record.machinery_name.clone(),
+                record.batch_code.clone().unwrap_or_default(),
+                record.from.0 .0.to_rfc3339(),
+                record.to.0 .0.to_rfc3339(),
+                record.amount.to_string(),
+                record.units.to_string(),
+                record.required_seconds.to_string(),
+                record
+                    .hold_seconds
+                    .map(|hold| hold.to_string())
+                    .unwrap_or_default(),
+                record.still_inside.to_string(),
+                record.temperature_readings.to_string(),
+                record.temperature_violations.to_string(),
+                format!("{:?}", record.status),
+            ];
+            result.push_str(&row.map(csv_field).join(","));
+            result.push('\n');
+        }
+    }
+    result
+}
+
+/// Temperature readings and violations among the checks taken in [from, to]
+fn temperature(checks: &[QualityCheck], from: DateTime<Utc>, to: DateTime<Utc>) -> (usize, usize) {
+    let checks: Vec<&QualityCheck> = checks
+        .iter()
+        .filter(|check| check.temperature.is_some())
+        .filter(|check| check.checked_at.0 .0 >= from && check.checked_at.0 .0 <= to)
+        .collect();
+    let violations = checks
+        .iter()
+        .filter(|check| {
+            check
+                .violations
+                .iter()
+                .any(|violation| violation.parameter == QualityParameter::Temperature)
+        })
+        .count();
+    (checks.len(), violations)
+}
+
+pub struct HoldTimeUseCases {}
+
+impl HoldTimeUseCases {
+    /// Readings of every pipe over [from, to] with the last one before, converted into `units`
+    async fn series(
+        pipes: &[Pipe],
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+        units: &ThingDerived,
+        converter: &UnitConverter,
+        db: &Db,
+        ctx: &dyn Ctx,
+    ) -> ApiResult<Vec<Vec<FlowPoint>>> {
+        let point = |reading: &PipeStats| {
+            FlowPoint::from(reading).convert(&reading.units, units, converter, ctx)
+        };
+        let mut result = vec![];
+        for pipe in pipes {
+            let previous =
+                PipeStatsUseCases::select_last_reading_before(pipe, from.into(), db, ctx).await?;
+            let readings =
+                PipeStatsUseCases::select_by_pipe_and_range(pipe, from.into(), to.into(), db, ctx)
+                    .await?;
+            result.push(
+                previous
+                    .iter()
+                    .chain(readings.iter())
+                    .map(point)
+                    .collect::<ApiResult<Vec<_>>>()?,
+            );
+        }
+        Ok(result)
+    }
+
+    /// Records of the process step for the product entering it during [from, to)
+    async fn records(
+        machinery: &Machinery,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+        offset: FixedOffset,
+        converter: &UnitConverter,
+        db: &Db,
+        ctx: &dyn Ctx,
+    ) -> ApiResult<Vec<HoldTimeRecord>> {
+        let required_seconds = machinery.min_hold_seconds.ok_or(ApiError {
+            req_id: ctx.req_id(),
+            error: Error::Generic {
+                description: format!(
+                    "Hold time report: {} has no minimum hold time",
+                    machinery.name
+                ),
+            },
+        })?;
+        let required = Duration::seconds(required_seconds);
+        // Product entering before `from` may still be inside, product entering
+        // before `to` leaves after it. Readings around the interval are taken for both
+        let margin = Duration::days(1) + required;
+        let start = from - margin;
+        let end = (to + margin).min(Utc::now());
+        if end <= from {
+            return Ok(vec![]);
+        }
+        let units = MachineryUseCases::units(machinery, db, ctx).await?;
+        let input_pipes = PipeFromUseCases::select_input_pipes(machinery, db, ctx).await?;
+        let output_pipes = PipeToUseCases::select_output_pipes(machinery, db, ctx).await?;
+        let inflow = cumulative(
+            &Self::series(&input_pipes, start, end, &units, converter, db, ctx).await?,
+            start,
+            end,
+        );
+        let outflow = cumulative(
+            &Self::series(&output_pipes, start, end, &units, converter, db, ctx).await?,
+            start,
+            end,
+        );
+        let content = initial_content(&inflow, &outflow);
+
+        let id: ThingDerived = machinery.thing(ctx)?.into();
+        let mut objects = vec![machinery.thing(ctx)?];
+        for pipe in output_pipes.iter() {
+            objects.push(pipe.thing(ctx)?);
+        }
+        let checks = QualityCheckRepository::select_by_objects_and_range(
+            objects,
+            start.into(),
+            end.into(),
+            db,
+            ctx,
+        )
+        .await?;
+        let record = |from: DateTime<Utc>,
+                      to: DateTime<Utc>,
+                      amount: Decimal,
+                      units: ThingDerived,
+                      batch: Option<&Batch>,
+                      hold: Option<Residence>,
+                      held: (usize, usize)| {
+            HoldTimeRecord {
+                machinery: id.clone(),
+                machinery_name: machinery.name.clone(),
+                batch: batch.and_then(|batch| batch.id.clone()),
+                batch_code: batch.map(|batch| batch.code.clone()),
+                from: from.into(),
+                to: to.into(),
+                amount,
+                units,
+                required_seconds,
+                hold_seconds: hold.map(|hold| hold.seconds),
+                still_inside: hold.is_some_and(|hold| !hold.left),
+                temperature_readings: held.0,
+                temperature_violations: held.1,
+                status: hold_status(hold, required_seconds, held.0, held.1),
+            }
+        };
+
+        let mut result = vec![];
+        let hours = Bucket::Hour
+            .bounds(from, to.min(end), offset, MAX_DAYS * 24)
+            .unwrap_or_default();
+        for (hour_from, hour_to) in hours {
+            let amount = volume_at(&inflow, hour_to) - volume_at(&inflow, hour_from);
+            if amount <= Decimal::ZERO {
+                continue;
+            }
+            let hold = min_residence(&inflow, &outflow, content, hour_from, hour_to);
+            result.push(record(
+                hour_from,
+                hour_to,
+                amount,
+                units.clone(),
+                None,
+                hold,
+                temperature(&checks, hour_from, hour_to + required),
+            ));
+        }
+
+        let batches = BatchRepository::select_by_machinery_and_range(
+            machinery,
+            from.into(),
+            to.into(),
+            db,
+            ctx,
+        )
+        .await?;
+        let mut ids = vec![];
+        for batch in batches.iter() {
+            ids.push(batch.thing(ctx)?);
+        }
+        let links = BatchRepository::select_links(ids, TraceDirection::Backward, db, ctx).await?;
+        let inputs = BatchRepository::select_by_ids(
+            links
+                .iter()
+                .map(|link| link.r#in.thing(ctx))
+                .collect::<ApiResult<Vec<_>>>()?,
+            db,
+            ctx,
+        )
+        .await?;
+        for batch in batches.iter() {
+            let id = batch.id.as_ref();
+            let batch_inputs: Vec<&Batch> = links
+                .iter()
+                .filter(|link| Some(&link.out) == id)
+                .filter_map(|link| {
+                    inputs
+                        .iter()
+                        .find(|input| input.id.as_ref() == Some(&link.r#in))
+                })
+                .collect();
+            // The last product to enter may be the first to leave
+            let hold = batch_inputs
+                .iter()
+                .map(|input| input.ended_at.0 .0)
+                .max()
+                .map(|entered| Residence {
+                    seconds: seconds(entered, batch.started_at.0 .0),
+                    left: true,
+                });
+            let held_from = batch_inputs
+                .iter()
+                .map(|input| input.started_at.0 .0)
+                .min()
+                .unwrap_or(batch.started_at.0 .0);
+            result.push(record(
+                batch.started_at.0 .0,
+                batch.ended_at.0 .0,
+                batch.amount,
+                batch.units.clone(),
+                Some(batch),
+                hold,
+                temperature(&checks, held_from, batch.ended_at.0 .0),
+            ));
+        }
+        Ok(result)
+    }
+
+    /// Compliance of process steps with their minimum hold time for every plant local day
+    /// from the day of `from` to the day of `to` inclusive. All process steps
+    /// if `machinery` is not set
+    pub async fn report(
+        from: DateTimeDerived,
+        to: DateTimeDerived,
+        machinery: Option<ThingDerived>,
+        options: &FlowIntegrationOptions,
+        db: &Db,
+        ctx: &dyn Ctx,
+    ) -> ApiResult<HoldTimeReport> {
+        let error = |description: String| ApiError {
+            req_id: ctx.req_id(),
+            error: Error::Generic {
+                description: format!("Hold time report: {description}"),
+            },
+        };
+        let offset = options.utc_offset(ctx)?;
+        let (from, _) = day_bounds(from.0 .0, offset);
+        let (_, to) = day_bounds(to.0 .0, offset);
+        if from >= to {
+            return Err(error("`from` should be before `to`".to_string()));
+        }
+        let days = Bucket::Day
+            .bounds(from, to, offset, MAX_DAYS)
+            .ok_or_else(|| error(format!("at most {MAX_DAYS} days allowed")))?;
+
+        let steps = match machinery {
+            Some(machinery) => vec![MachineryUseCases::select_by_id(&machinery, db, ctx).await?],
+            None => MachineryUseCases::select_process_steps(db, ctx).await?,
+        };
+        let converter = MeasureUnitsUseCases::converter(db, ctx).await?;
+        let mut records = vec![];
+        for step in steps.iter() {
+            records.extend(Self::records(step, from, to, offset, &converter, db, ctx).await?);
+        }
+        records.sort_by_key(|record| (record.machinery_name.clone(), record.from.0 .0));
+
+        let days: Vec<HoldTimeDay> = days
+            .into_iter()
+            .map(|(day_from, day_to)| {
+                let records: Vec<HoldTimeRecord> = records
+                    .iter()
+                    .filter(|record| record.from.0 .0 >= day_from && record.from.0 .0 < day_to)
+                    .cloned()
+                    .collect();
+                HoldTimeDay {
+                    date: day_from.into(),
+                    status: records
+                        .iter()
+                        .map(|record| record.status)
+                        .max()
+                        .unwrap_or(HoldTimeStatus::Compliant),
+                    records,
+                }
+            })
+            .collect();
+        Ok(HoldTimeReport {
+            from: from.into(),
+            to: to.into(),
+            status: days
+                .iter()
+                .map(|day| day.status)
+                .max()
+                .unwrap_or(HoldTimeStatus::Compliant),
+            days,
+        })
+    }
+
+    /// Report as CSV for auditors
+    pub async fn export(
+        from: DateTimeDerived,
+        to: DateTimeDerived,
+        machinery: Option<ThingDerived>,
+        options: &FlowIntegrationOptions,
+        db: &Db,
+        ctx: &dyn Ctx,
+    ) -> ApiResult<String> {
+        Ok(hold_time_csv(
+            &Self::report(from, to, machinery, options, db, ctx).await?,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::machinery::CreateMachineryInput;
+    use crate::pipe_stats::CreatePipeStatsInput;
+    use crate::prod_populate::{
+        connect_shortcut, machinery_type_shortcut, measure_units_shortcut, pipe_shortcut,
+        pipe_type_shortcut,
+    };
+    use crate::quality_check::{CreateQualityCheckInput, QualityCheckUseCases};
+    use crate::quality_spec::{CreateQualitySpecInput, QualitySpecUseCases};
+    use crate::raw_material::{CreateRawMaterialInput, RawMaterialUseCases};
+    use chrono::TimeZone;
+    use common::ctx::MockCtx;
+    use db::set_test_db;
+    use rstest::*;
+
+    #[fixture]
+    fn ctx() -> MockCtx {
+        let mut ctx = MockCtx::new();
+        ctx.expect_req_id().return_const(uuid::Uuid::new_v4());
+        ctx.expect_user_id_thing().returning(|| {
+            Err(ApiError {
+                req_id: uuid::Uuid::new_v4(),
+                error: Error::Generic {
+                    description: "No user".to_string(),
+                },
+            })
+        });
+        ctx
+    }
+
+    #[fixture]
+    async fn tdb() -> Db {
+        set_test_db().await
+    }
+
+    fn at(hour: u32, minute: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2024, 1, 1, hour, minute, 0).unwrap()
+    }
+
+    /// Flow `flow` during [from, to) hours, zero around
+    fn run(from: u32, to: u32, flow: i64) -> Vec<FlowPoint> {
+        vec![
+            FlowPoint {
+                date: at(from, 0),
+                flow: Decimal::from(flow),
+            },
+            FlowPoint {
+                date: at(to, 0),
+                flow: Decimal::ZERO,
+            },
+        ]
+    }
+
+    fn residence(
+        inflow: &[Vec<FlowPoint>],
+        outflow: &[Vec<FlowPoint>],
+        entered: (u32, u32),
+        end: u32,
+    ) -> Option<Residence> {
+        let inflow = cumulative(inflow, at(0, 0), at(end, 0));
+        let outflow = cumulative(outflow, at(0, 0), at(end, 0));
+        let content = initial_content(&inflow, &outflow);
+        min_residence(
+            &inflow,
+            &outflow,
+            content,
+            at(entered.0, 0),
+            at(entered.1, 0),
+        )
+    }
+
+    #[test]
+    fn min_residence_test() {
+        // Continuous flow leaves an hour after it enters
+        assert_eq!(
+            residence(&[run(0, 4, 10)], &[run(1, 5, 10)], (0, 4), 6),
+            Some(Residence {
+                seconds: 3600,
+                left: true,
+            })
+        );
+        // Twice faster outflow shortens the hold time
+        assert_eq!(
+            residence(&[run(0, 2, 10)], &[run(1, 2, 20)], (0, 2), 3),
+            Some(Residence {
+                seconds: 0,
+                left: true,
+            })
+        );
+        // Nothing has left yet
+        assert_eq!(
+            residence(&[run(0, 1, 10)], &[], (0, 1), 3),
+            Some(Residence {
+                seconds: 2 * 3600,
+                left: false,
+            })
+        );
+        // Content before the readings is drained first, then the product waits
+        // till the outflow resumes
+        assert_eq!(
+            residence(
+                &[run(2, 3, 10)],
+                &[[run(0, 1, 10), run(4, 5, 10)].concat()],
+                (2, 3),
+                6
+            ),
+            Some(Residence {
+                seconds: 2 * 3600,
+                left: true,
+            })
+        );
+        // Nothing entered
+        assert_eq!(
+            residence(&[run(0, 1, 10)], &[run(1, 2, 10)], (2, 3), 4),
+            None
+        );
+
+        let held = Residence {
+            seconds: 3600,
+            left: true,
+        };
+        assert_eq!(
+            hold_status(Some(held), 3600, 1, 0),
+            HoldTimeStatus::Compliant
+        );
+        assert_eq!(
+            hold_status(Some(held), 3600, 0, 0),
+            HoldTimeStatus::Unverified
+        );
+        assert_eq!(
+            hold_status(Some(held), 3600, 2, 1),
+            HoldTimeStatus::Violated
+        );
+        assert_eq!(
+            hold_status(Some(held), 7200, 1, 0),
+            HoldTimeStatus::Violated
+        );
+        assert_eq!(
+            hold_status(
+                Some(Residence {
+                    left: false,
+                    ..held
+                }),
+                7200,
+                1,
+                0
+            ),
+            HoldTimeStatus::Unverified
+        );
+        assert_eq!(hold_status(None, 3600, 1, 0), HoldTimeStatus::Unverified);
+    }
+
+    #[rstest]
+    #[tokio::test]
+    #[awt]
+    async fn hold_time_report_test(ctx: MockCtx, #[future] tdb: Db) {
+        let units = measure_units_shortcut("м^3", &tdb, &ctx).await.unwrap();
+        let pipe_type = pipe_type_shortcut("Трубопровод", &units, &tdb, &ctx)
+            .await
+            .unwrap();
+        let machinery_type = machinery_type_shortcut("Пастеризатор", &units, &tdb, &ctx)
+            .await
+            .unwrap();
+        let mut pipes = vec![];
+        for name in ["Фильтрованная смесь", "Пастеризованная смесь"]
+        {
+            let material = RawMaterialUseCases::create(
+                CreateRawMaterialInput {
+                    name: name.to_string(),
+                },
+                &tdb,
+                &ctx,
+            )
+            .await
+            .unwrap();
+            pipes.push(
+                pipe_shortcut(name, &pipe_type, &material, &tdb, &ctx)
+                    .await
+                    .unwrap(),
+            );
+        }
+        let pasteurizer = MachineryUseCases::create(
+            CreateMachineryInput {
+                name: "Пастеризатор № 1".to_string(),
+                machinery_type: machinery_type.id.clone().unwrap(),
+                min_hold_seconds: Some(3600),
+            },
+            &tdb,
+            &ctx,
+        )
+        .await
+        .unwrap();
+        connect_shortcut(&[&pipes[0]], &pasteurizer, &[&pipes[1]], &tdb, &ctx)
+            .await
+            .unwrap();
+
+        // Product enters during [0, 4) and leaves an hour later
+        for (pipe, readings) in [(&pipes[0], run(0, 4, 10)), (&pipes[1], run(1, 5, 10))] {
+            for reading in readings {
+                PipeStatsUseCases::create(
+                    CreatePipeStatsInput {
+                        date: reading.date.into(),
+                        flow: reading.flow,
+                        units: units.id.clone().unwrap(),
+                        wearout: Decimal::ZERO,
+                        pipe: pipe.id.clone().unwrap(),
+                    },
+                    &tdb,
+                    &ctx,
+                )
+                .await
+                .unwrap();
+            }
+        }
+        QualitySpecUseCases::create(
+            CreateQualitySpecInput {
+                material: pipes[1].material.clone(),
+                parameter: QualityParameter::Temperature,
+                min: Some(Decimal::from(80)),
+                max: Some(Decimal::from(85)),
+                comment: None,
+            },
+            &tdb,
+            &ctx,
+        )
+        .await
+        .unwrap();
+        for (checked_at, temperature) in [(at(1, 30), 82), (at(3, 30), 75)] {
+            QualityCheckUseCases::create(
+                CreateQualityCheckInput {
+                    object: pipes[1].id.clone().unwrap(),
+                    material: None,
+                    checked_at: checked_at.into(),
+                    temperature: Some(Decimal::from(temperature)),
+                    fat: None,
+                    viscosity: None,
+                    microbiology_passed: None,
+                    comment: None,
+                },
+                &tdb,
+                &ctx,
+            )
+            .await
+            .unwrap();
+        }
+
+        let options = FlowIntegrationOptions {
+            utc_offset_minutes: Some(0),
+            ..Default::default()
+        };
+        let report =
+            HoldTimeUseCases::report(at(0, 0).into(), at(0, 0).into(), None, &options, &tdb, &ctx)
+                .await
+                .unwrap();
+        assert_eq!(report.days.len(), 1);
+        assert_eq!(report.status, HoldTimeStatus::Violated);
+        let records = &report.days[0].records;
+        assert_eq!(records.len(), 4);
+        assert!(records
+            .iter()
+            .all(|record| record.hold_seconds == Some(3600) && record.amount == Decimal::from(10)));
+        // Out of spec temperature at 3:30 hits the product held during it
+        assert_eq!(
+            records
+                .iter()
+                .map(|record| record.status)
+                .collect::<Vec<_>>(),
+            vec![
+                HoldTimeStatus::Compliant,
+                HoldTimeStatus::Compliant,
+                HoldTimeStatus::Violated,
+                HoldTimeStatus::Violated,
+            ]
+        );
+
+        let csv = hold_time_csv(&report);
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(lines.len(), 5);
+        assert!(lines[0].starts_with("day,machinery,batch,from,to"));
+        assert!(lines[1].contains("Пастеризатор № 1"));
+        assert!(lines[4].ends_with(",3600,false,1,1,Violated"));
+
+        // Filtered mixer is not a process step
+        assert!(HoldTimeUseCases::report(
+            at(0, 0).into(),
+            at(0, 0).into(),
+            Some(surrealdb::sql::Thing::from(("Machinery", "unknown")).into()),
+            &options,
+            &tdb,
+            &ctx,
+        )
+        .await
+        .is_err());
+    }
+}
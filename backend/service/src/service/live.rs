@@ -0,0 +1,271 @@
+use crate::datetime::DateTimeDerived;
+use crate::flow_integration::{plant_day, FlowIntegrationOptions, FlowIntegrationUseCases};
+use crate::maintenance::{MaintenanceAlert, MaintenanceOptions, MaintenanceUseCases};
+use crate::pipe_stats::PipeStats;
+use crate::plant_topology::PlantTopologyUseCases;
+use crate::production_info::{ProductionInfo, ProductionInfoUseCases};
+use crate::service::guard::RoleGuard;
+use crate::thing_derived::ThingDerived;
+use crate::thing_wrapper::ObjectWithThing;
+use async_graphql::SimpleObject;
+use chrono::Utc;
+use common::{
+    ctx::Ctx,
+    error::{ApiError, IntoApiError},
+    role::Role,
+    ApiResult,
+};
+
+use db::Db;
+use futures::{future, stream, Stream, StreamExt};
+use rust_decimal::Decimal;
+use surrealdb::{sql::Value, Action, Notification};
+
+/// Production of the current plant local day, recomputed on every reading of the final pipe
+#[derive(Clone, Debug, SimpleObject)]
+#[graphql(guard = "RoleGuard::new(Role::User)")]
+pub struct LiveProductionFact {
+    pub final_pipe: ThingDerived,
+    /// Start of the plant local day
+    pub day: DateTimeDerived,
+    /// Volume through the final pipe since the start of the day. None if there are no readings
+    pub fact: Option<Decimal>,
+    pub computed_at: DateTimeDerived,
+    /// ProductionInfo of the day if it is already created
+    pub production_info: Option<ProductionInfo>,
+}
+
+/// SurrealDB live queries. Killed once the stream is dropped
+pub struct LiveRepository {}
+
+impl LiveRepository {
+    pub async fn pipe_stats<'a>(
+        pipe: &dyn ObjectWithThing,
+        db: &Db,
+        ctx: &'a dyn Ctx,
+    ) -> ApiResult<impl Stream<Item = ApiResult<Notification<PipeStats>>> + 'a> {
+        // Live queries do not keep bound parameters, the record id is escaped by Display
+        let mut response = db
+            .query(format!(
+                "LIVE SELECT * FROM PipeStats WHERE pipe = {};",
+                pipe.thing(ctx)?
+            ))
+            .await
+            .map_err(ApiError::from(ctx))?;
+        let notifications = response
+            .stream::<Notification<PipeStats>>(0)
+            .map_err(ApiError::from(ctx))?;
+        Ok(notifications
+            .map(move |notification| notification.map_err(|err| err.into_api_error(ctx))))
+    }
+
+    /// Any change of the tables
+    pub async fn changes(
+        tables: &[&str],
+        db: &Db,
+        ctx: &dyn Ctx,
+    ) -> ApiResult<impl Stream<Item = Notification<Value>>> {
+        let query: String = tables
+            .iter()
+            .map(|table| format!("LIVE SELECT * FROM {table};"))
+            .collect();
+        let mut response = db.query(query).await.map_err(ApiError::from(ctx))?;
+        response.stream::<Value>(()).map_err(ApiError::from(ctx))
+    }
+}
+
+pub struct LiveUseCases {}
+
+impl LiveUseCases {
+    /// Readings of the pipe created from now on
+    pub async fn pipe_stats<'a>(
+        pipe: &dyn ObjectWithThing,
+        db: &Db,
+        ctx: &'a dyn Ctx,
+    ) -> ApiResult<impl Stream<Item = ApiResult<PipeStats>> + 'a> {
+        Ok(LiveRepository::pipe_stats(pipe, db, ctx)
+            .await?
+            .filter_map(|notification| {
+                future::ready(match notification {
+                    Ok(Notification {
+                        action: Action::Create,
+                        data,
+                        ..
+                    }) => Some(Ok(data)),
+                    Ok(_) => None,
+                    Err(err) => Some(Err(err)),
+                })
+            }))
+    }
+
+    pub async fn production_fact(
+        final_pipe: &ThingDerived,
+        options: &FlowIntegrationOptions,
+        db: &Db,
+        ctx: &dyn Ctx,
+    ) -> ApiResult<LiveProductionFact> {
+        let now: DateTimeDerived = Utc::now().into();
+        let fact =
+            FlowIntegrationUseCases::integrate_pipe_day(final_pipe, now.clone(), options, db, ctx)
+                .await?;
+        let production_info =
+            ProductionInfoUseCases::select_by_date(Some(final_pipe.clone()), now.clone(), db, ctx)
+                .await?;
+        Ok(LiveProductionFact {
+            final_pipe: final_pipe.clone(),
+            day: plant_day(&now, ctx)?,
+            fact: fact.has_data().then_some(fact.volume),
+            computed_at: now,
+            production_info,
+        })
+    }
+
+    /// Current fact first, then on every reading of the final pipe and change of ProductionInfo.
+    /// Uses the only final pipe of the plant if `final_pipe` is not set
+    pub async fn production_facts<'a>(
+        final_pipe: Option<ThingDerived>,
+        options: FlowIntegrationOptions,
+        db: &'a Db,
+        ctx: &'a dyn Ctx,
+    ) -> ApiResult<impl Stream<Item = ApiResult<LiveProductionFact>> + 'a> {
+        let final_pipe = match final_pipe {
+            Some(final_pipe) => final_pipe,
+            None => PlantTopologyUseCases::select_final_pipe(db, ctx).await?,
+        };
+        let readings = LiveRepository::pipe_stats(&final_pipe, db, ctx)
+            .await?
+            .map(|_| ());
+        let infos = LiveRepository::changes(&["ProductionInfo"], db, ctx)
+            .await?
+            .map(|_| ());
+        Ok(stream::once(future::ready(()))
+            .chain(stream::select(readings, infos))
+            .then(move |_| {
+                let final_pipe = final_pipe.clone();
+                let options = options.clone();
+                async move { Self::production_fact(&final_pipe, &options, db, ctx).await }
+            }))
+    }
+
+    /// Current alerts first, then every time they change
+    pub async fn maintenance_alerts<'a>(
+        options: MaintenanceOptions,
+        db: &'a Db,
+        ctx: &'a dyn Ctx,
+    ) -> ApiResult<impl Stream<Item = ApiResult<Vec<MaintenanceAlert>>> + 'a> {
+        // Wear grows with readings and is reset by maintenance orders
+        let changes = LiveRepository::changes(&["PipeStats", "MaintenanceOrder"], db, ctx)
+            .await?
+            .map(|_| ());
+        let mut last: Option<Vec<MaintenanceAlert>> = None;
+        Ok(stream::once(future::ready(()))
+            .chain(changes)
+            .then(move |_| {
+                let options = options.clone();
+                async move { MaintenanceUseCases::alerts(&options, db, ctx).await }
+            })
+            .filter_map(move |alerts| {
+                if let Ok(alerts) = &alerts {
+                    if last.as_ref() == Some(alerts) {
+                        return future::ready(None);
+                    }
+                    last = Some(alerts.clone());
+                }
+                future::ready(Some(alerts))
+            }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::maintenance::MaintenanceAlertLevel;
+    use crate::pipe::{CreatePipeInput, PipeUseCases};
+    use crate::pipe_stats::{tests::create_pipe, CreatePipeStatsInput, PipeStatsUseCases};
+    use common::{ctx::MockCtx, error::Error};
+    use db::set_test_db;
+    use rstest::*;
+    use std::time::Duration;
+    use tokio::time::timeout;
+
+    #[fixture]
+    fn ctx() -> MockCtx {
+        let mut ctx = MockCtx::new();
+        ctx.expect_req_id().return_const(uuid::Uuid::new_v4());
+        ctx.expect_user_id_thing().returning(|| {
+            Err(ApiError {
+                req_id: uuid::Uuid::new_v4(),
+                error: Error::Generic {
+                    description: "No user".to_string(),
+                },
+            })
+        });
+        ctx
+    }
+
+    #[fixture]
+    async fn tdb() -> Db {
+        set_test_db().await
+    }
+
+    async fn next<T>(stream: &mut (impl Stream<Item = T> + Unpin)) -> Option<T> {
+        timeout(Duration::from_secs(5), stream.next())
+            .await
+            .ok()
+            .flatten()
+    }
+
+    #[rstest]
+    #[tokio::test]
+    #[awt]
+    async fn live_test(ctx: MockCtx, #[future] tdb: Db) {
+        let (pipe, units) = create_pipe(&ctx, &tdb).await.unwrap();
+        let other = PipeUseCases::create(
+            CreatePipeInput {
+                name: "Трубопровод № 2".to_string(),
+                pipe_type: pipe.pipe_type.clone(),
+                material: pipe.material.clone(),
+            },
+            &tdb,
+            &ctx,
+        )
+        .await
+        .unwrap();
+        let readings = LiveUseCases::pipe_stats(&pipe, &tdb, &ctx).await.unwrap();
+        let alerts = LiveUseCases::maintenance_alerts(MaintenanceOptions::default(), &tdb, &ctx)
+            .await
+            .unwrap();
+        let (mut readings, mut alerts) = (Box::pin(readings), Box::pin(alerts));
+        assert_eq!(next(&mut alerts).await.unwrap().unwrap(), vec![]);
+
+        for (pipe, wearout) in [(&other, 1), (&pipe, 2), (&pipe, 1000000)] {
+            PipeStatsUseCases::create(
+                CreatePipeStatsInput {
+                    date: Utc::now().into(),
+                    flow: Decimal::ONE,
+                    units: units.id.clone().unwrap(),
+                    wearout: Decimal::from(wearout),
+                    pipe: pipe.id.clone().unwrap(),
+                },
+                &tdb,
+                &ctx,
+            )
+            .await
+            .unwrap();
+        }
+        // Only readings of the pipe
+        let reading = next(&mut readings).await.unwrap().unwrap();
+        assert_eq!(reading.wearout, Decimal::from(2));
+        let reading = next(&mut readings).await.unwrap().unwrap();
+        assert_eq!(reading.wearout, Decimal::from(1000000));
+
+        // Small wearout does not change the alerts, so they are sent once more only
+        let sent = next(&mut alerts).await.unwrap().unwrap();
+        assert_eq!(sent.len(), 1);
+        assert_eq!(sent[0].object, pipe.id.clone().unwrap());
+        assert_eq!(sent[0].level, MaintenanceAlertLevel::Critical);
+        assert!(timeout(Duration::from_millis(500), alerts.next())
+            .await
+            .is_err());
+    }
+}
@@ -1,5 +1,9 @@
+use crate::audit_log::{AuditLogRepository, Audited};
 use crate::common::Unwrapper;
 use crate::machinery_type::{MachineryType, MachineryTypeUseCases};
+use crate::pipe::Pipe;
+use crate::pipe_from::PipeFromUseCases;
+use crate::pipe_to::PipeToUseCases;
 use crate::service::guard::RoleGuard;
 use crate::thing_derived::ThingDerived;
 use crate::thing_wrapper::ObjectWithThing;
@@ -26,6 +30,9 @@ pub struct Machinery {
     pub name: String,
     #[graphql(skip)]
     pub machinery_type: ThingDerived,
+    /// Minimum residence time of the product. Set for process steps
+    /// with regulatory hold time like pasteurization and maturation
+    pub min_hold_seconds: Option<i64>,
 }
 
 #[ComplexObject]
@@ -35,6 +42,20 @@ impl Machinery {
         let ctx = ctx.data::<CtxStruct>()?;
         Ok(MachineryTypeUseCases::select_by_id(&self.machinery_type.thing(ctx)?, db, ctx).await?)
     }
+
+    /// Pipes feeding this machinery
+    async fn input_pipes(&self, ctx: &Context<'_>) -> Result<Vec<Pipe>> {
+        let db = ctx.data::<Db>()?;
+        let ctx = ctx.data::<CtxStruct>()?;
+        Ok(PipeFromUseCases::select_input_pipes(self, db, ctx).await?)
+    }
+
+    /// Pipes this machinery outputs into
+    async fn output_pipes(&self, ctx: &Context<'_>) -> Result<Vec<Pipe>> {
+        let db = ctx.data::<Db>()?;
+        let ctx = ctx.data::<CtxStruct>()?;
+        Ok(PipeToUseCases::select_output_pipes(self, db, ctx).await?)
+    }
 }
 
 impl ObjectWithThing for Machinery {
@@ -109,18 +130,31 @@ impl MachineryRepository {
         Unwrapper::unwrapper_option(query, 0, "Can't get tag by id", ctx).await
     }
 
+    /// Machinery with minimum hold time
+    pub async fn select_process_steps(db: &Db, ctx: &dyn Ctx) -> ApiResult<Vec<Machinery>> {
+        let query = db.query(format!(
+            "SELECT * FROM {RESOURCE} WHERE min_hold_seconds != NONE ORDER BY name;"
+        ));
+        Unwrapper::unwrapper_vec(query, 0, ctx).await
+    }
+
     pub async fn create(
         name: String,
         machinery_type: ThingDerived,
+        min_hold_seconds: Option<i64>,
         db: &Db,
         ctx: &dyn Ctx,
     ) -> ApiResult<Machinery> {
         db.create(RESOURCE)
-            .content(Machinery {
-                id: None,
-                name,
-                machinery_type,
-            })
+            .content(Audited::new(
+                Machinery {
+                    id: None,
+                    name,
+                    machinery_type,
+                    min_hold_seconds,
+                },
+                ctx,
+            ))
             .await
             .map_err(ApiError::from(ctx))
             .map(|v: Vec<Machinery>| {
@@ -141,7 +175,7 @@ impl MachineryRepository {
         ctx: &dyn Ctx,
     ) -> ApiResult<Machinery> {
         db.update((RESOURCE, id.id.to_string()))
-            .content(ct_input)
+            .content(Audited::new(ct_input, ctx))
             .await
             .map_err(ApiError::from(ctx))?
             .ok_or(ApiError {
@@ -154,9 +188,8 @@ impl MachineryRepository {
     }
 
     pub async fn delete(id: Thing, db: &Db, ctx: &dyn Ctx) -> ApiResult<Machinery> {
-        db.delete((RESOURCE, id))
-            .await
-            .map_err(ApiError::from(ctx))?
+        AuditLogRepository::delete(RESOURCE, id.id, db, ctx)
+            .await?
             .ok_or(ApiError {
                 req_id: ctx.req_id(),
                 error: Error::SurrealDbNoResult {
@@ -172,6 +205,8 @@ pub struct CreateMachineryInput {
     #[graphql(validator(min_length = 4))]
     pub name: String,
     pub machinery_type: ThingDerived,
+    #[graphql(validator(minimum = 1))]
+    pub min_hold_seconds: Option<i64>,
 }
 
 pub struct MachineryUseCases {
@@ -185,7 +220,14 @@ impl MachineryUseCases {
         db: &Db,
         ctx: &dyn Ctx,
     ) -> ApiResult<Machinery> {
-        MachineryRepository::create(ct_input.name, ct_input.machinery_type, db, ctx).await
+        MachineryRepository::create(
+            ct_input.name,
+            ct_input.machinery_type,
+            ct_input.min_hold_seconds,
+            db,
+            ctx,
+        )
+        .await
     }
 
     pub async fn update(
@@ -209,6 +251,24 @@ impl MachineryUseCases {
         MachineryRepository::select_by_id(id, db, ctx).await
     }
 
+    /// Units of the machinery type. Flow of the machinery is measured in them
+    pub async fn units(
+        id: &dyn ObjectWithThing,
+        db: &Db,
+        ctx: &dyn Ctx,
+    ) -> ApiResult<ThingDerived> {
+        let machinery = MachineryRepository::select_by_id(id, db, ctx).await?;
+        Ok(
+            MachineryTypeUseCases::select_by_id(&machinery.machinery_type, db, ctx)
+                .await?
+                .units,
+        )
+    }
+
+    pub async fn select_process_steps(db: &Db, ctx: &dyn Ctx) -> ApiResult<Vec<Machinery>> {
+        MachineryRepository::select_process_steps(db, ctx).await
+    }
+
     pub async fn count(db: &Db, ctx: &dyn Ctx) -> ApiResult<usize> {
         MachineryRepository::count(db, ctx).await
     }
@@ -0,0 +1,385 @@
+use crate::audit_log::{AuditLogRepository, Audited};
+use crate::capacity::check_capacity;
+use crate::machinery::MachineryUseCases;
+use crate::machinery_type::MachineryTypeUseCases;
+use crate::measure_units::MeasureUnitsUseCases;
+use crate::service::guard::RoleGuard;
+use crate::thing_derived::ThingDerived;
+use crate::thing_wrapper::ObjectWithThing;
+use crate::{common::Unwrapper, datetime::DateTimeDerived};
+use async_graphql::{InputObject, SimpleObject};
+use common::{
+    ctx::Ctx,
+    error::{ApiError, Error},
+    role::Role,
+    ApiResult,
+};
+
+use db::Db;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use surrealdb::sql::Thing;
+
+#[allow(dead_code)]
+const RESOURCE: &str = "MachineryStats";
+
+#[derive(Clone, Debug, Serialize, Deserialize, SimpleObject)]
+#[graphql(guard = "RoleGuard::new(Role::User)")]
+pub struct MachineryStats {
+    pub id: Option<ThingDerived>,
+    pub date: DateTimeDerived,
+    pub flow: Decimal,
+    pub units: ThingDerived,
+    pub wearout: Decimal,
+    pub machinery: ThingDerived,
+    /// Flow is above max_flow of the machinery type
+    #[serde(default)]
+    pub over_capacity: bool,
+}
+
+impl ObjectWithThing for MachineryStats {
+    fn thing(&self, ctx: &dyn Ctx) -> ApiResult<Thing> {
+        self.id
+            .as_ref()
+            .ok_or(ApiError {
+                req_id: ctx.req_id(),
+                error: Error::Generic {
+                    description: "Can't get thing. Get none instead".to_string(),
+                },
+            })?
+            .thing(ctx)
+    }
+}
+
+pub struct MachineryStatsRepository {}
+
+impl MachineryStatsRepository {
+    pub async fn list(
+        offset: usize,
+        limit: usize,
+        db: &Db,
+        ctx: &dyn Ctx,
+    ) -> ApiResult<Vec<MachineryStats>> {
+        let query = db.query(format!(
+            "SELECT * FROM {RESOURCE} LIMIT {limit} START {offset};"
+        ));
+        Unwrapper::unwrapper_vec(query, 0, ctx).await
+    }
+
+    pub async fn count(db: &Db, ctx: &dyn Ctx) -> ApiResult<usize> {
+        let query = db.query(format!("(SELECT count() FROM {RESOURCE} GROUP ALL).count"));
+        Unwrapper::unwrapper_option(query, 0, &format!("Cant't {RESOURCE} count"), ctx).await
+    }
+
+    pub async fn select_by_id(
+        id: &dyn ObjectWithThing,
+        db: &Db,
+        ctx: &dyn Ctx,
+    ) -> ApiResult<MachineryStats> {
+        let thing_id = id.thing(ctx)?;
+        if thing_id.tb != RESOURCE {
+            return Err(ApiError {
+                error: Error::Generic {
+                    description: "Wrong table name in select_by_id".to_string(),
+                },
+                req_id: ctx.req_id(),
+            });
+        }
+        let query = db
+            .query("SELECT * FROM $thing_id".to_string())
+            .bind(("thing_id", thing_id));
+
+        Unwrapper::unwrapper_option(query, 0, "Can't get tag by id", ctx).await
+    }
+
+    pub async fn select_by_machinery_and_date(
+        machinery: &dyn ObjectWithThing,
+        date: DateTimeDerived,
+        db: &Db,
+        ctx: &dyn Ctx,
+    ) -> ApiResult<Vec<MachineryStats>> {
+        let query = db
+            .query(
+                "SELECT * FROM MachineryStats WHERE machinery = $machinery AND time::floor(date, 1d) = time::floor($date, 1d) ORDER BY date ASC;"
+                    .to_string(),
+            )
+            .bind(("machinery", machinery.thing(ctx)?))
+            .bind(("date", date.0));
+
+        Unwrapper::unwrapper_vec(query, 0, ctx).await
+    }
+
+    pub async fn select_previous_reading_by_machinery_and_date(
+        machinery: &dyn ObjectWithThing,
+        date: DateTimeDerived,
+        db: &Db,
+        ctx: &dyn Ctx,
+    ) -> ApiResult<Option<MachineryStats>> {
+        let query = db
+            .query(
+                "SELECT * FROM MachineryStats WHERE machinery = $machinery AND time::floor(date, 1d) <= time::floor($date , 1d)-1d ORDER BY date DESC LIMIT 1;"
+                    .to_string(),
+            )
+            .bind(("machinery", machinery.thing(ctx)?))
+            .bind(("date", date.0));
+
+        Unwrapper::unwrapper_option_without_error(query, 0, ctx).await
+    }
+
+    /// Readings in [from, to) ordered by date
+    pub async fn select_by_machinery_and_range(
+        machinery: &dyn ObjectWithThing,
+        from: DateTimeDerived,
+        to: DateTimeDerived,
+        db: &Db,
+        ctx: &dyn Ctx,
+    ) -> ApiResult<Vec<MachineryStats>> {
+        let query = db
+            .query(format!(
+                "SELECT * FROM {RESOURCE} WHERE machinery = $machinery AND date >= $from AND date < $to ORDER BY date ASC;"
+            ))
+            .bind(("machinery", machinery.thing(ctx)?))
+            .bind(("from", from.0))
+            .bind(("to", to.0));
+
+        Unwrapper::unwrapper_vec(query, 0, ctx).await
+    }
+
+    /// Last reading strictly before the date
+    pub async fn select_last_reading_before(
+        machinery: &dyn ObjectWithThing,
+        date: DateTimeDerived,
+        db: &Db,
+        ctx: &dyn Ctx,
+    ) -> ApiResult<Option<MachineryStats>> {
+        let query = db
+            .query(format!(
+                "SELECT * FROM {RESOURCE} WHERE machinery = $machinery AND date < $date ORDER BY date DESC LIMIT 1;"
+            ))
+            .bind(("machinery", machinery.thing(ctx)?))
+            .bind(("date", date.0));
+
+        Unwrapper::unwrapper_option_without_error(query, 0, ctx).await
+    }
+
+    /// First reading at the date or after it
+    pub async fn select_first_reading_from(
+        machinery: &dyn ObjectWithThing,
+        date: DateTimeDerived,
+        db: &Db,
+        ctx: &dyn Ctx,
+    ) -> ApiResult<Option<MachineryStats>> {
+        let query = db
+            .query(format!(
+                "SELECT * FROM {RESOURCE} WHERE machinery = $machinery AND date >= $date ORDER BY date ASC LIMIT 1;"
+            ))
+            .bind(("machinery", machinery.thing(ctx)?))
+            .bind(("date", date.0));
+
+        Unwrapper::unwrapper_option_without_error(query, 0, ctx).await
+    }
+
+    pub async fn create(
+        ct_input: CreateMachineryStatsInput,
+        over_capacity: bool,
+        db: &Db,
+        ctx: &dyn Ctx,
+    ) -> ApiResult<MachineryStats> {
+        db.create(RESOURCE)
+            .content(Audited::new(
+                MachineryStats {
+                    id: None,
+                    date: ct_input.date,
+                    flow: ct_input.flow,
+                    units: ct_input.units,
+                    wearout: ct_input.wearout,
+                    machinery: ct_input.machinery,
+                    over_capacity,
+                },
+                ctx,
+            ))
+            .await
+            .map_err(ApiError::from(ctx))
+            .map(|v: Vec<MachineryStats>| {
+                v.into_iter().next().ok_or(ApiError {
+                    req_id: ctx.req_id(),
+                    error: Error::SurrealDbNoResult {
+                        source: "internal".to_string(),
+                        id: "Error while creating ".to_string(),
+                    },
+                })
+            })?
+    }
+
+    pub async fn update(
+        ct_input: CreateMachineryStatsInput,
+        over_capacity: bool,
+        id: Thing,
+        db: &Db,
+        ctx: &dyn Ctx,
+    ) -> ApiResult<MachineryStats> {
+        db.update((RESOURCE, id.id.to_string()))
+            .content(Audited::new(
+                MachineryStats {
+                    id: None,
+                    date: ct_input.date,
+                    flow: ct_input.flow,
+                    units: ct_input.units,
+                    wearout: ct_input.wearout,
+                    machinery: ct_input.machinery,
+                    over_capacity,
+                },
+                ctx,
+            ))
+            .await
+            .map_err(ApiError::from(ctx))?
+            .ok_or(ApiError {
+                req_id: ctx.req_id(),
+                error: Error::SurrealDbNoResult {
+                    source: "internal".to_string(),
+                    id: id.to_string(),
+                },
+            })
+    }
+
+    pub async fn delete(id: Thing, db: &Db, ctx: &dyn Ctx) -> ApiResult<MachineryStats> {
+        AuditLogRepository::delete(RESOURCE, id.id, db, ctx)
+            .await?
+            .ok_or(ApiError {
+                req_id: ctx.req_id(),
+                error: Error::SurrealDbNoResult {
+                    source: "internal".to_string(),
+                    id: "Error while deleting ".to_string(),
+                },
+            })
+    }
+}
+
+#[derive(Deserialize, InputObject, Clone, Serialize, Debug)]
+pub struct CreateMachineryStatsInput {
+    pub date: DateTimeDerived,
+    pub flow: Decimal,
+    pub units: ThingDerived,
+    pub wearout: Decimal,
+    pub machinery: ThingDerived,
+}
+
+pub struct MachineryStatsUseCases {
+    //pub db: &'a Db,
+    //pub ctx: &'a dyn Ctx,
+}
+
+impl MachineryStatsUseCases {
+    /// Whether the reading is above max_flow of the machinery type.
+    /// Error if such readings are rejected, see REJECT_OVER_CAPACITY
+    async fn over_capacity(
+        ct_input: &CreateMachineryStatsInput,
+        db: &Db,
+        ctx: &dyn Ctx,
+    ) -> ApiResult<bool> {
+        let machinery = MachineryUseCases::select_by_id(&ct_input.machinery, db, ctx).await?;
+        let machinery_type =
+            MachineryTypeUseCases::select_by_id(&machinery.machinery_type, db, ctx).await?;
+        let converter = MeasureUnitsUseCases::converter(db, ctx).await?;
+        // Readings in units that don't convert into the units of the type can't be compared
+        let flow =
+            match converter.convert(ct_input.flow, &ct_input.units, &machinery_type.units, ctx) {
+                Ok(flow) => flow,
+                Err(ApiError {
+                    error: Error::IncompatibleUnits { .. },
+                    ..
+                }) => return Ok(false),
+                Err(err) => return Err(err),
+            };
+        check_capacity(&ct_input.machinery, flow, machinery_type.max_flow, ctx)
+    }
+
+    /// Readings above max_flow are flagged with over_capacity or rejected
+    pub async fn create(
+        ct_input: CreateMachineryStatsInput,
+        db: &Db,
+        ctx: &dyn Ctx,
+    ) -> ApiResult<MachineryStats> {
+        let over_capacity = Self::over_capacity(&ct_input, db, ctx).await?;
+        MachineryStatsRepository::create(ct_input, over_capacity, db, ctx).await
+    }
+
+    pub async fn update(
+        ct_input: CreateMachineryStatsInput,
+        id: &dyn ObjectWithThing,
+        db: &Db,
+        ctx: &dyn Ctx,
+    ) -> ApiResult<MachineryStats> {
+        let over_capacity = Self::over_capacity(&ct_input, db, ctx).await?;
+        MachineryStatsRepository::update(ct_input, over_capacity, id.thing(ctx)?, db, ctx).await
+    }
+
+    pub async fn delete(
+        id: &dyn ObjectWithThing,
+        db: &Db,
+        ctx: &dyn Ctx,
+    ) -> ApiResult<MachineryStats> {
+        MachineryStatsRepository::delete(id.thing(ctx)?, db, ctx).await
+    }
+
+    pub async fn select_by_id(
+        id: &dyn ObjectWithThing,
+        db: &Db,
+        ctx: &dyn Ctx,
+    ) -> ApiResult<MachineryStats> {
+        MachineryStatsRepository::select_by_id(id, db, ctx).await
+    }
+
+    pub async fn select_by_machinery_and_date(
+        machinery: &dyn ObjectWithThing,
+        date: DateTimeDerived,
+        db: &Db,
+        ctx: &dyn Ctx,
+    ) -> ApiResult<Vec<MachineryStats>> {
+        MachineryStatsRepository::select_by_machinery_and_date(machinery, date, db, ctx).await
+    }
+
+    pub async fn select_by_machinery_and_range(
+        machinery: &dyn ObjectWithThing,
+        from: DateTimeDerived,
+        to: DateTimeDerived,
+        db: &Db,
+        ctx: &dyn Ctx,
+    ) -> ApiResult<Vec<MachineryStats>> {
+        MachineryStatsRepository::select_by_machinery_and_range(machinery, from, to, db, ctx).await
+    }
+
+    pub async fn select_last_reading_before(
+        machinery: &dyn ObjectWithThing,
+        date: DateTimeDerived,
+        db: &Db,
+        ctx: &dyn Ctx,
+    ) -> ApiResult<Option<MachineryStats>> {
+        MachineryStatsRepository::select_last_reading_before(machinery, date, db, ctx).await
+    }
+
+    pub async fn select_first_reading_from(
+        machinery: &dyn ObjectWithThing,
+        date: DateTimeDerived,
+        db: &Db,
+        ctx: &dyn Ctx,
+    ) -> ApiResult<Option<MachineryStats>> {
+        MachineryStatsRepository::select_first_reading_from(machinery, date, db, ctx).await
+    }
+
+    pub async fn count(db: &Db, ctx: &dyn Ctx) -> ApiResult<usize> {
+        MachineryStatsRepository::count(db, ctx).await
+    }
+
+    pub async fn list(
+        offset: Option<usize>,
+        limit: Option<usize>,
+        db: &Db,
+        ctx: &dyn Ctx,
+    ) -> ApiResult<Vec<MachineryStats>> {
+        let limit = limit.unwrap_or(10);
+        let offset = offset.unwrap_or(0);
+
+        MachineryStatsRepository::list(offset, limit, db, ctx).await
+    }
+}
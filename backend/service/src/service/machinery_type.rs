@@ -1,3 +1,4 @@
+use crate::audit_log::{AuditLogRepository, Audited};
 use crate::measure_units::MeasureUnitsUseCases;
 use crate::service::guard::RoleGuard;
 use crate::thing_derived::ThingDerived;
@@ -121,13 +122,16 @@ impl MachineryTypeRepository {
         ctx: &dyn Ctx,
     ) -> ApiResult<MachineryType> {
         db.create(RESOURCE)
-            .content(MachineryType {
-                id: None,
-                name,
-                max_flow,
-                wearout_max,
-                units,
-            })
+            .content(Audited::new(
+                MachineryType {
+                    id: None,
+                    name,
+                    max_flow,
+                    wearout_max,
+                    units,
+                },
+                ctx,
+            ))
             .await
             .map_err(ApiError::from(ctx))
             .map(|v: Vec<MachineryType>| {
@@ -148,7 +152,7 @@ impl MachineryTypeRepository {
         ctx: &dyn Ctx,
     ) -> ApiResult<MachineryType> {
         db.update((RESOURCE, id.id.to_string()))
-            .content(ct_input)
+            .content(Audited::new(ct_input, ctx))
             .await
             .map_err(ApiError::from(ctx))?
             .ok_or(ApiError {
@@ -161,9 +165,8 @@ impl MachineryTypeRepository {
     }
 
     pub async fn delete(id: Thing, db: &Db, ctx: &dyn Ctx) -> ApiResult<MachineryType> {
-        db.delete((RESOURCE, id))
-            .await
-            .map_err(ApiError::from(ctx))?
+        AuditLogRepository::delete(RESOURCE, id.id, db, ctx)
+            .await?
             .ok_or(ApiError {
                 req_id: ctx.req_id(),
                 error: Error::SurrealDbNoResult {
@@ -0,0 +1,578 @@
+use crate::common::Unwrapper;
+use crate::datetime::DateTimeDerived;
+use crate::maintenance_order::MaintenanceOrderUseCases;
+use crate::plant_topology::{PlantTopologyUseCases, TopologyNode, TopologyNodeKind};
+use crate::service::guard::RoleGuard;
+use crate::thing_derived::ThingDerived;
+use crate::thing_wrapper::ObjectWithThing;
+use async_graphql::{Enum, InputObject, SimpleObject};
+use chrono::{DateTime, Duration, Utc};
+use common::{
+    ctx::Ctx,
+    error::{ApiError, Error},
+    role::Role,
+    ApiResult,
+};
+
+use db::Db;
+use rust_decimal::prelude::ToPrimitive;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// Days of readings the wear rate is taken from
+pub const DEFAULT_RATE_WINDOW_DAYS: i64 = 30;
+/// Objects expected to reach wearout_max within this many days are due
+pub const DEFAULT_HORIZON_DAYS: i64 = 14;
+/// Share of wearout_max after which objects are due regardless of the rate
+pub const DEFAULT_WARNING_RATIO: Decimal = Decimal::from_parts(9, 0, 0, false, 1);
+
+#[derive(InputObject, Clone, Debug, Default, Serialize, Deserialize)]
+pub struct MaintenanceOptions {
+    /// Moment the state is computed for. Now by default
+    pub at: Option<DateTimeDerived>,
+    /// DEFAULT_RATE_WINDOW_DAYS by default
+    pub rate_window_days: Option<i64>,
+    /// DEFAULT_HORIZON_DAYS by default
+    pub horizon_days: Option<i64>,
+    /// DEFAULT_WARNING_RATIO by default
+    pub warning_ratio: Option<Decimal>,
+}
+
+impl MaintenanceOptions {
+    pub fn at(&self) -> DateTime<Utc> {
+        self.at.as_ref().map(|at| at.0 .0).unwrap_or_else(Utc::now)
+    }
+
+    pub fn rate_window_days(&self) -> i64 {
+        self.rate_window_days.unwrap_or(DEFAULT_RATE_WINDOW_DAYS)
+    }
+
+    pub fn horizon_days(&self) -> i64 {
+        self.horizon_days.unwrap_or(DEFAULT_HORIZON_DAYS)
+    }
+
+    pub fn warning_ratio(&self) -> Decimal {
+        self.warning_ratio.unwrap_or(DEFAULT_WARNING_RATIO)
+    }
+}
+
+/// Sum of `wearout` of the readings of one object
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct WearSum {
+    pub object: Option<ThingDerived>,
+    pub wear: Decimal,
+    pub last_reading: Option<DateTimeDerived>,
+}
+
+#[derive(Clone, Debug, SimpleObject)]
+#[graphql(guard = "RoleGuard::new(Role::User)")]
+pub struct WearStatus {
+    /// Pipe or Machinery
+    pub object: ThingDerived,
+    pub kind: TopologyNodeKind,
+    pub name: String,
+    pub wearout_max: Decimal,
+    /// Completion of the last maintenance. Wear is counted from it
+    pub wear_since: Option<DateTimeDerived>,
+    /// Sum of `wearout` of the readings since the last maintenance
+    pub cumulative_wear: Decimal,
+    /// cumulative_wear / wearout_max. None if wearout_max is zero
+    pub wear_ratio: Option<Decimal>,
+    /// wearout_max - cumulative_wear
+    pub remaining: Decimal,
+    /// Average wear per day over the rate window
+    pub rate_per_day: Decimal,
+    /// None if nothing wears the object out
+    pub days_left: Option<Decimal>,
+    /// When wearout_max is expected to be reached
+    pub projected_at: Option<DateTimeDerived>,
+    pub last_reading: Option<DateTimeDerived>,
+    /// wearout_max is reached already
+    pub overdue: bool,
+    /// Overdue, worn over the warning ratio or projected within the horizon
+    pub due: bool,
+}
+
+#[derive(Enum, Copy, Clone, Debug, Eq, PartialEq)]
+pub enum MaintenanceAlertLevel {
+    /// Due for maintenance
+    Warning,
+    /// wearout_max is reached
+    Critical,
+}
+
+#[derive(Clone, Debug, PartialEq, SimpleObject)]
+#[graphql(guard = "RoleGuard::new(Role::User)")]
+pub struct MaintenanceAlert {
+    pub object: ThingDerived,
+    pub kind: TopologyNodeKind,
+    pub name: String,
+    pub level: MaintenanceAlertLevel,
+    pub message: String,
+}
+
+impl WearStatus {
+    /// `total` covers the readings since `wear_since`, `recent` only the rate window before `at`
+    pub fn new(
+        node: &TopologyNode,
+        wear_since: Option<&DateTimeDerived>,
+        total: &WearSum,
+        recent: &WearSum,
+        at: DateTime<Utc>,
+        options: &MaintenanceOptions,
+    ) -> Self {
+        let cumulative_wear = total.wear;
+        let remaining = node.wearout_max - cumulative_wear;
+        let wear_ratio = (!node.wearout_max.is_zero()).then(|| cumulative_wear / node.wearout_max);
+        let window = options.rate_window_days();
+        let rate_per_day = if window > 0 {
+            recent.wear / Decimal::from(window)
+        } else {
+            Decimal::ZERO
+        };
+        let overdue = !node.wearout_max.is_zero() && remaining <= Decimal::ZERO;
+        let days_left = if overdue {
+            Some(Decimal::ZERO)
+        } else if rate_per_day > Decimal::ZERO {
+            Some((remaining / rate_per_day).round_dp(2))
+        } else {
+            None
+        };
+        let projected_at = days_left.and_then(|days| {
+            (days * Decimal::from(86400))
+                .to_i64()
+                .map(|seconds| (at + Duration::seconds(seconds)).into())
+        });
+        let due = overdue
+            || wear_ratio.is_some_and(|ratio| ratio >= options.warning_ratio())
+            || days_left.is_some_and(|days| days <= Decimal::from(options.horizon_days()));
+        Self {
+            object: node.id.clone(),
+            kind: node.kind,
+            name: node.name.clone(),
+            wearout_max: node.wearout_max,
+            wear_since: wear_since.cloned(),
+            cumulative_wear,
+            wear_ratio,
+            remaining,
+            rate_per_day,
+            days_left,
+            projected_at,
+            last_reading: total.last_reading.clone(),
+            overdue,
+            due,
+        }
+    }
+
+    pub fn alert(&self) -> Option<MaintenanceAlert> {
+        let (level, message) = if self.overdue {
+            (
+                MaintenanceAlertLevel::Critical,
+                format!(
+                    "{} has reached its wearout limit: {} of {}",
+                    self.name, self.cumulative_wear, self.wearout_max
+                ),
+            )
+        } else if self.due {
+            let message = match &self.days_left {
+                Some(days) => format!(
+                    "{} is expected to reach its wearout limit in {} days",
+                    self.name, days
+                ),
+                None => format!(
+                    "{} is worn out by {}%",
+                    self.name,
+                    (self.wear_ratio.unwrap_or_default() * Decimal::ONE_HUNDRED).round_dp(2)
+                ),
+            };
+            (MaintenanceAlertLevel::Warning, message)
+        } else {
+            return None;
+        };
+        Some(MaintenanceAlert {
+            object: self.object.clone(),
+            kind: self.kind,
+            name: self.name.clone(),
+            level,
+            message,
+        })
+    }
+}
+
+pub struct MaintenanceRepository {}
+
+impl MaintenanceRepository {
+    /// Wear per object of the stats table over [from, to). From the first reading if `from` is None.
+    /// All objects of the kind if `object` is None
+    pub async fn select_wear(
+        kind: TopologyNodeKind,
+        object: Option<ThingDerived>,
+        from: Option<DateTimeDerived>,
+        to: DateTimeDerived,
+        db: &Db,
+        ctx: &dyn Ctx,
+    ) -> ApiResult<Vec<WearSum>> {
+        let (table, field) = match kind {
+            TopologyNodeKind::Pipe => ("PipeStats", "pipe"),
+            TopologyNodeKind::Machinery => ("MachineryStats", "machinery"),
+        };
+        let query = db
+            .query(format!(
+                "SELECT {field} AS object, math::sum(wearout) AS wear, \
+                 time::max(date) AS last_reading FROM {table} \
+                 WHERE ($object = NONE OR {field} = $object) \
+                 AND ($from = NONE OR date >= $from) AND date < $to GROUP BY object;"
+            ))
+            .bind((
+                "object",
+                object.map(|object| object.thing(ctx)).transpose()?,
+            ))
+            .bind(("from", from.map(|from| from.0)))
+            .bind(("to", to.0));
+        Unwrapper::unwrapper_vec(query, 0, ctx).await
+    }
+}
+
+pub struct MaintenanceUseCases {}
+
+impl MaintenanceUseCases {
+    async fn wear(
+        kind: TopologyNodeKind,
+        from: Option<DateTime<Utc>>,
+        to: DateTime<Utc>,
+        db: &Db,
+        ctx: &dyn Ctx,
+    ) -> ApiResult<HashMap<String, WearSum>> {
+        Ok(
+            MaintenanceRepository::select_wear(
+                kind,
+                None,
+                from.map(Into::into),
+                to.into(),
+                db,
+                ctx,
+            )
+            .await?
+            .into_iter()
+            .filter_map(|sum| Some((sum.object.as_ref()?.to_string(), sum)))
+            .collect(),
+        )
+    }
+
+    /// Wear of the object since its last maintenance
+    async fn wear_since(
+        node: &TopologyNode,
+        since: &DateTimeDerived,
+        to: DateTime<Utc>,
+        db: &Db,
+        ctx: &dyn Ctx,
+    ) -> ApiResult<WearSum> {
+        Ok(MaintenanceRepository::select_wear(
+            node.kind,
+            Some(node.id.clone()),
+            Some(since.clone()),
+            to.into(),
+            db,
+            ctx,
+        )
+        .await?
+        .into_iter()
+        .next()
+        .unwrap_or_default())
+    }
+
+    /// Wear of every pipe and machinery of the plant.
+    /// Wear is reset by completion of a maintenance order
+    pub async fn statuses(
+        options: &MaintenanceOptions,
+        db: &Db,
+        ctx: &dyn Ctx,
+    ) -> ApiResult<Vec<WearStatus>> {
+        let at = options.at();
+        let window_start = at - Duration::days(options.rate_window_days());
+        let topology = PlantTopologyUseCases::get(db, ctx).await?;
+        let maintained: HashMap<String, DateTimeDerived> =
+            MaintenanceOrderUseCases::select_last_completed(at.into(), db, ctx)
+                .await?
+                .into_iter()
+                .map(|last| (last.object.to_string(), last.completed_at))
+                .collect();
+
+        let mut statuses = vec![];
+        for kind in [TopologyNodeKind::Machinery, TopologyNodeKind::Pipe] {
+            let total = Self::wear(kind, None, at, db, ctx).await?;
+            let recent = Self::wear(kind, Some(window_start), at, db, ctx).await?;
+            for node in topology.nodes.iter().filter(|node| node.kind == kind) {
+                let key = node.id.to_string();
+                let wear_since = maintained.get(&key);
+                let since_maintenance = match wear_since {
+                    Some(since) => Some(Self::wear_since(node, since, at, db, ctx).await?),
+                    None => None,
+                };
+                statuses.push(WearStatus::new(
+                    node,
+                    wear_since,
+                    since_maintenance
+                        .as_ref()
+                        .or(total.get(&key))
+                        .unwrap_or(&WearSum::default()),
+                    recent.get(&key).unwrap_or(&WearSum::default()),
+                    at,
+                    options,
+                ));
+            }
+        }
+        Ok(statuses)
+    }
+
+    pub async fn status(
+        object: &dyn ObjectWithThing,
+        options: &MaintenanceOptions,
+        db: &Db,
+        ctx: &dyn Ctx,
+    ) -> ApiResult<WearStatus> {
+        let object: ThingDerived = object.thing(ctx)?.into();
+        Self::statuses(options, db, ctx)
+            .await?
+            .into_iter()
+            .find(|status| status.object == object)
+            .ok_or(ApiError {
+                req_id: ctx.req_id(),
+                error: Error::Generic {
+                    description: format!("{object} is neither pipe nor machinery"),
+                },
+            })
+    }
+
+    /// Due objects, the ones closest to their wearout limit first
+    pub async fn due(
+        options: &MaintenanceOptions,
+        db: &Db,
+        ctx: &dyn Ctx,
+    ) -> ApiResult<Vec<WearStatus>> {
+        let mut due: Vec<WearStatus> = Self::statuses(options, db, ctx)
+            .await?
+            .into_iter()
+            .filter(|status| status.due)
+            .collect();
+        due.sort_by(|a, b| match (a.days_left, b.days_left) {
+            (Some(a), Some(b)) => a.cmp(&b),
+            (Some(_), None) => std::cmp::Ordering::Less,
+            (None, Some(_)) => std::cmp::Ordering::Greater,
+            (None, None) => b.wear_ratio.cmp(&a.wear_ratio),
+        });
+        Ok(due)
+    }
+
+    /// Critical alerts first
+    pub async fn alerts(
+        options: &MaintenanceOptions,
+        db: &Db,
+        ctx: &dyn Ctx,
+    ) -> ApiResult<Vec<MaintenanceAlert>> {
+        let mut alerts: Vec<MaintenanceAlert> = Self::due(options, db, ctx)
+            .await?
+            .iter()
+            .filter_map(WearStatus::alert)
+            .collect();
+        alerts.sort_by_key(|alert| alert.level != MaintenanceAlertLevel::Critical);
+        Ok(alerts)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::machinery_stats::{CreateMachineryStatsInput, MachineryStatsUseCases};
+    use crate::maintenance_order::{
+        CreateMaintenanceOrderInput, MaintenanceKind, MaintenanceOrderStatus,
+    };
+    use crate::prod_populate::{
+        machinery_shortcut, machinery_type_shortcut, measure_units_shortcut,
+    };
+    use chrono::TimeZone;
+    use common::ctx::MockCtx;
+    use db::set_test_db;
+    use rstest::*;
+    use surrealdb::sql::Thing;
+
+    #[fixture]
+    fn ctx() -> MockCtx {
+        let mut ctx = MockCtx::new();
+        ctx.expect_req_id().return_const(uuid::Uuid::new_v4());
+        ctx.expect_user_id_thing().returning(|| {
+            Err(ApiError {
+                req_id: uuid::Uuid::new_v4(),
+                error: Error::Generic {
+                    description: "No user".to_string(),
+                },
+            })
+        });
+        ctx
+    }
+
+    #[fixture]
+    async fn tdb() -> Db {
+        set_test_db().await
+    }
+
+    fn at(day: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2024, 1, day, 0, 0, 0).unwrap()
+    }
+
+    fn thing(tb: &str, id: &str) -> ThingDerived {
+        Thing::from((tb, id)).into()
+    }
+
+    fn node(wearout_max: i64) -> TopologyNode {
+        TopologyNode {
+            id: thing("Machinery", "filter"),
+            kind: TopologyNodeKind::Machinery,
+            name: "Фильтр № 1".to_string(),
+            node_type: thing("MachineryType", "filter"),
+            type_name: "Фильтр".to_string(),
+            max_flow: Decimal::from(100),
+            wearout_max: Decimal::from(wearout_max),
+            units: thing("MeasureUnits", "m3"),
+            units_name: "м^3".to_string(),
+            material: None,
+        }
+    }
+
+    fn wear(wear: i64) -> WearSum {
+        WearSum {
+            object: Some(thing("Machinery", "filter")),
+            wear: Decimal::from(wear),
+            last_reading: Some(at(1).into()),
+        }
+    }
+
+    #[test]
+    fn wear_status_test() {
+        let options = MaintenanceOptions {
+            rate_window_days: Some(10),
+            ..Default::default()
+        };
+        // 10 per day, 500 left
+        let status = WearStatus::new(&node(1000), None, &wear(500), &wear(100), at(1), &options);
+        assert_eq!(status.rate_per_day, Decimal::from(10));
+        assert_eq!(status.days_left, Some(Decimal::from(50)));
+        assert_eq!(
+            status.projected_at,
+            Some(at(1).checked_add_signed(Duration::days(50)).unwrap().into())
+        );
+        assert!(!status.due);
+        assert!(status.alert().is_none());
+
+        // 100 per day, 500 left
+        let soon = WearStatus::new(&node(1000), None, &wear(500), &wear(1000), at(1), &options);
+        assert_eq!(soon.days_left, Some(Decimal::from(5)));
+        assert!(soon.due);
+        assert_eq!(soon.alert().unwrap().level, MaintenanceAlertLevel::Warning);
+
+        // Nothing wears it out lately, but it is worn over the warning ratio
+        let worn = WearStatus::new(&node(1000), None, &wear(950), &wear(0), at(1), &options);
+        assert_eq!(worn.days_left, None);
+        assert!(worn.due);
+
+        let overdue = WearStatus::new(&node(1000), None, &wear(1000), &wear(0), at(1), &options);
+        assert!(overdue.overdue);
+        assert_eq!(overdue.days_left, Some(Decimal::ZERO));
+        assert_eq!(
+            overdue.alert().unwrap().level,
+            MaintenanceAlertLevel::Critical
+        );
+
+        // No limit set
+        let unlimited = WearStatus::new(&node(0), None, &wear(1000), &wear(0), at(1), &options);
+        assert_eq!(unlimited.wear_ratio, None);
+        assert!(!unlimited.due);
+    }
+
+    #[rstest]
+    #[tokio::test]
+    #[awt]
+    async fn statuses_test(mut ctx: MockCtx, #[future] tdb: Db) {
+        ctx.expect_user_id_thing()
+            .returning(|| Ok(Thing::from(("User", "mechanic"))));
+        let units = measure_units_shortcut("м^3", &tdb, &ctx).await.unwrap();
+        let machinery_type = machinery_type_shortcut("Фильтр", &units, &tdb, &ctx)
+            .await
+            .unwrap();
+        let machinery = machinery_shortcut("Фильтр № 1", &machinery_type, &tdb, &ctx)
+            .await
+            .unwrap();
+        for (day, wearout) in [(1, 100), (20, 50), (25, 50)] {
+            MachineryStatsUseCases::create(
+                CreateMachineryStatsInput {
+                    date: at(day).into(),
+                    flow: Decimal::ONE,
+                    units: units.id.clone().unwrap(),
+                    wearout: Decimal::from(wearout),
+                    machinery: machinery.id.clone().unwrap(),
+                },
+                &tdb,
+                &ctx,
+            )
+            .await
+            .unwrap();
+        }
+        let options = MaintenanceOptions {
+            at: Some(at(31).into()),
+            rate_window_days: Some(20),
+            ..Default::default()
+        };
+        let status = MaintenanceUseCases::status(&machinery, &options, &tdb, &ctx)
+            .await
+            .unwrap();
+        assert_eq!(status.cumulative_wear, Decimal::from(200));
+        // 100 over the last 20 days
+        assert_eq!(status.rate_per_day, Decimal::from(5));
+        assert_eq!(status.last_reading, Some(at(25).into()));
+
+        let order = MaintenanceOrderUseCases::create(
+            CreateMaintenanceOrderInput {
+                object: machinery.id.clone().unwrap(),
+                kind: MaintenanceKind::Planned,
+                reason: "Filter replacement".to_string(),
+                planned_at: Some(at(20).into()),
+                started_at: None,
+                completed_at: None,
+                performed_by: None,
+            },
+            &tdb,
+            &ctx,
+        )
+        .await
+        .unwrap();
+        assert_eq!(order.status, MaintenanceOrderStatus::Open);
+        let order =
+            MaintenanceOrderUseCases::complete(&order, Some(at(20).into()), None, &tdb, &ctx)
+                .await
+                .unwrap();
+        assert_eq!(order.status, MaintenanceOrderStatus::Completed);
+        assert!(MaintenanceOrderUseCases::cancel(&order, &tdb, &ctx)
+            .await
+            .is_err());
+
+        // Wear is counted from the completion, the rate still covers the whole window
+        let status = MaintenanceUseCases::status(&machinery, &options, &tdb, &ctx)
+            .await
+            .unwrap();
+        assert_eq!(status.wear_since, Some(at(20).into()));
+        assert_eq!(status.cumulative_wear, Decimal::from(100));
+        assert_eq!(status.rate_per_day, Decimal::from(5));
+
+        // Maintenance completed later doesn't affect the past state
+        let before = MaintenanceOptions {
+            at: Some(at(10).into()),
+            ..options
+        };
+        let status = MaintenanceUseCases::status(&machinery, &before, &tdb, &ctx)
+            .await
+            .unwrap();
+        assert_eq!(status.wear_since, None);
+        assert_eq!(status.cumulative_wear, Decimal::from(100));
+    }
+}
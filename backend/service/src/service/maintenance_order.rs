@@ -0,0 +1,357 @@
+use crate::audit_log::{AuditLogRepository, Audited};
+use crate::common::Unwrapper;
+use crate::datetime::DateTimeDerived;
+use crate::service::guard::RoleGuard;
+use crate::thing_derived::ThingDerived;
+use crate::thing_wrapper::ObjectWithThing;
+use async_graphql::{Enum, InputObject, SimpleObject};
+use chrono::Utc;
+use common::{
+    ctx::Ctx,
+    error::{ApiError, Error},
+    role::Role,
+    ApiResult,
+};
+
+use db::Db;
+use serde::{Deserialize, Serialize};
+use surrealdb::sql::Thing;
+
+#[allow(dead_code)]
+const RESOURCE: &str = "MaintenanceOrder";
+
+/// Kind of maintenance work and of downtime
+#[derive(Enum, Copy, Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub enum MaintenanceKind {
+    Planned,
+    Unplanned,
+}
+
+#[derive(Enum, Copy, Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub enum MaintenanceOrderStatus {
+    Open,
+    /// Wear of the object is counted from completed_at
+    Completed,
+    Cancelled,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, SimpleObject)]
+#[graphql(guard = "RoleGuard::new(Role::User)")]
+pub struct MaintenanceOrder {
+    pub id: Option<ThingDerived>,
+    /// Pipe or Machinery
+    pub object: ThingDerived,
+    pub kind: MaintenanceKind,
+    pub status: MaintenanceOrderStatus,
+    pub reason: String,
+    pub planned_at: Option<DateTimeDerived>,
+    pub started_at: Option<DateTimeDerived>,
+    pub completed_at: Option<DateTimeDerived>,
+    /// User who completed the work
+    pub performed_by: Option<ThingDerived>,
+    pub created_at: Option<DateTimeDerived>,
+}
+
+impl ObjectWithThing for MaintenanceOrder {
+    fn thing(&self, ctx: &dyn Ctx) -> ApiResult<Thing> {
+        self.id
+            .as_ref()
+            .ok_or(ApiError {
+                req_id: ctx.req_id(),
+                error: Error::Generic {
+                    description: "Can't get thing. Get none instead".to_string(),
+                },
+            })?
+            .thing(ctx)
+    }
+}
+
+/// Completion of the last maintenance of the object
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct LastMaintenance {
+    pub object: ThingDerived,
+    pub completed_at: DateTimeDerived,
+}
+
+pub struct MaintenanceOrderRepository {}
+
+impl MaintenanceOrderRepository {
+    pub async fn list(
+        offset: usize,
+        limit: usize,
+        object: Option<ThingDerived>,
+        db: &Db,
+        ctx: &dyn Ctx,
+    ) -> ApiResult<Vec<MaintenanceOrder>> {
+        let query = db
+            .query(format!(
+                "SELECT * FROM {RESOURCE} WHERE $object = NONE OR object = $object \
+                 ORDER BY created_at DESC LIMIT {limit} START {offset};"
+            ))
+            .bind((
+                "object",
+                object.map(|object| object.thing(ctx)).transpose()?,
+            ));
+        Unwrapper::unwrapper_vec(query, 0, ctx).await
+    }
+
+    pub async fn count(db: &Db, ctx: &dyn Ctx) -> ApiResult<usize> {
+        let query = db.query(format!("(SELECT count() FROM {RESOURCE} GROUP ALL).count"));
+        Unwrapper::unwrapper_option_without_error(query, 0, ctx)
+            .await
+            .map(|count| count.unwrap_or(0))
+    }
+
+    pub async fn select_by_id(
+        id: &dyn ObjectWithThing,
+        db: &Db,
+        ctx: &dyn Ctx,
+    ) -> ApiResult<MaintenanceOrder> {
+        let thing_id = id.thing(ctx)?;
+        if thing_id.tb != RESOURCE {
+            return Err(ApiError {
+                error: Error::Generic {
+                    description: "Wrong table name in select_by_id".to_string(),
+                },
+                req_id: ctx.req_id(),
+            });
+        }
+        let query = db
+            .query("SELECT * FROM $thing_id".to_string())
+            .bind(("thing_id", thing_id));
+
+        Unwrapper::unwrapper_option(query, 0, "Can't get maintenance order by id", ctx).await
+    }
+
+    /// Last completion not later than `at` of every maintained object
+    pub async fn select_last_completed(
+        at: DateTimeDerived,
+        db: &Db,
+        ctx: &dyn Ctx,
+    ) -> ApiResult<Vec<LastMaintenance>> {
+        let query = db
+            .query(format!(
+                "SELECT object, time::max(completed_at) AS completed_at FROM {RESOURCE} \
+                 WHERE status = \"Completed\" AND completed_at <= $at GROUP BY object;"
+            ))
+            .bind(("at", at.0));
+        Unwrapper::unwrapper_vec(query, 0, ctx).await
+    }
+
+    pub async fn create(
+        order: MaintenanceOrder,
+        db: &Db,
+        ctx: &dyn Ctx,
+    ) -> ApiResult<MaintenanceOrder> {
+        db.create(RESOURCE)
+            .content(Audited::new(order, ctx))
+            .await
+            .map_err(ApiError::from(ctx))
+            .map(|v: Vec<MaintenanceOrder>| {
+                v.into_iter().next().ok_or(ApiError {
+                    req_id: ctx.req_id(),
+                    error: Error::SurrealDbNoResult {
+                        source: "internal".to_string(),
+                        id: "Error while creating ".to_string(),
+                    },
+                })
+            })?
+    }
+
+    pub async fn update(
+        order: MaintenanceOrder,
+        id: Thing,
+        db: &Db,
+        ctx: &dyn Ctx,
+    ) -> ApiResult<MaintenanceOrder> {
+        db.update((RESOURCE, id.id.to_string()))
+            .content(Audited::new(MaintenanceOrder { id: None, ..order }, ctx))
+            .await
+            .map_err(ApiError::from(ctx))?
+            .ok_or(ApiError {
+                req_id: ctx.req_id(),
+                error: Error::SurrealDbNoResult {
+                    source: "internal".to_string(),
+                    id: id.to_string(),
+                },
+            })
+    }
+
+    pub async fn delete(id: Thing, db: &Db, ctx: &dyn Ctx) -> ApiResult<MaintenanceOrder> {
+        AuditLogRepository::delete(RESOURCE, id.id, db, ctx)
+            .await?
+            .ok_or(ApiError {
+                req_id: ctx.req_id(),
+                error: Error::SurrealDbNoResult {
+                    source: "internal".to_string(),
+                    id: "Error while deleting ".to_string(),
+                },
+            })
+    }
+}
+
+#[derive(Deserialize, InputObject, Clone, Serialize, Debug)]
+pub struct CreateMaintenanceOrderInput {
+    /// Pipe or Machinery
+    pub object: ThingDerived,
+    pub kind: MaintenanceKind,
+    pub reason: String,
+    pub planned_at: Option<DateTimeDerived>,
+    pub started_at: Option<DateTimeDerived>,
+    /// Order is created completed if set
+    pub completed_at: Option<DateTimeDerived>,
+    /// Current user by default if the order is completed
+    pub performed_by: Option<ThingDerived>,
+}
+
+pub struct MaintenanceOrderUseCases {}
+
+impl MaintenanceOrderUseCases {
+    /// Explicit user or the current one. None if there is no user in the context
+    fn performer(performed_by: Option<ThingDerived>, ctx: &dyn Ctx) -> Option<ThingDerived> {
+        performed_by.or_else(|| ctx.user_id_thing().ok().map(Into::into))
+    }
+
+    fn order(
+        ct_input: CreateMaintenanceOrderInput,
+        status: MaintenanceOrderStatus,
+        created_at: Option<DateTimeDerived>,
+        ctx: &dyn Ctx,
+    ) -> MaintenanceOrder {
+        let status = match ct_input.completed_at {
+            Some(_) => MaintenanceOrderStatus::Completed,
+            None => status,
+        };
+        let performed_by = match status {
+            MaintenanceOrderStatus::Completed => Self::performer(ct_input.performed_by, ctx),
+            _ => ct_input.performed_by,
+        };
+        MaintenanceOrder {
+            id: None,
+            object: ct_input.object,
+            kind: ct_input.kind,
+            status,
+            reason: ct_input.reason,
+            planned_at: ct_input.planned_at,
+            started_at: ct_input.started_at,
+            completed_at: ct_input.completed_at,
+            performed_by,
+            created_at,
+        }
+    }
+
+    fn wrong_status(order: &MaintenanceOrder, action: &str, ctx: &dyn Ctx) -> ApiError {
+        ApiError {
+            req_id: ctx.req_id(),
+            error: Error::Generic {
+                description: format!(
+                    "Can't {action} maintenance order in {:?} status",
+                    order.status
+                ),
+            },
+        }
+    }
+
+    pub async fn create(
+        ct_input: CreateMaintenanceOrderInput,
+        db: &Db,
+        ctx: &dyn Ctx,
+    ) -> ApiResult<MaintenanceOrder> {
+        let order = Self::order(ct_input, MaintenanceOrderStatus::Open, None, ctx);
+        MaintenanceOrderRepository::create(order, db, ctx).await
+    }
+
+    /// Cancelled order stays cancelled unless completed_at is set
+    pub async fn update(
+        ct_input: CreateMaintenanceOrderInput,
+        id: &dyn ObjectWithThing,
+        db: &Db,
+        ctx: &dyn Ctx,
+    ) -> ApiResult<MaintenanceOrder> {
+        let current = MaintenanceOrderRepository::select_by_id(id, db, ctx).await?;
+        let status = match current.status {
+            MaintenanceOrderStatus::Cancelled => MaintenanceOrderStatus::Cancelled,
+            _ => MaintenanceOrderStatus::Open,
+        };
+        let order = Self::order(ct_input, status, current.created_at, ctx);
+        MaintenanceOrderRepository::update(order, id.thing(ctx)?, db, ctx).await
+    }
+
+    /// Completes the order now by default. Wear of the object is reset from completed_at
+    pub async fn complete(
+        id: &dyn ObjectWithThing,
+        completed_at: Option<DateTimeDerived>,
+        performed_by: Option<ThingDerived>,
+        db: &Db,
+        ctx: &dyn Ctx,
+    ) -> ApiResult<MaintenanceOrder> {
+        let order = MaintenanceOrderRepository::select_by_id(id, db, ctx).await?;
+        if order.status == MaintenanceOrderStatus::Cancelled {
+            return Err(Self::wrong_status(&order, "complete", ctx));
+        }
+        let order = MaintenanceOrder {
+            status: MaintenanceOrderStatus::Completed,
+            completed_at: Some(completed_at.unwrap_or_else(|| Utc::now().into())),
+            performed_by: Self::performer(performed_by, ctx),
+            ..order
+        };
+        MaintenanceOrderRepository::update(order, id.thing(ctx)?, db, ctx).await
+    }
+
+    pub async fn cancel(
+        id: &dyn ObjectWithThing,
+        db: &Db,
+        ctx: &dyn Ctx,
+    ) -> ApiResult<MaintenanceOrder> {
+        let order = MaintenanceOrderRepository::select_by_id(id, db, ctx).await?;
+        if order.status == MaintenanceOrderStatus::Completed {
+            return Err(Self::wrong_status(&order, "cancel", ctx));
+        }
+        let order = MaintenanceOrder {
+            status: MaintenanceOrderStatus::Cancelled,
+            ..order
+        };
+        MaintenanceOrderRepository::update(order, id.thing(ctx)?, db, ctx).await
+    }
+
+    pub async fn delete(
+        id: &dyn ObjectWithThing,
+        db: &Db,
+        ctx: &dyn Ctx,
+    ) -> ApiResult<MaintenanceOrder> {
+        MaintenanceOrderRepository::delete(id.thing(ctx)?, db, ctx).await
+    }
+
+    pub async fn select_by_id(
+        id: &dyn ObjectWithThing,
+        db: &Db,
+        ctx: &dyn Ctx,
+    ) -> ApiResult<MaintenanceOrder> {
+        MaintenanceOrderRepository::select_by_id(id, db, ctx).await
+    }
+
+    pub async fn select_last_completed(
+        at: DateTimeDerived,
+        db: &Db,
+        ctx: &dyn Ctx,
+    ) -> ApiResult<Vec<LastMaintenance>> {
+        MaintenanceOrderRepository::select_last_completed(at, db, ctx).await
+    }
+
+    pub async fn count(db: &Db, ctx: &dyn Ctx) -> ApiResult<usize> {
+        MaintenanceOrderRepository::count(db, ctx).await
+    }
+
+    pub async fn list(
+        offset: Option<usize>,
+        limit: Option<usize>,
+        object: Option<ThingDerived>,
+        db: &Db,
+        ctx: &dyn Ctx,
+    ) -> ApiResult<Vec<MaintenanceOrder>> {
+        let limit = limit.unwrap_or(10);
+        let offset = offset.unwrap_or(0);
+
+        MaintenanceOrderRepository::list(offset, limit, object, db, ctx).await
+    }
+}
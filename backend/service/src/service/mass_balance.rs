@@ -0,0 +1,347 @@
+use crate::datetime::DateTimeDerived;
+use crate::flow_integration::{FlowIntegrationOptions, FlowIntegrationUseCases};
+use crate::measure_units::{MeasureUnitsUseCases, UnitConverter};
+use crate::plant_topology::{
+    PlantTopology, PlantTopologyUseCases, TopologyEdgeKind, TopologyNode, TopologyNodeKind,
+};
+use crate::service::guard::RoleGuard;
+use crate::thing_derived::ThingDerived;
+use async_graphql::SimpleObject;
+use common::{
+    ctx::Ctx,
+    error::{ApiError, Error},
+    role::Role,
+    ApiResult,
+};
+
+use db::Db;
+use rust_decimal::Decimal;
+
+/// Share of inbound volume that may be lost (or gained) without flagging
+pub const DEFAULT_TOLERANCE: Decimal = Decimal::from_parts(5, 0, 0, false, 2);
+
+/// Volume that went through the pipe during the window
+#[derive(Clone, Debug, SimpleObject)]
+#[graphql(guard = "RoleGuard::new(Role::User)")]
+pub struct PipeVolume {
+    pub pipe: ThingDerived,
+    pub name: String,
+    pub units: ThingDerived,
+    pub units_name: String,
+    pub volume: Decimal,
+    /// Number of readings inside the window
+    pub readings: usize,
+    /// False if the pipe has no readings inside or before the window
+    pub has_data: bool,
+}
+
+#[derive(Clone, Debug, SimpleObject)]
+#[graphql(guard = "RoleGuard::new(Role::User)")]
+pub struct MachineryBalance {
+    pub machinery: ThingDerived,
+    pub name: String,
+    /// Units of inbound, outbound and loss: units of the first pipe
+    pub units: Option<ThingDerived>,
+    pub inbound: Decimal,
+    pub outbound: Decimal,
+    /// inbound - outbound
+    pub loss: Decimal,
+    /// loss / inbound. None if nothing came in
+    pub loss_ratio: Option<Decimal>,
+    /// False if units of some pipes don't convert into each other.
+    /// Such machinery is never flagged
+    pub comparable: bool,
+    /// False if some of the pipes have no readings.
+    /// Such machinery is never flagged
+    pub complete: bool,
+    pub imbalanced: bool,
+    pub input_pipes: Vec<PipeVolume>,
+    pub output_pipes: Vec<PipeVolume>,
+}
+
+#[derive(Clone, Debug, SimpleObject)]
+#[graphql(guard = "RoleGuard::new(Role::User)")]
+pub struct MassBalanceReport {
+    pub from: DateTimeDerived,
+    pub to: DateTimeDerived,
+    pub tolerance: Decimal,
+    /// In topological order when the line has no cycles
+    pub machinery: Vec<MachineryBalance>,
+}
+
+impl MachineryBalance {
+    pub fn new(
+        machinery: &TopologyNode,
+        input_pipes: Vec<PipeVolume>,
+        output_pipes: Vec<PipeVolume>,
+        tolerance: Decimal,
+        converter: &UnitConverter,
+        ctx: &dyn Ctx,
+    ) -> ApiResult<Self> {
+        let units = input_pipes
+            .first()
+            .or(output_pipes.first())
+            .map(|pipe| pipe.units.clone());
+        let mut comparable = true;
+        let mut total = |pipes: &[PipeVolume]| -> ApiResult<Decimal> {
+            let mut total = Decimal::ZERO;
+            for pipe in pipes.iter() {
+                let Some(units) = &units else {
+                    continue;
+                };
+                total += match converter.convert(pipe.volume, &pipe.units, units, ctx) {
+                    Ok(volume) => volume,
+                    Err(ApiError {
+                        error: Error::IncompatibleUnits { .. },
+                        ..
+                    }) => {
+                        comparable = false;
+                        pipe.volume
+                    }
+                    Err(err) => return Err(err),
+                };
+            }
+            Ok(total)
+        };
+        let inbound = total(&input_pipes)?;
+        let outbound = total(&output_pipes)?;
+        let loss = inbound - outbound;
+        let loss_ratio = if inbound.is_zero() {
+            None
+        } else {
+            Some(loss / inbound)
+        };
+        let complete = input_pipes
+            .iter()
+            .chain(output_pipes.iter())
+            .all(|pipe| pipe.has_data);
+        let imbalanced = comparable
+            && complete
+            && match loss_ratio {
+                Some(ratio) => ratio.abs() > tolerance,
+                None => !loss.is_zero(),
+            };
+        Ok(Self {
+            machinery: machinery.id.clone(),
+            name: machinery.name.clone(),
+            units,
+            inbound,
+            outbound,
+            loss,
+            loss_ratio,
+            comparable,
+            complete,
+            imbalanced,
+            input_pipes,
+            output_pipes,
+        })
+    }
+}
+
+pub struct MassBalanceUseCases {}
+
+impl MassBalanceUseCases {
+    async fn pipe_volume(
+        pipe: &TopologyNode,
+        from: &DateTimeDerived,
+        to: &DateTimeDerived,
+        options: &FlowIntegrationOptions,
+        db: &Db,
+        ctx: &dyn Ctx,
+    ) -> ApiResult<PipeVolume> {
+        let integral = FlowIntegrationUseCases::integrate_pipe(
+            &pipe.id,
+            from.clone(),
+            to.clone(),
+            options,
+            db,
+            ctx,
+        )
+        .await?;
+        Ok(PipeVolume {
+            pipe: pipe.id.clone(),
+            name: pipe.name.clone(),
+            units: pipe.units.clone(),
+            units_name: pipe.units_name.clone(),
+            volume: integral.volume,
+            readings: integral.readings,
+            has_data: integral.has_data(),
+        })
+    }
+
+    pub async fn get(
+        from: DateTimeDerived,
+        to: DateTimeDerived,
+        tolerance: Option<Decimal>,
+        options: Option<FlowIntegrationOptions>,
+        db: &Db,
+        ctx: &dyn Ctx,
+    ) -> ApiResult<MassBalanceReport> {
+        if from.0 >= to.0 {
+            return Err(ApiError {
+                req_id: ctx.req_id(),
+                error: Error::Generic {
+                    description: "Mass balance: `from` should be before `to`".to_string(),
+                },
+            });
+        }
+        let tolerance = tolerance.unwrap_or(DEFAULT_TOLERANCE);
+        let options = options.unwrap_or_default();
+        let topology: PlantTopology = PlantTopologyUseCases::get(db, ctx).await?;
+        let converter = MeasureUnitsUseCases::converter(db, ctx).await?;
+
+        let order: Vec<ThingDerived> = topology
+            .topological_order
+            .clone()
+            .unwrap_or_else(|| topology.nodes.iter().map(|node| node.id.clone()).collect());
+        let node = |id: &ThingDerived| topology.nodes.iter().find(|node| &node.id == id);
+
+        let mut machinery = vec![];
+        for id in order.iter() {
+            let Some(machinery_node) =
+                node(id).filter(|node| node.kind == TopologyNodeKind::Machinery)
+            else {
+                continue;
+            };
+            let mut input_pipes = vec![];
+            let mut output_pipes = vec![];
+            for edge in topology.edges.iter() {
+                let (pipe, volumes) = match edge.kind {
+                    TopologyEdgeKind::PipeFrom if &edge.to == id => (&edge.from, &mut input_pipes),
+                    TopologyEdgeKind::PipeTo if &edge.from == id => (&edge.to, &mut output_pipes),
+                    _ => continue,
+                };
+                if let Some(pipe) = node(pipe) {
+                    volumes.push(Self::pipe_volume(pipe, &from, &to, &options, db, ctx).await?);
+                }
+            }
+            machinery.push(MachineryBalance::new(
+                machinery_node,
+                input_pipes,
+                output_pipes,
+                tolerance,
+                &converter,
+                ctx,
+            )?);
+        }
+
+        Ok(MassBalanceReport {
+            from,
+            to,
+            tolerance,
+            machinery,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::recipe::tests::converter;
+    use common::ctx::MockCtx;
+    use surrealdb::sql::Thing;
+
+    fn thing(tb: &str, id: &str) -> ThingDerived {
+        Thing::from((tb, id)).into()
+    }
+
+    fn volume(id: &str, units: &str, volume: i64) -> PipeVolume {
+        PipeVolume {
+            pipe: thing("Pipe", id),
+            name: id.to_string(),
+            units: thing("MeasureUnits", units),
+            units_name: units.to_string(),
+            volume: Decimal::from(volume),
+            readings: 1,
+            has_data: true,
+        }
+    }
+
+    fn machinery() -> TopologyNode {
+        TopologyNode {
+            id: thing("Machinery", "filter"),
+            kind: TopologyNodeKind::Machinery,
+            name: "Фильтр № 1".to_string(),
+            node_type: thing("MachineryType", "filter"),
+            type_name: "Фильтр".to_string(),
+            max_flow: Decimal::from(100),
+            wearout_max: Decimal::from(100),
+            units: thing("MeasureUnits", "m3"),
+            units_name: "м^3".to_string(),
+            material: None,
+        }
+    }
+
+    #[test]
+    fn machinery_balance_test() {
+        let mut ctx = MockCtx::new();
+        ctx.expect_req_id().return_const(uuid::Uuid::new_v4());
+        let converter = converter();
+        let tolerance = DEFAULT_TOLERANCE;
+        let balanced = MachineryBalance::new(
+            &machinery(),
+            vec![volume("in", "m3", 100)],
+            vec![volume("out", "m3", 97)],
+            tolerance,
+            &converter,
+            &ctx,
+        )
+        .unwrap();
+        assert_eq!(balanced.loss, Decimal::from(3));
+        assert!(balanced.comparable);
+        assert!(!balanced.imbalanced);
+
+        let lossy = MachineryBalance::new(
+            &machinery(),
+            vec![volume("in", "m3", 100)],
+            vec![volume("out", "m3", 80)],
+            tolerance,
+            &converter,
+            &ctx,
+        )
+        .unwrap();
+        assert_eq!(lossy.loss_ratio, Some(Decimal::new(2, 1)));
+        assert!(lossy.imbalanced);
+
+        let mixed_units = MachineryBalance::new(
+            &machinery(),
+            vec![volume("in", "kg", 100)],
+            vec![volume("out", "m3", 10)],
+            tolerance,
+            &converter,
+            &ctx,
+        )
+        .unwrap();
+        assert!(!mixed_units.comparable);
+        assert!(!mixed_units.imbalanced);
+
+        // Liters in, m3 out
+        let converted = MachineryBalance::new(
+            &machinery(),
+            vec![volume("in", "l", 100000)],
+            vec![volume("out", "m3", 97)],
+            tolerance,
+            &converter,
+            &ctx,
+        )
+        .unwrap();
+        assert!(converted.comparable);
+        assert_eq!(converted.units, Some(thing("MeasureUnits", "l")));
+        assert_eq!(converted.loss, Decimal::from(3000));
+        assert!(!converted.imbalanced);
+
+        let mut no_readings = volume("out", "m3", 0);
+        no_readings.has_data = false;
+        let incomplete = MachineryBalance::new(
+            &machinery(),
+            vec![volume("in", "m3", 100)],
+            vec![no_readings],
+            tolerance,
+            &converter,
+            &ctx,
+        )
+        .unwrap();
+        assert!(!incomplete.complete);
+        assert!(!incomplete.imbalanced);
+    }
+}
@@ -1,8 +1,9 @@
+use crate::audit_log::{AuditLogRepository, Audited};
 use crate::common::Unwrapper;
 use crate::service::guard::RoleGuard;
 use crate::thing_derived::ThingDerived;
 use crate::thing_wrapper::ObjectWithThing;
-use async_graphql::{InputObject, SimpleObject};
+use async_graphql::{Enum, InputObject, SimpleObject};
 use common::{
     ctx::Ctx,
     error::{ApiError, Error},
@@ -11,17 +12,39 @@ use common::{
 };
 
 use db::Db;
+use rust_decimal::Decimal;
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use surrealdb::sql::Thing;
 
 #[allow(dead_code)]
 const RESOURCE: &str = "MeasureUnits";
 
+/// Units of one dimension convert into each other
+#[derive(Enum, Copy, Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub enum UnitDimension {
+    Volume,
+    Mass,
+    Count,
+    Length,
+}
+
+fn default_factor() -> Decimal {
+    Decimal::ONE
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize, SimpleObject)]
 #[graphql(guard = "RoleGuard::new(Role::User)")]
 pub struct MeasureUnits {
     pub id: Option<ThingDerived>,
     pub name: String,
+    /// None if the units convert only into themselves
+    #[serde(default)]
+    pub dimension: Option<UnitDimension>,
+    /// Amount of the base units of the dimension in one unit,
+    /// e.g. 0.001 for liters with m^3 as the base or 480 for pallets of 480 briquettes
+    #[serde(default = "default_factor")]
+    pub factor: Decimal,
 }
 
 impl ObjectWithThing for MeasureUnits {
@@ -96,9 +119,15 @@ impl MeasureUnitsRepository {
         Unwrapper::unwrapper_option(query, 0, "Can't get tag by id", ctx).await
     }
 
-    pub async fn create(name: String, db: &Db, ctx: &dyn Ctx) -> ApiResult<MeasureUnits> {
+    /// Every unit, for conversion
+    pub async fn select_all(db: &Db, ctx: &dyn Ctx) -> ApiResult<Vec<MeasureUnits>> {
+        let query = db.query(format!("SELECT * FROM {RESOURCE};"));
+        Unwrapper::unwrapper_vec(query, 0, ctx).await
+    }
+
+    pub async fn create(units: MeasureUnits, db: &Db, ctx: &dyn Ctx) -> ApiResult<MeasureUnits> {
         db.create(RESOURCE)
-            .content(MeasureUnits { id: None, name })
+            .content(Audited::new(units, ctx))
             .await
             .map_err(ApiError::from(ctx))
             .map(|v: Vec<MeasureUnits>| {
@@ -113,13 +142,13 @@ impl MeasureUnitsRepository {
     }
 
     pub async fn update(
-        ct_input: CreateMeasureUnitsTypeInput,
+        units: MeasureUnits,
         id: Thing,
         db: &Db,
         ctx: &dyn Ctx,
     ) -> ApiResult<MeasureUnits> {
         db.update((RESOURCE, id.id.to_string()))
-            .content(ct_input)
+            .content(Audited::new(MeasureUnits { id: None, ..units }, ctx))
             .await
             .map_err(ApiError::from(ctx))?
             .ok_or(ApiError {
@@ -132,9 +161,8 @@ impl MeasureUnitsRepository {
     }
 
     pub async fn delete(id: Thing, db: &Db, ctx: &dyn Ctx) -> ApiResult<MeasureUnits> {
-        db.delete((RESOURCE, id))
-            .await
-            .map_err(ApiError::from(ctx))?
+        AuditLogRepository::delete(RESOURCE, id.id, db, ctx)
+            .await?
             .ok_or(ApiError {
                 req_id: ctx.req_id(),
                 error: Error::SurrealDbNoResult {
@@ -149,6 +177,87 @@ impl MeasureUnitsRepository {
 pub struct CreateMeasureUnitsTypeInput {
     #[graphql(validator(min_length = 4))]
     pub name: String,
+    /// None if the units convert only into themselves
+    pub dimension: Option<UnitDimension>,
+    /// Amount of the base units of the dimension in one unit. 1 by default
+    pub factor: Option<Decimal>,
+}
+
+/// Converts amounts between units of one dimension.
+/// Units without dimension convert only into themselves
+pub struct UnitConverter {
+    units: HashMap<String, MeasureUnits>,
+}
+
+impl UnitConverter {
+    pub fn new(units: Vec<MeasureUnits>) -> Self {
+        Self {
+            units: units
+                .into_iter()
+                .filter_map(|units| Some((units.id.as_ref()?.to_string(), units)))
+                .collect(),
+        }
+    }
+
+    fn name(&self, units: &ThingDerived) -> String {
+        self.units
+            .get(&units.to_string())
+            .map_or_else(|| units.to_string(), |units| units.name.clone())
+    }
+
+    /// Factors of `from` and `to` relative to the base units of their common dimension
+    fn factors(
+        &self,
+        from: &ThingDerived,
+        to: &ThingDerived,
+        ctx: &dyn Ctx,
+    ) -> ApiResult<(Decimal, Decimal)> {
+        if from == to {
+            return Ok((Decimal::ONE, Decimal::ONE));
+        }
+        match (
+            self.units.get(&from.to_string()),
+            self.units.get(&to.to_string()),
+        ) {
+            (Some(from), Some(to))
+                if from.dimension.is_some()
+                    && from.dimension == to.dimension
+                    && !to.factor.is_zero() =>
+            {
+                Ok((from.factor, to.factor))
+            }
+            _ => Err(ApiError {
+                req_id: ctx.req_id(),
+                error: Error::IncompatibleUnits {
+                    from: self.name(from),
+                    to: self.name(to),
+                },
+            }),
+        }
+    }
+
+    /// Amount of `to` in one `from`
+    pub fn factor(
+        &self,
+        from: &ThingDerived,
+        to: &ThingDerived,
+        ctx: &dyn Ctx,
+    ) -> ApiResult<Decimal> {
+        let (from, to) = self.factors(from, to, ctx)?;
+        Ok(from / to)
+    }
+
+    pub fn convert(
+        &self,
+        amount: Decimal,
+        from: &ThingDerived,
+        to: &ThingDerived,
+        ctx: &dyn Ctx,
+    ) -> ApiResult<Decimal> {
+        // Multiplied first, so 240 briquettes are exactly half of a pallet of 480
+        let (from, to) = self.factors(from, to, ctx)?;
+        Ok(amount * from / to)
+    }
 }
 
 pub struct MeasureUnitsUseCases {
@@ -157,12 +266,30 @@ pub struct MeasureUnitsUseCases {
 }
 
 impl MeasureUnitsUseCases {
+    fn units(ct_input: CreateMeasureUnitsTypeInput, ctx: &dyn Ctx) -> ApiResult<MeasureUnits> {
+        let factor = ct_input.factor.unwrap_or(Decimal::ONE);
+        if factor <= Decimal::ZERO {
+            return Err(ApiError {
+                req_id: ctx.req_id(),
+                error: Error::Generic {
+                    description: format!("Factor of units should be positive, got {factor}"),
+                },
+            });
+        }
+        Ok(MeasureUnits {
+            id: None,
+            name: ct_input.name,
+            dimension: ct_input.dimension,
+            factor,
+        })
+    }
+
     pub async fn create(
         ct_input: CreateMeasureUnitsTypeInput,
         db: &Db,
         ctx: &dyn Ctx,
     ) -> ApiResult<MeasureUnits> {
-        MeasureUnitsRepository::create(ct_input.name, db, ctx).await
+        MeasureUnitsRepository::create(Self::units(ct_input, ctx)?, db, ctx).await
     }
 
     pub async fn update(
@@ -171,7 +298,25 @@ impl MeasureUnitsUseCases {
         db: &Db,
         ctx: &dyn Ctx,
     ) -> ApiResult<MeasureUnits> {
-        MeasureUnitsRepository::update(ct_input, id.thing(ctx)?, db, ctx).await
+        MeasureUnitsRepository::update(Self::units(ct_input, ctx)?, id.thing(ctx)?, db, ctx).await
+    }
+
+    pub async fn converter(db: &Db, ctx: &dyn Ctx) -> ApiResult<UnitConverter> {
+        Ok(UnitConverter::new(
+            MeasureUnitsRepository::select_all(db, ctx).await?,
+        ))
+    }
+
+    pub async fn convert(
+        amount: Decimal,
+        from: &ThingDerived,
+        to: &ThingDerived,
+        db: &Db,
+        ctx: &dyn Ctx,
+    ) -> ApiResult<Decimal> {
+        Self::converter(db, ctx)
+            .await?
+            .convert(amount, from, to, ctx)
     }
 
     pub async fn delete(id: &dyn ObjectWithThing, db: &Db, ctx: &dyn Ctx) -> ApiResult<MeasureUnits> {
@@ -208,3 +353,68 @@ impl MeasureUnitsUseCases {
         }
     }
 }
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use common::ctx::MockCtx;
+    use rstest::*;
+
+    #[fixture]
+    fn ctx() -> MockCtx {
+        let mut ctx = MockCtx::new();
+        ctx.expect_req_id().return_const(uuid::Uuid::new_v4());
+        ctx.expect_user_id_thing().returning(|| {
+            Err(ApiError {
+                req_id: uuid::Uuid::new_v4(),
+                error: Error::Generic {
+                    description: "No user".to_string(),
+                },
+            })
+        });
+        ctx
+    }
+
+    pub fn units(id: &str, dimension: Option<UnitDimension>, factor: Decimal) -> MeasureUnits {
+        MeasureUnits {
+            id: Some(Thing::from((RESOURCE, id)).into()),
+            name: id.to_string(),
+            dimension,
+            factor,
+        }
+    }
+
+    fn thing(id: &str) -> ThingDerived {
+        Thing::from((RESOURCE, id)).into()
+    }
+
+    #[rstest]
+    fn unit_converter_test(ctx: MockCtx) {
+        let converter = UnitConverter::new(vec![
+            units("m3", Some(UnitDimension::Volume), Decimal::ONE),
+            units("l", Some(UnitDimension::Volume), Decimal::new(1, 3)),
+            units("briquette", Some(UnitDimension::Count), Decimal::ONE),
+            units("pallet", Some(UnitDimension::Count), Decimal::from(480)),
+            units("box", None, Decimal::ONE),
+        ]);
+        let convert = |amount: i64, from: &str, to: &str| {
+            converter.convert(Decimal::from(amount), &thing(from), &thing(to), &ctx)
+        };
+        assert_eq!(convert(2500, "l", "m3"), Ok(Decimal::new(25, 1)));
+        assert_eq!(convert(3, "m3", "l"), Ok(Decimal::from(3000)));
+        assert_eq!(convert(2, "pallet", "briquette"), Ok(Decimal::from(960)));
+        assert_eq!(convert(240, "briquette", "pallet"), Ok(Decimal::new(5, 1)));
+        assert_eq!(convert(7, "box", "box"), Ok(Decimal::from(7)));
+
+        let error = convert(1, "l", "briquette").unwrap_err();
+        assert_eq!(
+            error.error,
+            Error::IncompatibleUnits {
+                from: "l".to_string(),
+                to: "briquette".to_string()
+            }
+        );
+        assert!(convert(1, "box", "briquette").is_err());
+        assert!(convert(1, "m3", "unknown").is_err());
+    }
+}
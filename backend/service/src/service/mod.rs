@@ -12,6 +12,9 @@ pub mod user;
 pub mod pipe_type;
 pub mod machinery_type;
 pub mod machinery;
+pub mod maintenance;
+pub mod maintenance_order;
+pub mod downtime;
 pub mod pipe;
 pub mod pipe_to;
 pub mod pipe_from;
@@ -21,3 +24,22 @@ pub mod sales_per_day;
 pub mod production_per_day;
 pub mod production_info;
 pub mod raw_material;
+pub mod recipe;
+pub mod stock_movement;
+pub mod stock;
+pub mod mrp;
+pub mod batch;
+pub mod quality_spec;
+pub mod quality_check;
+pub mod hold_time;
+pub mod live;
+pub mod audit_log;
+pub mod plant_topology;
+pub mod mass_balance;
+pub mod flow_integration;
+pub mod capacity;
+pub mod bottleneck;
+pub mod plan_feasibility;
+pub mod plan_fact;
+pub mod plan_revision;
+pub mod production_info_failure;
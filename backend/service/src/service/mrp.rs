@@ -0,0 +1,574 @@
+use crate::datetime::DateTimeDerived;
+use crate::flow_integration::{day_bounds, Bucket, FlowIntegrationOptions};
+use crate::measure_units::MeasureUnitsUseCases;
+use crate::plant_topology::PlantTopologyUseCases;
+use crate::production_per_day::{ProductionPlanPerDay, ProductionPlanPerDayUseCases};
+use crate::recipe::RecipeUseCases;
+use crate::sales_per_day::SalesPlanPerDayUnitsUseCases;
+use crate::service::guard::RoleGuard;
+use crate::stock::StockUseCases;
+use crate::thing_derived::ThingDerived;
+use async_graphql::{Enum, InputObject, SimpleObject};
+use chrono::{DateTime, Utc};
+use common::{
+    ctx::Ctx,
+    error::{ApiError, Error},
+    role::Role,
+    ApiResult,
+};
+
+use db::Db;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// Upper limit of days in the horizon
+const MAX_DAYS: usize = 366;
+
+#[derive(Enum, Copy, Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub enum MrpDemandSource {
+    ProductionPlan,
+    /// There is no production plan of the line for the day
+    SalesPlan,
+}
+
+/// Amount of the product of the line to be made on the day
+#[derive(Clone, Debug, SimpleObject)]
+#[graphql(guard = "RoleGuard::new(Role::User)")]
+pub struct MrpDemand {
+    /// Final pipe of the product line
+    pub line: ThingDerived,
+    /// Start of the plant local day
+    pub date: DateTimeDerived,
+    pub amount: Decimal,
+    pub units: ThingDerived,
+    pub source: MrpDemandSource,
+    /// True if the plan comes from the what-if scenario
+    pub scenario: bool,
+}
+
+#[derive(Clone, Debug, SimpleObject)]
+#[graphql(guard = "RoleGuard::new(Role::User)")]
+pub struct MrpDay {
+    /// Start of the plant local day
+    pub date: DateTimeDerived,
+    /// Projected on-hand balance, negative when the requirements are not covered
+    pub opening: Decimal,
+    /// Receipts and adjustments of the stock and receipts of the scenario
+    pub receipts: Decimal,
+    pub requirement: Decimal,
+    pub closing: Decimal,
+    /// Part of the requirement of the day not covered by the stock
+    pub net_requirement: Decimal,
+    /// Missing amount at the end of the day
+    pub shortfall: Decimal,
+}
+
+#[derive(Clone, Debug, SimpleObject)]
+#[graphql(guard = "RoleGuard::new(Role::User)")]
+pub struct MrpMaterial {
+    pub material: ThingDerived,
+    /// Units the material is first required in
+    pub units: ThingDerived,
+    /// On-hand balance at the start of the horizon
+    pub opening: Decimal,
+    pub closing: Decimal,
+    pub days: Vec<MrpDay>,
+    pub total_requirement: Decimal,
+    pub total_receipts: Decimal,
+    pub total_net_requirement: Decimal,
+    /// First day the stock doesn't cover the requirements
+    pub first_shortfall: Option<DateTimeDerived>,
+}
+
+#[derive(Clone, Debug, SimpleObject)]
+#[graphql(guard = "RoleGuard::new(Role::User)")]
+pub struct MrpReport {
+    pub from: DateTimeDerived,
+    pub to: DateTimeDerived,
+    pub demands: Vec<MrpDemand>,
+    /// Raw materials in the order they are first required
+    pub materials: Vec<MrpMaterial>,
+}
+
+#[derive(InputObject, Clone, Debug, Serialize, Deserialize)]
+pub struct MrpPlanInput {
+    pub amount: Decimal,
+    pub units: ThingDerived,
+    pub date: DateTimeDerived,
+    /// The only final pipe of the plant by default
+    pub line: Option<ThingDerived>,
+}
+
+#[derive(InputObject, Clone, Debug, Serialize, Deserialize)]
+pub struct MrpReceiptInput {
+    pub material: ThingDerived,
+    pub amount: Decimal,
+    pub units: ThingDerived,
+    pub date: DateTimeDerived,
+}
+
+/// What-if changes applied on top of the stored plans. Nothing is saved
+#[derive(InputObject, Clone, Debug, Default, Serialize, Deserialize)]
+pub struct MrpScenarioInput {
+    /// Replace stored plans of the same line and day
+    pub production_plans: Option<Vec<MrpPlanInput>>,
+    pub sales_plans: Option<Vec<MrpPlanInput>>,
+    /// Expected deliveries in addition to the stock movements
+    pub receipts: Option<Vec<MrpReceiptInput>>,
+    /// Only plans of the scenario are used. False by default
+    pub ignore_stored_plans: Option<bool>,
+}
+
+/// Demand of every line and day: its production plan if there is one, its sales plan otherwise.
+/// Later plans of the same line, day and source replace earlier ones. Sorted by day
+pub fn mrp_demands(plans: Vec<MrpDemand>) -> Vec<MrpDemand> {
+    let mut keys: Vec<(DateTimeDerived, ThingDerived)> = vec![];
+    let mut production: HashMap<String, MrpDemand> = HashMap::new();
+    let mut sales: HashMap<String, MrpDemand> = HashMap::new();
+    for plan in plans {
+        let key = format!("{} {}", plan.line, plan.date.0 .0);
+        if !production.contains_key(&key) && !sales.contains_key(&key) {
+            keys.push((plan.date.clone(), plan.line.clone()));
+        }
+        match plan.source {
+            MrpDemandSource::ProductionPlan => production.insert(key, plan),
+            MrpDemandSource::SalesPlan => sales.insert(key, plan),
+        };
+    }
+    keys.sort_by_key(|(date, line)| (date.0 .0, line.to_string()));
+    keys.into_iter()
+        .filter_map(|(date, line)| {
+            let key = format!("{} {}", line, date.0 .0);
+            production.remove(&key).or_else(|| sales.remove(&key))
+        })
+        .collect()
+}
+
+/// Projects the balance of one material. `requirements` and `receipts` are in the same order
+/// as the days and in the same units as `opening`
+pub fn mrp_days(
+    opening: Decimal,
+    days: &[(DateTime<Utc>, DateTime<Utc>)],
+    requirements: &[Decimal],
+    receipts: &[Decimal],
+) -> Vec<MrpDay> {
+    let mut balance = opening;
+    days.iter()
+        .zip(requirements.iter().zip(receipts.iter()))
+        .map(|(&(from, _), (&requirement, &receipts))| {
+            let opening = balance;
+            balance += receipts - requirement;
+            let shortfall = (-balance).max(Decimal::ZERO);
+            MrpDay {
+                date: from.into(),
+                opening,
+                receipts,
+                requirement,
+                closing: balance,
+                net_requirement: (shortfall - (-opening).max(Decimal::ZERO)).max(Decimal::ZERO),
+                shortfall,
+            }
+        })
+        .collect()
+}
+
+pub struct MrpUseCases {}
+
+impl MrpUseCases {
+    async fn line(
+        line: Option<ThingDerived>,
+        final_pipe: &mut Option<ThingDerived>,
+        db: &Db,
+        ctx: &dyn Ctx,
+    ) -> ApiResult<ThingDerived> {
+        if let Some(line) = line.or_else(|| final_pipe.clone()) {
+            return Ok(line);
+        }
+        let line = PlantTopologyUseCases::select_final_pipe(db, ctx).await?;
+        *final_pipe = Some(line.clone());
+        Ok(line)
+    }
+
+    /// Raw material requirements and shortfalls for every plant local day from the day
+    /// of `from` to the day of `to` inclusive. Requirements come from the plans of every day
+    /// of the horizon, the stock at its start is taken from the stock report
+    pub async fn report(
+        from: DateTimeDerived,
+        to: DateTimeDerived,
+        scenario: Option<MrpScenarioInput>,
+        options: &FlowIntegrationOptions,
+        db: &Db,
+        ctx: &dyn Ctx,
+    ) -> ApiResult<MrpReport> {
+        let offset = options.utc_offset(ctx)?;
+        let (from, _) = day_bounds(from.0 .0, offset);
+        let (_, to) = day_bounds(to.0 .0, offset);
+        if from >= to {
+            return Err(ApiError {
+                req_id: ctx.req_id(),
+                error: Error::Generic {
+                    description: "MRP: `from` should be before `to`".to_string(),
+                },
+            });
+        }
+        let days = Bucket::Day
+            .bounds(from, to, offset, MAX_DAYS)
+            .ok_or(ApiError {
+                req_id: ctx.req_id(),
+                error: Error::Generic {
+                    description: format!("MRP: at most {MAX_DAYS} days allowed"),
+                },
+            })?;
+        let scenario = scenario.unwrap_or_default();
+
+        let mut final_pipe = None;
+        let mut plans = vec![];
+        if !scenario.ignore_stored_plans.unwrap_or(false) {
+            let production_plans = ProductionPlanPerDayUseCases::select_by_range(
+                from.into(),
+                to.into(),
+                None,
+                db,
+                ctx,
+            )
+            .await?
+            .into_iter()
+            .map(|plan| (plan.line, plan.date, plan.amount, plan.units));
+            let sales_plans = SalesPlanPerDayUnitsUseCases::select_by_range(
+                from.into(),
+                to.into(),
+                None,
+                db,
+                ctx,
+            )
+            .await?
+            .into_iter()
+            .map(|plan| (plan.line, plan.date, plan.amount, plan.units));
+            for (source, (line, date, amount, units)) in production_plans
+                .map(|plan| (MrpDemandSource::ProductionPlan, plan))
+                .chain(sales_plans.map(|plan| (MrpDemandSource::SalesPlan, plan)))
+            {
+                plans.push(MrpDemand {
+                    line: Self::line(line, &mut final_pipe, db, ctx).await?,
+                    date: day_bounds(date.0 .0, offset).0.into(),
+                    amount,
+                    units,
+                    source,
+                    scenario: false,
+                });
+            }
+        }
+        let scenario_plans = scenario
+            .production_plans
+            .unwrap_or_default()
+            .into_iter()
+            .map(|plan| (MrpDemandSource::ProductionPlan, plan))
+            .chain(
+                scenario
+                    .sales_plans
+                    .unwrap_or_default()
+                    .into_iter()
+                    .map(|plan| (MrpDemandSource::SalesPlan, plan)),
+            );
+        for (source, plan) in scenario_plans {
+            let (day, _) = day_bounds(plan.date.0 .0, offset);
+            if day < from || day >= to {
+                continue;
+            }
+            plans.push(MrpDemand {
+                line: Self::line(plan.line, &mut final_pipe, db, ctx).await?,
+                date: day.into(),
+                amount: plan.amount,
+                units: plan.units,
+                source,
+                scenario: true,
+            });
+        }
+        let demands = mrp_demands(plans);
+
+        let day_index = |date: DateTime<Utc>| days.partition_point(|&(_, day_to)| day_to <= date);
+        let explosions = RecipeUseCases::plans_requirements(
+            &demands
+                .iter()
+                .map(|demand| ProductionPlanPerDay {
+                    id: None,
+                    amount: demand.amount,
+                    units: demand.units.clone(),
+                    date: demand.date.clone(),
+                    line: Some(demand.line.clone()),
+                    day: Some(demand.date.clone()),
+                })
+                .collect::<Vec<_>>(),
+            db,
+            ctx,
+        )
+        .await?;
+        let converter = MeasureUnitsUseCases::converter(db, ctx).await?;
+        let mut materials: Vec<(ThingDerived, ThingDerived, Vec<Decimal>)> = vec![];
+        let mut indexes: HashMap<String, usize> = HashMap::new();
+        for (demand, explosion) in demands.iter().zip(explosions.iter()) {
+            let day = day_index(demand.date.0 .0);
+            for requirement in explosion.raw_materials.iter() {
+                let index = *indexes
+                    .entry(requirement.material.to_string())
+                    .or_insert_with(|| {
+                        materials.push((
+                            requirement.material.clone(),
+                            requirement.units.clone(),
+                            vec![Decimal::ZERO; days.len()],
+                        ));
+                        materials.len() - 1
+                    });
+                let (_, units, requirements) = &mut materials[index];
+                requirements[day] +=
+                    converter.convert(requirement.amount, &requirement.units, units, ctx)?;
+            }
+        }
+
+        let receipts = scenario.receipts.unwrap_or_default();
+        let mut result = vec![];
+        for (material, units, requirements) in materials {
+            let stock = StockUseCases::report(
+                &material,
+                from.into(),
+                days.last().map_or(from, |&(day, _)| day).into(),
+                Some(units.clone()),
+                options,
+                db,
+                ctx,
+            )
+            .await?;
+            let mut material_receipts: Vec<Decimal> = stock
+                .days
+                .iter()
+                .map(|day| day.receipts + day.adjustments)
+                .collect();
+            for receipt in receipts
+                .iter()
+                .filter(|receipt| receipt.material == material)
+            {
+                let day = day_index(receipt.date.0 .0);
+                if receipt.date.0 .0 < from || day >= days.len() {
+                    continue;
+                }
+                material_receipts[day] +=
+                    converter.convert(receipt.amount, &receipt.units, &units, ctx)?;
+            }
+            let days = mrp_days(stock.opening, &days, &requirements, &material_receipts);
+            result.push(MrpMaterial {
+                material,
+                units,
+                opening: stock.opening,
+                closing: days.last().map_or(stock.opening, |day| day.closing),
+                total_requirement: days.iter().map(|day| day.requirement).sum(),
+                total_receipts: days.iter().map(|day| day.receipts).sum(),
+                total_net_requirement: days.iter().map(|day| day.net_requirement).sum(),
+                first_shortfall: days
+                    .iter()
+                    .find(|day| day.shortfall > Decimal::ZERO)
+                    .map(|day| day.date.clone()),
+                days,
+            });
+        }
+
+        Ok(MrpReport {
+            from: from.into(),
+            to: to.into(),
+            demands,
+            materials: result,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::measure_units::UnitDimension;
+    use crate::pipe_stats::tests::create_pipe;
+    use crate::prod_populate::dimension_units_shortcut;
+    use crate::raw_material::{CreateRawMaterialInput, RawMaterialUseCases};
+    use crate::recipe::{CreateRecipeIngredientInput, CreateRecipeInput};
+    use crate::stock_movement::{
+        CreateStockMovementInput, StockMovementKind, StockMovementUseCases,
+    };
+    use chrono::TimeZone;
+    use common::ctx::MockCtx;
+    use db::set_test_db;
+    use rstest::*;
+    use surrealdb::sql::Thing;
+
+    #[fixture]
+    fn ctx() -> MockCtx {
+        let mut ctx = MockCtx::new();
+        ctx.expect_req_id().return_const(uuid::Uuid::new_v4());
+        ctx.expect_user_id_thing().returning(|| {
+            Err(ApiError {
+                req_id: uuid::Uuid::new_v4(),
+                error: Error::Generic {
+                    description: "No user".to_string(),
+                },
+            })
+        });
+        ctx
+    }
+
+    #[fixture]
+    async fn tdb() -> Db {
+        set_test_db().await
+    }
+
+    fn at(day: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2024, 1, day, 0, 0, 0).unwrap()
+    }
+
+    fn demand(line: &str, day: u32, amount: i64, source: MrpDemandSource) -> MrpDemand {
+        MrpDemand {
+            line: Thing::from(("Pipe", line)).into(),
+            date: at(day).into(),
+            amount: Decimal::from(amount),
+            units: Thing::from(("MeasureUnits", "palette")).into(),
+            source,
+            scenario: false,
+        }
+    }
+
+    #[test]
+    fn mrp_demands_test() {
+        let result = mrp_demands(vec![
+            demand("a", 2, 10, MrpDemandSource::SalesPlan),
+            demand("a", 1, 20, MrpDemandSource::SalesPlan),
+            demand("a", 1, 30, MrpDemandSource::ProductionPlan),
+            demand("b", 1, 40, MrpDemandSource::ProductionPlan),
+            // Scenario replaces the stored plan
+            MrpDemand {
+                scenario: true,
+                ..demand("b", 1, 50, MrpDemandSource::ProductionPlan)
+            },
+        ]);
+        let amounts: Vec<_> = result.iter().map(|demand| demand.amount).collect();
+        assert_eq!(amounts, [30, 50, 10].map(Decimal::from));
+        assert_eq!(result[0].source, MrpDemandSource::ProductionPlan);
+        assert!(result[1].scenario);
+        assert_eq!(result[2].source, MrpDemandSource::SalesPlan);
+    }
+
+    #[test]
+    fn mrp_days_test() {
+        let days: Vec<_> = (1..=4).map(|day| (at(day), at(day + 1))).collect();
+        let result = mrp_days(
+            Decimal::from(100),
+            &days,
+            &[60, 60, 30, 10].map(Decimal::from),
+            &[0, 0, 50, 0].map(Decimal::from),
+        );
+        assert_eq!(result[0].net_requirement, Decimal::ZERO);
+        assert_eq!(result[1].closing, Decimal::from(-20));
+        assert_eq!(result[1].net_requirement, Decimal::from(20));
+        assert_eq!(result[1].shortfall, Decimal::from(20));
+        // Receipt covers the shortfall and the requirement of the day
+        assert_eq!(result[2].closing, Decimal::ZERO);
+        assert_eq!(result[2].net_requirement, Decimal::ZERO);
+        assert_eq!(result[3].net_requirement, Decimal::from(10));
+        assert_eq!(result[3].shortfall, Decimal::from(10));
+    }
+
+    #[rstest]
+    #[tokio::test]
+    #[awt]
+    async fn mrp_report_test(ctx: MockCtx, #[future] tdb: Db) {
+        let (pipe, units) = create_pipe(&ctx, &tdb).await.unwrap();
+        let units = units.id.unwrap();
+        let kg = dimension_units_shortcut("кг", UnitDimension::Mass, Decimal::ONE, &tdb, &ctx)
+            .await
+            .unwrap()
+            .id
+            .unwrap();
+        let sugar = RawMaterialUseCases::create(
+            CreateRawMaterialInput {
+                name: "Просеянный сахар".to_string(),
+            },
+            &tdb,
+            &ctx,
+        )
+        .await
+        .unwrap()
+        .id
+        .unwrap();
+        RecipeUseCases::create(
+            CreateRecipeInput {
+                product: pipe.material.clone(),
+                output_amount: Decimal::ONE,
+                output_units: units.clone(),
+                ingredients: vec![CreateRecipeIngredientInput {
+                    material: sugar.clone(),
+                    amount: Decimal::from(150),
+                    units: kg.clone(),
+                }],
+            },
+            &tdb,
+            &ctx,
+        )
+        .await
+        .unwrap();
+        StockMovementUseCases::create(
+            CreateStockMovementInput {
+                material: sugar.clone(),
+                date: at(1).into(),
+                kind: StockMovementKind::Receipt,
+                amount: Decimal::from(200),
+                units: kg.clone(),
+                comment: None,
+            },
+            &tdb,
+            &ctx,
+        )
+        .await
+        .unwrap();
+
+        let plan = |day: u32| MrpPlanInput {
+            amount: Decimal::ONE,
+            units: units.clone(),
+            date: at(day).into(),
+            line: pipe.id.clone(),
+        };
+        let scenario = MrpScenarioInput {
+            production_plans: Some(vec![plan(2), plan(3)]),
+            sales_plans: Some(vec![plan(4)]),
+            receipts: Some(vec![MrpReceiptInput {
+                material: sugar.clone(),
+                amount: Decimal::from(50),
+                units: kg.clone(),
+                date: at(3).into(),
+            }]),
+            ignore_stored_plans: Some(true),
+        };
+        let options = FlowIntegrationOptions {
+            utc_offset_minutes: Some(0),
+            ..Default::default()
+        };
+        let report = MrpUseCases::report(
+            at(2).into(),
+            at(4).into(),
+            Some(scenario),
+            &options,
+            &tdb,
+            &ctx,
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(report.demands.len(), 3);
+        assert_eq!(report.demands[2].source, MrpDemandSource::SalesPlan);
+        assert_eq!(report.materials.len(), 1);
+        let material = &report.materials[0];
+        assert_eq!(material.material, sugar);
+        assert_eq!(material.units, kg);
+        assert_eq!(material.opening, Decimal::from(200));
+        assert_eq!(material.total_requirement, Decimal::from(450));
+        assert_eq!(material.closing, Decimal::from(-200));
+        assert_eq!(material.days[1].shortfall, Decimal::from(50));
+        assert_eq!(material.total_net_requirement, Decimal::from(200));
+        assert_eq!(material.first_shortfall, Some(at(3).into()));
+    }
+}
@@ -1,3 +1,7 @@
+use crate::audit_log::{AuditLogRepository, Audited};
+use crate::machinery::Machinery;
+use crate::pipe_from::PipeFromUseCases;
+use crate::pipe_to::PipeToUseCases;
 use crate::pipe_type::PipeTypeUseCases;
 use crate::service::guard::RoleGuard;
 use crate::thing_derived::ThingDerived;
@@ -37,6 +41,20 @@ impl Pipe {
         let ctx = ctx.data::<CtxStruct>()?;
         Ok(PipeTypeUseCases::select_by_id(&self.pipe_type.thing(ctx)?, db, ctx).await?)
     }
+
+    /// Machinery that outputs into this pipe
+    async fn source_machinery(&self, ctx: &Context<'_>) -> Result<Vec<Machinery>> {
+        let db = ctx.data::<Db>()?;
+        let ctx = ctx.data::<CtxStruct>()?;
+        Ok(PipeToUseCases::select_source_machinery(self, db, ctx).await?)
+    }
+
+    /// Downstream machinery fed by this pipe
+    async fn target_machinery(&self, ctx: &Context<'_>) -> Result<Vec<Machinery>> {
+        let db = ctx.data::<Db>()?;
+        let ctx = ctx.data::<CtxStruct>()?;
+        Ok(PipeFromUseCases::select_target_machinery(self, db, ctx).await?)
+    }
 }
 
 impl ObjectWithThing for Pipe {
@@ -110,12 +128,15 @@ impl PipeRepository {
         ctx: &dyn Ctx,
     ) -> ApiResult<Pipe> {
         db.create(RESOURCE)
-            .content(Pipe {
-                id: None,
-                name,
-                pipe_type,
-                material,
-            })
+            .content(Audited::new(
+                Pipe {
+                    id: None,
+                    name,
+                    pipe_type,
+                    material,
+                },
+                ctx,
+            ))
             .await
             .map_err(ApiError::from(ctx))
             .map(|v: Vec<Pipe>| {
@@ -136,7 +157,7 @@ impl PipeRepository {
         ctx: &dyn Ctx,
     ) -> ApiResult<Pipe> {
         db.update((RESOURCE, id.id.to_string()))
-            .content(ct_input)
+            .content(Audited::new(ct_input, ctx))
             .await
             .map_err(ApiError::from(ctx))?
             .ok_or(ApiError {
@@ -149,9 +170,8 @@ impl PipeRepository {
     }
 
     pub async fn delete(id: Thing, db: &Db, ctx: &dyn Ctx) -> ApiResult<Pipe> {
-        db.delete((RESOURCE, id))
-            .await
-            .map_err(ApiError::from(ctx))?
+        AuditLogRepository::delete(RESOURCE, id.id, db, ctx)
+            .await?
             .ok_or(ApiError {
                 req_id: ctx.req_id(),
                 error: Error::SurrealDbNoResult {
@@ -204,6 +224,18 @@ impl PipeUseCases {
         PipeRepository::select_by_id(id, db, ctx).await
     }
 
+    /// Units of the pipe type. Flow of the pipe is measured in them
+    pub async fn units(
+        id: &dyn ObjectWithThing,
+        db: &Db,
+        ctx: &dyn Ctx,
+    ) -> ApiResult<ThingDerived> {
+        let pipe = PipeRepository::select_by_id(id, db, ctx).await?;
+        Ok(PipeTypeUseCases::select_by_id(&pipe.pipe_type, db, ctx)
+            .await?
+            .units)
+    }
+
     pub async fn count(db: &Db, ctx: &dyn Ctx) -> ApiResult<usize> {
         PipeRepository::count(db, ctx).await
     }
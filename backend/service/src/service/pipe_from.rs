@@ -1,8 +1,10 @@
+use crate::audit_log::AuditLogRepository;
+use crate::common::Unwrapper;
 use crate::machinery::{Machinery, MachineryUseCases};
+use crate::pipe::{Pipe, PipeUseCases};
 use crate::service::guard::RoleGuard;
 use crate::thing_derived::ThingDerived;
 use crate::thing_wrapper::ObjectWithThing;
-use crate::{common::Unwrapper, pipe_type::PipeType};
 use async_graphql::{ComplexObject, Context, InputObject, Result, SimpleObject};
 use common::{
     ctx::{Ctx, CtxStruct},
@@ -18,6 +20,8 @@ use surrealdb::sql::Thing;
 #[allow(dead_code)]
 const RESOURCE: &str = "PipeFrom";
 
+/// Graph edge: Pipe -> PipeFrom -> Machinery
+/// Pipe (in) feeds the machinery (out)
 #[derive(Clone, Debug, Serialize, Deserialize, SimpleObject)]
 #[graphql(guard = "RoleGuard::new(Role::User)")]
 #[graphql(complex)]
@@ -31,10 +35,10 @@ pub struct PipeFrom {
 
 #[ComplexObject]
 impl PipeFrom {
-    async fn r#in(&self, ctx: &Context<'_>) -> Result<Machinery> {
+    async fn r#in(&self, ctx: &Context<'_>) -> Result<Pipe> {
         let db = ctx.data::<Db>()?;
         let ctx = ctx.data::<CtxStruct>()?;
-        Ok(MachineryUseCases::select_by_id(&self.r#in.thing(ctx)?, db, ctx).await?)
+        Ok(PipeUseCases::select_by_id(&self.r#in.thing(ctx)?, db, ctx).await?)
     }
     async fn out(&self, ctx: &Context<'_>) -> Result<Machinery> {
         let db = ctx.data::<Db>()?;
@@ -72,23 +76,6 @@ impl PipeFromRepository {
         Unwrapper::unwrapper_vec(query, 0, ctx).await
     }
 
-    pub async fn filtered_list_by_name(
-        offset: usize,
-        limit: usize,
-        name: String,
-        db: &Db,
-        ctx: &dyn Ctx,
-    ) -> ApiResult<Vec<PipeFrom>> {
-        let query = db
-            .query(format!(
-                "SELECT * FROM {RESOURCE} 
-                WHERE name @@ $name
-                LIMIT {limit} START {offset}"
-            ))
-            .bind(("name", name));
-        Unwrapper::unwrapper_vec(query, 0, ctx).await
-    }
-
     pub async fn count(db: &Db, ctx: &dyn Ctx) -> ApiResult<usize> {
         let query = db.query(format!("(SELECT count() FROM {RESOURCE} GROUP ALL).count"));
         Unwrapper::unwrapper_option(query, 0, &format!("Cant't {RESOURCE} count"), ctx).await
@@ -115,54 +102,49 @@ impl PipeFromRepository {
         Unwrapper::unwrapper_option(query, 0, "Can't get tag by id", ctx).await
     }
 
-    pub async fn create(
-        r#in: ThingDerived,
-        out: ThingDerived,
+    /// Pipes feeding the machinery
+    pub async fn select_input_pipes(
+        machinery: &dyn ObjectWithThing,
         db: &Db,
         ctx: &dyn Ctx,
-    ) -> ApiResult<PipeFrom> {
-        db.create(RESOURCE)
-            .content(PipeFrom {
-                id: None,
-                r#in,
-                out,
-            })
-            .await
-            .map_err(ApiError::from(ctx))
-            .map(|v: Vec<PipeFrom>| {
-                v.into_iter().next().ok_or(ApiError {
-                    req_id: ctx.req_id(),
-                    error: Error::SurrealDbNoResult {
-                        source: "internal".to_string(),
-                        id: "Error while creating ".to_string(),
-                    },
-                })
-            })?
+    ) -> ApiResult<Vec<Pipe>> {
+        let query = db
+            .query(format!("SELECT * FROM $machinery<-{RESOURCE}<-Pipe;"))
+            .bind(("machinery", machinery.thing(ctx)?));
+        Unwrapper::unwrapper_vec(query, 0, ctx).await
     }
 
-    pub async fn update(
-        ct_input: CreatePipeFromInput,
-        id: Thing,
+    /// Downstream machinery, fed by the pipe
+    pub async fn select_target_machinery(
+        pipe: &dyn ObjectWithThing,
+        db: &Db,
+        ctx: &dyn Ctx,
+    ) -> ApiResult<Vec<Machinery>> {
+        let query = db
+            .query(format!("SELECT * FROM $pipe->{RESOURCE}->Machinery;"))
+            .bind(("pipe", pipe.thing(ctx)?));
+        Unwrapper::unwrapper_vec(query, 0, ctx).await
+    }
+
+    pub async fn create(
+        r#in: ThingDerived,
+        out: ThingDerived,
         db: &Db,
         ctx: &dyn Ctx,
     ) -> ApiResult<PipeFrom> {
-        db.update((RESOURCE, id.id.to_string()))
-            .content(ct_input)
-            .await
-            .map_err(ApiError::from(ctx))?
-            .ok_or(ApiError {
-                req_id: ctx.req_id(),
-                error: Error::SurrealDbNoResult {
-                    source: "internal".to_string(),
-                    id: id.to_string(),
-                },
-            })
+        let query = db
+            .query(format!(
+                "RELATE $in->{RESOURCE}->$out SET changed_by = $changed_by;"
+            ))
+            .bind(("in", r#in.thing(ctx)?))
+            .bind(("out", out.thing(ctx)?))
+            .bind(("changed_by", ctx.user_id_thing().ok()));
+        Unwrapper::unwrapper_option(query, 0, "Error while creating ", ctx).await
     }
 
     pub async fn delete(id: Thing, db: &Db, ctx: &dyn Ctx) -> ApiResult<PipeFrom> {
-        db.delete((RESOURCE, id))
-            .await
-            .map_err(ApiError::from(ctx))?
+        AuditLogRepository::delete(RESOURCE, id.id, db, ctx)
+            .await?
             .ok_or(ApiError {
                 req_id: ctx.req_id(),
                 error: Error::SurrealDbNoResult {
@@ -175,7 +157,9 @@ impl PipeFromRepository {
 
 #[derive(Deserialize, InputObject, Clone, Serialize, Debug)]
 pub struct CreatePipeFromInput {
+    /// Pipe
     pub r#in: ThingDerived,
+    /// Machinery
     pub out: ThingDerived,
 }
 
@@ -185,17 +169,20 @@ pub struct PipeFromUseCases {
 }
 
 impl PipeFromUseCases {
-    pub async fn create(ct_input: CreatePipeFromInput, db: &Db, ctx: &dyn Ctx) -> ApiResult<PipeFrom> {
-        PipeFromRepository::create(ct_input.r#in, ct_input.out, db, ctx).await
-    }
-
-    pub async fn update(
+    pub async fn create(
         ct_input: CreatePipeFromInput,
-        id: &dyn ObjectWithThing,
         db: &Db,
         ctx: &dyn Ctx,
     ) -> ApiResult<PipeFrom> {
-        PipeFromRepository::update(ct_input, id.thing(ctx)?, db, ctx).await
+        let pipe = PipeUseCases::select_by_id(&ct_input.r#in, db, ctx).await?;
+        let machinery = MachineryUseCases::select_by_id(&ct_input.out, db, ctx).await?;
+        PipeFromRepository::create(
+            pipe.thing(ctx)?.into(),
+            machinery.thing(ctx)?.into(),
+            db,
+            ctx,
+        )
+        .await
     }
 
     pub async fn delete(id: &dyn ObjectWithThing, db: &Db, ctx: &dyn Ctx) -> ApiResult<PipeFrom> {
@@ -210,6 +197,22 @@ impl PipeFromUseCases {
         PipeFromRepository::select_by_id(id, db, ctx).await
     }
 
+    pub async fn select_input_pipes(
+        machinery: &dyn ObjectWithThing,
+        db: &Db,
+        ctx: &dyn Ctx,
+    ) -> ApiResult<Vec<Pipe>> {
+        PipeFromRepository::select_input_pipes(machinery, db, ctx).await
+    }
+
+    pub async fn select_target_machinery(
+        pipe: &dyn ObjectWithThing,
+        db: &Db,
+        ctx: &dyn Ctx,
+    ) -> ApiResult<Vec<Machinery>> {
+        PipeFromRepository::select_target_machinery(pipe, db, ctx).await
+    }
+
     pub async fn count(db: &Db, ctx: &dyn Ctx) -> ApiResult<usize> {
         PipeFromRepository::count(db, ctx).await
     }
@@ -217,16 +220,12 @@ impl PipeFromUseCases {
     pub async fn list(
         offset: Option<usize>,
         limit: Option<usize>,
-        name: Option<String>,
         db: &Db,
         ctx: &dyn Ctx,
     ) -> ApiResult<Vec<PipeFrom>> {
         let limit = limit.unwrap_or(10);
-        let offset = offset.unwrap_or(10);
+        let offset = offset.unwrap_or(0);
 
-        match name {
-            Some(name) => PipeFromRepository::filtered_list_by_name(offset, limit, name, db, ctx).await,
-            None => PipeFromRepository::list(offset, limit, db, ctx).await,
-        }
+        PipeFromRepository::list(offset, limit, db, ctx).await
     }
 }
@@ -1,3 +1,11 @@
+use crate::audit_log::{AuditLogRepository, Audited};
+use crate::capacity::check_capacity;
+use crate::flow_integration::{
+    aggregate, Bucket, FlowBucket, FlowIntegrationOptions, FlowIntegrationUseCases, FlowPoint,
+};
+use crate::measure_units::MeasureUnitsUseCases;
+use crate::pipe::PipeUseCases;
+use crate::pipe_type::PipeTypeUseCases;
 use crate::service::guard::RoleGuard;
 use crate::thing_derived::ThingDerived;
 use crate::thing_wrapper::ObjectWithThing;
@@ -18,6 +26,9 @@ use surrealdb::sql::Thing;
 #[allow(dead_code)]
 const RESOURCE: &str = "PipeStats";
 
+/// Upper limit of buckets in one aggregation request
+const MAX_BUCKETS: usize = 10000;
+
 #[derive(Clone, Debug, Serialize, Deserialize, SimpleObject)]
 #[graphql(guard = "RoleGuard::new(Role::User)")]
 pub struct PipeStats {
@@ -27,6 +38,9 @@ pub struct PipeStats {
     pub units: ThingDerived,
     pub wearout: Decimal,
     pub pipe: ThingDerived,
+    /// Flow is above max_flow of the pipe type
+    #[serde(default)]
+    pub over_capacity: bool,
 }
 
 impl ObjectWithThing for PipeStats {
@@ -118,24 +132,78 @@ impl PipeStatsRepository {
         Unwrapper::unwrapper_option_without_error(query, 0, ctx).await
     }
 
-    pub async fn create(
+    /// Readings in [from, to) ordered by date
+    pub async fn select_by_pipe_and_range(
+        pipe: &dyn ObjectWithThing,
+        from: DateTimeDerived,
+        to: DateTimeDerived,
+        db: &Db,
+        ctx: &dyn Ctx,
+    ) -> ApiResult<Vec<PipeStats>> {
+        let query = db
+            .query(format!(
+                "SELECT * FROM {RESOURCE} WHERE pipe = $pipe AND date >= $from AND date < $to ORDER BY date ASC;"
+            ))
+            .bind(("pipe", pipe.thing(ctx)?))
+            .bind(("from", from.0))
+            .bind(("to", to.0));
+
+        Unwrapper::unwrapper_vec(query, 0, ctx).await
+    }
+
+    /// Last reading strictly before the date
+    pub async fn select_last_reading_before(
+        pipe: &dyn ObjectWithThing,
         date: DateTimeDerived,
-        flow: Decimal,
-        units: ThingDerived,
-        wearout: Decimal,
-        pipe: ThingDerived,
+        db: &Db,
+        ctx: &dyn Ctx,
+    ) -> ApiResult<Option<PipeStats>> {
+        let query = db
+            .query(format!(
+                "SELECT * FROM {RESOURCE} WHERE pipe = $pipe AND date < $date ORDER BY date DESC LIMIT 1;"
+            ))
+            .bind(("pipe", pipe.thing(ctx)?))
+            .bind(("date", date.0));
+
+        Unwrapper::unwrapper_option_without_error(query, 0, ctx).await
+    }
+
+    /// First reading at the date or after it
+    pub async fn select_first_reading_from(
+        pipe: &dyn ObjectWithThing,
+        date: DateTimeDerived,
+        db: &Db,
+        ctx: &dyn Ctx,
+    ) -> ApiResult<Option<PipeStats>> {
+        let query = db
+            .query(format!(
+                "SELECT * FROM {RESOURCE} WHERE pipe = $pipe AND date >= $date ORDER BY date ASC LIMIT 1;"
+            ))
+            .bind(("pipe", pipe.thing(ctx)?))
+            .bind(("date", date.0));
+
+        Unwrapper::unwrapper_option_without_error(query, 0, ctx).await
+    }
+
+    pub async fn create(
+        ct_input: CreatePipeStatsInput,
+        over_capacity: bool,
         db: &Db,
         ctx: &dyn Ctx,
     ) -> ApiResult<PipeStats> {
         db.create(RESOURCE)
-            .content(PipeStats {
-                id: None,
-                date,
-                flow,
-                units,
-                wearout,
-                pipe,
-            })
+            .content(Audited::new(
+                PipeStats {
+                    id: None,
+                    date: ct_input.date,
+                    flow: ct_input.flow,
+                    units: ct_input.units,
+                    wearout: ct_input.wearout,
+                    pipe: ct_input.pipe,
+                    over_capacity,
+                },
+                ctx,
+            ))
             .await
             .map_err(ApiError::from(ctx))
             .map(|v: Vec<PipeStats>| {
@@ -151,12 +219,24 @@ impl PipeStatsRepository {
 
     pub async fn update(
         ct_input: CreatePipeStatsInput,
+        over_capacity: bool,
         id: Thing,
         db: &Db,
         ctx: &dyn Ctx,
     ) -> ApiResult<PipeStats> {
         db.update((RESOURCE, id.id.to_string()))
-            .content(ct_input)
+            .content(Audited::new(
+                PipeStats {
+                    id: None,
+                    date: ct_input.date,
+                    flow: ct_input.flow,
+                    units: ct_input.units,
+                    wearout: ct_input.wearout,
+                    pipe: ct_input.pipe,
+                    over_capacity,
+                },
+                ctx,
+            ))
             .await
             .map_err(ApiError::from(ctx))?
             .ok_or(ApiError {
@@ -169,9 +249,8 @@ impl PipeStatsRepository {
     }
 
     pub async fn delete(id: Thing, db: &Db, ctx: &dyn Ctx) -> ApiResult<PipeStats> {
-        db.delete((RESOURCE, id))
-            .await
-            .map_err(ApiError::from(ctx))?
+        AuditLogRepository::delete(RESOURCE, id.id, db, ctx)
+            .await?
             .ok_or(ApiError {
                 req_id: ctx.req_id(),
                 error: Error::SurrealDbNoResult {
@@ -197,21 +276,36 @@ pub struct PipeStatsUseCases {
 }
 
 impl PipeStatsUseCases {
+    /// Whether the reading is above max_flow of the pipe type.
+    /// Error if such readings are rejected, see REJECT_OVER_CAPACITY
+    async fn over_capacity(
+        ct_input: &CreatePipeStatsInput,
+        db: &Db,
+        ctx: &dyn Ctx,
+    ) -> ApiResult<bool> {
+        let pipe = PipeUseCases::select_by_id(&ct_input.pipe, db, ctx).await?;
+        let pipe_type = PipeTypeUseCases::select_by_id(&pipe.pipe_type, db, ctx).await?;
+        let converter = MeasureUnitsUseCases::converter(db, ctx).await?;
+        // Readings in units that don't convert into the units of the type can't be compared
+        let flow = match converter.convert(ct_input.flow, &ct_input.units, &pipe_type.units, ctx) {
+            Ok(flow) => flow,
+            Err(ApiError {
+                error: Error::IncompatibleUnits { .. },
+                ..
+            }) => return Ok(false),
+            Err(err) => return Err(err),
+        };
+        check_capacity(&ct_input.pipe, flow, pipe_type.max_flow, ctx)
+    }
+
+    /// Readings above max_flow are flagged with over_capacity or rejected
     pub async fn create(
         ct_input: CreatePipeStatsInput,
         db: &Db,
         ctx: &dyn Ctx,
     ) -> ApiResult<PipeStats> {
-        PipeStatsRepository::create(
-            ct_input.date,
-            ct_input.flow,
-            ct_input.units,
-            ct_input.wearout,
-            ct_input.pipe,
-            db,
-            ctx,
-        )
-        .await
+        let over_capacity = Self::over_capacity(&ct_input, db, ctx).await?;
+        PipeStatsRepository::create(ct_input, over_capacity, db, ctx).await
     }
 
     pub async fn update(
@@ -220,7 +314,8 @@ impl PipeStatsUseCases {
         db: &Db,
         ctx: &dyn Ctx,
     ) -> ApiResult<PipeStats> {
-        PipeStatsRepository::update(ct_input, id.thing(ctx)?, db, ctx).await
+        let over_capacity = Self::over_capacity(&ct_input, db, ctx).await?;
+        PipeStatsRepository::update(ct_input, over_capacity, id.thing(ctx)?, db, ctx).await
     }
 
     pub async fn delete(id: &dyn ObjectWithThing, db: &Db, ctx: &dyn Ctx) -> ApiResult<PipeStats> {
@@ -235,6 +330,76 @@ impl PipeStatsUseCases {
         PipeStatsRepository::select_by_id(id, db, ctx).await
     }
 
+    pub async fn select_by_pipe_and_range(
+        pipe: &dyn ObjectWithThing,
+        from: DateTimeDerived,
+        to: DateTimeDerived,
+        db: &Db,
+        ctx: &dyn Ctx,
+    ) -> ApiResult<Vec<PipeStats>> {
+        PipeStatsRepository::select_by_pipe_and_range(pipe, from, to, db, ctx).await
+    }
+
+    pub async fn select_last_reading_before(
+        pipe: &dyn ObjectWithThing,
+        date: DateTimeDerived,
+        db: &Db,
+        ctx: &dyn Ctx,
+    ) -> ApiResult<Option<PipeStats>> {
+        PipeStatsRepository::select_last_reading_before(pipe, date, db, ctx).await
+    }
+
+    pub async fn select_first_reading_from(
+        pipe: &dyn ObjectWithThing,
+        date: DateTimeDerived,
+        db: &Db,
+        ctx: &dyn Ctx,
+    ) -> ApiResult<Option<PipeStats>> {
+        PipeStatsRepository::select_first_reading_from(pipe, date, db, ctx).await
+    }
+
+    /// Volume and flow statistics of the pipe in buckets over [from, to)
+    pub async fn aggregate_by_pipe(
+        pipe: &dyn ObjectWithThing,
+        from: DateTimeDerived,
+        to: DateTimeDerived,
+        bucket: Bucket,
+        options: &FlowIntegrationOptions,
+        db: &Db,
+        ctx: &dyn Ctx,
+    ) -> ApiResult<Vec<FlowBucket>> {
+        let buckets = bucket
+            .bounds(from.0 .0, to.0 .0, options.utc_offset(ctx)?, MAX_BUCKETS)
+            .ok_or(ApiError {
+                req_id: ctx.req_id(),
+                error: Error::Generic {
+                    description: format!("Too many buckets, at most {MAX_BUCKETS} allowed"),
+                },
+            })?;
+        let previous =
+            PipeStatsRepository::select_last_reading_before(pipe, from.clone(), db, ctx).await?;
+        let readings =
+            PipeStatsRepository::select_by_pipe_and_range(pipe, from.clone(), to.clone(), db, ctx)
+                .await?;
+        let downtimes =
+            FlowIntegrationUseCases::downtime(pipe, from.clone(), to.clone(), options, db, ctx)
+                .await?;
+        let next = PipeStatsRepository::select_first_reading_from(pipe, to, db, ctx).await?;
+        let units = PipeUseCases::units(pipe, db, ctx).await?;
+        let converter = MeasureUnitsUseCases::converter(db, ctx).await?;
+        let point = |reading: &PipeStats| {
+            FlowPoint::from(reading).convert(&reading.units, &units, &converter, ctx)
+        };
+        Ok(aggregate(
+            previous.as_ref().map(point).transpose()?,
+            &readings.iter().map(point).collect::<ApiResult<Vec<_>>>()?,
+            next.as_ref().map(point).transpose()?,
+            &buckets,
+            &downtimes,
+            options,
+        ))
+    }
+
     pub async fn count(db: &Db, ctx: &dyn Ctx) -> ApiResult<usize> {
         PipeStatsRepository::count(db, ctx).await
     }
@@ -251,3 +416,173 @@ impl PipeStatsUseCases {
         PipeStatsRepository::list(offset, limit, db, ctx).await
     }
 }
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::measure_units::{MeasureUnits, UnitDimension};
+    use crate::pipe::{CreatePipeInput, Pipe, PipeUseCases};
+    use crate::prod_populate::{
+        dimension_units_shortcut, measure_units_shortcut, pipe_type_shortcut,
+    };
+    use crate::raw_material::{CreateRawMaterialInput, RawMaterialUseCases};
+    use chrono::Utc;
+    use common::ctx::MockCtx;
+    use db::set_test_db;
+    use rstest::*;
+
+    #[fixture]
+    fn ctx() -> MockCtx {
+        let mut ctx = MockCtx::new();
+        ctx.expect_req_id().return_const(uuid::Uuid::new_v4());
+        ctx.expect_user_id_thing().returning(|| {
+            Err(ApiError {
+                req_id: uuid::Uuid::new_v4(),
+                error: Error::Generic {
+                    description: "No user".to_string(),
+                },
+            })
+        });
+        ctx
+    }
+
+    #[fixture]
+    async fn tdb() -> Db {
+        set_test_db().await
+    }
+
+    pub async fn create_pipe(ctx: &impl Ctx, tdb: &Db) -> ApiResult<(Pipe, MeasureUnits)> {
+        let units = measure_units_shortcut("м^3", tdb, ctx).await?;
+        let pipe_type = pipe_type_shortcut("Трубопровод", &units, tdb, ctx).await?;
+        let material = RawMaterialUseCases::create(
+            CreateRawMaterialInput {
+                name: "Изначальная смесь".to_string(),
+            },
+            tdb,
+            ctx,
+        )
+        .await?;
+        let pipe = PipeUseCases::create(
+            CreatePipeInput {
+                name: "Трубопровод № 1".to_string(),
+                pipe_type: pipe_type.id.unwrap(),
+                material: material.id.unwrap(),
+            },
+            tdb,
+            ctx,
+        )
+        .await?;
+        Ok((pipe, units))
+    }
+
+    fn input(
+        pipe: &Pipe,
+        units: &MeasureUnits,
+        flow: Decimal,
+        wearout: Decimal,
+    ) -> CreatePipeStatsInput {
+        CreatePipeStatsInput {
+            date: Utc::now().into(),
+            flow,
+            units: units.id.clone().unwrap(),
+            wearout,
+            pipe: pipe.id.clone().unwrap(),
+        }
+    }
+
+    #[rstest]
+    #[tokio::test]
+    #[awt]
+    async fn pipe_stats_create_test(ctx: impl Ctx, #[future] tdb: Db) {
+        let (pipe, units) = create_pipe(&ctx, &tdb).await.unwrap();
+        let stats = PipeStatsUseCases::create(
+            input(&pipe, &units, Decimal::new(5, 1), Decimal::new(1002, 1)),
+            &tdb,
+            &ctx,
+        )
+        .await
+        .unwrap();
+        assert_eq!(stats.flow, Decimal::new(5, 1));
+
+        let fetched = PipeStatsUseCases::select_by_id(&stats, &tdb, &ctx)
+            .await
+            .unwrap();
+        assert_eq!(fetched.wearout, Decimal::new(1002, 1));
+        assert!(!fetched.over_capacity);
+
+        // pipe_type_shortcut sets max_flow to 1000000
+        let over = PipeStatsUseCases::create(
+            input(&pipe, &units, Decimal::new(1000001, 0), Decimal::ZERO),
+            &tdb,
+            &ctx,
+        )
+        .await
+        .unwrap();
+        assert!(over.over_capacity);
+    }
+
+    #[rstest]
+    #[tokio::test]
+    #[awt]
+    async fn pipe_stats_over_capacity_units_test(ctx: impl Ctx, #[future] tdb: Db) {
+        let (other, no_dimension) = create_pipe(&ctx, &tdb).await.unwrap();
+        let m3 = dimension_units_shortcut("м3", UnitDimension::Volume, Decimal::ONE, &tdb, &ctx)
+            .await
+            .unwrap();
+        let l =
+            dimension_units_shortcut("л", UnitDimension::Volume, Decimal::new(1, 3), &tdb, &ctx)
+                .await
+                .unwrap();
+        let pipe_type = pipe_type_shortcut("Трубопровод, м3", &m3, &tdb, &ctx)
+            .await
+            .unwrap();
+        let pipe = PipeUseCases::create(
+            CreatePipeInput {
+                name: "Трубопровод № 2".to_string(),
+                pipe_type: pipe_type.id.unwrap(),
+                material: other.material,
+            },
+            &tdb,
+            &ctx,
+        )
+        .await
+        .unwrap();
+        let over_capacity = |units: &MeasureUnits| {
+            PipeStatsUseCases::create(
+                input(&pipe, units, Decimal::new(1000001, 0), Decimal::ZERO),
+                &tdb,
+                &ctx,
+            )
+        };
+
+        // pipe_type_shortcut sets max_flow to 1000000 of the type units
+        assert!(over_capacity(&m3).await.unwrap().over_capacity);
+        assert!(!over_capacity(&l).await.unwrap().over_capacity);
+        // Units that don't convert are not compared
+        assert!(!over_capacity(&no_dimension).await.unwrap().over_capacity);
+    }
+
+    #[rstest]
+    #[tokio::test]
+    #[awt]
+    async fn pipe_stats_schema_rejects_invalid_test(ctx: impl Ctx, #[future] tdb: Db) {
+        let (pipe, units) = create_pipe(&ctx, &tdb).await.unwrap();
+
+        let negative_flow = PipeStatsUseCases::create(
+            input(&pipe, &units, Decimal::new(-1, 0), Decimal::ZERO),
+            &tdb,
+            &ctx,
+        )
+        .await;
+        assert!(negative_flow.is_err());
+
+        // pipe_type_shortcut sets wearout_max to 1000000
+        let worn_out = PipeStatsUseCases::create(
+            input(&pipe, &units, Decimal::ONE, Decimal::new(1000001, 0)),
+            &tdb,
+            &ctx,
+        )
+        .await;
+        assert!(worn_out.is_err());
+    }
+}
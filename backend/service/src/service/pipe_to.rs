@@ -1,8 +1,10 @@
+use crate::audit_log::AuditLogRepository;
 use crate::machinery::{Machinery, MachineryUseCases};
+use crate::pipe::{Pipe, PipeUseCases};
 use crate::service::guard::RoleGuard;
 use crate::thing_derived::ThingDerived;
 use crate::thing_wrapper::ObjectWithThing;
-use crate::{common::Unwrapper, pipe_type::PipeType};
+use crate::common::Unwrapper;
 use async_graphql::{ComplexObject, Context, InputObject, Result, SimpleObject};
 use common::{
     ctx::{Ctx, CtxStruct},
@@ -18,6 +20,8 @@ use surrealdb::sql::Thing;
 #[allow(dead_code)]
 const RESOURCE: &str = "PipeTo";
 
+/// Graph edge: Machinery -> PipeTo -> Pipe
+/// Machinery (in) outputs its product into the pipe (out)
 #[derive(Clone, Debug, Serialize, Deserialize, SimpleObject)]
 #[graphql(guard = "RoleGuard::new(Role::User)")]
 #[graphql(complex)]
@@ -36,10 +40,10 @@ impl PipeTo {
         let ctx = ctx.data::<CtxStruct>()?;
         Ok(MachineryUseCases::select_by_id(&self.r#in.thing(ctx)?, db, ctx).await?)
     }
-    async fn out(&self, ctx: &Context<'_>) -> Result<Machinery> {
+    async fn out(&self, ctx: &Context<'_>) -> Result<Pipe> {
         let db = ctx.data::<Db>()?;
         let ctx = ctx.data::<CtxStruct>()?;
-        Ok(MachineryUseCases::select_by_id(&self.out.thing(ctx)?, db, ctx).await?)
+        Ok(PipeUseCases::select_by_id(&self.out.thing(ctx)?, db, ctx).await?)
     }
 }
 
@@ -67,30 +71,13 @@ impl PipeToRepository {
         ctx: &dyn Ctx,
     ) -> ApiResult<Vec<PipeTo>> {
         let query = db.query(format!(
-            "SELECT * To {RESOURCE} LIMIT {limit} START {offset};"
+            "SELECT * FROM {RESOURCE} LIMIT {limit} START {offset};"
         ));
         Unwrapper::unwrapper_vec(query, 0, ctx).await
     }
 
-    pub async fn filtered_list_by_name(
-        offset: usize,
-        limit: usize,
-        name: String,
-        db: &Db,
-        ctx: &dyn Ctx,
-    ) -> ApiResult<Vec<PipeTo>> {
-        let query = db
-            .query(format!(
-                "SELECT * To {RESOURCE} 
-                WHERE name @@ $name
-                LIMIT {limit} START {offset}"
-            ))
-            .bind(("name", name));
-        Unwrapper::unwrapper_vec(query, 0, ctx).await
-    }
-
     pub async fn count(db: &Db, ctx: &dyn Ctx) -> ApiResult<usize> {
-        let query = db.query(format!("(SELECT count() To {RESOURCE} GROUP ALL).count"));
+        let query = db.query(format!("(SELECT count() FROM {RESOURCE} GROUP ALL).count"));
         Unwrapper::unwrapper_option(query, 0, &format!("Cant't {RESOURCE} count"), ctx).await
     }
 
@@ -109,60 +96,55 @@ impl PipeToRepository {
             });
         }
         let query = db
-            .query("SELECT * To $thing_id".to_string())
+            .query("SELECT * FROM $thing_id".to_string())
             .bind(("thing_id", thing_id));
 
         Unwrapper::unwrapper_option(query, 0, "Can't get tag by id", ctx).await
     }
 
-    pub async fn create(
-        r#in: ThingDerived,
-        out: ThingDerived,
+    /// Pipes the machinery outputs into
+    pub async fn select_output_pipes(
+        machinery: &dyn ObjectWithThing,
         db: &Db,
         ctx: &dyn Ctx,
-    ) -> ApiResult<PipeTo> {
-        db.create(RESOURCE)
-            .content(PipeTo {
-                id: None,
-                r#in,
-                out,
-            })
-            .await
-            .map_err(ApiError::from(ctx))
-            .map(|v: Vec<PipeTo>| {
-                v.into_iter().next().ok_or(ApiError {
-                    req_id: ctx.req_id(),
-                    error: Error::SurrealDbNoResult {
-                        source: "internal".to_string(),
-                        id: "Error while creating ".to_string(),
-                    },
-                })
-            })?
+    ) -> ApiResult<Vec<Pipe>> {
+        let query = db
+            .query(format!("SELECT * FROM $machinery->{RESOURCE}->Pipe;"))
+            .bind(("machinery", machinery.thing(ctx)?));
+        Unwrapper::unwrapper_vec(query, 0, ctx).await
     }
 
-    pub async fn update(
-        ct_input: CreatePipeToInput,
-        id: Thing,
+    /// Machinery that outputs into the pipe
+    pub async fn select_source_machinery(
+        pipe: &dyn ObjectWithThing,
+        db: &Db,
+        ctx: &dyn Ctx,
+    ) -> ApiResult<Vec<Machinery>> {
+        let query = db
+            .query(format!("SELECT * FROM $pipe<-{RESOURCE}<-Machinery;"))
+            .bind(("pipe", pipe.thing(ctx)?));
+        Unwrapper::unwrapper_vec(query, 0, ctx).await
+    }
+
+    pub async fn create(
+        r#in: ThingDerived,
+        out: ThingDerived,
         db: &Db,
         ctx: &dyn Ctx,
     ) -> ApiResult<PipeTo> {
-        db.update((RESOURCE, id.id.to_string()))
-            .content(ct_input)
-            .await
-            .map_err(ApiError::from(ctx))?
-            .ok_or(ApiError {
-                req_id: ctx.req_id(),
-                error: Error::SurrealDbNoResult {
-                    source: "internal".to_string(),
-                    id: id.to_string(),
-                },
-            })
+        let query = db
+            .query(format!(
+                "RELATE $in->{RESOURCE}->$out SET changed_by = $changed_by;"
+            ))
+            .bind(("in", r#in.thing(ctx)?))
+            .bind(("out", out.thing(ctx)?))
+            .bind(("changed_by", ctx.user_id_thing().ok()));
+        Unwrapper::unwrapper_option(query, 0, "Error while creating ", ctx).await
     }
 
     pub async fn delete(id: Thing, db: &Db, ctx: &dyn Ctx) -> ApiResult<PipeTo> {
-        db.delete((RESOURCE, id))
-            .await
-            .map_err(ApiError::from(ctx))?
+        AuditLogRepository::delete(RESOURCE, id.id, db, ctx)
+            .await?
             .ok_or(ApiError {
                 req_id: ctx.req_id(),
                 error: Error::SurrealDbNoResult {
@@ -175,7 +157,9 @@ impl PipeToRepository {
 
 #[derive(Deserialize, InputObject, Clone, Serialize, Debug)]
 pub struct CreatePipeToInput {
+    /// Machinery
     pub r#in: ThingDerived,
+    /// Pipe
     pub out: ThingDerived,
 }
 
@@ -186,16 +170,15 @@ pub struct PipeToUseCases {
 
 impl PipeToUseCases {
     pub async fn create(ct_input: CreatePipeToInput, db: &Db, ctx: &dyn Ctx) -> ApiResult<PipeTo> {
-        PipeToRepository::create(ct_input.r#in, ct_input.out, db, ctx).await
-    }
-
-    pub async fn update(
-        ct_input: CreatePipeToInput,
-        id: &dyn ObjectWithThing,
-        db: &Db,
-        ctx: &dyn Ctx,
-    ) -> ApiResult<PipeTo> {
-        PipeToRepository::update(ct_input, id.thing(ctx)?, db, ctx).await
+        let machinery = MachineryUseCases::select_by_id(&ct_input.r#in, db, ctx).await?;
+        let pipe = PipeUseCases::select_by_id(&ct_input.out, db, ctx).await?;
+        PipeToRepository::create(
+            machinery.thing(ctx)?.into(),
+            pipe.thing(ctx)?.into(),
+            db,
+            ctx,
+        )
+        .await
     }
 
     pub async fn delete(id: &dyn ObjectWithThing, db: &Db, ctx: &dyn Ctx) -> ApiResult<PipeTo> {
@@ -210,6 +193,22 @@ impl PipeToUseCases {
         PipeToRepository::select_by_id(id, db, ctx).await
     }
 
+    pub async fn select_output_pipes(
+        machinery: &dyn ObjectWithThing,
+        db: &Db,
+        ctx: &dyn Ctx,
+    ) -> ApiResult<Vec<Pipe>> {
+        PipeToRepository::select_output_pipes(machinery, db, ctx).await
+    }
+
+    pub async fn select_source_machinery(
+        pipe: &dyn ObjectWithThing,
+        db: &Db,
+        ctx: &dyn Ctx,
+    ) -> ApiResult<Vec<Machinery>> {
+        PipeToRepository::select_source_machinery(pipe, db, ctx).await
+    }
+
     pub async fn count(db: &Db, ctx: &dyn Ctx) -> ApiResult<usize> {
         PipeToRepository::count(db, ctx).await
     }
@@ -217,16 +216,115 @@ impl PipeToUseCases {
     pub async fn list(
         offset: Option<usize>,
         limit: Option<usize>,
-        name: Option<String>,
         db: &Db,
         ctx: &dyn Ctx,
     ) -> ApiResult<Vec<PipeTo>> {
         let limit = limit.unwrap_or(10);
-        let offset = offset.unwrap_or(10);
+        let offset = offset.unwrap_or(0);
 
-        match name {
-            Some(name) => PipeToRepository::filtered_list_by_name(offset, limit, name, db, ctx).await,
-            None => PipeToRepository::list(offset, limit, db, ctx).await,
-        }
+        PipeToRepository::list(offset, limit, db, ctx).await
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::pipe_from::{CreatePipeFromInput, PipeFromUseCases};
+    use crate::pipe_stats::tests::create_pipe;
+    use crate::prod_populate::{machinery_shortcut, machinery_type_shortcut};
+    use common::ctx::MockCtx;
+    use db::set_test_db;
+    use rstest::*;
+
+    #[fixture]
+    fn ctx() -> MockCtx {
+        let mut ctx = MockCtx::new();
+        ctx.expect_req_id().return_const(uuid::Uuid::new_v4());
+        ctx.expect_user_id_thing().returning(|| {
+            Err(ApiError {
+                req_id: uuid::Uuid::new_v4(),
+                error: Error::Generic {
+                    description: "No user".to_string(),
+                },
+            })
+        });
+        ctx
+    }
+
+    #[fixture]
+    async fn tdb() -> Db {
+        set_test_db().await
+    }
+
+    #[rstest]
+    #[tokio::test]
+    #[awt]
+    async fn pipe_edges_traversal_test(ctx: impl Ctx, #[future] tdb: Db) {
+        let (pipe, units) = create_pipe(&ctx, &tdb).await.unwrap();
+        let machinery_type = machinery_type_shortcut("Смеситель", &units, &tdb, &ctx)
+            .await
+            .unwrap();
+        let mixer = machinery_shortcut("Смеситель № 1", &machinery_type, &tdb, &ctx)
+            .await
+            .unwrap();
+        let filter = machinery_shortcut("Фильтр № 1", &machinery_type, &tdb, &ctx)
+            .await
+            .unwrap();
+
+        let pipe_to = PipeToUseCases::create(
+            CreatePipeToInput {
+                r#in: mixer.id.clone().unwrap(),
+                out: pipe.id.clone().unwrap(),
+            },
+            &tdb,
+            &ctx,
+        )
+        .await
+        .unwrap();
+        PipeFromUseCases::create(
+            CreatePipeFromInput {
+                r#in: pipe.id.clone().unwrap(),
+                out: filter.id.clone().unwrap(),
+            },
+            &tdb,
+            &ctx,
+        )
+        .await
+        .unwrap();
+
+        let fetched = PipeToUseCases::select_by_id(&pipe_to, &tdb, &ctx)
+            .await
+            .unwrap();
+        assert_eq!(fetched.r#in, mixer.id.clone().unwrap());
+        assert_eq!(PipeToUseCases::count(&tdb, &ctx).await.unwrap(), 1);
+
+        let outputs = PipeToUseCases::select_output_pipes(&mixer, &tdb, &ctx)
+            .await
+            .unwrap();
+        assert_eq!(outputs.len(), 1);
+        assert_eq!(outputs[0].id, pipe.id);
+
+        let downstream = PipeFromUseCases::select_target_machinery(&pipe, &tdb, &ctx)
+            .await
+            .unwrap();
+        assert_eq!(downstream.len(), 1);
+        assert_eq!(downstream[0].id, filter.id);
+
+        let inputs = PipeFromUseCases::select_input_pipes(&filter, &tdb, &ctx)
+            .await
+            .unwrap();
+        assert_eq!(inputs[0].id, pipe.id);
+
+        // Wrong direction: pipe can't be the source of PipeTo
+        let wrong = PipeToUseCases::create(
+            CreatePipeToInput {
+                r#in: pipe.id.clone().unwrap(),
+                out: mixer.id.clone().unwrap(),
+            },
+            &tdb,
+            &ctx,
+        )
+        .await;
+        assert!(wrong.is_err());
     }
 }
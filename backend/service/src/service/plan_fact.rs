@@ -0,0 +1,315 @@
+use crate::datetime::DateTimeDerived;
+use crate::flow_integration::{day_bounds, Bucket, FlowBucket, FlowIntegrationOptions};
+use crate::measure_units::MeasureUnitsUseCases;
+use crate::pipe::PipeUseCases;
+use crate::pipe_stats::PipeStatsUseCases;
+use crate::pipe_type::PipeTypeUseCases;
+use crate::plant_topology::PlantTopologyUseCases;
+use crate::production_per_day::{ProductionPlanPerDay, ProductionPlanPerDayUseCases};
+use crate::sales_per_day::{SalesPlanPerDay, SalesPlanPerDayUnitsUseCases};
+use crate::service::guard::RoleGuard;
+use crate::thing_derived::ThingDerived;
+use async_graphql::SimpleObject;
+use chrono::{DateTime, FixedOffset, Utc};
+use common::{
+    ctx::Ctx,
+    error::{ApiError, Error},
+    role::Role,
+    ApiResult,
+};
+
+use db::Db;
+use rust_decimal::Decimal;
+
+/// Upper limit of days in one report
+const MAX_DAYS: usize = 3660;
+
+#[derive(Clone, Debug, SimpleObject)]
+#[graphql(guard = "RoleGuard::new(Role::User)")]
+pub struct PlanFactDay {
+    /// Start of the plant local day
+    pub date: DateTimeDerived,
+    pub production_plan: Option<Decimal>,
+    pub sales_plan: Option<Decimal>,
+    /// None if there are no readings for the day or before it
+    pub fact: Option<Decimal>,
+    /// fact - production plan
+    pub deviation: Option<Decimal>,
+    /// Deviation in percent of production plan
+    pub deviation_percent: Option<Decimal>,
+    pub cumulative_production_plan: Decimal,
+    pub cumulative_sales_plan: Decimal,
+    pub cumulative_fact: Decimal,
+    pub cumulative_deviation: Decimal,
+}
+
+#[derive(Clone, Debug, SimpleObject)]
+#[graphql(guard = "RoleGuard::new(Role::User)")]
+pub struct PlanFactReport {
+    pub from: DateTimeDerived,
+    pub to: DateTimeDerived,
+    pub final_pipe: ThingDerived,
+    /// Units of the final pipe
+    pub units: ThingDerived,
+    pub days: Vec<PlanFactDay>,
+    pub total_production_plan: Decimal,
+    pub total_sales_plan: Decimal,
+    pub total_fact: Decimal,
+    pub total_deviation: Decimal,
+    /// Total deviation in percent of total production plan
+    pub total_deviation_percent: Option<Decimal>,
+}
+
+fn percent(value: Decimal, base: Decimal) -> Option<Decimal> {
+    if base.is_zero() {
+        return None;
+    }
+    Some((value / base * Decimal::ONE_HUNDRED).round_dp(2))
+}
+
+/// Sum of amounts of plans falling on the local day starting at `day`
+fn day_amount<'a>(
+    plans: impl Iterator<Item = (&'a DateTimeDerived, Decimal)>,
+    day: DateTime<Utc>,
+    offset: FixedOffset,
+) -> Option<Decimal> {
+    plans
+        .filter(|(date, _)| day_bounds(date.0 .0, offset).0 == day)
+        .map(|(_, amount)| amount)
+        .reduce(|sum, amount| sum + amount)
+}
+
+/// Builds per day rows. `facts` are day buckets in the same order as the days
+pub fn plan_fact_days(
+    facts: &[FlowBucket],
+    production_plans: &[ProductionPlanPerDay],
+    sales_plans: &[SalesPlanPerDay],
+    offset: FixedOffset,
+) -> Vec<PlanFactDay> {
+    let mut cumulative_production_plan = Decimal::ZERO;
+    let mut cumulative_sales_plan = Decimal::ZERO;
+    let mut cumulative_fact = Decimal::ZERO;
+    facts
+        .iter()
+        .map(|bucket| {
+            let day = bucket.from.0 .0;
+            let production_plan = day_amount(
+                production_plans
+                    .iter()
+                    .map(|plan| (&plan.date, plan.amount)),
+                day,
+                offset,
+            );
+            let sales_plan = day_amount(
+                sales_plans.iter().map(|plan| (&plan.date, plan.amount)),
+                day,
+                offset,
+            );
+            let fact = (!bucket.covered_hours.is_zero()).then_some(bucket.volume);
+            let deviation = match (fact, production_plan) {
+                (Some(fact), Some(plan)) => Some(fact - plan),
+                _ => None,
+            };
+            let deviation_percent = match (deviation, production_plan) {
+                (Some(deviation), Some(plan)) => percent(deviation, plan),
+                _ => None,
+            };
+            cumulative_production_plan += production_plan.unwrap_or_default();
+            cumulative_sales_plan += sales_plan.unwrap_or_default();
+            cumulative_fact += fact.unwrap_or_default();
+            PlanFactDay {
+                date: bucket.from.clone(),
+                production_plan,
+                sales_plan,
+                fact,
+                deviation,
+                deviation_percent,
+                cumulative_production_plan,
+                cumulative_sales_plan,
+                cumulative_fact,
+                cumulative_deviation: cumulative_fact - cumulative_production_plan,
+            }
+        })
+        .collect()
+}
+
+pub struct PlanFactUseCases {}
+
+impl PlanFactUseCases {
+    /// Report for every plant local day from the day of `from` to the day of `to` inclusive.
+    /// Plans of the line of `final_pipe` are used. If it is not set, the only final pipe
+    /// of the plant is used
+    pub async fn report(
+        from: DateTimeDerived,
+        to: DateTimeDerived,
+        final_pipe: Option<ThingDerived>,
+        options: &FlowIntegrationOptions,
+        db: &Db,
+        ctx: &dyn Ctx,
+    ) -> ApiResult<PlanFactReport> {
+        let offset = options.utc_offset(ctx)?;
+        let (from, _) = day_bounds(from.0 .0, offset);
+        let (_, to) = day_bounds(to.0 .0, offset);
+        if from >= to {
+            return Err(ApiError {
+                req_id: ctx.req_id(),
+                error: Error::Generic {
+                    description: "Plan-fact report: `from` should be before `to`".to_string(),
+                },
+            });
+        }
+        if Bucket::Day.bounds(from, to, offset, MAX_DAYS).is_none() {
+            return Err(ApiError {
+                req_id: ctx.req_id(),
+                error: Error::Generic {
+                    description: format!("Plan-fact report: at most {MAX_DAYS} days allowed"),
+                },
+            });
+        }
+
+        let final_pipe = match final_pipe {
+            Some(final_pipe) => final_pipe,
+            None => PlantTopologyUseCases::select_final_pipe(db, ctx).await?,
+        };
+        let pipe = PipeUseCases::select_by_id(&final_pipe, db, ctx).await?;
+        let pipe_type = PipeTypeUseCases::select_by_id(&pipe.pipe_type, db, ctx).await?;
+
+        let facts = PipeStatsUseCases::aggregate_by_pipe(
+            &final_pipe,
+            from.into(),
+            to.into(),
+            Bucket::Day,
+            options,
+            db,
+            ctx,
+        )
+        .await?;
+        let line = Some(final_pipe.clone());
+        let production_plans = ProductionPlanPerDayUseCases::select_by_range(
+            from.into(),
+            to.into(),
+            line.clone(),
+            db,
+            ctx,
+        )
+        .await?;
+        let sales_plans =
+            SalesPlanPerDayUnitsUseCases::select_by_range(from.into(), to.into(), line, db, ctx)
+                .await?;
+
+        // Plans are compared with the fact in units of the final pipe
+        let units = pipe_type.units;
+        let converter = MeasureUnitsUseCases::converter(db, ctx).await?;
+        let production_plans = production_plans
+            .into_iter()
+            .map(|plan| {
+                Ok(ProductionPlanPerDay {
+                    amount: converter.convert(plan.amount, &plan.units, &units, ctx)?,
+                    units: units.clone(),
+                    ..plan
+                })
+            })
+            .collect::<ApiResult<Vec<_>>>()?;
+        let sales_plans = sales_plans
+            .into_iter()
+            .map(|plan| {
+                Ok(SalesPlanPerDay {
+                    amount: converter.convert(plan.amount, &plan.units, &units, ctx)?,
+                    units: units.clone(),
+                    ..plan
+                })
+            })
+            .collect::<ApiResult<Vec<_>>>()?;
+
+        let days = plan_fact_days(&facts, &production_plans, &sales_plans, offset);
+        let (total_production_plan, total_sales_plan, total_fact) = days
+            .last()
+            .map(|day| {
+                (
+                    day.cumulative_production_plan,
+                    day.cumulative_sales_plan,
+                    day.cumulative_fact,
+                )
+            })
+            .unwrap_or_default();
+        Ok(PlanFactReport {
+            from: from.into(),
+            to: to.into(),
+            final_pipe,
+            units,
+            days,
+            total_production_plan,
+            total_sales_plan,
+            total_fact,
+            total_deviation: total_fact - total_production_plan,
+            total_deviation_percent: percent(
+                total_fact - total_production_plan,
+                total_production_plan,
+            ),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use surrealdb::sql::Thing;
+
+    fn at(day: u32, hour: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2024, 1, day, hour, 0, 0).unwrap()
+    }
+
+    fn units() -> ThingDerived {
+        Thing::from(("MeasureUnits", "palette")).into()
+    }
+
+    fn bucket(day: u32, volume: i64, covered_hours: i64) -> FlowBucket {
+        FlowBucket {
+            from: at(day, 0).into(),
+            to: at(day + 1, 0).into(),
+            volume: Decimal::from(volume),
+            average_flow: None,
+            min_flow: None,
+            max_flow: None,
+            readings: 0,
+            covered_hours: Decimal::from(covered_hours),
+            downtime_hours: Decimal::ZERO,
+        }
+    }
+
+    #[test]
+    fn plan_fact_days_test() {
+        let utc = FixedOffset::east_opt(0).unwrap();
+        let facts = [bucket(1, 90, 24), bucket(2, 110, 24), bucket(3, 0, 0)];
+        let production_plans = [1, 2, 3].map(|day| ProductionPlanPerDay {
+            id: None,
+            amount: Decimal::from(100),
+            units: units(),
+            date: at(day, 12).into(),
+            line: None,
+            day: None,
+        });
+        let sales_plans = [SalesPlanPerDay {
+            id: None,
+            amount: Decimal::from(80),
+            units: units(),
+            date: at(2, 0).into(),
+            line: None,
+            day: None,
+        }];
+        let days = plan_fact_days(&facts, &production_plans, &sales_plans, utc);
+
+        assert_eq!(days[0].deviation, Some(Decimal::from(-10)));
+        assert_eq!(days[0].deviation_percent, Some(Decimal::from(-10)));
+        assert_eq!(days[0].sales_plan, None);
+        assert_eq!(days[1].sales_plan, Some(Decimal::from(80)));
+        assert_eq!(days[1].cumulative_deviation, Decimal::ZERO);
+        // No readings on the third day
+        assert_eq!(days[2].fact, None);
+        assert_eq!(days[2].deviation, None);
+        assert_eq!(days[2].cumulative_production_plan, Decimal::from(300));
+        assert_eq!(days[2].cumulative_fact, Decimal::from(200));
+        assert_eq!(days[2].cumulative_sales_plan, Decimal::from(80));
+    }
+}
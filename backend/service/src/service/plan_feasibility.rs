@@ -0,0 +1,414 @@
+use crate::bottleneck::{without_nodes, BottleneckAnalysis};
+use crate::datetime::DateTimeDerived;
+use crate::downtime::{Downtime, DowntimeUseCases};
+use crate::flow_integration::{day_bounds, hours, merge_intervals, FlowIntegrationOptions};
+use crate::maintenance_order::MaintenanceKind;
+use crate::measure_units::{MeasureUnitsUseCases, UnitConverter};
+use crate::plant_topology::{PlantTopology, PlantTopologyUseCases};
+use crate::production_per_day::ProductionPlanPerDay;
+use crate::sales_per_day::{SalesPlanPerDay, SalesPlanPerDayUnitsUseCases};
+use crate::service::guard::RoleGuard;
+use crate::thing_derived::ThingDerived;
+use async_graphql::{Enum, SimpleObject};
+use chrono::{DateTime, Utc};
+use common::{
+    ctx::Ctx,
+    error::{ApiError, Error},
+    role::Role,
+    ApiResult,
+};
+
+use db::Db;
+use rust_decimal::Decimal;
+
+#[derive(Enum, Copy, Clone, Debug, Eq, PartialEq)]
+pub enum PlanWarningKind {
+    /// Amount is above what the line can output during the day
+    OverCapacity,
+    /// Amount does not cover the sales plan
+    BelowSalesPlan,
+    /// Amount is more than the sales plan needs
+    AboveSalesPlan,
+    NoSalesPlan,
+}
+
+#[derive(Clone, Debug, SimpleObject)]
+#[graphql(guard = "RoleGuard::new(Role::User)")]
+pub struct PlanWarning {
+    pub kind: PlanWarningKind,
+    pub message: String,
+    /// Capacity or sales plan the amount is compared with
+    pub limit: Option<Decimal>,
+}
+
+#[derive(Clone, Debug, SimpleObject)]
+#[graphql(guard = "RoleGuard::new(Role::User)")]
+pub struct PlanFeasibility {
+    /// Final pipe of the product line
+    pub line: ThingDerived,
+    /// Start of the plant local day
+    pub day: DateTimeDerived,
+    pub amount: Decimal,
+    /// Units of the plan. Capacity and sales plan are converted into them
+    pub units: ThingDerived,
+    /// Output of the line over the day. None if nothing on the way limits it
+    pub capacity: Option<Decimal>,
+    /// Hours of the day when any pipe or machinery of the plant is stopped by planned downtime
+    pub downtime_hours: Decimal,
+    pub sales_plan: Option<Decimal>,
+    /// Amount is within capacity of the line
+    pub feasible: bool,
+    pub warnings: Vec<PlanWarning>,
+}
+
+/// Output of the line into `final_pipe` over [from, to). The day is split at downtime
+/// boundaries and stopped pipes and machinery are taken out of the topology for every part.
+/// None if the throughput is not limited
+pub fn line_capacity(
+    topology: &PlantTopology,
+    final_pipe: &ThingDerived,
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+    downtimes: &[Downtime],
+    ctx: &dyn Ctx,
+) -> ApiResult<Option<Decimal>> {
+    let intervals: Vec<_> = downtimes
+        .iter()
+        .filter_map(|downtime| {
+            downtime
+                .interval(from, to)
+                .map(|interval| (downtime.object.to_string(), interval))
+        })
+        .collect();
+    let mut bounds: Vec<DateTime<Utc>> = intervals
+        .iter()
+        .flat_map(|(_, (start, end))| [*start, *end])
+        .chain([from, to])
+        .collect();
+    bounds.sort();
+    bounds.dedup();
+
+    let mut capacity = Decimal::ZERO;
+    for segment in bounds.windows(2) {
+        let (start, end) = (segment[0], segment[1]);
+        let stopped: Vec<String> = intervals
+            .iter()
+            .filter(|(_, (stop, resume))| *stop <= start && *resume >= end)
+            .map(|(object, _)| object.clone())
+            .collect();
+        if stopped.contains(&final_pipe.to_string()) {
+            continue;
+        }
+        let analysis =
+            BottleneckAnalysis::new(&without_nodes(topology, &stopped), final_pipe, ctx)?;
+        match analysis.max_throughput {
+            Some(throughput) => capacity += throughput * hours(start, end),
+            None => return Ok(None),
+        }
+    }
+    Ok(Some(capacity))
+}
+
+impl PlanFeasibility {
+    /// Checks the amount planned for [from, to) against capacity of the line
+    /// reduced by the downtime and against the sales plan of the day.
+    /// Error if the plan is in units incompatible with the line or the sales plan
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        amount: Decimal,
+        units: &ThingDerived,
+        line: &ThingDerived,
+        (from, to): (DateTime<Utc>, DateTime<Utc>),
+        topology: &PlantTopology,
+        downtimes: &[Downtime],
+        sales_plan: Option<&SalesPlanPerDay>,
+        converter: &UnitConverter,
+        ctx: &dyn Ctx,
+    ) -> ApiResult<Self> {
+        let line_units = topology
+            .nodes
+            .iter()
+            .find(|node| node.id == *line)
+            .map(|node| node.units.clone())
+            .ok_or(ApiError {
+                req_id: ctx.req_id(),
+                error: Error::Generic {
+                    description: format!("Plan feasibility: {line} is not in the topology"),
+                },
+            })?;
+        let capacity = line_capacity(topology, line, from, to, downtimes, ctx)?
+            .map(|capacity| converter.convert(capacity, &line_units, units, ctx))
+            .transpose()?;
+        let sales_plan = sales_plan
+            .map(|sales_plan| converter.convert(sales_plan.amount, &sales_plan.units, units, ctx))
+            .transpose()?;
+        let stopped: Vec<(DateTime<Utc>, DateTime<Utc>)> = downtimes
+            .iter()
+            .filter_map(|downtime| downtime.interval(from, to))
+            .collect();
+        let downtime_hours = merge_intervals(&stopped, from, to)
+            .into_iter()
+            .map(|(start, end)| hours(start, end))
+            .sum();
+
+        let mut warnings = vec![];
+        let mut feasible = true;
+        if let Some(capacity) = capacity.filter(|capacity| amount > *capacity) {
+            feasible = false;
+            warnings.push(PlanWarning {
+                kind: PlanWarningKind::OverCapacity,
+                message: format!(
+                    "Amount {amount} is above capacity {capacity} of the line \
+                     with {downtime_hours} hours of planned downtime"
+                ),
+                limit: Some(capacity),
+            });
+        }
+        match sales_plan {
+            None => warnings.push(PlanWarning {
+                kind: PlanWarningKind::NoSalesPlan,
+                message: "There is no sales plan for the day".to_string(),
+                limit: None,
+            }),
+            Some(sales_plan) if amount < sales_plan => warnings.push(PlanWarning {
+                kind: PlanWarningKind::BelowSalesPlan,
+                message: format!("Amount {amount} is below sales plan {sales_plan}"),
+                limit: Some(sales_plan),
+            }),
+            Some(sales_plan) if amount > sales_plan => warnings.push(PlanWarning {
+                kind: PlanWarningKind::AboveSalesPlan,
+                message: format!("Amount {amount} is above sales plan {sales_plan}"),
+                limit: Some(sales_plan),
+            }),
+            Some(_) => {}
+        }
+        Ok(Self {
+            line: line.clone(),
+            day: from.into(),
+            amount,
+            units: units.clone(),
+            capacity,
+            downtime_hours,
+            sales_plan,
+            feasible,
+            warnings,
+        })
+    }
+}
+
+/// Production plan as saved with the result of its check
+#[derive(Clone, Debug, SimpleObject)]
+#[graphql(guard = "RoleGuard::new(Role::User)")]
+pub struct CheckedProductionPlanPerDay {
+    pub plan: ProductionPlanPerDay,
+    pub feasibility: PlanFeasibility,
+}
+
+pub struct PlanFeasibilityUseCases {}
+
+impl PlanFeasibilityUseCases {
+    /// Checks the amount for the plant local day of `date`. Only planned downtime
+    /// reduces capacity, unplanned downtime is not known in advance
+    pub async fn check(
+        amount: Decimal,
+        units: &ThingDerived,
+        date: DateTimeDerived,
+        line: &ThingDerived,
+        db: &Db,
+        ctx: &dyn Ctx,
+    ) -> ApiResult<PlanFeasibility> {
+        let offset = FlowIntegrationOptions::default().utc_offset(ctx)?;
+        let (from, to) = day_bounds(date.0 .0, offset);
+        let topology = PlantTopologyUseCases::get(db, ctx).await?;
+        let downtimes = DowntimeUseCases::select_by_range(
+            from.into(),
+            to.into(),
+            Some(MaintenanceKind::Planned),
+            db,
+            ctx,
+        )
+        .await?;
+        let sales_plan = SalesPlanPerDayUnitsUseCases::select_by_date(line, date, db, ctx).await?;
+        let converter = MeasureUnitsUseCases::converter(db, ctx).await?;
+        PlanFeasibility::new(
+            amount,
+            units,
+            line,
+            (from, to),
+            &topology,
+            &downtimes,
+            sales_plan.as_ref(),
+            &converter,
+            ctx,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bottleneck::tests::{line, thing};
+    use crate::measure_units::tests::units;
+    use crate::measure_units::UnitDimension;
+    use chrono::TimeZone;
+    use common::ctx::MockCtx;
+    use rstest::*;
+
+    #[fixture]
+    fn ctx() -> MockCtx {
+        let mut ctx = MockCtx::new();
+        ctx.expect_req_id().return_const(uuid::Uuid::new_v4());
+        ctx.expect_user_id_thing().returning(|| {
+            Err(ApiError {
+                req_id: uuid::Uuid::new_v4(),
+                error: Error::Generic {
+                    description: "No user".to_string(),
+                },
+            })
+        });
+        ctx
+    }
+
+    fn at(hour: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap() + chrono::Duration::hours(hour as i64)
+    }
+
+    fn downtime(object: &str, from: u32, to: Option<u32>) -> Downtime {
+        Downtime {
+            id: None,
+            object: thing("Machinery", object),
+            kind: MaintenanceKind::Planned,
+            started_at: at(from).into(),
+            ended_at: to.map(|to| at(to).into()),
+            reason: "Плановый ремонт".to_string(),
+            maintenance_order: None,
+            reported_by: None,
+        }
+    }
+
+    fn sales_plan(amount: i64) -> SalesPlanPerDay {
+        SalesPlanPerDay {
+            id: None,
+            amount: Decimal::from(amount),
+            units: thing("MeasureUnits", "kg"),
+            date: at(0).into(),
+            line: Some(thing("Pipe", "final")),
+            day: Some(at(0).into()),
+        }
+    }
+
+    #[rstest]
+    fn line_capacity_test(ctx: MockCtx) {
+        let final_pipe = thing("Pipe", "final");
+        let day = (at(0), at(24));
+        let capacity = |downtimes: &[Downtime]| {
+            line_capacity(&line(6), &final_pipe, day.0, day.1, downtimes, &ctx).unwrap()
+        };
+        assert_eq!(capacity(&[]), Some(Decimal::from(144)));
+        // Packer is stopped for 4 hours
+        assert_eq!(
+            capacity(&[downtime("packer", 2, Some(6))]),
+            Some(Decimal::from(120))
+        );
+        // Only mixer_2 runs for 6 hours at 3 per hour, overlapping downtimes
+        assert_eq!(
+            capacity(&[
+                downtime("mixer_1", 0, Some(6)),
+                downtime("mixer_1", 3, Some(4))
+            ]),
+            Some(Decimal::from(126))
+        );
+        // Open downtime lasts until the end of the day
+        assert_eq!(
+            capacity(&[downtime("packer", 12, None)]),
+            Some(Decimal::from(72))
+        );
+    }
+
+    #[rstest]
+    fn plan_feasibility_test(ctx: MockCtx) {
+        let (final_pipe, kg) = (thing("Pipe", "final"), thing("MeasureUnits", "kg"));
+        let day = (at(0), at(24));
+        let topology = line(6);
+        let downtimes = [downtime("packer", 2, Some(6))];
+        let converter = UnitConverter::new(vec![
+            units("kg", Some(UnitDimension::Mass), Decimal::ONE),
+            units("t", Some(UnitDimension::Mass), Decimal::from(1000)),
+            units("pcs", Some(UnitDimension::Count), Decimal::ONE),
+        ]);
+        let try_check = |amount: Decimal, units: &ThingDerived, sales: Option<&SalesPlanPerDay>| {
+            PlanFeasibility::new(
+                amount,
+                units,
+                &final_pipe,
+                day,
+                &topology,
+                &downtimes,
+                sales,
+                &converter,
+                &ctx,
+            )
+        };
+        let check = |amount: i64, units: &ThingDerived, sales: Option<&SalesPlanPerDay>| {
+            try_check(Decimal::from(amount), units, sales).unwrap()
+        };
+        let kinds = |feasibility: &PlanFeasibility| -> Vec<PlanWarningKind> {
+            feasibility
+                .warnings
+                .iter()
+                .map(|warning| warning.kind)
+                .collect()
+        };
+
+        let feasibility = check(120, &kg, Some(&sales_plan(120)));
+        assert!(feasibility.feasible);
+        assert!(feasibility.warnings.is_empty());
+        assert_eq!(feasibility.downtime_hours, Decimal::from(4));
+
+        let feasibility = check(130, &kg, Some(&sales_plan(140)));
+        assert!(!feasibility.feasible);
+        assert_eq!(
+            kinds(&feasibility),
+            vec![
+                PlanWarningKind::OverCapacity,
+                PlanWarningKind::BelowSalesPlan
+            ]
+        );
+        assert_eq!(feasibility.warnings[0].limit, Some(Decimal::from(120)));
+
+        let feasibility = check(100, &kg, None);
+        assert_eq!(kinds(&feasibility), vec![PlanWarningKind::NoSalesPlan]);
+
+        // Capacity and sales plan are converted into tonnes of the plan
+        let tonnes = try_check(
+            Decimal::new(13, 2),
+            &thing("MeasureUnits", "t"),
+            Some(&sales_plan(100)),
+        )
+        .unwrap();
+        assert!(!tonnes.feasible);
+        assert_eq!(tonnes.capacity, Some(Decimal::new(12, 2)));
+        assert_eq!(tonnes.sales_plan, Some(Decimal::new(1, 1)));
+        assert_eq!(
+            kinds(&tonnes),
+            vec![
+                PlanWarningKind::OverCapacity,
+                PlanWarningKind::AboveSalesPlan
+            ]
+        );
+
+        assert!(try_check(Decimal::ONE, &thing("MeasureUnits", "pcs"), None).is_err());
+        assert!(PlanFeasibility::new(
+            Decimal::ONE,
+            &kg,
+            &thing("Pipe", "unknown"),
+            day,
+            &topology,
+            &downtimes,
+            None,
+            &converter,
+            &ctx,
+        )
+        .is_err());
+    }
+}
@@ -0,0 +1,353 @@
+use crate::flow_integration::plant_day;
+use crate::service::guard::RoleGuard;
+use crate::thing_derived::ThingDerived;
+use crate::thing_wrapper::ObjectWithThing;
+use crate::{common::Unwrapper, datetime::DateTimeDerived};
+use async_graphql::SimpleObject;
+use common::{
+    ctx::Ctx,
+    error::{ApiError, Error},
+    role::Role,
+    ApiResult,
+};
+
+use db::Db;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+
+#[allow(dead_code)]
+const RESOURCE: &str = "PlanRevision";
+
+/// State of a sales or production plan after one of its changes.
+/// Written by the events of the plan tables, never changed afterwards
+#[derive(Clone, Debug, Serialize, Deserialize, SimpleObject)]
+#[graphql(guard = "RoleGuard::new(Role::User)")]
+pub struct PlanRevision {
+    pub id: Option<ThingDerived>,
+    /// SalesPlanPerDay or ProductionPlanPerDay
+    pub plan: ThingDerived,
+    /// Number of the revision, from 1 for every plan
+    pub revision: i64,
+    pub amount: Decimal,
+    pub units: ThingDerived,
+    pub date: DateTimeDerived,
+    pub line: Option<ThingDerived>,
+    pub day: Option<DateTimeDerived>,
+    /// The plan was deleted, the other fields are its last state
+    pub deleted: bool,
+    /// None for changes made by the system
+    pub created_by: Option<ThingDerived>,
+    pub created_at: DateTimeDerived,
+}
+
+pub struct PlanRevisionRepository {}
+
+impl PlanRevisionRepository {
+    pub async fn select_by_id(
+        id: &dyn ObjectWithThing,
+        db: &Db,
+        ctx: &dyn Ctx,
+    ) -> ApiResult<PlanRevision> {
+        let thing_id = id.thing(ctx)?;
+        if thing_id.tb != RESOURCE {
+            return Err(ApiError {
+                error: Error::Generic {
+                    description: "Wrong table name in select_by_id".to_string(),
+                },
+                req_id: ctx.req_id(),
+            });
+        }
+        let query = db
+            .query("SELECT * FROM $thing_id".to_string())
+            .bind(("thing_id", thing_id));
+
+        Unwrapper::unwrapper_option(query, 0, "Can't get plan revision by id", ctx).await
+    }
+
+    /// Revisions of the plan, newest first
+    pub async fn select_by_plan(
+        plan: &dyn ObjectWithThing,
+        db: &Db,
+        ctx: &dyn Ctx,
+    ) -> ApiResult<Vec<PlanRevision>> {
+        let query = db
+            .query(format!(
+                "SELECT * FROM {RESOURCE} WHERE plan = $plan ORDER BY revision DESC;"
+            ))
+            .bind(("plan", plan.thing(ctx)?));
+        Unwrapper::unwrapper_vec(query, 0, ctx).await
+    }
+
+    pub async fn select_latest(
+        plan: &dyn ObjectWithThing,
+        db: &Db,
+        ctx: &dyn Ctx,
+    ) -> ApiResult<Option<PlanRevision>> {
+        let query = db
+            .query(format!(
+                "SELECT * FROM {RESOURCE} WHERE plan = $plan ORDER BY revision DESC LIMIT 1;"
+            ))
+            .bind(("plan", plan.thing(ctx)?));
+        Unwrapper::unwrapper_option_without_error(query, 0, ctx).await
+    }
+
+    /// Revisions created until `as_of` of every plan that has ever been planned for the line and day.
+    /// Newest first for every plan
+    pub async fn select_by_day(
+        line: &ThingDerived,
+        day: DateTimeDerived,
+        as_of: DateTimeDerived,
+        db: &Db,
+        ctx: &dyn Ctx,
+    ) -> ApiResult<Vec<PlanRevision>> {
+        let query = db
+            .query(format!(
+                "SELECT * FROM {RESOURCE} \
+                 WHERE plan IN (SELECT VALUE plan FROM {RESOURCE} WHERE line = $line AND day = $day) \
+                 AND created_at <= $as_of ORDER BY plan, revision DESC;"
+            ))
+            .bind(("line", line.thing(ctx)?))
+            .bind(("day", day.0))
+            .bind(("as_of", as_of.0));
+        Unwrapper::unwrapper_vec(query, 0, ctx).await
+    }
+}
+
+pub struct PlanRevisionUseCases {}
+
+impl PlanRevisionUseCases {
+    pub async fn select_by_id(
+        id: &dyn ObjectWithThing,
+        db: &Db,
+        ctx: &dyn Ctx,
+    ) -> ApiResult<PlanRevision> {
+        PlanRevisionRepository::select_by_id(id, db, ctx).await
+    }
+
+    pub async fn select_by_plan(
+        plan: &dyn ObjectWithThing,
+        db: &Db,
+        ctx: &dyn Ctx,
+    ) -> ApiResult<Vec<PlanRevision>> {
+        PlanRevisionRepository::select_by_plan(plan, db, ctx).await
+    }
+
+    /// Current revision of the plan. None for plans not changed since versioning was introduced
+    pub async fn select_latest(
+        plan: &dyn ObjectWithThing,
+        db: &Db,
+        ctx: &dyn Ctx,
+    ) -> ApiResult<Option<PlanRevision>> {
+        PlanRevisionRepository::select_latest(plan, db, ctx).await
+    }
+
+    /// Plan of the table for the line and plant local day containing the date as it was at `as_of`.
+    /// None if there was no plan for the day then. Later changes, deletes included, don't matter
+    pub async fn select_as_of(
+        plan_table: &str,
+        line: &ThingDerived,
+        date: DateTimeDerived,
+        as_of: DateTimeDerived,
+        db: &Db,
+        ctx: &dyn Ctx,
+    ) -> ApiResult<Option<PlanRevision>> {
+        let day = plant_day(&date, ctx)?;
+        let revisions =
+            PlanRevisionRepository::select_by_day(line, day.clone(), as_of, db, ctx).await?;
+        // Only the last revision of every plan before `as_of` counts,
+        // an earlier one may be planned for the day while the plan was already moved
+        let mut current: Vec<PlanRevision> = vec![];
+        for revision in revisions {
+            if revision.plan.tb() == plan_table
+                && !current.iter().any(|other| other.plan == revision.plan)
+            {
+                current.push(revision);
+            }
+        }
+        Ok(current
+            .into_iter()
+            .filter(|revision| {
+                !revision.deleted
+                    && revision.line.as_ref() == Some(line)
+                    && revision.day.as_ref() == Some(&day)
+            })
+            .max_by_key(|revision| revision.created_at.0 .0))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pipe_stats::tests::create_pipe;
+    use crate::production_info::{CreateProductionInfoInput, ProductionInfoUseCases};
+    use crate::production_per_day::{
+        CreateProductionPlanPerDayTypeInput, ProductionPlanPerDayUseCases,
+    };
+    use crate::sales_per_day::{CreateSalesPlanPerDayTypeInput, SalesPlanPerDayUnitsUseCases};
+    use crate::user::tests::create_user;
+    use chrono::{DateTime, TimeZone, Utc};
+    use common::ctx::MockCtx;
+    use db::set_test_db;
+    use rstest::*;
+    use surrealdb::sql::Thing;
+
+    fn ctx(user: Option<Thing>) -> MockCtx {
+        let mut ctx = MockCtx::new();
+        ctx.expect_req_id().return_const(uuid::Uuid::new_v4());
+        ctx.expect_user_id_thing().returning(move || {
+            user.clone().ok_or(ApiError {
+                req_id: uuid::Uuid::new_v4(),
+                error: Error::Generic {
+                    description: "No user".to_string(),
+                },
+            })
+        });
+        ctx
+    }
+
+    #[fixture]
+    async fn tdb() -> Db {
+        set_test_db().await
+    }
+
+    fn at(day: u32, hour: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2024, 1, day, hour, 0, 0).unwrap()
+    }
+
+    #[rstest]
+    #[tokio::test]
+    #[awt]
+    async fn plan_revision_test(#[future] tdb: Db) {
+        let system = ctx(None);
+        let (user, _) = create_user(&system, &tdb).await.unwrap();
+        let author = ctx(Some(user.thing(&system).unwrap()));
+        let (pipe, units) = create_pipe(&system, &tdb).await.unwrap();
+        let (line, units) = (pipe.id.unwrap(), units.id.unwrap());
+        let input = |amount: i64, day: u32| CreateSalesPlanPerDayTypeInput {
+            amount: Decimal::from(amount),
+            units: units.clone(),
+            date: at(day, 8).into(),
+            line: Some(line.clone()),
+        };
+        let as_of = |day: u32, as_of: DateTime<Utc>| {
+            SalesPlanPerDayUnitsUseCases::select_by_date_as_of(
+                &line,
+                at(day, 12).into(),
+                as_of.into(),
+                &tdb,
+                &system,
+            )
+        };
+
+        let before_create = Utc::now();
+        let plan = SalesPlanPerDayUnitsUseCases::create(input(100, 1), &tdb, &system)
+            .await
+            .unwrap();
+        let created = Utc::now();
+        SalesPlanPerDayUnitsUseCases::update(input(120, 1), &plan, &tdb, &author)
+            .await
+            .unwrap();
+        // Saving the same plan again is not a revision
+        SalesPlanPerDayUnitsUseCases::update(input(120, 1), &plan, &tdb, &author)
+            .await
+            .unwrap();
+        let updated = Utc::now();
+
+        // ProductionInfo keeps the revision it was written with
+        let production_plan = ProductionPlanPerDayUseCases::create(
+            CreateProductionPlanPerDayTypeInput {
+                amount: Decimal::from(150),
+                units: units.clone(),
+                date: at(1, 8).into(),
+                line: Some(line.clone()),
+            },
+            &tdb,
+            &system,
+        )
+        .await
+        .unwrap()
+        .plan;
+        let info = ProductionInfoUseCases::create(
+            CreateProductionInfoInput {
+                sales_plan: plan.id.clone().unwrap(),
+                production_plan: production_plan.id.clone().unwrap(),
+                final_pipe: line.clone(),
+                measure_units: units.clone(),
+                date: at(1, 0).into(),
+            },
+            &tdb,
+            &system,
+        )
+        .await
+        .unwrap();
+
+        // Moved to the next day, then deleted
+        SalesPlanPerDayUnitsUseCases::update(input(120, 2), &plan, &tdb, &author)
+            .await
+            .unwrap();
+        let moved = Utc::now();
+        SalesPlanPerDayUnitsUseCases::delete(&plan, &tdb, &author)
+            .await
+            .unwrap();
+
+        let revisions = SalesPlanPerDayUnitsUseCases::revisions(&plan, &tdb, &system)
+            .await
+            .unwrap();
+        assert_eq!(
+            revisions
+                .iter()
+                .map(|revision| (revision.revision, revision.amount, revision.deleted))
+                .collect::<Vec<_>>(),
+            vec![
+                (4, Decimal::from(120), true),
+                (3, Decimal::from(120), false),
+                (2, Decimal::from(120), false),
+                (1, Decimal::from(100), false),
+            ]
+        );
+        assert_eq!(revisions[3].created_by, None);
+        assert_eq!(revisions[2].created_by, user.id);
+        // Author of the deletion comes from the audit log mark
+        assert_eq!(revisions[0].created_by, user.id);
+        assert_eq!(info.sales_plan_revision, revisions[2].id);
+        assert_eq!(
+            info.production_plan_revision,
+            PlanRevisionUseCases::select_latest(&production_plan, &tdb, &system)
+                .await
+                .unwrap()
+                .unwrap()
+                .id
+        );
+
+        let amount = |revision: Option<PlanRevision>| revision.map(|revision| revision.amount);
+        assert_eq!(amount(as_of(1, before_create).await.unwrap()), None);
+        assert_eq!(
+            amount(as_of(1, created).await.unwrap()),
+            Some(Decimal::from(100))
+        );
+        assert_eq!(
+            amount(as_of(1, updated).await.unwrap()),
+            Some(Decimal::from(120))
+        );
+        assert_eq!(amount(as_of(1, moved).await.unwrap()), None);
+        assert_eq!(
+            amount(as_of(2, moved).await.unwrap()),
+            Some(Decimal::from(120))
+        );
+        assert_eq!(amount(as_of(2, Utc::now()).await.unwrap()), None);
+        // Production plan of the same day is not taken for the sales plan
+        assert_eq!(
+            ProductionPlanPerDayUseCases::select_by_date_as_of(
+                &line,
+                at(1, 12).into(),
+                Utc::now().into(),
+                &tdb,
+                &system,
+            )
+            .await
+            .unwrap()
+            .map(|revision| revision.plan),
+            production_plan.id
+        );
+    }
+}
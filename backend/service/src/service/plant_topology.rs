@@ -0,0 +1,467 @@
+use crate::machinery::Machinery;
+use crate::machinery_type::MachineryType;
+use crate::measure_units::MeasureUnits;
+use crate::pipe::Pipe;
+use crate::pipe_from::PipeFrom;
+use crate::pipe_to::PipeTo;
+use crate::pipe_type::PipeType;
+use crate::service::guard::RoleGuard;
+use crate::thing_derived::ThingDerived;
+use crate::thing_wrapper::ObjectWithThing;
+use async_graphql::{Enum, SimpleObject};
+use common::{
+    ctx::Ctx,
+    error::{ApiError, Error},
+    role::Role,
+    ApiResult,
+};
+
+use db::Db;
+use petgraph::algo::{tarjan_scc, toposort};
+use petgraph::graph::{DiGraph, NodeIndex};
+use rust_decimal::Decimal;
+use std::collections::HashMap;
+
+#[derive(Enum, Copy, Clone, Debug, Eq, PartialEq)]
+pub enum TopologyNodeKind {
+    Machinery,
+    Pipe,
+}
+
+#[derive(Enum, Copy, Clone, Debug, Eq, PartialEq)]
+pub enum TopologyEdgeKind {
+    /// Machinery -> Pipe
+    PipeTo,
+    /// Pipe -> Machinery
+    PipeFrom,
+}
+
+/// Machinery or Pipe with its type and units resolved
+#[derive(Clone, Debug, SimpleObject)]
+#[graphql(guard = "RoleGuard::new(Role::User)")]
+pub struct TopologyNode {
+    pub id: ThingDerived,
+    pub kind: TopologyNodeKind,
+    pub name: String,
+    /// MachineryType or PipeType
+    pub node_type: ThingDerived,
+    pub type_name: String,
+    pub max_flow: Decimal,
+    pub wearout_max: Decimal,
+    pub units: ThingDerived,
+    pub units_name: String,
+    /// RawMaterial. Only for pipes
+    pub material: Option<ThingDerived>,
+}
+
+#[derive(Clone, Debug, SimpleObject)]
+#[graphql(guard = "RoleGuard::new(Role::User)")]
+pub struct TopologyEdge {
+    pub id: ThingDerived,
+    pub kind: TopologyEdgeKind,
+    pub from: ThingDerived,
+    pub to: ThingDerived,
+}
+
+/// Whole production line as a directed graph
+#[derive(Clone, Debug, SimpleObject)]
+#[graphql(guard = "RoleGuard::new(Role::User)")]
+pub struct PlantTopology {
+    pub nodes: Vec<TopologyNode>,
+    pub edges: Vec<TopologyEdge>,
+    pub has_cycles: bool,
+    /// Every group of nodes that are reachable from each other
+    pub cycles: Vec<Vec<ThingDerived>>,
+    /// Nodes ordered from raw inputs to final products. None if the line has cycles
+    pub topological_order: Option<Vec<ThingDerived>>,
+    /// Pipes that no machinery outputs into
+    pub input_pipes: Vec<ThingDerived>,
+    /// Pipes that feed no machinery
+    pub final_pipes: Vec<ThingDerived>,
+}
+
+/// Raw records the topology is built from
+#[derive(Clone, Debug, Default)]
+pub struct PlantRecords {
+    pub machinery: Vec<Machinery>,
+    pub machinery_types: Vec<MachineryType>,
+    pub pipes: Vec<Pipe>,
+    pub pipe_types: Vec<PipeType>,
+    pub units: Vec<MeasureUnits>,
+    pub pipe_to: Vec<PipeTo>,
+    pub pipe_from: Vec<PipeFrom>,
+}
+
+fn not_found(what: &str, id: &ThingDerived, ctx: &dyn Ctx) -> ApiError {
+    ApiError {
+        req_id: ctx.req_id(),
+        error: Error::Generic {
+            description: format!("Plant topology: {what} {id} not found"),
+        },
+    }
+}
+
+fn by_id<'a, T: ObjectWithThing>(
+    records: &'a [T],
+    ctx: &dyn Ctx,
+) -> ApiResult<HashMap<String, &'a T>> {
+    records
+        .iter()
+        .map(|record| Ok((record.thing(ctx)?.to_string(), record)))
+        .collect()
+}
+
+impl PlantTopology {
+    pub fn from_records(records: &PlantRecords, ctx: &dyn Ctx) -> ApiResult<Self> {
+        let machinery_types = by_id(&records.machinery_types, ctx)?;
+        let pipe_types = by_id(&records.pipe_types, ctx)?;
+        let units = by_id(&records.units, ctx)?;
+        let units_name = |id: &ThingDerived| -> ApiResult<String> {
+            Ok(units
+                .get(&id.to_string())
+                .ok_or_else(|| not_found("MeasureUnits", id, ctx))?
+                .name
+                .clone())
+        };
+
+        let mut nodes = vec![];
+        for machinery in records.machinery.iter() {
+            let machinery_type = machinery_types
+                .get(&machinery.machinery_type.to_string())
+                .ok_or_else(|| not_found("MachineryType", &machinery.machinery_type, ctx))?;
+            nodes.push(TopologyNode {
+                id: machinery.thing(ctx)?.into(),
+                kind: TopologyNodeKind::Machinery,
+                name: machinery.name.clone(),
+                node_type: machinery.machinery_type.clone(),
+                type_name: machinery_type.name.clone(),
+                max_flow: machinery_type.max_flow,
+                wearout_max: machinery_type.wearout_max,
+                units: machinery_type.units.clone(),
+                units_name: units_name(&machinery_type.units)?,
+                material: None,
+            });
+        }
+        for pipe in records.pipes.iter() {
+            let pipe_type = pipe_types
+                .get(&pipe.pipe_type.to_string())
+                .ok_or_else(|| not_found("PipeType", &pipe.pipe_type, ctx))?;
+            nodes.push(TopologyNode {
+                id: pipe.thing(ctx)?.into(),
+                kind: TopologyNodeKind::Pipe,
+                name: pipe.name.clone(),
+                node_type: pipe.pipe_type.clone(),
+                type_name: pipe_type.name.clone(),
+                max_flow: pipe_type.max_flow,
+                wearout_max: pipe_type.wearout_max,
+                units: pipe_type.units.clone(),
+                units_name: units_name(&pipe_type.units)?,
+                material: Some(pipe.material.clone()),
+            });
+        }
+
+        let mut edges = vec![];
+        for pipe_to in records.pipe_to.iter() {
+            edges.push(TopologyEdge {
+                id: pipe_to.thing(ctx)?.into(),
+                kind: TopologyEdgeKind::PipeTo,
+                from: pipe_to.r#in.clone(),
+                to: pipe_to.out.clone(),
+            });
+        }
+        for pipe_from in records.pipe_from.iter() {
+            edges.push(TopologyEdge {
+                id: pipe_from.thing(ctx)?.into(),
+                kind: TopologyEdgeKind::PipeFrom,
+                from: pipe_from.r#in.clone(),
+                to: pipe_from.out.clone(),
+            });
+        }
+
+        let mut graph = DiGraph::<usize, ()>::new();
+        let mut indexes: HashMap<String, NodeIndex> = HashMap::new();
+        for (i, node) in nodes.iter().enumerate() {
+            indexes.insert(node.id.to_string(), graph.add_node(i));
+        }
+        for edge in edges.iter() {
+            let from = *indexes
+                .get(&edge.from.to_string())
+                .ok_or_else(|| not_found("node", &edge.from, ctx))?;
+            let to = *indexes
+                .get(&edge.to.to_string())
+                .ok_or_else(|| not_found("node", &edge.to, ctx))?;
+            graph.add_edge(from, to, ());
+        }
+
+        let cycles: Vec<Vec<ThingDerived>> = tarjan_scc(&graph)
+            .into_iter()
+            .filter(|component| {
+                component.len() > 1 || graph.contains_edge(component[0], component[0])
+            })
+            .map(|component| {
+                component
+                    .into_iter()
+                    .map(|index| nodes[graph[index]].id.clone())
+                    .collect()
+            })
+            .collect();
+        let topological_order = toposort(&graph, None).ok().map(|order| {
+            order
+                .into_iter()
+                .map(|index| nodes[graph[index]].id.clone())
+                .collect()
+        });
+
+        let pipes_without = |kind: TopologyEdgeKind| -> Vec<ThingDerived> {
+            nodes
+                .iter()
+                .filter(|node| node.kind == TopologyNodeKind::Pipe)
+                .filter(|node| {
+                    !edges.iter().any(|edge| {
+                        edge.kind == kind
+                            && match kind {
+                                TopologyEdgeKind::PipeTo => edge.to == node.id,
+                                TopologyEdgeKind::PipeFrom => edge.from == node.id,
+                            }
+                    })
+                })
+                .map(|node| node.id.clone())
+                .collect()
+        };
+        let input_pipes = pipes_without(TopologyEdgeKind::PipeTo);
+        let final_pipes = pipes_without(TopologyEdgeKind::PipeFrom);
+
+        Ok(Self {
+            has_cycles: !cycles.is_empty(),
+            cycles,
+            topological_order,
+            input_pipes,
+            final_pipes,
+            nodes,
+            edges,
+        })
+    }
+
+    /// Final pipes reachable from the node, the node itself if it is one
+    pub fn downstream_final_pipes(&self, node: &ThingDerived) -> Vec<ThingDerived> {
+        let mut visited = vec![node.clone()];
+        let mut queue = vec![node.clone()];
+        while let Some(current) = queue.pop() {
+            for edge in self.edges.iter().filter(|edge| edge.from == current) {
+                if !visited.contains(&edge.to) {
+                    visited.push(edge.to.clone());
+                    queue.push(edge.to.clone());
+                }
+            }
+        }
+        self.final_pipes
+            .iter()
+            .filter(|pipe| visited.contains(pipe))
+            .cloned()
+            .collect()
+    }
+}
+
+pub struct PlantTopologyRepository {}
+
+impl PlantTopologyRepository {
+    pub async fn select_records(db: &Db, ctx: &dyn Ctx) -> ApiResult<PlantRecords> {
+        let mut response = db
+            .query("SELECT * FROM Machinery;")
+            .query("SELECT * FROM MachineryType;")
+            .query("SELECT * FROM Pipe;")
+            .query("SELECT * FROM PipeType;")
+            .query("SELECT * FROM MeasureUnits;")
+            .query("SELECT * FROM PipeTo;")
+            .query("SELECT * FROM PipeFrom;")
+            .await
+            .map_err(ApiError::from(ctx))?;
+        Ok(PlantRecords {
+            machinery: response.take(0).map_err(ApiError::from(ctx))?,
+            machinery_types: response.take(1).map_err(ApiError::from(ctx))?,
+            pipes: response.take(2).map_err(ApiError::from(ctx))?,
+            pipe_types: response.take(3).map_err(ApiError::from(ctx))?,
+            units: response.take(4).map_err(ApiError::from(ctx))?,
+            pipe_to: response.take(5).map_err(ApiError::from(ctx))?,
+            pipe_from: response.take(6).map_err(ApiError::from(ctx))?,
+        })
+    }
+}
+
+pub struct PlantTopologyUseCases {}
+
+impl PlantTopologyUseCases {
+    pub async fn get(db: &Db, ctx: &dyn Ctx) -> ApiResult<PlantTopology> {
+        let records = PlantTopologyRepository::select_records(db, ctx).await?;
+        PlantTopology::from_records(&records, ctx)
+    }
+
+    /// The only pipe that feeds no machinery.
+    /// Error if the line has several final pipes or none
+    pub async fn select_final_pipe(db: &Db, ctx: &dyn Ctx) -> ApiResult<ThingDerived> {
+        let topology = Self::get(db, ctx).await?;
+        match topology.final_pipes.as_slice() {
+            [final_pipe] => Ok(final_pipe.clone()),
+            final_pipes => Err(ApiError {
+                req_id: ctx.req_id(),
+                error: Error::Generic {
+                    description: format!(
+                        "Can't choose final pipe: line has {} of them",
+                        final_pipes.len()
+                    ),
+                },
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use common::ctx::MockCtx;
+    use rstest::*;
+    use surrealdb::sql::Thing;
+
+    #[fixture]
+    fn ctx() -> MockCtx {
+        let mut ctx = MockCtx::new();
+        ctx.expect_req_id().return_const(uuid::Uuid::new_v4());
+        ctx.expect_user_id_thing().returning(|| {
+            Err(ApiError {
+                req_id: uuid::Uuid::new_v4(),
+                error: Error::Generic {
+                    description: "No user".to_string(),
+                },
+            })
+        });
+        ctx
+    }
+
+    fn thing(tb: &str, id: &str) -> ThingDerived {
+        Thing::from((tb, id)).into()
+    }
+
+    /// in_pipe -> mixer -> mid_pipe -> packer -> final_pipe
+    fn line() -> PlantRecords {
+        let units = MeasureUnits {
+            id: Some(thing("MeasureUnits", "kg")),
+            name: "кг".to_string(),
+            dimension: None,
+            factor: Decimal::ONE,
+        };
+        let machinery_type = MachineryType {
+            id: Some(thing("MachineryType", "mt")),
+            name: "Смеситель".to_string(),
+            wearout_max: Decimal::from(100),
+            max_flow: Decimal::from(10),
+            units: thing("MeasureUnits", "kg"),
+        };
+        let pipe_type = PipeType {
+            id: Some(thing("PipeType", "pt")),
+            name: "Конвейер".to_string(),
+            max_flow: Decimal::from(10),
+            wearout_max: Decimal::from(100),
+            units: thing("MeasureUnits", "kg"),
+        };
+        let machinery = |id: &str| Machinery {
+            id: Some(thing("Machinery", id)),
+            name: id.to_string(),
+            machinery_type: thing("MachineryType", "mt"),
+            min_hold_seconds: None,
+        };
+        let pipe = |id: &str| Pipe {
+            id: Some(thing("Pipe", id)),
+            name: id.to_string(),
+            pipe_type: thing("PipeType", "pt"),
+            material: thing("RawMaterial", "rm"),
+        };
+        let pipe_to = |id: &str, from: &str, to: &str| PipeTo {
+            id: Some(thing("PipeTo", id)),
+            r#in: thing("Machinery", from),
+            out: thing("Pipe", to),
+        };
+        let pipe_from = |id: &str, from: &str, to: &str| PipeFrom {
+            id: Some(thing("PipeFrom", id)),
+            r#in: thing("Pipe", from),
+            out: thing("Machinery", to),
+        };
+        PlantRecords {
+            machinery: vec![machinery("packer"), machinery("mixer")],
+            machinery_types: vec![machinery_type],
+            pipes: vec![pipe("final_pipe"), pipe("mid_pipe"), pipe("in_pipe")],
+            pipe_types: vec![pipe_type],
+            units: vec![units],
+            pipe_to: vec![
+                pipe_to("1", "mixer", "mid_pipe"),
+                pipe_to("2", "packer", "final_pipe"),
+            ],
+            pipe_from: vec![
+                pipe_from("1", "in_pipe", "mixer"),
+                pipe_from("2", "mid_pipe", "packer"),
+            ],
+        }
+    }
+
+    #[rstest]
+    fn topology_order_test(ctx: MockCtx) {
+        let topology = PlantTopology::from_records(&line(), &ctx).unwrap();
+        assert_eq!(topology.nodes.len(), 5);
+        assert_eq!(topology.edges.len(), 4);
+        assert!(!topology.has_cycles);
+        assert_eq!(
+            topology.topological_order.unwrap(),
+            vec![
+                thing("Pipe", "in_pipe"),
+                thing("Machinery", "mixer"),
+                thing("Pipe", "mid_pipe"),
+                thing("Machinery", "packer"),
+                thing("Pipe", "final_pipe"),
+            ]
+        );
+        assert_eq!(topology.input_pipes, vec![thing("Pipe", "in_pipe")]);
+        assert_eq!(topology.final_pipes, vec![thing("Pipe", "final_pipe")]);
+        assert_eq!(topology.nodes[0].units_name, "кг");
+    }
+
+    #[rstest]
+    fn downstream_final_pipes_test(ctx: MockCtx) {
+        let topology = PlantTopology::from_records(&line(), &ctx).unwrap();
+        assert_eq!(
+            topology.downstream_final_pipes(&thing("Machinery", "mixer")),
+            vec![thing("Pipe", "final_pipe")]
+        );
+        assert_eq!(
+            topology.downstream_final_pipes(&thing("Pipe", "final_pipe")),
+            vec![thing("Pipe", "final_pipe")]
+        );
+        assert!(topology
+            .downstream_final_pipes(&thing("Pipe", "unknown"))
+            .is_empty());
+    }
+
+    #[rstest]
+    fn topology_cycle_test(ctx: MockCtx) {
+        let mut records = line();
+        // Return line from packer back to mixer
+        records.pipe_from.push(PipeFrom {
+            id: Some(thing("PipeFrom", "3")),
+            r#in: thing("Pipe", "final_pipe"),
+            out: thing("Machinery", "mixer"),
+        });
+        let topology = PlantTopology::from_records(&records, &ctx).unwrap();
+        assert!(topology.has_cycles);
+        assert!(topology.topological_order.is_none());
+        assert_eq!(topology.cycles.len(), 1);
+        assert_eq!(topology.cycles[0].len(), 4);
+        assert!(topology.final_pipes.is_empty());
+    }
+
+    #[rstest]
+    fn topology_dangling_edge_test(ctx: MockCtx) {
+        let mut records = line();
+        records
+            .machinery
+            .retain(|machinery| machinery.name != "packer");
+        assert!(PlantTopology::from_records(&records, &ctx).is_err());
+    }
+}
@@ -1,12 +1,19 @@
 use crate::{
     datetime::DateTimeDerived,
+    machinery::{CreateMachineryInput, Machinery, MachineryUseCases},
     machinery_type::{CreateMachineryTypeInput, MachineryType, MachineryTypeUseCases},
-    measure_units::{CreateMeasureUnitsTypeInput, MeasureUnits, MeasureUnitsUseCases},
+    measure_units::{
+        CreateMeasureUnitsTypeInput, MeasureUnits, MeasureUnitsUseCases, UnitDimension,
+    },
     pipe::{CreatePipeInput, Pipe, PipeUseCases},
+    pipe_from::{CreatePipeFromInput, PipeFromUseCases},
     pipe_stats::{CreatePipeStatsInput, PipeStats, PipeStatsUseCases},
+    pipe_to::{CreatePipeToInput, PipeToUseCases},
     pipe_type::{CreatePipeTypeInput, PipeType, PipeTypeUseCases},
     production_info::ProductionInfoUseCases,
     production_per_day::{CreateProductionPlanPerDayTypeInput, ProductionPlanPerDayUseCases},
+    raw_material::RawMaterial,
+    recipe::{CreateRecipeIngredientInput, CreateRecipeInput, Recipe, RecipeUseCases},
     sales_per_day::{CreateSalesPlanPerDayTypeInput, SalesPlanPerDayUnitsUseCases},
     thing_derived::ThingDerived,
     thing_wrapper::ObjectWithThing,
@@ -103,10 +110,130 @@ pub async fn pipe_type_shortcut(
     .await
 }
 
+pub async fn machinery_shortcut(
+    name: &str,
+    machinery_type: &MachineryType,
+    db: &Db,
+    ctx: &dyn Ctx,
+) -> ApiResult<Machinery> {
+    MachineryUseCases::create(
+        CreateMachineryInput {
+            name: name.to_string(),
+            machinery_type: machinery_type.id.clone().unwrap(),
+            min_hold_seconds: None,
+        },
+        db,
+        ctx,
+    )
+    .await
+}
+
+pub async fn pipe_shortcut(
+    name: &str,
+    pipe_type: &PipeType,
+    material: &dyn ObjectWithThing,
+    db: &Db,
+    ctx: &dyn Ctx,
+) -> ApiResult<Pipe> {
+    PipeUseCases::create(
+        CreatePipeInput {
+            name: name.to_string(),
+            pipe_type: pipe_type.id.clone().unwrap(),
+            material: ThingDerived::from(material.thing(ctx)?),
+        },
+        db,
+        ctx,
+    )
+    .await
+}
+
+/// Connects machinery with its input and output pipes
+/// input pipes -> PipeFrom -> machinery -> PipeTo -> output pipes
+pub async fn connect_shortcut(
+    inputs: &[&Pipe],
+    machinery: &Machinery,
+    outputs: &[&Pipe],
+    db: &Db,
+    ctx: &dyn Ctx,
+) -> ApiResult<()> {
+    for pipe in inputs {
+        PipeFromUseCases::create(
+            CreatePipeFromInput {
+                r#in: pipe.id.clone().unwrap(),
+                out: machinery.id.clone().unwrap(),
+            },
+            db,
+            ctx,
+        )
+        .await?;
+    }
+    for pipe in outputs {
+        PipeToUseCases::create(
+            CreatePipeToInput {
+                r#in: machinery.id.clone().unwrap(),
+                out: pipe.id.clone().unwrap(),
+            },
+            db,
+            ctx,
+        )
+        .await?;
+    }
+    Ok(())
+}
+
 pub async fn measure_units_shortcut(name: &str, db: &Db, ctx: &dyn Ctx) -> ApiResult<MeasureUnits> {
     MeasureUnitsUseCases::create(
         CreateMeasureUnitsTypeInput {
             name: name.to_string(),
+            dimension: None,
+            factor: None,
+        },
+        db,
+        ctx,
+    )
+    .await
+}
+
+pub async fn dimension_units_shortcut(
+    name: &str,
+    dimension: UnitDimension,
+    factor: Decimal,
+    db: &Db,
+    ctx: &dyn Ctx,
+) -> ApiResult<MeasureUnits> {
+    MeasureUnitsUseCases::create(
+        CreateMeasureUnitsTypeInput {
+            name: name.to_string(),
+            dimension: Some(dimension),
+            factor: Some(factor),
+        },
+        db,
+        ctx,
+    )
+    .await
+}
+
+pub async fn recipe_shortcut(
+    product: &RawMaterial,
+    output_amount: Decimal,
+    output_units: &MeasureUnits,
+    ingredients: &[(&RawMaterial, Decimal, &MeasureUnits)],
+    db: &Db,
+    ctx: &dyn Ctx,
+) -> ApiResult<Recipe> {
+    RecipeUseCases::create(
+        CreateRecipeInput {
+            product: product.id.clone().unwrap(),
+            output_amount,
+            output_units: output_units.id.clone().unwrap(),
+            ingredients: ingredients
+                .iter()
+                .map(|(material, amount, units)| CreateRecipeIngredientInput {
+                    material: material.id.clone().unwrap(),
+                    amount: *amount,
+                    units: units.id.clone().unwrap(),
+                })
+                .collect(),
         },
         db,
         ctx,
@@ -140,12 +267,27 @@ pub async fn pipe_stats_shortcut(
 pub async fn seed_data() {
     use crate::service::{
         measure_units,
+        quality_spec::{CreateQualitySpecInput, QualityParameter, QualitySpecUseCases},
         raw_material::{CreateRawMaterialInput, RawMaterialUseCases},
+        stock_movement::{CreateStockMovementInput, StockMovementKind, StockMovementUseCases},
+    };
+    use common::{
+        ctx::MockCtx,
+        error::{ApiError, Error},
+        role::Role,
     };
-    use common::{ctx::MockCtx, role::Role};
     use db::DB;
     let mut ctx = MockCtx::new();
     ctx.expect_req_id().return_const(uuid::Uuid::new_v4());
+    // Сидирование выполняется без пользователя
+    ctx.expect_user_id_thing().returning(|| {
+        Err(ApiError {
+            req_id: uuid::Uuid::new_v4(),
+            error: Error::Generic {
+                description: "No user".to_string(),
+            },
+        })
+    });
     // Вход:
     // - Просеенный сахар
     // - Очищенное масло
@@ -282,45 +424,37 @@ pub async fn seed_data() {
     .await
     .unwrap();
 
-    let m_3 = measure_units_shortcut("м^3", &DB, &ctx).await.unwrap();
-    let briquette = measure_units_shortcut("брикет", &DB, &ctx).await.unwrap();
-    let palette = measure_units_shortcut("палет", &DB, &ctx).await.unwrap();
-
-    let production_plan_per_day0 = ProductionPlanPerDayUseCases::create(
-        CreateProductionPlanPerDayTypeInput {
-            amount: Decimal::new(100, 0),
-            units: palette.id.clone().unwrap(),
-            date: DateTimeDerived(
-                DateTime::from_utc(
-                    NaiveDate::from_ymd_opt(2024, 1, 1)
-                        .unwrap()
-                        .and_hms_opt(0, 0, 0)
-                        .unwrap(),
-                    Utc,
-                )
-                .into(),
-            ),
-        },
+    let m_3 = dimension_units_shortcut("м^3", UnitDimension::Volume, Decimal::ONE, &DB, &ctx)
+        .await
+        .unwrap();
+    let liter =
+        dimension_units_shortcut("литр", UnitDimension::Volume, Decimal::new(1, 3), &DB, &ctx)
+            .await
+            .unwrap();
+    let briquette =
+        dimension_units_shortcut("брикет", UnitDimension::Count, Decimal::ONE, &DB, &ctx)
+            .await
+            .unwrap();
+    // 480 брикетов на палете
+    let palette = dimension_units_shortcut(
+        "палет",
+        UnitDimension::Count,
+        Decimal::new(480, 0),
         &DB,
         &ctx,
     )
     .await
     .unwrap();
 
-    let production_plan_per_day1 = ProductionPlanPerDayUseCases::create(
-        CreateProductionPlanPerDayTypeInput {
-            amount: Decimal::new(105, 0),
-            units: palette.id.clone().unwrap(),
-            date: DateTimeDerived(
-                DateTime::from_utc(
-                    NaiveDate::from_ymd_opt(2024, 1, 2)
-                        .unwrap()
-                        .and_hms_opt(0, 0, 0)
-                        .unwrap(),
-                    Utc,
-                )
-                .into(),
-            ),
+    let pipe_type = pipe_type_shortcut("Конвейер с палетами мороженого", &palette, &DB, &ctx)
+        .await
+        .unwrap();
+
+    let final_pipe = PipeUseCases::create(
+        CreatePipeInput {
+            name: "Конвейер с палетами мороженого № 1".to_string(),
+            material: ice_cream_pallet.id.clone().unwrap(),
+            pipe_type: pipe_type.id.clone().unwrap(),
         },
         &DB,
         &ctx,
@@ -328,63 +462,223 @@ pub async fn seed_data() {
     .await
     .unwrap();
 
-    let sales_plan_per_day0 = SalesPlanPerDayUnitsUseCases::create(
-        CreateSalesPlanPerDayTypeInput {
-            amount: Decimal::new(105, 0),
-            units: palette.id.clone().unwrap(),
-            date: DateTimeDerived(
-                DateTime::from_utc(
-                    NaiveDate::from_ymd_opt(2024, 1, 1)
-                        .unwrap()
-                        .and_hms_opt(0, 0, 0)
-                        .unwrap(),
-                    Utc,
-                )
-                .into(),
-            ),
-        },
+    // Производственная линия: трубы и конвейеры между этапами
+    let kg = dimension_units_shortcut("кг", UnitDimension::Mass, Decimal::ONE, &DB, &ctx)
+        .await
+        .unwrap();
+    let bulk_type = pipe_type_shortcut("Конвейер сыпучих продуктов", &kg, &DB, &ctx)
+        .await
+        .unwrap();
+    let liquid_type = pipe_type_shortcut("Трубопровод", &m_3, &DB, &ctx)
+        .await
+        .unwrap();
+    let briquette_type = pipe_type_shortcut("Конвейер с брикетами", &briquette, &DB, &ctx)
+        .await
+        .unwrap();
+
+    let sugar_pipe = pipe_shortcut("Конвейер сахара", &bulk_type, &sugar, &DB, &ctx)
+        .await
+        .unwrap();
+    let butter_pipe = pipe_shortcut("Конвейер масла", &bulk_type, &butter, &DB, &ctx)
+        .await
+        .unwrap();
+    let milk_pipe = pipe_shortcut("Трубопровод молока", &liquid_type, &milk, &DB, &ctx)
+        .await
+        .unwrap();
+    let cream_pipe = pipe_shortcut("Трубопровод сливок", &liquid_type, &cream, &DB, &ctx)
+        .await
+        .unwrap();
+
+    // Этап - (оборудование, продукт этапа, минимальная выдержка в секундах)
+    // Пастеризация - не менее 25 с, созревание смеси - не менее 4 ч
+    let stages = [
+        ("Смеситель", &initial_mix, None),
+        ("Фильтр", &filtered_mix, None),
+        ("Пастеризатор", &pasteurized_mix, Some(25)),
+        ("Гомогенизатор", &homogenized_mix, None),
+        ("Охладитель", &cooled_mix, None),
+        ("Ванна созревания", &matured_mix, Some(4 * 3600)),
+        ("Фризер", &ice_cream, None),
+        ("Фасовочный автомат", &ice_cream_briquette, None),
+    ];
+    let mut inputs = vec![sugar_pipe, butter_pipe, milk_pipe, cream_pipe];
+    let mut stage_pipes = vec![];
+    for (machinery_name, product, min_hold_seconds) in stages {
+        let machinery_type = machinery_type_shortcut(machinery_name, &m_3, &DB, &ctx)
+            .await
+            .unwrap();
+        let machinery = MachineryUseCases::create(
+            CreateMachineryInput {
+                name: format!("{machinery_name} № 1"),
+                machinery_type: machinery_type.id.clone().unwrap(),
+                min_hold_seconds,
+            },
+            &DB,
+            &ctx,
+        )
+        .await
+        .unwrap();
+        let output_type = if product.id == ice_cream_briquette.id {
+            &briquette_type
+        } else {
+            &liquid_type
+        };
+        let output = pipe_shortcut(&product.name, output_type, product, &DB, &ctx)
+            .await
+            .unwrap();
+        connect_shortcut(
+            &inputs.iter().collect::<Vec<_>>(),
+            &machinery,
+            &[&output],
+            &DB,
+            &ctx,
+        )
+        .await
+        .unwrap();
+        stage_pipes.push(output.clone());
+        inputs = vec![output];
+    }
+    let packing_type = machinery_type_shortcut("Упаковочный автомат", &palette, &DB, &ctx)
+        .await
+        .unwrap();
+    let packing = machinery_shortcut("Упаковочный автомат № 1", &packing_type, &DB, &ctx)
+        .await
+        .unwrap();
+    connect_shortcut(
+        &inputs.iter().collect::<Vec<_>>(),
+        &packing,
+        &[&final_pipe],
         &DB,
         &ctx,
     )
     .await
     .unwrap();
 
-    let sales_plan_per_day1 = SalesPlanPerDayUnitsUseCases::create(
-        CreateSalesPlanPerDayTypeInput {
-            amount: Decimal::new(105, 0),
-            units: palette.id.clone().unwrap(),
-            date: DateTimeDerived(
-                DateTime::from_utc(
-                    NaiveDate::from_ymd_opt(2024, 1, 2)
-                        .unwrap()
-                        .and_hms_opt(0, 0, 0)
-                        .unwrap(),
-                    Utc,
-                )
-                .into(),
-            ),
-        },
+    // Рецептуры этапов: сколько входа нужно на единицу продукта этапа
+    recipe_shortcut(
+        &initial_mix,
+        Decimal::ONE,
+        &m_3,
+        &[
+            (&sugar, Decimal::new(150, 0), &kg),
+            (&butter, Decimal::new(100, 0), &kg),
+            (&milk, Decimal::new(6, 1), &m_3),
+            (&cream, Decimal::new(25, 2), &m_3),
+        ],
         &DB,
         &ctx,
     )
     .await
     .unwrap();
-
-    let pipe_type = pipe_type_shortcut("Конвейер с палетами мороженого", &palette, &DB, &ctx)
+    // При пастеризации теряется 10% смеси
+    let mix_stages = [
+        (&filtered_mix, Decimal::ONE, &initial_mix),
+        (&pasteurized_mix, Decimal::new(9, 1), &filtered_mix),
+        (&homogenized_mix, Decimal::ONE, &pasteurized_mix),
+        (&cooled_mix, Decimal::ONE, &homogenized_mix),
+        (&matured_mix, Decimal::ONE, &cooled_mix),
+    ];
+    for (product, output_amount, input) in mix_stages {
+        recipe_shortcut(
+            product,
+            output_amount,
+            &m_3,
+            &[(input, Decimal::ONE, &m_3)],
+            &DB,
+            &ctx,
+        )
         .await
         .unwrap();
-
-    let final_pipe = PipeUseCases::create(
-        CreatePipeInput {
-            name: "Конвейер с палетами мороженого № 1".to_string(),
-            material: ice_cream_pallet.id.clone().unwrap(),
-            pipe_type: pipe_type.id.clone().unwrap(),
-        },
+    }
+    // Взбитый воздух удваивает объем смеси
+    recipe_shortcut(
+        &ice_cream,
+        Decimal::new(2, 0),
+        &m_3,
+        &[(&matured_mix, Decimal::ONE, &m_3)],
+        &DB,
+        &ctx,
+    )
+    .await
+    .unwrap();
+    recipe_shortcut(
+        &ice_cream_briquette,
+        Decimal::ONE,
+        &briquette,
+        &[(&ice_cream, Decimal::new(5, 1), &liter)],
         &DB,
         &ctx,
     )
     .await
     .unwrap();
+    recipe_shortcut(
+        &ice_cream_pallet,
+        Decimal::ONE,
+        &palette,
+        &[(&ice_cream_briquette, Decimal::new(480, 0), &briquette)],
+        &DB,
+        &ctx,
+    )
+    .await
+    .unwrap();
+
+    // Начальные запасы сырья на складе
+    let receipts = [
+        (&sugar, Decimal::new(20000, 0), &kg),
+        (&butter, Decimal::new(15000, 0), &kg),
+        (&milk, Decimal::new(90, 0), &m_3),
+        (&cream, Decimal::new(40, 0), &m_3),
+    ];
+    for (material, amount, units) in receipts {
+        StockMovementUseCases::create(
+            CreateStockMovementInput {
+                material: material.id.clone().unwrap(),
+                date: Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap().into(),
+                kind: StockMovementKind::Receipt,
+                amount,
+                units: units.id.clone().unwrap(),
+                comment: None,
+            },
+            &DB,
+            &ctx,
+        )
+        .await
+        .unwrap();
+    }
+
+    // Допуски по стадиям: пастеризация при 80-85 °C, охлаждение и созревание при 2-6 °C
+    let specs = [
+        (&initial_mix, QualityParameter::Fat, Some(10), Some(16)),
+        (
+            &pasteurized_mix,
+            QualityParameter::Temperature,
+            Some(80),
+            Some(85),
+        ),
+        (&cooled_mix, QualityParameter::Temperature, Some(2), Some(6)),
+        (
+            &matured_mix,
+            QualityParameter::Temperature,
+            Some(2),
+            Some(6),
+        ),
+        (&matured_mix, QualityParameter::Viscosity, None, Some(300)),
+    ];
+    for (material, parameter, min, max) in specs {
+        QualitySpecUseCases::create(
+            CreateQualitySpecInput {
+                material: material.id.clone().unwrap(),
+                parameter,
+                min: min.map(Decimal::from),
+                max: max.map(Decimal::from),
+                comment: None,
+            },
+            &DB,
+            &ctx,
+        )
+        .await
+        .unwrap();
+    }
 
     // Теперь необходимо добавить pipe_stats для данной сущности
     // pipe_stats считается по изменению
@@ -466,6 +760,108 @@ pub async fn seed_data() {
     .await
     .unwrap();
 
+    let production_plan_per_day0 = ProductionPlanPerDayUseCases::create(
+        CreateProductionPlanPerDayTypeInput {
+            amount: Decimal::new(100, 0),
+            units: palette.id.clone().unwrap(),
+            date: DateTimeDerived(
+                DateTime::from_utc(
+                    NaiveDate::from_ymd_opt(2024, 1, 1)
+                        .unwrap()
+                        .and_hms_opt(0, 0, 0)
+                        .unwrap(),
+                    Utc,
+                )
+                .into(),
+            ),
+            line: Some(final_pipe.thing(&ctx).unwrap().into()),
+        },
+        &DB,
+        &ctx,
+    )
+    .await
+    .unwrap();
+
+    let production_plan_per_day1 = ProductionPlanPerDayUseCases::create(
+        CreateProductionPlanPerDayTypeInput {
+            amount: Decimal::new(105, 0),
+            units: palette.id.clone().unwrap(),
+            date: DateTimeDerived(
+                DateTime::from_utc(
+                    NaiveDate::from_ymd_opt(2024, 1, 2)
+                        .unwrap()
+                        .and_hms_opt(0, 0, 0)
+                        .unwrap(),
+                    Utc,
+                )
+                .into(),
+            ),
+            line: Some(final_pipe.thing(&ctx).unwrap().into()),
+        },
+        &DB,
+        &ctx,
+    )
+    .await
+    .unwrap();
+
+    let sales_plan_per_day0 = SalesPlanPerDayUnitsUseCases::create(
+        CreateSalesPlanPerDayTypeInput {
+            amount: Decimal::new(105, 0),
+            units: palette.id.clone().unwrap(),
+            date: DateTimeDerived(
+                DateTime::from_utc(
+                    NaiveDate::from_ymd_opt(2024, 1, 1)
+                        .unwrap()
+                        .and_hms_opt(0, 0, 0)
+                        .unwrap(),
+                    Utc,
+                )
+                .into(),
+            ),
+            line: Some(final_pipe.thing(&ctx).unwrap().into()),
+        },
+        &DB,
+        &ctx,
+    )
+    .await
+    .unwrap();
+
+    let sales_plan_per_day1 = SalesPlanPerDayUnitsUseCases::create(
+        CreateSalesPlanPerDayTypeInput {
+            amount: Decimal::new(105, 0),
+            units: palette.id.clone().unwrap(),
+            date: DateTimeDerived(
+                DateTime::from_utc(
+                    NaiveDate::from_ymd_opt(2024, 1, 2)
+                        .unwrap()
+                        .and_hms_opt(0, 0, 0)
+                        .unwrap(),
+                    Utc,
+                )
+                .into(),
+            ),
+            line: Some(final_pipe.thing(&ctx).unwrap().into()),
+        },
+        &DB,
+        &ctx,
+    )
+    .await
+    .unwrap();
+
+    // Показания между фильтром и пастеризатором:
+    // при пастеризации теряется 10% смеси
+    let day_start = Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap();
+    let filtered_pipe = &stage_pipes[1];
+    let pasteurized_pipe = &stage_pipes[2];
+    for (pipe, flow) in [
+        (filtered_pipe, Decimal::new(3, 0)),
+        (pasteurized_pipe, Decimal::new(27, 1)),
+    ] {
+        let _ = pipe_stats_shortcut(day_start.into(), flow, wearout, &m_3, pipe, &DB, &ctx)
+            .await
+            .unwrap();
+    }
+
     let production_info_0 = ProductionInfoUseCases::select_create(
         date_1.into(),
         Some(final_pipe.thing(&ctx).unwrap().into()),
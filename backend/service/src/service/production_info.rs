@@ -1,12 +1,17 @@
+use crate::audit_log::{AuditLogRepository, Audited};
+use crate::flow_integration::{
+    day_bounds, plant_day, FlowIntegrationOptions, FlowIntegrationUseCases,
+};
 use crate::measure_units::{MeasureUnits, MeasureUnitsUseCases};
 use crate::pipe::{Pipe, PipeUseCases};
-use crate::pipe_stats::{self, PipeStats, PipeStatsRepository};
 use crate::pipe_type::PipeTypeUseCases;
-use crate::production_info;
+use crate::plan_revision::{PlanRevision, PlanRevisionUseCases};
+use crate::plant_topology::PlantTopologyUseCases;
+use crate::production_info_failure::ProductionInfoFailureUseCases;
 use crate::production_per_day::{
-    CreateProductionPlanPerDayTypeInput, ProductionPlanPerDay, ProductionPlanPerDayUseCases,
-    ProductionPlandPerDayRepository,
+    ProductionPlanPerDay, ProductionPlanPerDayUseCases, ProductionPlandPerDayRepository,
 };
+use crate::quality_check::{QualityCheck, QualityCheckRepository};
 use crate::sales_per_day::{
     SalesPlanPerDay, SalesPlanPerDayUnitsUseCases, SalesPlandPerDayRepository,
 };
@@ -27,7 +32,6 @@ use db::Db;
 use rust_decimal::Decimal;
 use serde::{Deserialize, Serialize};
 use surrealdb::sql::Thing;
-use tokio::net::unix::pipe;
 
 #[allow(dead_code)]
 const RESOURCE: &str = "ProductionInfo";
@@ -46,6 +50,29 @@ pub struct ProductionInfo {
     #[graphql(skip)]
     pub measure_units: ThingDerived,
     pub date: DateTimeDerived,
+    /// Start of the plant local day of `date`. Unique per final pipe
+    pub day: Option<DateTimeDerived>,
+    /// Fact persisted when the day is closed
+    pub fact: Option<Decimal>,
+    pub fact_computed_at: Option<DateTimeDerived>,
+    /// Revisions of the plans current when the record was last written
+    #[graphql(skip)]
+    pub sales_plan_revision: Option<ThingDerived>,
+    #[graphql(skip)]
+    pub production_plan_revision: Option<ThingDerived>,
+}
+
+/// Result of one materialization run
+#[derive(Clone, Debug, Default, SimpleObject)]
+#[graphql(guard = "RoleGuard::new(Role::User)")]
+pub struct MaterializationSummary {
+    pub created: usize,
+    /// Days whose ProductionInfo got the missing fact
+    pub completed: usize,
+    /// Days that already have ProductionInfo with fact
+    pub skipped: usize,
+    /// Days recorded into ProductionInfoFailure
+    pub failed: usize,
 }
 
 #[ComplexObject]
@@ -68,6 +95,28 @@ impl ProductionInfo {
         )
     }
 
+    /// Revision of the sales plan the record was written with.
+    /// None for records written before plans were versioned
+    async fn sales_plan_revision(&self, ctx: &Context<'_>) -> Result<Option<PlanRevision>> {
+        let db = ctx.data::<Db>()?;
+        let ctx = ctx.data::<CtxStruct>()?;
+        Ok(match &self.sales_plan_revision {
+            Some(revision) => Some(PlanRevisionUseCases::select_by_id(revision, db, ctx).await?),
+            None => None,
+        })
+    }
+
+    /// Revision of the production plan the record was written with.
+    /// None for records written before plans were versioned
+    async fn production_plan_revision(&self, ctx: &Context<'_>) -> Result<Option<PlanRevision>> {
+        let db = ctx.data::<Db>()?;
+        let ctx = ctx.data::<CtxStruct>()?;
+        Ok(match &self.production_plan_revision {
+            Some(revision) => Some(PlanRevisionUseCases::select_by_id(revision, db, ctx).await?),
+            None => None,
+        })
+    }
+
     async fn final_pipe(&self, ctx: &Context<'_>) -> Result<Pipe> {
         let db = ctx.data::<Db>()?;
         let ctx = ctx.data::<CtxStruct>()?;
@@ -86,87 +135,47 @@ impl ProductionInfo {
         )
     }
 
-    // TODO: добавить кейс когда за этот день данных нет
-    async fn production_fact(&self, ctx: &Context<'_>) -> Result<Decimal> {
+    /// Volume that went through the final pipe during the day.
+    /// None if there are no readings for the day or before it.
+    /// Persisted fact is returned if no options are given
+    async fn production_fact(
+        &self,
+        ctx: &Context<'_>,
+        options: Option<FlowIntegrationOptions>,
+    ) -> Result<Option<Decimal>> {
+        if let (Some(fact), None) = (self.fact, &options) {
+            return Ok(Some(fact));
+        }
         let db = ctx.data::<Db>()?;
         let ctx = ctx.data::<CtxStruct>()?;
-
-        // По дате собираем все нужные pipe_stats +
-        // последний pipe_stats предыдущего дня
-        //
-        // Все за нужный день
-        let pipe_stats_by_date = PipeStatsRepository::select_by_pipe_and_date(
+        let fact = FlowIntegrationUseCases::integrate_pipe_day(
             &self.final_pipe,
             self.date.clone(),
+            &options.unwrap_or_default(),
             db,
             ctx,
         )
         .await?;
+        Ok(fact.has_data().then_some(fact.volume))
+    }
 
-        let pipe_stats_by_previouse_date_lates =
-            PipeStatsRepository::select_previous_reading_by_pipe_and_date(
-                &self.final_pipe,
-                self.date.clone(),
-                db,
-                ctx,
-            )
-            .await?;
-        println!(
-            "Previous date stats: {:?}",
-            pipe_stats_by_previouse_date_lates
-        );
-
-        let mut result = Decimal::new(0, 0);
-
-        fn time_delta(from: DateTime<Utc>, to: DateTime<Utc>) -> Decimal {
-            let duration = to.signed_duration_since(from);
-            Decimal::new(duration.num_seconds(), 0) / Decimal::new(3600, 0)
-        }
-        fn hours_until_next_day(dt: DateTime<Utc>) -> Decimal {
-            // Calculate the start of the next day
-            let next_day = dt.date().succ().and_hms(0, 0, 0);
-
-            // Calculate the duration until the start of the next day
-            let duration = next_day.signed_duration_since(dt);
-
-            // Convert the duration to a floating-point number of hours
-            Decimal::new(duration.num_seconds(), 0) / Decimal::new(3600, 0)
-        }
-        fn hours_from_current_day(dt: DateTime<Utc>) -> Decimal {
-            // Get the start of the current day (midnight)
-            let start_of_day = dt.date().and_hms(0, 0, 0);
-
-            // Calculate the duration from the start of the current day
-            let duration = dt.signed_duration_since(start_of_day);
-
-            // Convert the duration to a floating-point number of hours
-            Decimal::new(duration.num_seconds(), 0) / Decimal::new(3600, 0)
-        }
-        println!("Result = {:?}", result);
-
-        match pipe_stats_by_previouse_date_lates {
-            None => {}
-            Some(pipe_stats) => {
-                if pipe_stats_by_date.is_empty() {
-                    result = pipe_stats.flow * Decimal::new(24, 0);
-                    return Ok(result);
-                }
-                result = pipe_stats.flow * hours_from_current_day(pipe_stats_by_date[0].date.0.0);
-            }
-        }
-        println!("pipe_stats_by_date = {:?}", pipe_stats_by_date);
-        for (i, el) in pipe_stats_by_date.iter().enumerate() {
-            println!("Result = {:?}", result);
-            if i == pipe_stats_by_date.len() - 1 {
-                result += el.flow * hours_until_next_day(el.date.0 .0);
-            } else {
-                println!("el.flow = {:?}", el.flow);
-                result += el.flow * time_delta(el.date.0 .0, pipe_stats_by_date[i + 1].date.0 .0);
-            }
-        }
-        println!("Result = {:?}", result);
-
-        Ok(result)
+    /// Quality checks of the day taken on the line. Only out of spec ones by default
+    async fn quality_checks(
+        &self,
+        ctx: &Context<'_>,
+        all: Option<bool>,
+    ) -> Result<Vec<QualityCheck>> {
+        let db = ctx.data::<Db>()?;
+        let ctx = ctx.data::<CtxStruct>()?;
+        let out_of_spec = (!all.unwrap_or(false)).then_some(true);
+        Ok(QualityCheckRepository::select_by_date(
+            &self.final_pipe,
+            self.date.clone(),
+            out_of_spec,
+            db,
+            ctx,
+        )
+        .await?)
     }
 }
 
@@ -242,16 +251,19 @@ impl ProductionInfoRepository {
         Unwrapper::unwrapper_option(query, 0, "Can't get tag by id", ctx).await
     }
 
+    /// Record of the line for the plant local day containing the date
     pub async fn select_by_date(
+        final_pipe: &ThingDerived,
         date: DateTimeDerived,
         db: &Db,
         ctx: &dyn Ctx,
     ) -> ApiResult<Option<ProductionInfo>> {
         let query = db
             .query(format!(
-                "SELECT * FROM {RESOURCE} WHERE time::floor(date, 1d) = time::floor($date, 1d);"
+                "SELECT * FROM {RESOURCE} WHERE final_pipe = $final_pipe AND day = $day;"
             ))
-            .bind(("date", date.0));
+            .bind(("final_pipe", final_pipe.thing(ctx)?))
+            .bind(("day", plant_day(&date, ctx)?.0));
         let result = Unwrapper::unwrapper_vec::<ProductionInfo, _>(query, 0, ctx).await?;
         if result.is_empty() {
             return Ok(None);
@@ -259,24 +271,69 @@ impl ProductionInfoRepository {
         Ok(Some(result[0].clone()))
     }
 
+    /// Records with date in [from, to). All lines if `final_pipe` is None
+    pub async fn select_by_range(
+        from: DateTimeDerived,
+        to: DateTimeDerived,
+        final_pipe: Option<ThingDerived>,
+        db: &Db,
+        ctx: &dyn Ctx,
+    ) -> ApiResult<Vec<ProductionInfo>> {
+        let query = db
+            .query(format!(
+                "SELECT * FROM {RESOURCE} WHERE date >= $from AND date < $to \
+                 AND ($final_pipe = NONE OR final_pipe = $final_pipe) ORDER BY date ASC;"
+            ))
+            .bind(("from", from.0))
+            .bind(("to", to.0))
+            .bind((
+                "final_pipe",
+                final_pipe.map(|pipe| pipe.thing(ctx)).transpose()?,
+            ));
+        Unwrapper::unwrapper_vec(query, 0, ctx).await
+    }
+
+    /// Current revisions of the sales and production plans of the record
+    async fn plan_revisions(
+        ct_input: &CreateProductionInfoInput,
+        db: &Db,
+        ctx: &dyn Ctx,
+    ) -> ApiResult<(Option<ThingDerived>, Option<ThingDerived>)> {
+        let revision = |revision: Option<PlanRevision>| revision.and_then(|revision| revision.id);
+        Ok((
+            revision(PlanRevisionUseCases::select_latest(&ct_input.sales_plan, db, ctx).await?),
+            revision(
+                PlanRevisionUseCases::select_latest(&ct_input.production_plan, db, ctx).await?,
+            ),
+        ))
+    }
+
     pub async fn create(
-        sales_plan: ThingDerived,
-        production_plan: ThingDerived,
-        final_pipe: ThingDerived,
-        measure_units: ThingDerived,
-        date: DateTimeDerived,
+        ct_input: CreateProductionInfoInput,
+        fact: Option<Decimal>,
         db: &Db,
         ctx: &dyn Ctx,
     ) -> ApiResult<ProductionInfo> {
+        let day = plant_day(&ct_input.date, ctx)?;
+        let (sales_plan_revision, production_plan_revision) =
+            Self::plan_revisions(&ct_input, db, ctx).await?;
         db.create(RESOURCE)
-            .content(ProductionInfo {
-                id: None,
-                sales_plan,
-                production_plan,
-                final_pipe,
-                measure_units,
-                date,
-            })
+            .content(Audited::new(
+                ProductionInfo {
+                    id: None,
+                    sales_plan: ct_input.sales_plan,
+                    production_plan: ct_input.production_plan,
+                    final_pipe: ct_input.final_pipe,
+                    measure_units: ct_input.measure_units,
+                    date: ct_input.date,
+                    day: Some(day),
+                    fact_computed_at: fact.map(|_| Utc::now().into()),
+                    fact,
+                    sales_plan_revision,
+                    production_plan_revision,
+                },
+                ctx,
+            ))
             .await
             .map_err(ApiError::from(ctx))
             .map(|v: Vec<ProductionInfo>| {
@@ -296,8 +353,27 @@ impl ProductionInfoRepository {
         db: &Db,
         ctx: &dyn Ctx,
     ) -> ApiResult<ProductionInfo> {
+        let day = plant_day(&ct_input.date, ctx)?;
+        let (sales_plan_revision, production_plan_revision) =
+            Self::plan_revisions(&ct_input, db, ctx).await?;
+        // Persisted fact is dropped, it doesn't match the changed record anymore
         db.update((RESOURCE, id.id.to_string()))
-            .content(ct_input)
+            .content(Audited::new(
+                ProductionInfo {
+                    id: None,
+                    sales_plan: ct_input.sales_plan,
+                    production_plan: ct_input.production_plan,
+                    final_pipe: ct_input.final_pipe,
+                    measure_units: ct_input.measure_units,
+                    date: ct_input.date,
+                    day: Some(day),
+                    fact: None,
+                    fact_computed_at: None,
+                    sales_plan_revision,
+                    production_plan_revision,
+                },
+                ctx,
+            ))
             .await
             .map_err(ApiError::from(ctx))?
             .ok_or(ApiError {
@@ -309,10 +385,35 @@ impl ProductionInfoRepository {
             })
     }
 
-    pub async fn delete(id: Thing, db: &Db, ctx: &dyn Ctx) -> ApiResult<ProductionInfo> {
-        db.delete((RESOURCE, id))
+    /// Persists the fact of the record, the rest of it is kept
+    pub async fn set_fact(
+        id: Thing,
+        fact: Decimal,
+        db: &Db,
+        ctx: &dyn Ctx,
+    ) -> ApiResult<ProductionInfo> {
+        db.update((RESOURCE, id.id.to_string()))
+            .merge(Audited::new(
+                ProductionInfoFact {
+                    fact,
+                    fact_computed_at: Utc::now().into(),
+                },
+                ctx,
+            ))
             .await
             .map_err(ApiError::from(ctx))?
+            .ok_or(ApiError {
+                req_id: ctx.req_id(),
+                error: Error::SurrealDbNoResult {
+                    source: "internal".to_string(),
+                    id: id.to_string(),
+                },
+            })
+    }
+
+    pub async fn delete(id: Thing, db: &Db, ctx: &dyn Ctx) -> ApiResult<ProductionInfo> {
+        AuditLogRepository::delete(RESOURCE, id.id, db, ctx)
+            .await?
             .ok_or(ApiError {
                 req_id: ctx.req_id(),
                 error: Error::SurrealDbNoResult {
@@ -323,6 +424,12 @@ impl ProductionInfoRepository {
     }
 }
 
+#[derive(Serialize)]
+struct ProductionInfoFact {
+    fact: Decimal,
+    fact_computed_at: DateTimeDerived,
+}
+
 #[derive(Deserialize, InputObject, Clone, Serialize, Debug)]
 pub struct CreateProductionInfoInput {
     pub sales_plan: ThingDerived,
@@ -340,16 +447,7 @@ impl ProductionInfoUseCases {
         db: &Db,
         ctx: &dyn Ctx,
     ) -> ApiResult<ProductionInfo> {
-        ProductionInfoRepository::create(
-            ct_input.sales_plan,
-            ct_input.production_plan,
-            ct_input.final_pipe,
-            ct_input.measure_units,
-            ct_input.date,
-            db,
-            ctx,
-        )
-        .await
+        ProductionInfoRepository::create(ct_input, None, db, ctx).await
     }
 
     /// If record is exist, then return the record
@@ -361,28 +459,37 @@ impl ProductionInfoUseCases {
         db: &Db,
         ctx: &dyn Ctx,
     ) -> ApiResult<Option<ProductionInfo>> {
+        let final_pipe = match final_pipe {
+            None => {
+                return Ok(None);
+            }
+            Some(pipe) => pipe,
+        };
         let production_info =
-            ProductionInfoRepository::select_by_date(date.clone(), db, ctx).await?;
+            ProductionInfoRepository::select_by_date(&final_pipe, date.clone(), db, ctx).await?;
         if production_info.is_some() {
             return Ok(production_info);
         }
         // Record does not exist. Gather needed data then
         let sales_plan =
-            match SalesPlandPerDayRepository::select_by_date(date.clone(), db, ctx).await? {
+            match SalesPlandPerDayRepository::select_by_date(&final_pipe, date.clone(), db, ctx)
+                .await?
+            {
                 None => return Ok(None),
                 Some(plan) => plan,
             };
-        let production_plan =
-            match ProductionPlandPerDayRepository::select_by_date(date.clone(), db, ctx).await? {
-                None => return Ok(None),
-                Some(plan) => plan,
-            };
-        let final_pipe = match final_pipe {
-            None => {
-                return Ok(None);
-            }
-            Some(pipe) => PipeUseCases::select_by_id(&pipe, db, ctx).await?,
+        let production_plan = match ProductionPlandPerDayRepository::select_by_date(
+            &final_pipe,
+            date.clone(),
+            db,
+            ctx,
+        )
+        .await?
+        {
+            None => return Ok(None),
+            Some(plan) => plan,
         };
+        let final_pipe = PipeUseCases::select_by_id(&final_pipe, db, ctx).await?;
         let pipe_type = PipeTypeUseCases::select_by_id(&final_pipe.pipe_type, db, ctx).await?;
         let input = CreateProductionInfoInput {
             sales_plan: sales_plan.thing(ctx)?.into(),
@@ -396,6 +503,168 @@ impl ProductionInfoUseCases {
         Ok(Some(result))
     }
 
+    /// Creates ProductionInfo with persisted fact for the plant local day containing the date
+    pub async fn materialize_day(
+        date: DateTimeDerived,
+        final_pipe: &ThingDerived,
+        options: &FlowIntegrationOptions,
+        db: &Db,
+        ctx: &dyn Ctx,
+    ) -> ApiResult<ProductionInfo> {
+        let (input, fact) = Self::day_fact(date, final_pipe, options, db, ctx).await?;
+        ProductionInfoRepository::create(input, Some(fact), db, ctx).await
+    }
+
+    /// Plans and fact of the final pipe for the plant local day containing the date
+    async fn day_fact(
+        date: DateTimeDerived,
+        final_pipe: &ThingDerived,
+        options: &FlowIntegrationOptions,
+        db: &Db,
+        ctx: &dyn Ctx,
+    ) -> ApiResult<(CreateProductionInfoInput, Decimal)> {
+        let (from, to) = day_bounds(date.0 .0, options.utc_offset(ctx)?);
+        let no_plan = |plan: &str| ApiError {
+            req_id: ctx.req_id(),
+            error: Error::Generic {
+                description: format!("No {plan} for {}", from.date_naive()),
+            },
+        };
+        let line = Some(final_pipe.clone());
+        let production_plan = ProductionPlanPerDayUseCases::select_by_range(
+            from.into(),
+            to.into(),
+            line.clone(),
+            db,
+            ctx,
+        )
+        .await?
+        .into_iter()
+        .next()
+        .ok_or_else(|| no_plan("production plan"))?;
+        let sales_plan =
+            SalesPlanPerDayUnitsUseCases::select_by_range(from.into(), to.into(), line, db, ctx)
+                .await?
+                .into_iter()
+                .next()
+                .ok_or_else(|| no_plan("sales plan"))?;
+        let pipe = PipeUseCases::select_by_id(final_pipe, db, ctx).await?;
+        let pipe_type = PipeTypeUseCases::select_by_id(&pipe.pipe_type, db, ctx).await?;
+        let fact = FlowIntegrationUseCases::integrate_pipe(
+            final_pipe,
+            from.into(),
+            to.into(),
+            options,
+            db,
+            ctx,
+        )
+        .await?;
+        if !fact.has_data() {
+            return Err(ApiError {
+                req_id: ctx.req_id(),
+                error: Error::Generic {
+                    description: format!("No readings of the final pipe for {}", from.date_naive()),
+                },
+            });
+        }
+        let input = CreateProductionInfoInput {
+            sales_plan: sales_plan.thing(ctx)?.into(),
+            production_plan: production_plan.thing(ctx)?.into(),
+            final_pipe: final_pipe.clone(),
+            date: from.into(),
+            measure_units: pipe_type.units,
+        };
+        Ok((input, fact.volume))
+    }
+
+    /// Materializes every line and day in [until - lookback_days, until) that has a production plan
+    /// and no ProductionInfo with fact yet. Existing records without fact get it stored.
+    /// Days that fail are stored in ProductionInfoFailure.
+    /// Plans without line are taken as plans of the only final pipe
+    pub async fn materialize(
+        until: DateTimeDerived,
+        lookback_days: i64,
+        options: &FlowIntegrationOptions,
+        db: &Db,
+        ctx: &dyn Ctx,
+    ) -> ApiResult<MaterializationSummary> {
+        let offset = options.utc_offset(ctx)?;
+        let (until, _) = day_bounds(until.0 .0, offset);
+        let from = until - Duration::days(lookback_days);
+        let plans =
+            ProductionPlanPerDayUseCases::select_by_range(from.into(), until.into(), None, db, ctx)
+                .await?;
+        let existing =
+            ProductionInfoRepository::select_by_range(from.into(), until.into(), None, db, ctx)
+                .await?;
+
+        let mut final_pipe = None;
+        let mut lines: Vec<(ApiResult<ThingDerived>, DateTime<Utc>)> = vec![];
+        for plan in plans.iter() {
+            let line = match &plan.line {
+                Some(line) => Ok(line.clone()),
+                None => final_pipe
+                    .get_or_insert(PlantTopologyUseCases::select_final_pipe(db, ctx).await)
+                    .clone(),
+            };
+            let day = day_bounds(plan.date.0 .0, offset).0;
+            if !lines.iter().any(|(other, other_day)| {
+                *other_day == day && other.as_ref().ok() == line.as_ref().ok()
+            }) {
+                lines.push((line, day));
+            }
+        }
+
+        let mut summary = MaterializationSummary::default();
+        for (line, day) in lines {
+            let info = line.as_ref().ok().and_then(|line| {
+                existing.iter().find(|info| {
+                    &info.final_pipe == line && day_bounds(info.date.0 .0, offset).0 == day
+                })
+            });
+            if info.is_some_and(|info| info.fact.is_some()) {
+                summary.skipped += 1;
+                continue;
+            }
+            let result = match (&line, info) {
+                (Ok(line), None) => Self::materialize_day(day.into(), line, options, db, ctx).await,
+                (Ok(line), Some(info)) => {
+                    match Self::day_fact(day.into(), line, options, db, ctx).await {
+                        Ok((_, fact)) => {
+                            ProductionInfoRepository::set_fact(info.thing(ctx)?, fact, db, ctx)
+                                .await
+                        }
+                        Err(error) => Err(error),
+                    }
+                }
+                (Err(error), _) => Err(error.clone()),
+            };
+            let line = line.ok();
+            match result {
+                Ok(_) => {
+                    ProductionInfoFailureUseCases::delete_by_date(day.into(), line, db, ctx)
+                        .await?;
+                    match info {
+                        None => summary.created += 1,
+                        Some(_) => summary.completed += 1,
+                    }
+                }
+                Err(error) => {
+                    ProductionInfoFailureUseCases::upsert(
+                        day.into(),
+                        line,
+                        format!("{:?}", error.error),
+                        db,
+                        ctx,
+                    )
+                    .await?;
+                    summary.failed += 1;
+                }
+            }
+        }
+        Ok(summary)
+    }
+
     pub async fn update(
         ct_input: CreateProductionInfoInput,
         id: &dyn ObjectWithThing,
@@ -421,12 +690,18 @@ impl ProductionInfoUseCases {
         ProductionInfoRepository::select_by_id(id, db, ctx).await
     }
 
+    /// Uses the only final pipe of the plant if `final_pipe` is not set
     pub async fn select_by_date(
+        final_pipe: Option<ThingDerived>,
         date: DateTimeDerived,
         db: &Db,
         ctx: &dyn Ctx,
     ) -> ApiResult<Option<ProductionInfo>> {
-        ProductionInfoRepository::select_by_date(date, db, ctx).await
+        let final_pipe = match final_pipe {
+            Some(final_pipe) => final_pipe,
+            None => PlantTopologyUseCases::select_final_pipe(db, ctx).await?,
+        };
+        ProductionInfoRepository::select_by_date(&final_pipe, date, db, ctx).await
     }
 
     pub async fn count(db: &Db, ctx: &dyn Ctx) -> ApiResult<usize> {
@@ -451,3 +726,193 @@ impl ProductionInfoUseCases {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pipe::CreatePipeInput;
+    use crate::pipe_stats::tests::create_pipe;
+    use crate::pipe_stats::{CreatePipeStatsInput, PipeStatsUseCases};
+    use crate::production_per_day::CreateProductionPlanPerDayTypeInput;
+    use crate::sales_per_day::CreateSalesPlanPerDayTypeInput;
+    use chrono::TimeZone;
+    use common::ctx::MockCtx;
+    use db::set_test_db;
+    use rstest::*;
+
+    #[fixture]
+    fn ctx() -> MockCtx {
+        let mut ctx = MockCtx::new();
+        ctx.expect_req_id().return_const(uuid::Uuid::new_v4());
+        ctx.expect_user_id_thing().returning(|| {
+            Err(ApiError {
+                req_id: uuid::Uuid::new_v4(),
+                error: Error::Generic {
+                    description: "No user".to_string(),
+                },
+            })
+        });
+        ctx
+    }
+
+    #[fixture]
+    async fn tdb() -> Db {
+        set_test_db().await
+    }
+
+    fn at(day: u32, hour: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2024, 1, day, hour, 0, 0).unwrap()
+    }
+
+    #[rstest]
+    #[tokio::test]
+    #[awt]
+    async fn materialize_test(ctx: MockCtx, #[future] tdb: Db) {
+        let (pipe, units) = create_pipe(&ctx, &tdb).await.unwrap();
+        let units = units.id.unwrap();
+        let line = pipe.id.clone().unwrap();
+        // Second packaging line without readings
+        let other_line = PipeUseCases::create(
+            CreatePipeInput {
+                name: "Трубопровод № 2".to_string(),
+                pipe_type: pipe.pipe_type.clone(),
+                material: pipe.material.clone(),
+            },
+            &tdb,
+            &ctx,
+        )
+        .await
+        .unwrap()
+        .id
+        .unwrap();
+        for (date, flow) in [
+            (at(1, 1), Decimal::new(5, 1)),
+            (at(1, 10), Decimal::new(6, 0)),
+        ] {
+            PipeStatsUseCases::create(
+                CreatePipeStatsInput {
+                    date: date.into(),
+                    flow,
+                    units: units.clone(),
+                    wearout: Decimal::ZERO,
+                    pipe: pipe.id.clone().unwrap(),
+                },
+                &tdb,
+                &ctx,
+            )
+            .await
+            .unwrap();
+        }
+        let production_plan = |line: &ThingDerived, day: u32| CreateProductionPlanPerDayTypeInput {
+            amount: Decimal::from(100),
+            units: units.clone(),
+            date: at(day, 0).into(),
+            line: Some(line.clone()),
+        };
+        let sales_plan = |line: &ThingDerived, day: u32| CreateSalesPlanPerDayTypeInput {
+            amount: Decimal::from(80),
+            units: units.clone(),
+            date: at(day, 0).into(),
+            line: Some(line.clone()),
+        };
+        for (line, day) in [(&line, 1), (&line, 2), (&line, 3), (&other_line, 1)] {
+            ProductionPlanPerDayUseCases::create(production_plan(line, day), &tdb, &ctx)
+                .await
+                .unwrap();
+        }
+        // No sales plan for the third day
+        for (line, day) in [(&line, 1), (&line, 2), (&other_line, 1)] {
+            SalesPlanPerDayUnitsUseCases::create(sales_plan(line, day), &tdb, &ctx)
+                .await
+                .unwrap();
+        }
+        // One plan per line and plant local day
+        assert!(
+            ProductionPlanPerDayUseCases::create(production_plan(&line, 1), &tdb, &ctx)
+                .await
+                .is_err()
+        );
+        let plan =
+            ProductionPlanPerDayUseCases::select_by_date(&other_line, at(1, 15).into(), &tdb, &ctx)
+                .await
+                .unwrap()
+                .unwrap();
+        assert_eq!(plan.line, Some(other_line.clone()));
+        assert_eq!(plan.day, Some(at(1, 0).into()));
+
+        // Record without fact made before the job, e.g. by an on-demand read
+        let unmaterialized =
+            ProductionInfoUseCases::select_create(at(1, 0).into(), Some(line.clone()), &tdb, &ctx)
+                .await
+                .unwrap()
+                .unwrap();
+        assert_eq!(unmaterialized.fact, None);
+
+        let options = FlowIntegrationOptions {
+            utc_offset_minutes: Some(0),
+            ..Default::default()
+        };
+        let summary = ProductionInfoUseCases::materialize(at(4, 3).into(), 7, &options, &tdb, &ctx)
+            .await
+            .unwrap();
+        assert_eq!(summary.created, 1);
+        assert_eq!(summary.completed, 1);
+        // Third day of the first line and the second line
+        assert_eq!(summary.failed, 2);
+        assert_eq!(
+            ProductionInfoFailureUseCases::count(&tdb, &ctx)
+                .await
+                .unwrap(),
+            2
+        );
+
+        let infos = ProductionInfoRepository::select_by_range(
+            at(1, 0).into(),
+            at(4, 0).into(),
+            Some(line.clone()),
+            &tdb,
+            &ctx,
+        )
+        .await
+        .unwrap();
+        assert_eq!(infos.len(), 2);
+        // Existing record got the fact
+        assert_eq!(infos[0].id, unmaterialized.id);
+        // 0.5 * 9 + 6 * 14
+        assert_eq!(infos[0].fact, Some(Decimal::new(885, 1)));
+        assert!(infos[0].fact_computed_at.is_some());
+        // Last reading of the first day lasts the whole second day
+        assert_eq!(infos[1].fact, Some(Decimal::from(144)));
+
+        // Materialized days are not touched again
+        let summary = ProductionInfoUseCases::materialize(at(4, 3).into(), 7, &options, &tdb, &ctx)
+            .await
+            .unwrap();
+        assert_eq!(summary.created, 0);
+        assert_eq!(summary.completed, 0);
+        assert_eq!(summary.skipped, 2);
+        assert_eq!(summary.failed, 2);
+        // Days that keep failing are recorded once
+        assert_eq!(
+            ProductionInfoFailureUseCases::count(&tdb, &ctx)
+                .await
+                .unwrap(),
+            2
+        );
+
+        let info = ProductionInfoUseCases::select_by_date(Some(line), at(2, 20).into(), &tdb, &ctx)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(info.day, Some(at(2, 0).into()));
+        assert!(ProductionInfoUseCases::select_by_date(
+            Some(other_line),
+            at(1, 0).into(),
+            &tdb,
+            &ctx
+        )
+        .await
+        .unwrap()
+        .is_none());
+    }
+}
@@ -0,0 +1,161 @@
+use crate::common::Unwrapper;
+use crate::datetime::DateTimeDerived;
+use crate::service::guard::RoleGuard;
+use crate::thing_derived::ThingDerived;
+use crate::thing_wrapper::ObjectWithThing;
+use async_graphql::SimpleObject;
+use common::{
+    ctx::Ctx,
+    error::{ApiError, Error},
+    role::Role,
+    ApiResult,
+};
+
+use db::Db;
+use serde::{Deserialize, Serialize};
+use surrealdb::sql::Thing;
+
+#[allow(dead_code)]
+const RESOURCE: &str = "ProductionInfoFailure";
+
+/// Day for which ProductionInfo couldn't be materialized
+#[derive(Clone, Debug, Serialize, Deserialize, SimpleObject)]
+#[graphql(guard = "RoleGuard::new(Role::User)")]
+pub struct ProductionInfoFailure {
+    pub id: Option<ThingDerived>,
+    /// Start of the plant local day
+    pub date: DateTimeDerived,
+    /// Final pipe of the line. None if it couldn't be chosen
+    pub line: Option<ThingDerived>,
+    pub error: String,
+    /// Time of the last failed run
+    pub created_at: Option<DateTimeDerived>,
+}
+
+impl ObjectWithThing for ProductionInfoFailure {
+    fn thing(&self, ctx: &dyn Ctx) -> ApiResult<Thing> {
+        self.id
+            .as_ref()
+            .ok_or(ApiError {
+                req_id: ctx.req_id(),
+                error: Error::Generic {
+                    description: "Can't get thing. Get none instead".to_string(),
+                },
+            })?
+            .thing(ctx)
+    }
+}
+
+pub struct ProductionInfoFailureRepository {}
+
+impl ProductionInfoFailureRepository {
+    pub async fn list(
+        offset: usize,
+        limit: usize,
+        db: &Db,
+        ctx: &dyn Ctx,
+    ) -> ApiResult<Vec<ProductionInfoFailure>> {
+        let query = db.query(format!(
+            "SELECT * FROM {RESOURCE} ORDER BY created_at DESC LIMIT {limit} START {offset};"
+        ));
+        Unwrapper::unwrapper_vec(query, 0, ctx).await
+    }
+
+    pub async fn count(db: &Db, ctx: &dyn Ctx) -> ApiResult<usize> {
+        let query = db.query(format!("(SELECT count() FROM {RESOURCE} GROUP ALL).count"));
+        Unwrapper::unwrapper_option_without_error(query, 0, ctx)
+            .await
+            .map(|count| count.unwrap_or(0))
+    }
+
+    /// Replaces the failure of the line and day, so a day that keeps failing has one record
+    pub async fn upsert(
+        date: DateTimeDerived,
+        line: Option<ThingDerived>,
+        error: String,
+        db: &Db,
+        ctx: &dyn Ctx,
+    ) -> ApiResult<ProductionInfoFailure> {
+        let query = db
+            .query(format!(
+                "BEGIN TRANSACTION; \
+                 DELETE {RESOURCE} WHERE date = $date AND line = $line; \
+                 CREATE {RESOURCE} CONTENT $content; \
+                 COMMIT TRANSACTION;"
+            ))
+            .bind(("date", date.0.clone()))
+            .bind((
+                "line",
+                line.clone().map(|line| line.thing(ctx)).transpose()?,
+            ))
+            .bind((
+                "content",
+                ProductionInfoFailure {
+                    id: None,
+                    date,
+                    line,
+                    error,
+                    created_at: None,
+                },
+            ));
+        Unwrapper::unwrapper_option(query, 1, "Error while creating ", ctx).await
+    }
+
+    /// Removes failures of the line and day once it is materialized
+    pub async fn delete_by_date(
+        date: DateTimeDerived,
+        line: Option<ThingDerived>,
+        db: &Db,
+        ctx: &dyn Ctx,
+    ) -> ApiResult<()> {
+        db.query(format!(
+            "DELETE {RESOURCE} WHERE date = $date AND line = $line;"
+        ))
+        .bind(("date", date.0))
+        .bind(("line", line.map(|line| line.thing(ctx)).transpose()?))
+        .await
+        .map_err(ApiError::from(ctx))?
+        .check()
+        .map_err(ApiError::from(ctx))?;
+        Ok(())
+    }
+}
+
+pub struct ProductionInfoFailureUseCases {}
+
+impl ProductionInfoFailureUseCases {
+    pub async fn upsert(
+        date: DateTimeDerived,
+        line: Option<ThingDerived>,
+        error: String,
+        db: &Db,
+        ctx: &dyn Ctx,
+    ) -> ApiResult<ProductionInfoFailure> {
+        ProductionInfoFailureRepository::upsert(date, line, error, db, ctx).await
+    }
+
+    pub async fn delete_by_date(
+        date: DateTimeDerived,
+        line: Option<ThingDerived>,
+        db: &Db,
+        ctx: &dyn Ctx,
+    ) -> ApiResult<()> {
+        ProductionInfoFailureRepository::delete_by_date(date, line, db, ctx).await
+    }
+
+    pub async fn count(db: &Db, ctx: &dyn Ctx) -> ApiResult<usize> {
+        ProductionInfoFailureRepository::count(db, ctx).await
+    }
+
+    pub async fn list(
+        offset: Option<usize>,
+        limit: Option<usize>,
+        db: &Db,
+        ctx: &dyn Ctx,
+    ) -> ApiResult<Vec<ProductionInfoFailure>> {
+        let limit = limit.unwrap_or(10);
+        let offset = offset.unwrap_or(0);
+
+        ProductionInfoFailureRepository::list(offset, limit, db, ctx).await
+    }
+}
@@ -1,3 +1,8 @@
+use crate::audit_log::{AuditLogRepository, Audited};
+use crate::flow_integration::plant_day;
+use crate::plan_feasibility::{CheckedProductionPlanPerDay, PlanFeasibilityUseCases};
+use crate::plan_revision::{PlanRevision, PlanRevisionUseCases};
+use crate::plant_topology::PlantTopologyUseCases;
 use crate::service::guard::RoleGuard;
 use crate::thing_derived::ThingDerived;
 use crate::thing_wrapper::ObjectWithThing;
@@ -25,6 +30,10 @@ pub struct ProductionPlanPerDay {
     pub amount: Decimal,
     pub units: ThingDerived,
     pub date: DateTimeDerived,
+    /// Final pipe of the product line. None only for records created before lines
+    pub line: Option<ThingDerived>,
+    /// Start of the plant local day of `date`
+    pub day: Option<DateTimeDerived>,
 }
 
 impl ObjectWithThing for ProductionPlanPerDay {
@@ -47,12 +56,15 @@ impl ProductionPlandPerDayRepository {
     pub async fn list(
         offset: usize,
         limit: usize,
+        line: Option<ThingDerived>,
         db: &Db,
         ctx: &dyn Ctx,
     ) -> ApiResult<Vec<ProductionPlanPerDay>> {
-        let query = db.query(format!(
-            "SELECT * FROM {RESOURCE} LIMIT {limit} START {offset};"
-        ));
+        let query = db
+            .query(format!(
+                "SELECT * FROM {RESOURCE} WHERE $line = NONE OR line = $line LIMIT {limit} START {offset};"
+            ))
+            .bind(("line", line.map(|line| line.thing(ctx)).transpose()?));
         Unwrapper::unwrapper_vec(query, 0, ctx).await
     }
 
@@ -82,16 +94,19 @@ impl ProductionPlandPerDayRepository {
         Unwrapper::unwrapper_option(query, 0, "Can't get tag by id", ctx).await
     }
 
+    /// Plan of the line for the plant local day containing the date
     pub async fn select_by_date(
+        line: &ThingDerived,
         date: DateTimeDerived,
         db: &Db,
         ctx: &dyn Ctx,
     ) -> ApiResult<Option<ProductionPlanPerDay>> {
         let query = db
             .query(format!(
-                "SELECT * FROM {RESOURCE} WHERE time::floor(date, 1d) = time::floor($date, 1d);"
+                "SELECT * FROM {RESOURCE} WHERE line = $line AND day = $day;"
             ))
-            .bind(("date", date.0));
+            .bind(("line", line.thing(ctx)?))
+            .bind(("day", plant_day(&date, ctx)?.0));
         let result = Unwrapper::unwrapper_vec::<ProductionPlanPerDay, _>(query, 0, ctx).await?;
         if result.is_empty() {
             return Ok(None);
@@ -99,20 +114,46 @@ impl ProductionPlandPerDayRepository {
         Ok(Some(result[0].clone()))
     }
 
+    /// Plans with date in [from, to) ordered by date. All lines if `line` is None
+    pub async fn select_by_range(
+        from: DateTimeDerived,
+        to: DateTimeDerived,
+        line: Option<ThingDerived>,
+        db: &Db,
+        ctx: &dyn Ctx,
+    ) -> ApiResult<Vec<ProductionPlanPerDay>> {
+        let query = db
+            .query(format!(
+                "SELECT * FROM {RESOURCE} WHERE date >= $from AND date < $to \
+                 AND ($line = NONE OR line = $line) ORDER BY date ASC;"
+            ))
+            .bind(("from", from.0))
+            .bind(("to", to.0))
+            .bind(("line", line.map(|line| line.thing(ctx)).transpose()?));
+        Unwrapper::unwrapper_vec(query, 0, ctx).await
+    }
+
     pub async fn create(
         amount: Decimal,
         units: ThingDerived,
         date: DateTimeDerived,
+        line: ThingDerived,
         db: &Db,
         ctx: &dyn Ctx,
     ) -> ApiResult<ProductionPlanPerDay> {
+        let day = plant_day(&date, ctx)?;
         db.create(RESOURCE)
-            .content(ProductionPlanPerDay {
-                id: None,
-                amount,
-                units,
-                date,
-            })
+            .content(Audited::new(
+                ProductionPlanPerDay {
+                    id: None,
+                    amount,
+                    units,
+                    date,
+                    line: Some(line),
+                    day: Some(day),
+                },
+                ctx,
+            ))
             .await
             .map_err(ApiError::from(ctx))
             .map(|v: Vec<ProductionPlanPerDay>| {
@@ -127,13 +168,27 @@ impl ProductionPlandPerDayRepository {
     }
 
     pub async fn update(
-        ct_input: CreateProductionPlanPerDayTypeInput,
+        amount: Decimal,
+        units: ThingDerived,
+        date: DateTimeDerived,
+        line: ThingDerived,
         id: Thing,
         db: &Db,
         ctx: &dyn Ctx,
     ) -> ApiResult<ProductionPlanPerDay> {
+        let day = plant_day(&date, ctx)?;
         db.update((RESOURCE, id.id.to_string()))
-            .content(ct_input)
+            .content(Audited::new(
+                ProductionPlanPerDay {
+                    id: None,
+                    amount,
+                    units,
+                    date,
+                    line: Some(line),
+                    day: Some(day),
+                },
+                ctx,
+            ))
             .await
             .map_err(ApiError::from(ctx))?
             .ok_or(ApiError {
@@ -146,9 +201,8 @@ impl ProductionPlandPerDayRepository {
     }
 
     pub async fn delete(id: Thing, db: &Db, ctx: &dyn Ctx) -> ApiResult<ProductionPlanPerDay> {
-        db.delete((RESOURCE, id))
-            .await
-            .map_err(ApiError::from(ctx))?
+        AuditLogRepository::delete(RESOURCE, id.id, db, ctx)
+            .await?
             .ok_or(ApiError {
                 req_id: ctx.req_id(),
                 error: Error::SurrealDbNoResult {
@@ -164,6 +218,8 @@ pub struct CreateProductionPlanPerDayTypeInput {
     pub amount: Decimal,
     pub units: ThingDerived,
     pub date: DateTimeDerived,
+    /// Final pipe of the product line. The only final pipe of the plant by default
+    pub line: Option<ThingDerived>,
 }
 
 pub struct ProductionPlanPerDayUseCases {
@@ -172,19 +228,39 @@ pub struct ProductionPlanPerDayUseCases {
 }
 
 impl ProductionPlanPerDayUseCases {
+    async fn line(line: Option<ThingDerived>, db: &Db, ctx: &dyn Ctx) -> ApiResult<ThingDerived> {
+        match line {
+            Some(line) => Ok(line),
+            None => PlantTopologyUseCases::select_final_pipe(db, ctx).await,
+        }
+    }
+
+    /// Plan is saved even if it is not feasible. The check is returned with it
     pub async fn create(
         ct_input: CreateProductionPlanPerDayTypeInput,
         db: &Db,
         ctx: &dyn Ctx,
-    ) -> ApiResult<ProductionPlanPerDay> {
-        ProductionPlandPerDayRepository::create(
+    ) -> ApiResult<CheckedProductionPlanPerDay> {
+        let line = Self::line(ct_input.line, db, ctx).await?;
+        let feasibility = PlanFeasibilityUseCases::check(
+            ct_input.amount,
+            &ct_input.units,
+            ct_input.date.clone(),
+            &line,
+            db,
+            ctx,
+        )
+        .await?;
+        let plan = ProductionPlandPerDayRepository::create(
             ct_input.amount,
             ct_input.units,
             ct_input.date,
+            line,
             db,
             ctx,
         )
-        .await
+        .await?;
+        Ok(CheckedProductionPlanPerDay { plan, feasibility })
     }
 
     pub async fn update(
@@ -192,8 +268,28 @@ impl ProductionPlanPerDayUseCases {
         id: &dyn ObjectWithThing,
         db: &Db,
         ctx: &dyn Ctx,
-    ) -> ApiResult<ProductionPlanPerDay> {
-        ProductionPlandPerDayRepository::update(ct_input, id.thing(ctx)?, db, ctx).await
+    ) -> ApiResult<CheckedProductionPlanPerDay> {
+        let line = Self::line(ct_input.line, db, ctx).await?;
+        let feasibility = PlanFeasibilityUseCases::check(
+            ct_input.amount,
+            &ct_input.units,
+            ct_input.date.clone(),
+            &line,
+            db,
+            ctx,
+        )
+        .await?;
+        let plan = ProductionPlandPerDayRepository::update(
+            ct_input.amount,
+            ct_input.units,
+            ct_input.date,
+            line,
+            id.thing(ctx)?,
+            db,
+            ctx,
+        )
+        .await?;
+        Ok(CheckedProductionPlanPerDay { plan, feasibility })
     }
 
     pub async fn delete(
@@ -212,6 +308,45 @@ impl ProductionPlanPerDayUseCases {
         ProductionPlandPerDayRepository::select_by_id(id, db, ctx).await
     }
 
+    pub async fn select_by_date(
+        line: &ThingDerived,
+        date: DateTimeDerived,
+        db: &Db,
+        ctx: &dyn Ctx,
+    ) -> ApiResult<Option<ProductionPlanPerDay>> {
+        ProductionPlandPerDayRepository::select_by_date(line, date, db, ctx).await
+    }
+
+    /// Plan of the line for the plant local day containing the date as it was at `as_of`
+    pub async fn select_by_date_as_of(
+        line: &ThingDerived,
+        date: DateTimeDerived,
+        as_of: DateTimeDerived,
+        db: &Db,
+        ctx: &dyn Ctx,
+    ) -> ApiResult<Option<PlanRevision>> {
+        PlanRevisionUseCases::select_as_of(RESOURCE, line, date, as_of, db, ctx).await
+    }
+
+    /// Every change of the plan, newest first
+    pub async fn revisions(
+        id: &dyn ObjectWithThing,
+        db: &Db,
+        ctx: &dyn Ctx,
+    ) -> ApiResult<Vec<PlanRevision>> {
+        PlanRevisionUseCases::select_by_plan(id, db, ctx).await
+    }
+
+    pub async fn select_by_range(
+        from: DateTimeDerived,
+        to: DateTimeDerived,
+        line: Option<ThingDerived>,
+        db: &Db,
+        ctx: &dyn Ctx,
+    ) -> ApiResult<Vec<ProductionPlanPerDay>> {
+        ProductionPlandPerDayRepository::select_by_range(from, to, line, db, ctx).await
+    }
+
     pub async fn count(db: &Db, ctx: &dyn Ctx) -> ApiResult<usize> {
         ProductionPlandPerDayRepository::count(db, ctx).await
     }
@@ -219,12 +354,13 @@ impl ProductionPlanPerDayUseCases {
     pub async fn list(
         offset: Option<usize>,
         limit: Option<usize>,
+        line: Option<ThingDerived>,
         db: &Db,
         ctx: &dyn Ctx,
     ) -> ApiResult<Vec<ProductionPlanPerDay>> {
         let limit = limit.unwrap_or(10);
         let offset = offset.unwrap_or(10);
 
-        ProductionPlandPerDayRepository::list(offset, limit, db, ctx).await
+        ProductionPlandPerDayRepository::list(offset, limit, line, db, ctx).await
     }
 }
@@ -0,0 +1,708 @@
+use crate::audit_log::{AuditLogRepository, Audited};
+use crate::common::Unwrapper;
+use crate::datetime::DateTimeDerived;
+use crate::flow_integration::plant_day;
+use crate::pipe::PipeUseCases;
+use crate::pipe_to::PipeToUseCases;
+use crate::plant_topology::PlantTopologyUseCases;
+use crate::production_info::{ProductionInfo, ProductionInfoRepository};
+use crate::quality_spec::{QualityParameter, QualitySpec, QualitySpecUseCases};
+use crate::service::guard::RoleGuard;
+use crate::thing_derived::ThingDerived;
+use crate::thing_wrapper::ObjectWithThing;
+use async_graphql::{ComplexObject, Context, InputObject, Result, SimpleObject};
+use common::{
+    ctx::{Ctx, CtxStruct},
+    error::{ApiError, Error},
+    role::Role,
+    ApiResult,
+};
+
+use db::Db;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use surrealdb::sql::Thing;
+
+#[allow(dead_code)]
+const RESOURCE: &str = "QualityCheck";
+
+/// Reading outside of the limits of the spec
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, SimpleObject)]
+#[graphql(guard = "RoleGuard::new(Role::User)")]
+pub struct QualityViolation {
+    pub parameter: QualityParameter,
+    /// None for microbiology
+    pub value: Option<Decimal>,
+    pub min: Option<Decimal>,
+    pub max: Option<Decimal>,
+}
+
+/// Laboratory or in-line readings taken on a pipe or a machinery
+#[derive(Clone, Debug, Serialize, Deserialize, SimpleObject)]
+#[graphql(guard = "RoleGuard::new(Role::User)")]
+#[graphql(complex)]
+pub struct QualityCheck {
+    pub id: Option<ThingDerived>,
+    /// Pipe or Machinery
+    pub object: ThingDerived,
+    /// RawMaterial the specs are taken from
+    pub material: ThingDerived,
+    pub checked_at: DateTimeDerived,
+    /// Start of the plant local day of `checked_at`
+    pub day: DateTimeDerived,
+    pub temperature: Option<Decimal>,
+    pub fat: Option<Decimal>,
+    pub viscosity: Option<Decimal>,
+    pub microbiology_passed: Option<bool>,
+    pub comment: Option<String>,
+    /// Evaluated against the specs in force when the check was saved
+    pub violations: Vec<QualityViolation>,
+    pub out_of_spec: bool,
+    /// Final pipes of the lines the object feeds
+    pub final_pipes: Vec<ThingDerived>,
+    pub checked_by: Option<ThingDerived>,
+}
+
+#[ComplexObject]
+impl QualityCheck {
+    /// ProductionInfo of the day of the check for every affected line.
+    /// Lines whose day is not materialized yet are missing
+    async fn production_info(&self, ctx: &Context<'_>) -> Result<Vec<ProductionInfo>> {
+        let db = ctx.data::<Db>()?;
+        let ctx = ctx.data::<CtxStruct>()?;
+        let mut result = vec![];
+        for final_pipe in self.final_pipes.iter() {
+            if let Some(info) =
+                ProductionInfoRepository::select_by_date(final_pipe, self.day.clone(), db, ctx)
+                    .await?
+            {
+                result.push(info);
+            }
+        }
+        Ok(result)
+    }
+}
+
+impl ObjectWithThing for QualityCheck {
+    fn thing(&self, ctx: &dyn Ctx) -> ApiResult<Thing> {
+        self.id
+            .as_ref()
+            .ok_or(ApiError {
+                req_id: ctx.req_id(),
+                error: Error::Generic {
+                    description: "Can't get thing. Get none instead".to_string(),
+                },
+            })?
+            .thing(ctx)
+    }
+}
+
+impl QualityCheck {
+    pub fn value(&self, parameter: QualityParameter) -> Option<Decimal> {
+        match parameter {
+            QualityParameter::Temperature => self.temperature,
+            QualityParameter::Fat => self.fat,
+            QualityParameter::Viscosity => self.viscosity,
+            QualityParameter::Microbiology => None,
+        }
+    }
+}
+
+/// Readings of the check outside of the specs. Parameters that were not measured are skipped
+pub fn violations(check: &QualityCheck, specs: &[QualitySpec]) -> Vec<QualityViolation> {
+    let mut result: Vec<QualityViolation> = specs
+        .iter()
+        .filter_map(|spec| {
+            let value = check.value(spec.parameter)?;
+            (!spec.contains(value)).then_some(QualityViolation {
+                parameter: spec.parameter,
+                value: Some(value),
+                min: spec.min,
+                max: spec.max,
+            })
+        })
+        .collect();
+    if check.microbiology_passed == Some(false) {
+        result.push(QualityViolation {
+            parameter: QualityParameter::Microbiology,
+            value: None,
+            min: None,
+            max: None,
+        });
+    }
+    result
+}
+
+pub struct QualityCheckRepository {}
+
+impl QualityCheckRepository {
+    pub async fn list(
+        offset: usize,
+        limit: usize,
+        object: Option<ThingDerived>,
+        out_of_spec: Option<bool>,
+        db: &Db,
+        ctx: &dyn Ctx,
+    ) -> ApiResult<Vec<QualityCheck>> {
+        let query = db
+            .query(format!(
+                "SELECT * FROM {RESOURCE} WHERE ($object = NONE OR object = $object) \
+                 AND ($out_of_spec = NONE OR out_of_spec = $out_of_spec) \
+                 ORDER BY checked_at DESC LIMIT {limit} START {offset};"
+            ))
+            .bind((
+                "object",
+                object.map(|object| object.thing(ctx)).transpose()?,
+            ))
+            .bind(("out_of_spec", out_of_spec));
+        Unwrapper::unwrapper_vec(query, 0, ctx).await
+    }
+
+    pub async fn count(db: &Db, ctx: &dyn Ctx) -> ApiResult<usize> {
+        let query = db.query(format!("(SELECT count() FROM {RESOURCE} GROUP ALL).count"));
+        Unwrapper::unwrapper_option_without_error(query, 0, ctx)
+            .await
+            .map(|count| count.unwrap_or(0))
+    }
+
+    pub async fn select_by_id(
+        id: &dyn ObjectWithThing,
+        db: &Db,
+        ctx: &dyn Ctx,
+    ) -> ApiResult<QualityCheck> {
+        let thing_id = id.thing(ctx)?;
+        if thing_id.tb != RESOURCE {
+            return Err(ApiError {
+                error: Error::Generic {
+                    description: "Wrong table name in select_by_id".to_string(),
+                },
+                req_id: ctx.req_id(),
+            });
+        }
+        let query = db
+            .query("SELECT * FROM $thing_id".to_string())
+            .bind(("thing_id", thing_id));
+
+        Unwrapper::unwrapper_option(query, 0, "Can't get quality check by id", ctx).await
+    }
+
+    /// Checks affecting the line on the plant local day containing the date
+    pub async fn select_by_date(
+        final_pipe: &ThingDerived,
+        date: DateTimeDerived,
+        out_of_spec: Option<bool>,
+        db: &Db,
+        ctx: &dyn Ctx,
+    ) -> ApiResult<Vec<QualityCheck>> {
+        let query = db
+            .query(format!(
+                "SELECT * FROM {RESOURCE} WHERE day = $day AND final_pipes CONTAINS $final_pipe \
+                 AND ($out_of_spec = NONE OR out_of_spec = $out_of_spec) \
+                 ORDER BY checked_at ASC;"
+            ))
+            .bind(("final_pipe", final_pipe.thing(ctx)?))
+            .bind(("day", plant_day(&date, ctx)?.0))
+            .bind(("out_of_spec", out_of_spec));
+        Unwrapper::unwrapper_vec(query, 0, ctx).await
+    }
+
+    /// Checks of the objects taken in [from, to], oldest first
+    pub async fn select_by_objects_and_range(
+        objects: Vec<Thing>,
+        from: DateTimeDerived,
+        to: DateTimeDerived,
+        db: &Db,
+        ctx: &dyn Ctx,
+    ) -> ApiResult<Vec<QualityCheck>> {
+        let query = db
+            .query(format!(
+                "SELECT * FROM {RESOURCE} WHERE object INSIDE $objects \
+                 AND checked_at >= $from AND checked_at <= $to ORDER BY checked_at ASC;"
+            ))
+            .bind(("objects", objects))
+            .bind(("from", from.0))
+            .bind(("to", to.0));
+        Unwrapper::unwrapper_vec(query, 0, ctx).await
+    }
+
+    pub async fn create(check: QualityCheck, db: &Db, ctx: &dyn Ctx) -> ApiResult<QualityCheck> {
+        db.create(RESOURCE)
+            .content(Audited::new(check, ctx))
+            .await
+            .map_err(ApiError::from(ctx))
+            .map(|v: Vec<QualityCheck>| {
+                v.into_iter().next().ok_or(ApiError {
+                    req_id: ctx.req_id(),
+                    error: Error::SurrealDbNoResult {
+                        source: "internal".to_string(),
+                        id: "Error while creating ".to_string(),
+                    },
+                })
+            })?
+    }
+
+    pub async fn update(
+        check: QualityCheck,
+        id: Thing,
+        db: &Db,
+        ctx: &dyn Ctx,
+    ) -> ApiResult<QualityCheck> {
+        db.update((RESOURCE, id.id.to_string()))
+            .content(Audited::new(QualityCheck { id: None, ..check }, ctx))
+            .await
+            .map_err(ApiError::from(ctx))?
+            .ok_or(ApiError {
+                req_id: ctx.req_id(),
+                error: Error::SurrealDbNoResult {
+                    source: "internal".to_string(),
+                    id: id.to_string(),
+                },
+            })
+    }
+
+    pub async fn delete(id: Thing, db: &Db, ctx: &dyn Ctx) -> ApiResult<QualityCheck> {
+        AuditLogRepository::delete(RESOURCE, id.id, db, ctx)
+            .await?
+            .ok_or(ApiError {
+                req_id: ctx.req_id(),
+                error: Error::SurrealDbNoResult {
+                    source: "internal".to_string(),
+                    id: "Error while deleting ".to_string(),
+                },
+            })
+    }
+}
+
+#[derive(Deserialize, InputObject, Clone, Serialize, Debug)]
+pub struct CreateQualityCheckInput {
+    /// Pipe or Machinery
+    pub object: ThingDerived,
+    /// Material of the pipe by default. Required for machinery with several output pipes
+    pub material: Option<ThingDerived>,
+    pub checked_at: DateTimeDerived,
+    pub temperature: Option<Decimal>,
+    pub fat: Option<Decimal>,
+    pub viscosity: Option<Decimal>,
+    pub microbiology_passed: Option<bool>,
+    pub comment: Option<String>,
+}
+
+pub struct QualityCheckUseCases {}
+
+impl QualityCheckUseCases {
+    async fn material(
+        object: &ThingDerived,
+        material: Option<ThingDerived>,
+        db: &Db,
+        ctx: &dyn Ctx,
+    ) -> ApiResult<ThingDerived> {
+        let error = |description: String| ApiError {
+            req_id: ctx.req_id(),
+            error: Error::Generic {
+                description: format!("Quality check: {description}"),
+            },
+        };
+        match object.thing(ctx)?.tb.as_str() {
+            "Pipe" => {
+                let pipe = PipeUseCases::select_by_id(object, db, ctx).await?;
+                match material {
+                    Some(material) if material != pipe.material => Err(error(format!(
+                        "pipe {object} carries {}, not {material}",
+                        pipe.material
+                    ))),
+                    _ => Ok(pipe.material),
+                }
+            }
+            "Machinery" => match material {
+                Some(material) => Ok(material),
+                None => match PipeToUseCases::select_output_pipes(object, db, ctx)
+                    .await?
+                    .as_slice()
+                {
+                    [pipe] => Ok(pipe.material.clone()),
+                    pipes => Err(error(format!(
+                        "`material` is required for machinery with {} output pipes",
+                        pipes.len()
+                    ))),
+                },
+            },
+            _ => Err(error(format!("{object} is neither a pipe nor a machinery"))),
+        }
+    }
+
+    async fn check(
+        ct_input: CreateQualityCheckInput,
+        db: &Db,
+        ctx: &dyn Ctx,
+    ) -> ApiResult<QualityCheck> {
+        if ct_input.temperature.is_none()
+            && ct_input.fat.is_none()
+            && ct_input.viscosity.is_none()
+            && ct_input.microbiology_passed.is_none()
+        {
+            return Err(ApiError {
+                req_id: ctx.req_id(),
+                error: Error::Generic {
+                    description: "Quality check: no readings".to_string(),
+                },
+            });
+        }
+        let material = Self::material(&ct_input.object, ct_input.material, db, ctx).await?;
+        let final_pipes = PlantTopologyUseCases::get(db, ctx)
+            .await?
+            .downstream_final_pipes(&ct_input.object);
+        let mut check = QualityCheck {
+            id: None,
+            day: plant_day(&ct_input.checked_at, ctx)?,
+            object: ct_input.object,
+            material,
+            checked_at: ct_input.checked_at,
+            temperature: ct_input.temperature,
+            fat: ct_input.fat,
+            viscosity: ct_input.viscosity,
+            microbiology_passed: ct_input.microbiology_passed,
+            comment: ct_input.comment,
+            violations: vec![],
+            out_of_spec: false,
+            final_pipes,
+            checked_by: ctx.user_id_thing().ok().map(Into::into),
+        };
+        let specs = QualitySpecUseCases::select_by_material(&check.material, db, ctx).await?;
+        check.violations = violations(&check, &specs);
+        check.out_of_spec = !check.violations.is_empty();
+        Ok(check)
+    }
+
+    pub async fn create(
+        ct_input: CreateQualityCheckInput,
+        db: &Db,
+        ctx: &dyn Ctx,
+    ) -> ApiResult<QualityCheck> {
+        QualityCheckRepository::create(Self::check(ct_input, db, ctx).await?, db, ctx).await
+    }
+
+    /// Violations are evaluated again against the current specs
+    pub async fn update(
+        ct_input: CreateQualityCheckInput,
+        id: &dyn ObjectWithThing,
+        db: &Db,
+        ctx: &dyn Ctx,
+    ) -> ApiResult<QualityCheck> {
+        let checked_by = QualityCheckRepository::select_by_id(id, db, ctx)
+            .await?
+            .checked_by;
+        // The editor is kept in changed_by, the check stays made by its author
+        let check = QualityCheck {
+            checked_by,
+            ..Self::check(ct_input, db, ctx).await?
+        };
+        QualityCheckRepository::update(check, id.thing(ctx)?, db, ctx).await
+    }
+
+    pub async fn delete(
+        id: &dyn ObjectWithThing,
+        db: &Db,
+        ctx: &dyn Ctx,
+    ) -> ApiResult<QualityCheck> {
+        QualityCheckRepository::delete(id.thing(ctx)?, db, ctx).await
+    }
+
+    pub async fn select_by_id(
+        id: &dyn ObjectWithThing,
+        db: &Db,
+        ctx: &dyn Ctx,
+    ) -> ApiResult<QualityCheck> {
+        QualityCheckRepository::select_by_id(id, db, ctx).await
+    }
+
+    /// Uses the only final pipe of the plant if `final_pipe` is not set
+    pub async fn select_by_date(
+        final_pipe: Option<ThingDerived>,
+        date: DateTimeDerived,
+        out_of_spec: Option<bool>,
+        db: &Db,
+        ctx: &dyn Ctx,
+    ) -> ApiResult<Vec<QualityCheck>> {
+        let final_pipe = match final_pipe {
+            Some(final_pipe) => final_pipe,
+            None => PlantTopologyUseCases::select_final_pipe(db, ctx).await?,
+        };
+        QualityCheckRepository::select_by_date(&final_pipe, date, out_of_spec, db, ctx).await
+    }
+
+    pub async fn count(db: &Db, ctx: &dyn Ctx) -> ApiResult<usize> {
+        QualityCheckRepository::count(db, ctx).await
+    }
+
+    pub async fn list(
+        offset: Option<usize>,
+        limit: Option<usize>,
+        object: Option<ThingDerived>,
+        out_of_spec: Option<bool>,
+        db: &Db,
+        ctx: &dyn Ctx,
+    ) -> ApiResult<Vec<QualityCheck>> {
+        let limit = limit.unwrap_or(10);
+        let offset = offset.unwrap_or(0);
+
+        QualityCheckRepository::list(offset, limit, object, out_of_spec, db, ctx).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pipe_stats::tests::create_pipe;
+    use crate::production_info::ProductionInfoUseCases;
+    use crate::production_per_day::{
+        CreateProductionPlanPerDayTypeInput, ProductionPlanPerDayUseCases,
+    };
+    use crate::quality_spec::CreateQualitySpecInput;
+    use crate::sales_per_day::{CreateSalesPlanPerDayTypeInput, SalesPlanPerDayUnitsUseCases};
+    use crate::user::tests::create_user;
+    use chrono::{DateTime, TimeZone, Utc};
+    use common::ctx::MockCtx;
+    use db::set_test_db;
+    use rstest::*;
+
+    #[fixture]
+    fn ctx() -> MockCtx {
+        let mut ctx = MockCtx::new();
+        ctx.expect_req_id().return_const(uuid::Uuid::new_v4());
+        ctx.expect_user_id_thing().returning(|| {
+            Err(ApiError {
+                req_id: uuid::Uuid::new_v4(),
+                error: Error::Generic {
+                    description: "No user".to_string(),
+                },
+            })
+        });
+        ctx
+    }
+
+    #[fixture]
+    async fn tdb() -> Db {
+        set_test_db().await
+    }
+
+    fn at(day: u32, hour: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2024, 1, day, hour, 0, 0).unwrap()
+    }
+
+    fn spec(parameter: QualityParameter, min: Option<i64>, max: Option<i64>) -> QualitySpec {
+        QualitySpec {
+            id: None,
+            material: Thing::from(("RawMaterial", "mix")).into(),
+            parameter,
+            min: min.map(Decimal::from),
+            max: max.map(Decimal::from),
+            comment: None,
+        }
+    }
+
+    #[test]
+    fn violations_test() {
+        let specs = [
+            spec(QualityParameter::Temperature, Some(80), Some(85)),
+            spec(QualityParameter::Fat, Some(10), None),
+            spec(QualityParameter::Viscosity, None, Some(300)),
+        ];
+        let mut check = QualityCheck {
+            id: None,
+            object: Thing::from(("Pipe", "pipe")).into(),
+            material: Thing::from(("RawMaterial", "mix")).into(),
+            checked_at: at(1, 10).into(),
+            day: at(1, 0).into(),
+            temperature: Some(Decimal::from(85)),
+            fat: Some(Decimal::new(95, 1)),
+            viscosity: None,
+            microbiology_passed: Some(true),
+            comment: None,
+            violations: vec![],
+            out_of_spec: false,
+            final_pipes: vec![],
+            checked_by: None,
+        };
+        // Limits are inclusive, viscosity was not measured
+        assert_eq!(
+            violations(&check, &specs),
+            vec![QualityViolation {
+                parameter: QualityParameter::Fat,
+                value: Some(Decimal::new(95, 1)),
+                min: Some(Decimal::from(10)),
+                max: None,
+            }]
+        );
+
+        check.fat = None;
+        check.viscosity = Some(Decimal::from(301));
+        check.microbiology_passed = Some(false);
+        let parameters: Vec<QualityParameter> = violations(&check, &specs)
+            .into_iter()
+            .map(|violation| violation.parameter)
+            .collect();
+        assert_eq!(
+            parameters,
+            vec![QualityParameter::Viscosity, QualityParameter::Microbiology]
+        );
+        assert!(violations(&check, &[]).len() == 1);
+    }
+
+    #[rstest]
+    #[tokio::test]
+    #[awt]
+    async fn quality_check_test(ctx: MockCtx, #[future] tdb: Db) {
+        let (pipe, units) = create_pipe(&ctx, &tdb).await.unwrap();
+        let line = pipe.id.clone().unwrap();
+        let units = units.id.unwrap();
+        for (parameter, min, max) in [
+            (QualityParameter::Temperature, Some(80), Some(85)),
+            (QualityParameter::Fat, Some(10), Some(16)),
+        ] {
+            QualitySpecUseCases::create(
+                CreateQualitySpecInput {
+                    material: pipe.material.clone(),
+                    parameter,
+                    min: min.map(Decimal::from),
+                    max: max.map(Decimal::from),
+                    comment: None,
+                },
+                &tdb,
+                &ctx,
+            )
+            .await
+            .unwrap();
+        }
+        // Wrong limits
+        assert!(QualitySpecUseCases::create(
+            CreateQualitySpecInput {
+                material: pipe.material.clone(),
+                parameter: QualityParameter::Viscosity,
+                min: Some(Decimal::from(300)),
+                max: Some(Decimal::from(100)),
+                comment: None,
+            },
+            &tdb,
+            &ctx,
+        )
+        .await
+        .is_err());
+
+        let input = |hour: u32, temperature: i64| CreateQualityCheckInput {
+            object: line.clone(),
+            material: None,
+            checked_at: at(1, hour).into(),
+            temperature: Some(Decimal::from(temperature)),
+            fat: Some(Decimal::from(12)),
+            viscosity: None,
+            microbiology_passed: None,
+            comment: None,
+        };
+        let ok = QualityCheckUseCases::create(input(9, 82), &tdb, &ctx)
+            .await
+            .unwrap();
+        assert!(!ok.out_of_spec);
+        assert_eq!(ok.material, pipe.material);
+        assert_eq!(ok.final_pipes, vec![line.clone()]);
+        let failed = QualityCheckUseCases::create(input(10, 75), &tdb, &ctx)
+            .await
+            .unwrap();
+        assert!(failed.out_of_spec);
+        assert_eq!(failed.violations.len(), 1);
+        assert_eq!(failed.violations[0].value, Some(Decimal::from(75)));
+        assert_eq!(failed.violations[0].min, Some(Decimal::from(80)));
+        // Other material than the pipe carries
+        assert!(QualityCheckUseCases::create(
+            CreateQualityCheckInput {
+                material: Some(Thing::from(("RawMaterial", "other")).into()),
+                ..input(11, 82)
+            },
+            &tdb,
+            &ctx,
+        )
+        .await
+        .is_err());
+
+        let day_checks =
+            QualityCheckUseCases::select_by_date(None, at(1, 20).into(), Some(true), &tdb, &ctx)
+                .await
+                .unwrap();
+        assert_eq!(day_checks.len(), 1);
+        assert_eq!(day_checks[0].id, failed.id);
+        assert_eq!(
+            QualityCheckUseCases::select_by_date(None, at(1, 20).into(), None, &tdb, &ctx)
+                .await
+                .unwrap()
+                .len(),
+            2
+        );
+        assert!(
+            QualityCheckUseCases::select_by_date(None, at(2, 10).into(), None, &tdb, &ctx)
+                .await
+                .unwrap()
+                .is_empty()
+        );
+
+        // Day of the check is linked once its ProductionInfo exists
+        ProductionPlanPerDayUseCases::create(
+            CreateProductionPlanPerDayTypeInput {
+                amount: Decimal::from(100),
+                units: units.clone(),
+                date: at(1, 0).into(),
+                line: Some(line.clone()),
+            },
+            &tdb,
+            &ctx,
+        )
+        .await
+        .unwrap();
+        SalesPlanPerDayUnitsUseCases::create(
+            CreateSalesPlanPerDayTypeInput {
+                amount: Decimal::from(80),
+                units: units.clone(),
+                date: at(1, 0).into(),
+                line: Some(line.clone()),
+            },
+            &tdb,
+            &ctx,
+        )
+        .await
+        .unwrap();
+        let info = ProductionInfoUseCases::select_create(
+            failed.checked_at.clone(),
+            Some(line.clone()),
+            &tdb,
+            &ctx,
+        )
+        .await
+        .unwrap()
+        .unwrap();
+        let linked = ProductionInfoRepository::select_by_date(
+            &failed.final_pipes[0],
+            failed.day.clone(),
+            &tdb,
+            &ctx,
+        )
+        .await
+        .unwrap()
+        .unwrap();
+        assert_eq!(linked.id, info.id);
+
+        // Edits keep the author of the check
+        let (user, _) = create_user(&ctx, &tdb).await.unwrap();
+        let user_id = user.thing(&ctx).unwrap();
+        let mut author = MockCtx::new();
+        author.expect_req_id().return_const(uuid::Uuid::new_v4());
+        author
+            .expect_user_id_thing()
+            .returning(move || Ok(user_id.clone()));
+        let checked = QualityCheckUseCases::create(input(12, 82), &tdb, &author)
+            .await
+            .unwrap();
+        let edited = QualityCheckUseCases::update(input(12, 83), &checked, &tdb, &ctx)
+            .await
+            .unwrap();
+        assert_eq!(edited.temperature, Some(Decimal::from(83)));
+        assert_eq!(edited.checked_by, user.id);
+    }
+}
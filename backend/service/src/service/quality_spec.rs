@@ -0,0 +1,277 @@
+use crate::audit_log::{AuditLogRepository, Audited};
+use crate::common::Unwrapper;
+use crate::service::guard::RoleGuard;
+use crate::thing_derived::ThingDerived;
+use crate::thing_wrapper::ObjectWithThing;
+use async_graphql::{Enum, InputObject, SimpleObject};
+use common::{
+    ctx::Ctx,
+    error::{ApiError, Error},
+    role::Role,
+    ApiResult,
+};
+
+use db::Db;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use surrealdb::sql::Thing;
+
+#[allow(dead_code)]
+const RESOURCE: &str = "QualitySpec";
+
+#[derive(Enum, Copy, Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub enum QualityParameter {
+    /// °C
+    Temperature,
+    /// Mass fraction of fat, %
+    Fat,
+    /// mPa·s
+    Viscosity,
+    /// Pass/fail. A failed test is out of spec for any material
+    Microbiology,
+}
+
+/// Limits of one parameter for a material. Stages of the line carry different
+/// materials, so pasteurization and maturation have their own limits
+#[derive(Clone, Debug, Serialize, Deserialize, SimpleObject)]
+#[graphql(guard = "RoleGuard::new(Role::User)")]
+pub struct QualitySpec {
+    pub id: Option<ThingDerived>,
+    pub material: ThingDerived,
+    pub parameter: QualityParameter,
+    /// Inclusive. No lower limit if None
+    pub min: Option<Decimal>,
+    /// Inclusive. No upper limit if None
+    pub max: Option<Decimal>,
+    pub comment: Option<String>,
+}
+
+impl ObjectWithThing for QualitySpec {
+    fn thing(&self, ctx: &dyn Ctx) -> ApiResult<Thing> {
+        self.id
+            .as_ref()
+            .ok_or(ApiError {
+                req_id: ctx.req_id(),
+                error: Error::Generic {
+                    description: "Can't get thing. Get none instead".to_string(),
+                },
+            })?
+            .thing(ctx)
+    }
+}
+
+impl QualitySpec {
+    pub fn contains(&self, value: Decimal) -> bool {
+        self.min.is_none_or(|min| value >= min) && self.max.is_none_or(|max| value <= max)
+    }
+}
+
+pub struct QualitySpecRepository {}
+
+impl QualitySpecRepository {
+    pub async fn list(
+        offset: usize,
+        limit: usize,
+        material: Option<ThingDerived>,
+        db: &Db,
+        ctx: &dyn Ctx,
+    ) -> ApiResult<Vec<QualitySpec>> {
+        let query = db
+            .query(format!(
+                "SELECT * FROM {RESOURCE} WHERE $material = NONE OR material = $material \
+                 ORDER BY material, parameter LIMIT {limit} START {offset};"
+            ))
+            .bind((
+                "material",
+                material.map(|material| material.thing(ctx)).transpose()?,
+            ));
+        Unwrapper::unwrapper_vec(query, 0, ctx).await
+    }
+
+    pub async fn count(db: &Db, ctx: &dyn Ctx) -> ApiResult<usize> {
+        let query = db.query(format!("(SELECT count() FROM {RESOURCE} GROUP ALL).count"));
+        Unwrapper::unwrapper_option_without_error(query, 0, ctx)
+            .await
+            .map(|count| count.unwrap_or(0))
+    }
+
+    pub async fn select_by_id(
+        id: &dyn ObjectWithThing,
+        db: &Db,
+        ctx: &dyn Ctx,
+    ) -> ApiResult<QualitySpec> {
+        let thing_id = id.thing(ctx)?;
+        if thing_id.tb != RESOURCE {
+            return Err(ApiError {
+                error: Error::Generic {
+                    description: "Wrong table name in select_by_id".to_string(),
+                },
+                req_id: ctx.req_id(),
+            });
+        }
+        let query = db
+            .query("SELECT * FROM $thing_id".to_string())
+            .bind(("thing_id", thing_id));
+
+        Unwrapper::unwrapper_option(query, 0, "Can't get quality spec by id", ctx).await
+    }
+
+    pub async fn select_by_material(
+        material: &dyn ObjectWithThing,
+        db: &Db,
+        ctx: &dyn Ctx,
+    ) -> ApiResult<Vec<QualitySpec>> {
+        let query = db
+            .query(format!(
+                "SELECT * FROM {RESOURCE} WHERE material = $material;"
+            ))
+            .bind(("material", material.thing(ctx)?));
+        Unwrapper::unwrapper_vec(query, 0, ctx).await
+    }
+
+    pub async fn create(spec: QualitySpec, db: &Db, ctx: &dyn Ctx) -> ApiResult<QualitySpec> {
+        db.create(RESOURCE)
+            .content(Audited::new(spec, ctx))
+            .await
+            .map_err(ApiError::from(ctx))
+            .map(|v: Vec<QualitySpec>| {
+                v.into_iter().next().ok_or(ApiError {
+                    req_id: ctx.req_id(),
+                    error: Error::SurrealDbNoResult {
+                        source: "internal".to_string(),
+                        id: "Error while creating ".to_string(),
+                    },
+                })
+            })?
+    }
+
+    pub async fn update(
+        spec: QualitySpec,
+        id: Thing,
+        db: &Db,
+        ctx: &dyn Ctx,
+    ) -> ApiResult<QualitySpec> {
+        db.update((RESOURCE, id.id.to_string()))
+            .content(Audited::new(QualitySpec { id: None, ..spec }, ctx))
+            .await
+            .map_err(ApiError::from(ctx))?
+            .ok_or(ApiError {
+                req_id: ctx.req_id(),
+                error: Error::SurrealDbNoResult {
+                    source: "internal".to_string(),
+                    id: id.to_string(),
+                },
+            })
+    }
+
+    pub async fn delete(id: Thing, db: &Db, ctx: &dyn Ctx) -> ApiResult<QualitySpec> {
+        AuditLogRepository::delete(RESOURCE, id.id, db, ctx)
+            .await?
+            .ok_or(ApiError {
+                req_id: ctx.req_id(),
+                error: Error::SurrealDbNoResult {
+                    source: "internal".to_string(),
+                    id: "Error while deleting ".to_string(),
+                },
+            })
+    }
+}
+
+#[derive(Deserialize, InputObject, Clone, Serialize, Debug)]
+pub struct CreateQualitySpecInput {
+    pub material: ThingDerived,
+    pub parameter: QualityParameter,
+    pub min: Option<Decimal>,
+    pub max: Option<Decimal>,
+    pub comment: Option<String>,
+}
+
+pub struct QualitySpecUseCases {}
+
+impl QualitySpecUseCases {
+    fn spec(ct_input: CreateQualitySpecInput, ctx: &dyn Ctx) -> ApiResult<QualitySpec> {
+        let error = |description: &str| ApiError {
+            req_id: ctx.req_id(),
+            error: Error::Generic {
+                description: format!("Quality spec: {description}"),
+            },
+        };
+        if ct_input.parameter == QualityParameter::Microbiology {
+            return Err(error("microbiology is pass/fail and has no limits"));
+        }
+        match (ct_input.min, ct_input.max) {
+            (None, None) => return Err(error("at least one of `min` and `max` is required")),
+            (Some(min), Some(max)) if min > max => {
+                return Err(error("`min` should not be greater than `max`"))
+            }
+            _ => {}
+        }
+        Ok(QualitySpec {
+            id: None,
+            material: ct_input.material,
+            parameter: ct_input.parameter,
+            min: ct_input.min,
+            max: ct_input.max,
+            comment: ct_input.comment,
+        })
+    }
+
+    pub async fn create(
+        ct_input: CreateQualitySpecInput,
+        db: &Db,
+        ctx: &dyn Ctx,
+    ) -> ApiResult<QualitySpec> {
+        QualitySpecRepository::create(Self::spec(ct_input, ctx)?, db, ctx).await
+    }
+
+    /// Checks saved before keep the violations evaluated against the old limits
+    pub async fn update(
+        ct_input: CreateQualitySpecInput,
+        id: &dyn ObjectWithThing,
+        db: &Db,
+        ctx: &dyn Ctx,
+    ) -> ApiResult<QualitySpec> {
+        QualitySpecRepository::update(Self::spec(ct_input, ctx)?, id.thing(ctx)?, db, ctx).await
+    }
+
+    pub async fn delete(
+        id: &dyn ObjectWithThing,
+        db: &Db,
+        ctx: &dyn Ctx,
+    ) -> ApiResult<QualitySpec> {
+        QualitySpecRepository::delete(id.thing(ctx)?, db, ctx).await
+    }
+
+    pub async fn select_by_id(
+        id: &dyn ObjectWithThing,
+        db: &Db,
+        ctx: &dyn Ctx,
+    ) -> ApiResult<QualitySpec> {
+        QualitySpecRepository::select_by_id(id, db, ctx).await
+    }
+
+    pub async fn select_by_material(
+        material: &dyn ObjectWithThing,
+        db: &Db,
+        ctx: &dyn Ctx,
+    ) -> ApiResult<Vec<QualitySpec>> {
+        QualitySpecRepository::select_by_material(material, db, ctx).await
+    }
+
+    pub async fn count(db: &Db, ctx: &dyn Ctx) -> ApiResult<usize> {
+        QualitySpecRepository::count(db, ctx).await
+    }
+
+    pub async fn list(
+        offset: Option<usize>,
+        limit: Option<usize>,
+        material: Option<ThingDerived>,
+        db: &Db,
+        ctx: &dyn Ctx,
+    ) -> ApiResult<Vec<QualitySpec>> {
+        let limit = limit.unwrap_or(10);
+        let offset = offset.unwrap_or(0);
+
+        QualitySpecRepository::list(offset, limit, material, db, ctx).await
+    }
+}
@@ -1,3 +1,4 @@
+use crate::audit_log::{AuditLogRepository, Audited};
 use crate::common::Unwrapper;
 use crate::service::guard::RoleGuard;
 use crate::thing_derived::ThingDerived;
@@ -98,7 +99,7 @@ impl RawMaterialRepository {
 
     pub async fn create(name: String, db: &Db, ctx: &dyn Ctx) -> ApiResult<RawMaterial> {
         db.create(RESOURCE)
-            .content(RawMaterial { id: None, name })
+            .content(Audited::new(RawMaterial { id: None, name }, ctx))
             .await
             .map_err(ApiError::from(ctx))
             .map(|v: Vec<RawMaterial>| {
@@ -119,7 +120,7 @@ impl RawMaterialRepository {
         ctx: &dyn Ctx,
     ) -> ApiResult<RawMaterial> {
         db.update((RESOURCE, id.id.to_string()))
-            .content(ct_input)
+            .content(Audited::new(ct_input, ctx))
             .await
             .map_err(ApiError::from(ctx))?
             .ok_or(ApiError {
@@ -132,9 +133,8 @@ impl RawMaterialRepository {
     }
 
     pub async fn delete(id: Thing, db: &Db, ctx: &dyn Ctx) -> ApiResult<RawMaterial> {
-        db.delete((RESOURCE, id))
-            .await
-            .map_err(ApiError::from(ctx))?
+        AuditLogRepository::delete(RESOURCE, id.id, db, ctx)
+            .await?
             .ok_or(ApiError {
                 req_id: ctx.req_id(),
                 error: Error::SurrealDbNoResult {
@@ -0,0 +1,758 @@
+use crate::audit_log::{AuditLogRepository, Audited};
+use crate::common::Unwrapper;
+use crate::measure_units::{MeasureUnitsUseCases, UnitConverter};
+use crate::pipe::PipeUseCases;
+use crate::plant_topology::PlantTopologyUseCases;
+use crate::production_per_day::{ProductionPlanPerDay, ProductionPlanPerDayUseCases};
+use crate::service::guard::RoleGuard;
+use crate::thing_derived::ThingDerived;
+use crate::thing_wrapper::ObjectWithThing;
+use async_graphql::{InputObject, SimpleObject};
+use common::{
+    ctx::Ctx,
+    error::{ApiError, Error},
+    role::Role,
+    ApiResult,
+};
+
+use db::Db;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use surrealdb::sql::Thing;
+
+#[allow(dead_code)]
+const RESOURCE: &str = "Recipe";
+
+#[derive(Clone, Debug, Serialize, Deserialize, SimpleObject)]
+#[graphql(guard = "RoleGuard::new(Role::User)")]
+pub struct RecipeIngredient {
+    pub material: ThingDerived,
+    pub amount: Decimal,
+    pub units: ThingDerived,
+}
+
+/// Bill of materials of one product. Ingredients that have recipes themselves
+/// are intermediate products, the rest are raw materials
+#[derive(Clone, Debug, Serialize, Deserialize, SimpleObject)]
+#[graphql(guard = "RoleGuard::new(Role::User)")]
+pub struct Recipe {
+    pub id: Option<ThingDerived>,
+    pub product: ThingDerived,
+    /// Amount of the product made from the ingredients
+    pub output_amount: Decimal,
+    pub output_units: ThingDerived,
+    pub ingredients: Vec<RecipeIngredient>,
+}
+
+impl ObjectWithThing for Recipe {
+    fn thing(&self, ctx: &dyn Ctx) -> ApiResult<Thing> {
+        self.id
+            .as_ref()
+            .ok_or(ApiError {
+                req_id: ctx.req_id(),
+                error: Error::Generic {
+                    description: "Can't get thing. Get none instead".to_string(),
+                },
+            })?
+            .thing(ctx)
+    }
+}
+
+#[derive(Clone, Debug, SimpleObject)]
+#[graphql(guard = "RoleGuard::new(Role::User)")]
+pub struct MaterialRequirement {
+    pub material: ThingDerived,
+    pub amount: Decimal,
+    /// Units the material is first required in
+    pub units: ThingDerived,
+}
+
+#[derive(Clone, Debug, SimpleObject)]
+#[graphql(guard = "RoleGuard::new(Role::User)")]
+pub struct RequirementExplosion {
+    pub product: ThingDerived,
+    pub amount: Decimal,
+    pub units: ThingDerived,
+    /// Materials without recipes, in the order they are reached
+    pub raw_materials: Vec<MaterialRequirement>,
+    /// Products made on the way to the product
+    pub intermediates: Vec<MaterialRequirement>,
+}
+
+/// Adds the amount to the requirement of the material
+fn accumulate(
+    requirements: &mut Vec<MaterialRequirement>,
+    material: &ThingDerived,
+    amount: Decimal,
+    units: &ThingDerived,
+    converter: &UnitConverter,
+    ctx: &dyn Ctx,
+) -> ApiResult<()> {
+    match requirements
+        .iter_mut()
+        .find(|requirement| requirement.material == *material)
+    {
+        Some(requirement) => {
+            requirement.amount += converter.convert(amount, units, &requirement.units, ctx)?
+        }
+        None => requirements.push(MaterialRequirement {
+            material: material.clone(),
+            amount,
+            units: units.clone(),
+        }),
+    }
+    Ok(())
+}
+
+struct Explosion<'a> {
+    recipes: HashMap<String, &'a Recipe>,
+    converter: &'a UnitConverter,
+    /// Products being exploded, to catch recipes that consume their own product
+    path: Vec<String>,
+    raw_materials: Vec<MaterialRequirement>,
+    intermediates: Vec<MaterialRequirement>,
+}
+
+impl Explosion<'_> {
+    fn add(
+        &mut self,
+        material: &ThingDerived,
+        amount: Decimal,
+        units: &ThingDerived,
+        ctx: &dyn Ctx,
+    ) -> ApiResult<()> {
+        let Some(recipe) = self.recipes.get(&material.to_string()).copied() else {
+            return accumulate(
+                &mut self.raw_materials,
+                material,
+                amount,
+                units,
+                self.converter,
+                ctx,
+            );
+        };
+        if self.path.contains(&material.to_string()) {
+            return Err(ApiError {
+                req_id: ctx.req_id(),
+                error: Error::Generic {
+                    description: format!("Recipes of {material} form a cycle"),
+                },
+            });
+        }
+        if !self.path.is_empty() {
+            accumulate(
+                &mut self.intermediates,
+                material,
+                amount,
+                units,
+                self.converter,
+                ctx,
+            )?;
+        }
+        let amount = self
+            .converter
+            .convert(amount, units, &recipe.output_units, ctx)?;
+        self.path.push(material.to_string());
+        for ingredient in recipe.ingredients.iter() {
+            // Multiplied first to keep exact amounts for exact ratios
+            let required = ingredient.amount * amount / recipe.output_amount;
+            self.add(&ingredient.material, required, &ingredient.units, ctx)?;
+        }
+        self.path.pop();
+        Ok(())
+    }
+}
+
+/// Materials needed to make `amount` of the product, following recipes of intermediate
+/// products down to raw materials. Amounts of one material are summed in the units
+/// it is first required in
+pub fn explode(
+    recipes: &[Recipe],
+    product: &ThingDerived,
+    amount: Decimal,
+    units: &ThingDerived,
+    converter: &UnitConverter,
+    ctx: &dyn Ctx,
+) -> ApiResult<RequirementExplosion> {
+    let mut explosion = Explosion {
+        recipes: recipes
+            .iter()
+            .map(|recipe| (recipe.product.to_string(), recipe))
+            .collect(),
+        converter,
+        path: vec![],
+        raw_materials: vec![],
+        intermediates: vec![],
+    };
+    explosion.add(product, amount, units, ctx)?;
+    Ok(RequirementExplosion {
+        product: product.clone(),
+        amount,
+        units: units.clone(),
+        raw_materials: explosion.raw_materials,
+        intermediates: explosion.intermediates,
+    })
+}
+
+pub struct RecipeRepository {}
+
+impl RecipeRepository {
+    pub async fn list(
+        offset: usize,
+        limit: usize,
+        db: &Db,
+        ctx: &dyn Ctx,
+    ) -> ApiResult<Vec<Recipe>> {
+        let query = db.query(format!(
+            "SELECT * FROM {RESOURCE} LIMIT {limit} START {offset};"
+        ));
+        Unwrapper::unwrapper_vec(query, 0, ctx).await
+    }
+
+    /// Every recipe, for explosion
+    pub async fn select_all(db: &Db, ctx: &dyn Ctx) -> ApiResult<Vec<Recipe>> {
+        let query = db.query(format!("SELECT * FROM {RESOURCE};"));
+        Unwrapper::unwrapper_vec(query, 0, ctx).await
+    }
+
+    pub async fn count(db: &Db, ctx: &dyn Ctx) -> ApiResult<usize> {
+        let query = db.query(format!("(SELECT count() FROM {RESOURCE} GROUP ALL).count"));
+        Unwrapper::unwrapper_option_without_error(query, 0, ctx)
+            .await
+            .map(|count| count.unwrap_or(0))
+    }
+
+    pub async fn select_by_id(
+        id: &dyn ObjectWithThing,
+        db: &Db,
+        ctx: &dyn Ctx,
+    ) -> ApiResult<Recipe> {
+        let thing_id = id.thing(ctx)?;
+        if thing_id.tb != RESOURCE {
+            return Err(ApiError {
+                error: Error::Generic {
+                    description: "Wrong table name in select_by_id".to_string(),
+                },
+                req_id: ctx.req_id(),
+            });
+        }
+        let query = db
+            .query("SELECT * FROM $thing_id".to_string())
+            .bind(("thing_id", thing_id));
+
+        Unwrapper::unwrapper_option(query, 0, "Can't get recipe by id", ctx).await
+    }
+
+    pub async fn select_by_product(
+        product: &ThingDerived,
+        db: &Db,
+        ctx: &dyn Ctx,
+    ) -> ApiResult<Option<Recipe>> {
+        let query = db
+            .query(format!(
+                "SELECT * FROM {RESOURCE} WHERE product = $product;"
+            ))
+            .bind(("product", product.thing(ctx)?));
+        Ok(Unwrapper::unwrapper_vec(query, 0, ctx)
+            .await?
+            .into_iter()
+            .next())
+    }
+
+    pub async fn create(recipe: Recipe, db: &Db, ctx: &dyn Ctx) -> ApiResult<Recipe> {
+        db.create(RESOURCE)
+            .content(Audited::new(recipe, ctx))
+            .await
+            .map_err(ApiError::from(ctx))
+            .map(|v: Vec<Recipe>| {
+                v.into_iter().next().ok_or(ApiError {
+                    req_id: ctx.req_id(),
+                    error: Error::SurrealDbNoResult {
+                        source: "internal".to_string(),
+                        id: "Error while creating ".to_string(),
+                    },
+                })
+            })?
+    }
+
+    pub async fn update(recipe: Recipe, id: Thing, db: &Db, ctx: &dyn Ctx) -> ApiResult<Recipe> {
+        db.update((RESOURCE, id.id.to_string()))
+            .content(Audited::new(Recipe { id: None, ..recipe }, ctx))
+            .await
+            .map_err(ApiError::from(ctx))?
+            .ok_or(ApiError {
+                req_id: ctx.req_id(),
+                error: Error::SurrealDbNoResult {
+                    source: "internal".to_string(),
+                    id: id.to_string(),
+                },
+            })
+    }
+
+    pub async fn delete(id: Thing, db: &Db, ctx: &dyn Ctx) -> ApiResult<Recipe> {
+        AuditLogRepository::delete(RESOURCE, id.id, db, ctx)
+            .await?
+            .ok_or(ApiError {
+                req_id: ctx.req_id(),
+                error: Error::SurrealDbNoResult {
+                    source: "internal".to_string(),
+                    id: "Error while deleting ".to_string(),
+                },
+            })
+    }
+}
+
+#[derive(Deserialize, InputObject, Clone, Serialize, Debug)]
+pub struct CreateRecipeIngredientInput {
+    pub material: ThingDerived,
+    pub amount: Decimal,
+    pub units: ThingDerived,
+}
+
+#[derive(Deserialize, InputObject, Clone, Serialize, Debug)]
+pub struct CreateRecipeInput {
+    pub product: ThingDerived,
+    pub output_amount: Decimal,
+    pub output_units: ThingDerived,
+    pub ingredients: Vec<CreateRecipeIngredientInput>,
+}
+
+pub struct RecipeUseCases {}
+
+impl RecipeUseCases {
+    /// Recipe of the input checked against the other recipes:
+    /// positive amounts, no cycles and units of ingredients convert into units of their recipes
+    async fn recipe(
+        ct_input: CreateRecipeInput,
+        id: Option<Thing>,
+        db: &Db,
+        ctx: &dyn Ctx,
+    ) -> ApiResult<Recipe> {
+        // Checked here and not only by the schema, explosion divides by the output amount
+        if ct_input.output_amount <= Decimal::ZERO
+            || ct_input
+                .ingredients
+                .iter()
+                .any(|ingredient| ingredient.amount <= Decimal::ZERO)
+        {
+            return Err(ApiError {
+                req_id: ctx.req_id(),
+                error: Error::Generic {
+                    description: "Recipe amounts must be positive".to_string(),
+                },
+            });
+        }
+        let recipe = Recipe {
+            id: None,
+            product: ct_input.product,
+            output_amount: ct_input.output_amount,
+            output_units: ct_input.output_units,
+            ingredients: ct_input
+                .ingredients
+                .into_iter()
+                .map(|ingredient| RecipeIngredient {
+                    material: ingredient.material,
+                    amount: ingredient.amount,
+                    units: ingredient.units,
+                })
+                .collect(),
+        };
+        let id = id.map(ThingDerived::from);
+        let mut recipes: Vec<Recipe> = RecipeRepository::select_all(db, ctx)
+            .await?
+            .into_iter()
+            .filter(|other| other.id != id)
+            .collect();
+        recipes.push(recipe.clone());
+        let converter = MeasureUnitsUseCases::converter(db, ctx).await?;
+        explode(
+            &recipes,
+            &recipe.product,
+            recipe.output_amount,
+            &recipe.output_units,
+            &converter,
+            ctx,
+        )?;
+        Ok(recipe)
+    }
+
+    pub async fn create(ct_input: CreateRecipeInput, db: &Db, ctx: &dyn Ctx) -> ApiResult<Recipe> {
+        let recipe = Self::recipe(ct_input, None, db, ctx).await?;
+        RecipeRepository::create(recipe, db, ctx).await
+    }
+
+    pub async fn update(
+        ct_input: CreateRecipeInput,
+        id: &dyn ObjectWithThing,
+        db: &Db,
+        ctx: &dyn Ctx,
+    ) -> ApiResult<Recipe> {
+        let recipe = Self::recipe(ct_input, Some(id.thing(ctx)?), db, ctx).await?;
+        RecipeRepository::update(recipe, id.thing(ctx)?, db, ctx).await
+    }
+
+    pub async fn delete(id: &dyn ObjectWithThing, db: &Db, ctx: &dyn Ctx) -> ApiResult<Recipe> {
+        RecipeRepository::delete(id.thing(ctx)?, db, ctx).await
+    }
+
+    pub async fn select_by_id(
+        id: &dyn ObjectWithThing,
+        db: &Db,
+        ctx: &dyn Ctx,
+    ) -> ApiResult<Recipe> {
+        RecipeRepository::select_by_id(id, db, ctx).await
+    }
+
+    pub async fn select_by_product(
+        product: &ThingDerived,
+        db: &Db,
+        ctx: &dyn Ctx,
+    ) -> ApiResult<Option<Recipe>> {
+        RecipeRepository::select_by_product(product, db, ctx).await
+    }
+
+    /// Raw materials and intermediate products needed to make `amount` of the product
+    pub async fn requirements(
+        product: &ThingDerived,
+        amount: Decimal,
+        units: &ThingDerived,
+        db: &Db,
+        ctx: &dyn Ctx,
+    ) -> ApiResult<RequirementExplosion> {
+        let recipes = RecipeRepository::select_all(db, ctx).await?;
+        let converter = MeasureUnitsUseCases::converter(db, ctx).await?;
+        explode(&recipes, product, amount, units, &converter, ctx)
+    }
+
+    /// Requirements of the production plan. The product is the material of the final pipe
+    /// of the plan line
+    pub async fn plan_requirements(
+        plan: &dyn ObjectWithThing,
+        db: &Db,
+        ctx: &dyn Ctx,
+    ) -> ApiResult<RequirementExplosion> {
+        let plan = ProductionPlanPerDayUseCases::select_by_id(plan, db, ctx).await?;
+        let mut explosions = Self::plans_requirements(&[plan], db, ctx).await?;
+        Ok(explosions.remove(0))
+    }
+
+    /// Explosion of every plan in the same order. The product of a plan is the material
+    /// of its line, plans without a line are for the only final pipe of the plant
+    pub async fn plans_requirements(
+        plans: &[ProductionPlanPerDay],
+        db: &Db,
+        ctx: &dyn Ctx,
+    ) -> ApiResult<Vec<RequirementExplosion>> {
+        let recipes = RecipeRepository::select_all(db, ctx).await?;
+        let converter = MeasureUnitsUseCases::converter(db, ctx).await?;
+        let mut products: HashMap<String, ThingDerived> = HashMap::new();
+        let mut explosions = vec![];
+        for plan in plans {
+            let line = match &plan.line {
+                Some(line) => line.clone(),
+                None => PlantTopologyUseCases::select_final_pipe(db, ctx).await?,
+            };
+            let product = match products.get(&line.to_string()) {
+                Some(product) => product.clone(),
+                None => {
+                    let product = PipeUseCases::select_by_id(&line, db, ctx).await?.material;
+                    products.insert(line.to_string(), product.clone());
+                    product
+                }
+            };
+            explosions.push(explode(
+                &recipes,
+                &product,
+                plan.amount,
+                &plan.units,
+                &converter,
+                ctx,
+            )?);
+        }
+        Ok(explosions)
+    }
+
+    pub async fn count(db: &Db, ctx: &dyn Ctx) -> ApiResult<usize> {
+        RecipeRepository::count(db, ctx).await
+    }
+
+    pub async fn list(
+        offset: Option<usize>,
+        limit: Option<usize>,
+        db: &Db,
+        ctx: &dyn Ctx,
+    ) -> ApiResult<Vec<Recipe>> {
+        let limit = limit.unwrap_or(10);
+        let offset = offset.unwrap_or(0);
+
+        RecipeRepository::list(offset, limit, db, ctx).await
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::datetime::DateTimeDerived;
+    use crate::measure_units::tests::units;
+    use crate::measure_units::UnitDimension;
+    use crate::pipe_stats::tests::create_pipe;
+    use crate::prod_populate::dimension_units_shortcut;
+    use crate::production_per_day::CreateProductionPlanPerDayTypeInput;
+    use crate::raw_material::{CreateRawMaterialInput, RawMaterialUseCases};
+    use chrono::{TimeZone, Utc};
+    use common::ctx::MockCtx;
+    use db::set_test_db;
+    use rstest::*;
+
+    #[fixture]
+    fn ctx() -> MockCtx {
+        let mut ctx = MockCtx::new();
+        ctx.expect_req_id().return_const(uuid::Uuid::new_v4());
+        ctx.expect_user_id_thing().returning(|| {
+            Err(ApiError {
+                req_id: uuid::Uuid::new_v4(),
+                error: Error::Generic {
+                    description: "No user".to_string(),
+                },
+            })
+        });
+        ctx
+    }
+
+    #[fixture]
+    async fn tdb() -> Db {
+        set_test_db().await
+    }
+
+    pub fn thing(tb: &str, id: &str) -> ThingDerived {
+        Thing::from((tb, id)).into()
+    }
+
+    pub fn converter() -> UnitConverter {
+        UnitConverter::new(vec![
+            units("kg", Some(UnitDimension::Mass), Decimal::ONE),
+            units("m3", Some(UnitDimension::Volume), Decimal::ONE),
+            units("l", Some(UnitDimension::Volume), Decimal::new(1, 3)),
+            units("briquette", Some(UnitDimension::Count), Decimal::ONE),
+            units("pallet", Some(UnitDimension::Count), Decimal::from(480)),
+        ])
+    }
+
+    fn recipe(product: &str, output: (i64, &str), ingredients: &[(&str, Decimal, &str)]) -> Recipe {
+        Recipe {
+            id: Some(thing(RESOURCE, product)),
+            product: thing("RawMaterial", product),
+            output_amount: Decimal::from(output.0),
+            output_units: thing("MeasureUnits", output.1),
+            ingredients: ingredients
+                .iter()
+                .map(|&(material, amount, units)| RecipeIngredient {
+                    material: thing("RawMaterial", material),
+                    amount,
+                    units: thing("MeasureUnits", units),
+                })
+                .collect(),
+        }
+    }
+
+    /// sugar, milk -> mix -> ice_cream -> briquette -> pallet
+    pub fn recipes() -> Vec<Recipe> {
+        vec![
+            recipe(
+                "mix",
+                (1, "m3"),
+                &[
+                    ("sugar", Decimal::from(150), "kg"),
+                    ("milk", Decimal::new(8, 1), "m3"),
+                ],
+            ),
+            // Air doubles the volume of the mix
+            recipe("ice_cream", (2, "m3"), &[("mix", Decimal::ONE, "m3")]),
+            recipe(
+                "briquette",
+                (1, "briquette"),
+                &[("ice_cream", Decimal::new(5, 1), "l")],
+            ),
+            recipe(
+                "pallet",
+                (1, "pallet"),
+                &[("briquette", Decimal::from(480), "briquette")],
+            ),
+        ]
+    }
+
+    #[rstest]
+    fn explode_test(ctx: MockCtx) {
+        let amount = |requirements: &[MaterialRequirement], material: &str| {
+            requirements
+                .iter()
+                .find(|requirement| requirement.material == thing("RawMaterial", material))
+                .map(|requirement| requirement.amount.normalize())
+        };
+        let explosion = explode(
+            &recipes(),
+            &thing("RawMaterial", "pallet"),
+            Decimal::from(10),
+            &thing("MeasureUnits", "pallet"),
+            &converter(),
+            &ctx,
+        )
+        .unwrap();
+        // 4800 briquettes of 0.5 l is 2.4 m3 of ice cream from 1.2 m3 of mix
+        assert_eq!(
+            amount(&explosion.intermediates, "briquette"),
+            Some(Decimal::from(4800))
+        );
+        assert_eq!(
+            amount(&explosion.intermediates, "ice_cream"),
+            Some(Decimal::from(2400))
+        );
+        assert_eq!(
+            amount(&explosion.intermediates, "mix"),
+            Some(Decimal::new(12, 1))
+        );
+        assert_eq!(
+            amount(&explosion.raw_materials, "sugar"),
+            Some(Decimal::from(180))
+        );
+        assert_eq!(
+            amount(&explosion.raw_materials, "milk"),
+            Some(Decimal::new(96, 2))
+        );
+        assert_eq!(explosion.raw_materials.len(), 2);
+
+        // Plan in briquettes is converted into pallets of the recipe
+        let explosion = explode(
+            &recipes(),
+            &thing("RawMaterial", "pallet"),
+            Decimal::from(240),
+            &thing("MeasureUnits", "briquette"),
+            &converter(),
+            &ctx,
+        )
+        .unwrap();
+        assert_eq!(
+            amount(&explosion.raw_materials, "sugar"),
+            Some(Decimal::from(9))
+        );
+
+        // Material without recipe is raw itself
+        let explosion = explode(
+            &recipes(),
+            &thing("RawMaterial", "sugar"),
+            Decimal::ONE,
+            &thing("MeasureUnits", "kg"),
+            &converter(),
+            &ctx,
+        )
+        .unwrap();
+        assert_eq!(explosion.raw_materials.len(), 1);
+        assert!(explosion.intermediates.is_empty());
+
+        assert!(explode(
+            &recipes(),
+            &thing("RawMaterial", "pallet"),
+            Decimal::ONE,
+            &thing("MeasureUnits", "kg"),
+            &converter(),
+            &ctx,
+        )
+        .is_err());
+
+        let mut cyclic = recipes();
+        cyclic.push(recipe("milk", (1, "m3"), &[("mix", Decimal::ONE, "m3")]));
+        assert!(explode(
+            &cyclic,
+            &thing("RawMaterial", "pallet"),
+            Decimal::ONE,
+            &thing("MeasureUnits", "pallet"),
+            &converter(),
+            &ctx,
+        )
+        .is_err());
+    }
+
+    #[rstest]
+    #[tokio::test]
+    #[awt]
+    async fn plan_requirements_test(ctx: MockCtx, #[future] tdb: Db) {
+        let (pipe, units) = create_pipe(&ctx, &tdb).await.unwrap();
+        let mix = pipe.material.clone();
+        let kg = dimension_units_shortcut("кг", UnitDimension::Mass, Decimal::ONE, &tdb, &ctx)
+            .await
+            .unwrap();
+        let sugar = RawMaterialUseCases::create(
+            CreateRawMaterialInput {
+                name: "Просеянный сахар".to_string(),
+            },
+            &tdb,
+            &ctx,
+        )
+        .await
+        .unwrap();
+        let input = |product: &ThingDerived, ingredient: &ThingDerived, units: &ThingDerived| {
+            CreateRecipeInput {
+                product: product.clone(),
+                output_amount: Decimal::ONE,
+                output_units: units.clone(),
+                ingredients: vec![CreateRecipeIngredientInput {
+                    material: ingredient.clone(),
+                    amount: Decimal::new(1505, 1),
+                    units: kg.id.clone().unwrap(),
+                }],
+            }
+        };
+        let sugar = sugar.id.unwrap();
+        let recipe =
+            RecipeUseCases::create(input(&mix, &sugar, units.id.as_ref().unwrap()), &tdb, &ctx)
+                .await
+                .unwrap();
+        assert_eq!(recipe.ingredients[0].amount, Decimal::new(1505, 1));
+        assert_eq!(
+            RecipeUseCases::select_by_product(&mix, &tdb, &ctx)
+                .await
+                .unwrap()
+                .and_then(|recipe| recipe.id),
+            recipe.id
+        );
+        // Nothing can be made from a zero output
+        let mut zero_output = input(&mix, &sugar, units.id.as_ref().unwrap());
+        zero_output.output_amount = Decimal::ZERO;
+        assert!(RecipeUseCases::create(zero_output, &tdb, &ctx)
+            .await
+            .is_err());
+        let mut zero_ingredient = input(&mix, &sugar, units.id.as_ref().unwrap());
+        zero_ingredient.ingredients[0].amount = Decimal::ZERO;
+        assert!(RecipeUseCases::create(zero_ingredient, &tdb, &ctx)
+            .await
+            .is_err());
+        // Sugar made of the mix would be a cycle
+        assert!(
+            RecipeUseCases::create(input(&sugar, &mix, &kg.id.clone().unwrap()), &tdb, &ctx)
+                .await
+                .is_err()
+        );
+
+        let plan = ProductionPlanPerDayUseCases::create(
+            CreateProductionPlanPerDayTypeInput {
+                amount: Decimal::from(2),
+                units: units.id.clone().unwrap(),
+                date: DateTimeDerived::from(Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap()),
+                line: pipe.id.clone(),
+            },
+            &tdb,
+            &ctx,
+        )
+        .await
+        .unwrap()
+        .plan;
+        let explosion = RecipeUseCases::plan_requirements(&plan.id.unwrap(), &tdb, &ctx)
+            .await
+            .unwrap();
+        assert_eq!(explosion.product, mix);
+        assert_eq!(explosion.raw_materials.len(), 1);
+        assert_eq!(explosion.raw_materials[0].material, sugar);
+        assert_eq!(explosion.raw_materials[0].amount, Decimal::from(301));
+    }
+}
@@ -1,3 +1,7 @@
+use crate::audit_log::{AuditLogRepository, Audited};
+use crate::flow_integration::plant_day;
+use crate::plan_revision::{PlanRevision, PlanRevisionUseCases};
+use crate::plant_topology::PlantTopologyUseCases;
 use crate::service::guard::RoleGuard;
 use crate::thing_derived::ThingDerived;
 use crate::thing_wrapper::ObjectWithThing;
@@ -25,6 +29,10 @@ pub struct SalesPlanPerDay {
     pub amount: Decimal,
     pub units: ThingDerived,
     pub date: DateTimeDerived,
+    /// Final pipe of the product line. None only for records created before lines
+    pub line: Option<ThingDerived>,
+    /// Start of the plant local day of `date`
+    pub day: Option<DateTimeDerived>,
 }
 
 impl ObjectWithThing for SalesPlanPerDay {
@@ -47,12 +55,15 @@ impl SalesPlandPerDayRepository {
     pub async fn list(
         offset: usize,
         limit: usize,
+        line: Option<ThingDerived>,
         db: &Db,
         ctx: &dyn Ctx,
     ) -> ApiResult<Vec<SalesPlanPerDay>> {
-        let query = db.query(format!(
-            "SELECT * FROM {RESOURCE} LIMIT {limit} START {offset};"
-        ));
+        let query = db
+            .query(format!(
+                "SELECT * FROM {RESOURCE} WHERE $line = NONE OR line = $line LIMIT {limit} START {offset};"
+            ))
+            .bind(("line", line.map(|line| line.thing(ctx)).transpose()?));
         Unwrapper::unwrapper_vec(query, 0, ctx).await
     }
 
@@ -82,16 +93,19 @@ impl SalesPlandPerDayRepository {
         Unwrapper::unwrapper_option(query, 0, "Can't get tag by id", ctx).await
     }
 
+    /// Plan of the line for the plant local day containing the date
     pub async fn select_by_date(
+        line: &ThingDerived,
         date: DateTimeDerived,
         db: &Db,
         ctx: &dyn Ctx,
     ) -> ApiResult<Option<SalesPlanPerDay>> {
         let query = db
             .query(format!(
-                "SELECT * FROM {RESOURCE} WHERE time::floor(date, 1d) = time::floor($date, 1d);"
+                "SELECT * FROM {RESOURCE} WHERE line = $line AND day = $day;"
             ))
-            .bind(("date", date.0));
+            .bind(("line", line.thing(ctx)?))
+            .bind(("day", plant_day(&date, ctx)?.0));
         let result = Unwrapper::unwrapper_vec::<SalesPlanPerDay, _>(query, 0, ctx).await?;
         if result.is_empty() {
             return Ok(None);
@@ -99,20 +113,46 @@ impl SalesPlandPerDayRepository {
         Ok(Some(result[0].clone()))
     }
 
+    /// Plans with date in [from, to) ordered by date. All lines if `line` is None
+    pub async fn select_by_range(
+        from: DateTimeDerived,
+        to: DateTimeDerived,
+        line: Option<ThingDerived>,
+        db: &Db,
+        ctx: &dyn Ctx,
+    ) -> ApiResult<Vec<SalesPlanPerDay>> {
+        let query = db
+            .query(format!(
+                "SELECT * FROM {RESOURCE} WHERE date >= $from AND date < $to \
+                 AND ($line = NONE OR line = $line) ORDER BY date ASC;"
+            ))
+            .bind(("from", from.0))
+            .bind(("to", to.0))
+            .bind(("line", line.map(|line| line.thing(ctx)).transpose()?));
+        Unwrapper::unwrapper_vec(query, 0, ctx).await
+    }
+
     pub async fn create(
         amount: Decimal,
         units: ThingDerived,
         date: DateTimeDerived,
+        line: ThingDerived,
         db: &Db,
         ctx: &dyn Ctx,
     ) -> ApiResult<SalesPlanPerDay> {
+        let day = plant_day(&date, ctx)?;
         db.create(RESOURCE)
-            .content(SalesPlanPerDay {
-                id: None,
-                amount,
-                units,
-                date,
-            })
+            .content(Audited::new(
+                SalesPlanPerDay {
+                    id: None,
+                    amount,
+                    units,
+                    date,
+                    line: Some(line),
+                    day: Some(day),
+                },
+                ctx,
+            ))
             .await
             .map_err(ApiError::from(ctx))
             .map(|v: Vec<SalesPlanPerDay>| {
@@ -127,13 +167,27 @@ impl SalesPlandPerDayRepository {
     }
 
     pub async fn update(
-        ct_input: CreateSalesPlanPerDayTypeInput,
+        amount: Decimal,
+        units: ThingDerived,
+        date: DateTimeDerived,
+        line: ThingDerived,
         id: Thing,
         db: &Db,
         ctx: &dyn Ctx,
     ) -> ApiResult<SalesPlanPerDay> {
+        let day = plant_day(&date, ctx)?;
         db.update((RESOURCE, id.id.to_string()))
-            .content(ct_input)
+            .content(Audited::new(
+                SalesPlanPerDay {
+                    id: None,
+                    amount,
+                    units,
+                    date,
+                    line: Some(line),
+                    day: Some(day),
+                },
+                ctx,
+            ))
             .await
             .map_err(ApiError::from(ctx))?
             .ok_or(ApiError {
@@ -146,9 +200,8 @@ impl SalesPlandPerDayRepository {
     }
 
     pub async fn delete(id: Thing, db: &Db, ctx: &dyn Ctx) -> ApiResult<SalesPlanPerDay> {
-        db.delete((RESOURCE, id))
-            .await
-            .map_err(ApiError::from(ctx))?
+        AuditLogRepository::delete(RESOURCE, id.id, db, ctx)
+            .await?
             .ok_or(ApiError {
                 req_id: ctx.req_id(),
                 error: Error::SurrealDbNoResult {
@@ -164,6 +217,8 @@ pub struct CreateSalesPlanPerDayTypeInput {
     pub amount: Decimal,
     pub units: ThingDerived,
     pub date: DateTimeDerived,
+    /// Final pipe of the product line. The only final pipe of the plant by default
+    pub line: Option<ThingDerived>,
 }
 
 pub struct SalesPlanPerDayUnitsUseCases {
@@ -172,13 +227,28 @@ pub struct SalesPlanPerDayUnitsUseCases {
 }
 
 impl SalesPlanPerDayUnitsUseCases {
+    async fn line(line: Option<ThingDerived>, db: &Db, ctx: &dyn Ctx) -> ApiResult<ThingDerived> {
+        match line {
+            Some(line) => Ok(line),
+            None => PlantTopologyUseCases::select_final_pipe(db, ctx).await,
+        }
+    }
+
     pub async fn create(
         ct_input: CreateSalesPlanPerDayTypeInput,
         db: &Db,
         ctx: &dyn Ctx,
     ) -> ApiResult<SalesPlanPerDay> {
-        SalesPlandPerDayRepository::create(ct_input.amount, ct_input.units, ct_input.date, db, ctx)
-            .await
+        let line = Self::line(ct_input.line, db, ctx).await?;
+        SalesPlandPerDayRepository::create(
+            ct_input.amount,
+            ct_input.units,
+            ct_input.date,
+            line,
+            db,
+            ctx,
+        )
+        .await
     }
 
     pub async fn update(
@@ -187,7 +257,17 @@ impl SalesPlanPerDayUnitsUseCases {
         db: &Db,
         ctx: &dyn Ctx,
     ) -> ApiResult<SalesPlanPerDay> {
-        SalesPlandPerDayRepository::update(ct_input, id.thing(ctx)?, db, ctx).await
+        let line = Self::line(ct_input.line, db, ctx).await?;
+        SalesPlandPerDayRepository::update(
+            ct_input.amount,
+            ct_input.units,
+            ct_input.date,
+            line,
+            id.thing(ctx)?,
+            db,
+            ctx,
+        )
+        .await
     }
 
     pub async fn delete(
@@ -206,6 +286,45 @@ impl SalesPlanPerDayUnitsUseCases {
         SalesPlandPerDayRepository::select_by_id(id, db, ctx).await
     }
 
+    pub async fn select_by_date(
+        line: &ThingDerived,
+        date: DateTimeDerived,
+        db: &Db,
+        ctx: &dyn Ctx,
+    ) -> ApiResult<Option<SalesPlanPerDay>> {
+        SalesPlandPerDayRepository::select_by_date(line, date, db, ctx).await
+    }
+
+    /// Plan of the line for the plant local day containing the date as it was at `as_of`
+    pub async fn select_by_date_as_of(
+        line: &ThingDerived,
+        date: DateTimeDerived,
+        as_of: DateTimeDerived,
+        db: &Db,
+        ctx: &dyn Ctx,
+    ) -> ApiResult<Option<PlanRevision>> {
+        PlanRevisionUseCases::select_as_of(RESOURCE, line, date, as_of, db, ctx).await
+    }
+
+    /// Every change of the plan, newest first
+    pub async fn revisions(
+        id: &dyn ObjectWithThing,
+        db: &Db,
+        ctx: &dyn Ctx,
+    ) -> ApiResult<Vec<PlanRevision>> {
+        PlanRevisionUseCases::select_by_plan(id, db, ctx).await
+    }
+
+    pub async fn select_by_range(
+        from: DateTimeDerived,
+        to: DateTimeDerived,
+        line: Option<ThingDerived>,
+        db: &Db,
+        ctx: &dyn Ctx,
+    ) -> ApiResult<Vec<SalesPlanPerDay>> {
+        SalesPlandPerDayRepository::select_by_range(from, to, line, db, ctx).await
+    }
+
     pub async fn count(db: &Db, ctx: &dyn Ctx) -> ApiResult<usize> {
         SalesPlandPerDayRepository::count(db, ctx).await
     }
@@ -213,12 +332,13 @@ impl SalesPlanPerDayUnitsUseCases {
     pub async fn list(
         offset: Option<usize>,
         limit: Option<usize>,
+        line: Option<ThingDerived>,
         db: &Db,
         ctx: &dyn Ctx,
     ) -> ApiResult<Vec<SalesPlanPerDay>> {
         let limit = limit.unwrap_or(10);
         let offset = offset.unwrap_or(10);
 
-        SalesPlandPerDayRepository::list(offset, limit, db, ctx).await
+        SalesPlandPerDayRepository::list(offset, limit, line, db, ctx).await
     }
 }
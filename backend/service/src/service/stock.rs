@@ -0,0 +1,418 @@
+use crate::datetime::DateTimeDerived;
+use crate::flow_integration::{day_bounds, Bucket, FlowIntegrationOptions};
+use crate::measure_units::MeasureUnitsUseCases;
+use crate::pipe_stats::PipeStatsUseCases;
+use crate::plant_topology::PlantTopologyUseCases;
+use crate::production_per_day::ProductionPlanPerDayUseCases;
+use crate::recipe::RecipeUseCases;
+use crate::service::guard::RoleGuard;
+use crate::stock_movement::{StockMovement, StockMovementKind, StockMovementUseCases};
+use crate::thing_derived::ThingDerived;
+use async_graphql::SimpleObject;
+use chrono::{DateTime, Utc};
+use common::{
+    ctx::Ctx,
+    error::{ApiError, Error},
+    role::Role,
+    ApiResult,
+};
+
+use db::Db;
+use rust_decimal::Decimal;
+
+/// Upper limit of days from the first movement of the material to the end of the report
+const MAX_DAYS: usize = 3660;
+
+#[derive(Clone, Debug, SimpleObject)]
+#[graphql(guard = "RoleGuard::new(Role::User)")]
+pub struct StockDay {
+    /// Start of the plant local day
+    pub date: DateTimeDerived,
+    pub opening: Decimal,
+    pub receipts: Decimal,
+    pub adjustments: Decimal,
+    /// Integrated flow of the input pipes carrying the material. None without readings
+    pub measured_consumption: Option<Decimal>,
+    /// Required by the production plans of the day through recipes
+    pub planned_consumption: Decimal,
+    /// Measured consumption for finished days, planned one for the current and later days
+    pub consumption: Decimal,
+    /// True if the consumption is planned
+    pub projected: bool,
+    pub closing: Decimal,
+}
+
+#[derive(Clone, Debug, SimpleObject)]
+#[graphql(guard = "RoleGuard::new(Role::User)")]
+pub struct StockReport {
+    pub material: ThingDerived,
+    pub units: ThingDerived,
+    pub from: DateTimeDerived,
+    pub to: DateTimeDerived,
+    /// Input pipes the material is consumed through
+    pub pipes: Vec<ThingDerived>,
+    /// On-hand balance at `from`
+    pub opening: Decimal,
+    pub closing: Decimal,
+    pub days: Vec<StockDay>,
+    pub total_receipts: Decimal,
+    pub total_adjustments: Decimal,
+    pub total_consumption: Decimal,
+    /// First day the stock doesn't cover the consumption. None if it lasts until `to`
+    pub run_out: Option<DateTimeDerived>,
+}
+
+/// Builds per day rows. `movements` are in the units of the stock,
+/// `measured` and `planned` consumption are given in the same order as the days
+pub fn stock_days(
+    opening: Decimal,
+    days: &[(DateTime<Utc>, DateTime<Utc>)],
+    movements: &[StockMovement],
+    measured: &[Option<Decimal>],
+    planned: &[Decimal],
+    now: DateTime<Utc>,
+) -> Vec<StockDay> {
+    let mut balance = opening;
+    days.iter()
+        .zip(measured.iter().zip(planned.iter()))
+        .map(
+            |(&(from, to), (&measured_consumption, &planned_consumption))| {
+                let sum = |kind: StockMovementKind| {
+                    movements
+                        .iter()
+                        .filter(|movement| movement.kind == kind)
+                        .filter(|movement| movement.date.0 .0 >= from && movement.date.0 .0 < to)
+                        .map(|movement| movement.amount)
+                        .sum::<Decimal>()
+                };
+                let receipts = sum(StockMovementKind::Receipt);
+                let adjustments = sum(StockMovementKind::Adjustment);
+                let projected = to > now;
+                let consumption = if projected {
+                    planned_consumption
+                } else {
+                    measured_consumption.unwrap_or_default()
+                };
+                let opening = balance;
+                balance += receipts + adjustments - consumption;
+                StockDay {
+                    date: from.into(),
+                    opening,
+                    receipts,
+                    adjustments,
+                    measured_consumption,
+                    planned_consumption,
+                    consumption,
+                    projected,
+                    closing: balance,
+                }
+            },
+        )
+        .collect()
+}
+
+pub struct StockUseCases {}
+
+impl StockUseCases {
+    /// Balance of the material for every plant local day from the day of `from` to the day
+    /// of `to` inclusive. The stock is empty before the first movement of the material.
+    /// Units of the input pipe carrying the material or of the last movement by default
+    pub async fn report(
+        material: &ThingDerived,
+        from: DateTimeDerived,
+        to: DateTimeDerived,
+        units: Option<ThingDerived>,
+        options: &FlowIntegrationOptions,
+        db: &Db,
+        ctx: &dyn Ctx,
+    ) -> ApiResult<StockReport> {
+        let offset = options.utc_offset(ctx)?;
+        let (from, _) = day_bounds(from.0 .0, offset);
+        let (_, to) = day_bounds(to.0 .0, offset);
+        if from >= to {
+            return Err(ApiError {
+                req_id: ctx.req_id(),
+                error: Error::Generic {
+                    description: "Stock report: `from` should be before `to`".to_string(),
+                },
+            });
+        }
+        let movements =
+            StockMovementUseCases::select_by_material(material, to.into(), db, ctx).await?;
+        let start = movements.first().map_or(from, |movement| {
+            day_bounds(movement.date.0 .0, offset).0.min(from)
+        });
+        let days = Bucket::Day
+            .bounds(start, to, offset, MAX_DAYS)
+            .ok_or(ApiError {
+                req_id: ctx.req_id(),
+                error: Error::Generic {
+                    description: format!(
+                        "Stock report: at most {MAX_DAYS} days since the first movement allowed"
+                    ),
+                },
+            })?;
+
+        // Material is taken from the stock by the pipes no machinery outputs into
+        let topology = PlantTopologyUseCases::get(db, ctx).await?;
+        let pipes: Vec<_> = topology
+            .nodes
+            .iter()
+            .filter(|node| node.material.as_ref() == Some(material))
+            .filter(|node| topology.input_pipes.contains(&node.id))
+            .collect();
+        let units = units
+            .or_else(|| pipes.first().map(|pipe| pipe.units.clone()))
+            .or_else(|| movements.last().map(|movement| movement.units.clone()))
+            .ok_or(ApiError {
+                req_id: ctx.req_id(),
+                error: Error::Generic {
+                    description: format!("Stock report: units of {material} are unknown"),
+                },
+            })?;
+        let converter = MeasureUnitsUseCases::converter(db, ctx).await?;
+        let movements = movements
+            .into_iter()
+            .map(|movement| {
+                Ok(StockMovement {
+                    amount: converter.convert(movement.amount, &movement.units, &units, ctx)?,
+                    units: units.clone(),
+                    ..movement
+                })
+            })
+            .collect::<ApiResult<Vec<_>>>()?;
+
+        let mut measured: Vec<Option<Decimal>> = vec![None; days.len()];
+        for pipe in pipes.iter() {
+            let buckets = PipeStatsUseCases::aggregate_by_pipe(
+                &pipe.id,
+                start.into(),
+                to.into(),
+                Bucket::Day,
+                options,
+                db,
+                ctx,
+            )
+            .await?;
+            for (consumption, bucket) in measured.iter_mut().zip(buckets.iter()) {
+                if bucket.covered_hours.is_zero() {
+                    continue;
+                }
+                let volume = converter.convert(bucket.volume, &pipe.units, &units, ctx)?;
+                *consumption = Some(consumption.unwrap_or_default() + volume);
+            }
+        }
+
+        // Plans of finished days are not needed, their consumption is measured
+        let now = Utc::now();
+        let mut planned = vec![Decimal::ZERO; days.len()];
+        let plans_from = day_bounds(now, offset).0.max(start);
+        if plans_from < to {
+            let plans = ProductionPlanPerDayUseCases::select_by_range(
+                plans_from.into(),
+                to.into(),
+                None,
+                db,
+                ctx,
+            )
+            .await?;
+            let explosions = RecipeUseCases::plans_requirements(&plans, db, ctx).await?;
+            for (plan, explosion) in plans.iter().zip(explosions.iter()) {
+                let day = days.partition_point(|&(_, day_to)| day_to <= plan.date.0 .0);
+                let Some(consumption) = planned.get_mut(day) else {
+                    continue;
+                };
+                for requirement in explosion
+                    .raw_materials
+                    .iter()
+                    .filter(|requirement| &requirement.material == material)
+                {
+                    *consumption +=
+                        converter.convert(requirement.amount, &requirement.units, &units, ctx)?;
+                }
+            }
+        }
+
+        let mut days = stock_days(Decimal::ZERO, &days, &movements, &measured, &planned, now);
+        let first = days.partition_point(|day| day.date.0 .0 < from);
+        let days = days.split_off(first);
+        let opening = days.first().map_or(Decimal::ZERO, |day| day.opening);
+        let closing = days.last().map_or(opening, |day| day.closing);
+        Ok(StockReport {
+            material: material.clone(),
+            units,
+            from: from.into(),
+            to: to.into(),
+            pipes: pipes.iter().map(|pipe| pipe.id.clone()).collect(),
+            opening,
+            closing,
+            total_receipts: days.iter().map(|day| day.receipts).sum(),
+            total_adjustments: days.iter().map(|day| day.adjustments).sum(),
+            total_consumption: days.iter().map(|day| day.consumption).sum(),
+            run_out: days
+                .iter()
+                .find(|day| day.closing < Decimal::ZERO)
+                .map(|day| day.date.clone()),
+            days,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pipe_stats::tests::create_pipe;
+    use crate::pipe_stats::CreatePipeStatsInput;
+    use crate::stock_movement::CreateStockMovementInput;
+    use chrono::TimeZone;
+    use common::ctx::MockCtx;
+    use db::set_test_db;
+    use rstest::*;
+    use surrealdb::sql::Thing;
+
+    #[fixture]
+    fn ctx() -> MockCtx {
+        let mut ctx = MockCtx::new();
+        ctx.expect_req_id().return_const(uuid::Uuid::new_v4());
+        ctx.expect_user_id_thing().returning(|| {
+            Err(ApiError {
+                req_id: uuid::Uuid::new_v4(),
+                error: Error::Generic {
+                    description: "No user".to_string(),
+                },
+            })
+        });
+        ctx
+    }
+
+    #[fixture]
+    async fn tdb() -> Db {
+        set_test_db().await
+    }
+
+    fn at(day: u32, hour: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2024, 1, day, hour, 0, 0).unwrap()
+    }
+
+    fn movement(day: u32, kind: StockMovementKind, amount: i64) -> StockMovement {
+        StockMovement {
+            id: None,
+            material: Thing::from(("RawMaterial", "sugar")).into(),
+            date: at(day, 8).into(),
+            kind,
+            amount: Decimal::from(amount),
+            units: Thing::from(("MeasureUnits", "kg")).into(),
+            comment: None,
+            created_by: None,
+        }
+    }
+
+    #[test]
+    fn stock_days_test() {
+        let days: Vec<_> = (1..=4).map(|day| (at(day, 0), at(day + 1, 0))).collect();
+        let movements = [
+            movement(1, StockMovementKind::Receipt, 1000),
+            movement(2, StockMovementKind::Adjustment, -50),
+            // Expected delivery
+            movement(4, StockMovementKind::Receipt, 200),
+        ];
+        let measured = [Some(300), None, Some(100), None].map(|flow| flow.map(Decimal::from));
+        let planned = [250, 250, 400, 400].map(Decimal::from);
+        let result = stock_days(
+            Decimal::from(10),
+            &days,
+            &movements,
+            &measured,
+            &planned,
+            at(3, 12),
+        );
+
+        assert_eq!(result[0].opening, Decimal::from(10));
+        assert_eq!(result[0].receipts, Decimal::from(1000));
+        assert_eq!(result[0].consumption, Decimal::from(300));
+        assert_eq!(result[0].closing, Decimal::from(710));
+        // No readings on a finished day
+        assert!(!result[1].projected);
+        assert_eq!(result[1].consumption, Decimal::ZERO);
+        assert_eq!(result[1].closing, Decimal::from(660));
+        // Current day is projected with the whole plan
+        assert!(result[2].projected);
+        assert_eq!(result[2].measured_consumption, Some(Decimal::from(100)));
+        assert_eq!(result[2].consumption, Decimal::from(400));
+        assert_eq!(result[2].closing, Decimal::from(260));
+        assert_eq!(result[3].opening, Decimal::from(260));
+        assert_eq!(result[3].closing, Decimal::from(60));
+    }
+
+    #[rstest]
+    #[tokio::test]
+    #[awt]
+    async fn stock_report_test(ctx: MockCtx, #[future] tdb: Db) {
+        let (pipe, units) = create_pipe(&ctx, &tdb).await.unwrap();
+        let units = units.id.unwrap();
+        let material = pipe.material.clone();
+        let movement = |day: u32, kind: StockMovementKind, amount: i64| CreateStockMovementInput {
+            material: material.clone(),
+            date: at(day, 8).into(),
+            kind,
+            amount: Decimal::from(amount),
+            units: units.clone(),
+            comment: None,
+        };
+        for input in [
+            movement(1, StockMovementKind::Receipt, 100),
+            movement(3, StockMovementKind::Adjustment, -80),
+        ] {
+            StockMovementUseCases::create(input, &tdb, &ctx)
+                .await
+                .unwrap();
+        }
+        assert!(StockMovementUseCases::create(
+            movement(3, StockMovementKind::Receipt, -1),
+            &tdb,
+            &ctx
+        )
+        .await
+        .is_err());
+        for (day, flow) in [(2, 1), (3, 0)] {
+            PipeStatsUseCases::create(
+                CreatePipeStatsInput {
+                    date: at(day, 0).into(),
+                    flow: Decimal::from(flow),
+                    units: units.clone(),
+                    wearout: Decimal::ZERO,
+                    pipe: pipe.id.clone().unwrap(),
+                },
+                &tdb,
+                &ctx,
+            )
+            .await
+            .unwrap();
+        }
+
+        let options = FlowIntegrationOptions {
+            utc_offset_minutes: Some(0),
+            ..Default::default()
+        };
+        let report = StockUseCases::report(
+            &material,
+            at(2, 0).into(),
+            at(3, 0).into(),
+            None,
+            &options,
+            &tdb,
+            &ctx,
+        )
+        .await
+        .unwrap();
+        assert_eq!(report.units, units);
+        assert_eq!(report.pipes, vec![pipe.id.unwrap()]);
+        // Receipt of the first day is before the report
+        assert_eq!(report.opening, Decimal::from(100));
+        assert_eq!(report.days.len(), 2);
+        assert_eq!(report.days[0].measured_consumption, Some(Decimal::from(24)));
+        assert_eq!(report.days[0].closing, Decimal::from(76));
+        assert_eq!(report.closing, Decimal::from(-4));
+        assert_eq!(report.run_out, Some(at(3, 0).into()));
+    }
+}
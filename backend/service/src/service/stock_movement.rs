@@ -0,0 +1,287 @@
+use crate::audit_log::{AuditLogRepository, Audited};
+use crate::common::Unwrapper;
+use crate::datetime::DateTimeDerived;
+use crate::service::guard::RoleGuard;
+use crate::thing_derived::ThingDerived;
+use crate::thing_wrapper::ObjectWithThing;
+use async_graphql::{Enum, InputObject, SimpleObject};
+use common::{
+    ctx::Ctx,
+    error::{ApiError, Error},
+    role::Role,
+    ApiResult,
+};
+
+use db::Db;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use surrealdb::sql::Thing;
+
+#[allow(dead_code)]
+const RESOURCE: &str = "StockMovement";
+
+#[derive(Enum, Copy, Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub enum StockMovementKind {
+    /// Delivery of the material. Amount is positive
+    Receipt,
+    /// Correction after a stocktake or a write-off. Amount is signed
+    Adjustment,
+}
+
+/// Change of the stock of a raw material not caused by production.
+/// Consumption is derived from the flows of the pipes carrying the material
+#[derive(Clone, Debug, Serialize, Deserialize, SimpleObject)]
+#[graphql(guard = "RoleGuard::new(Role::User)")]
+pub struct StockMovement {
+    pub id: Option<ThingDerived>,
+    pub material: ThingDerived,
+    /// Receipts in the future are expected deliveries
+    pub date: DateTimeDerived,
+    pub kind: StockMovementKind,
+    pub amount: Decimal,
+    pub units: ThingDerived,
+    pub comment: Option<String>,
+    pub created_by: Option<ThingDerived>,
+}
+
+impl ObjectWithThing for StockMovement {
+    fn thing(&self, ctx: &dyn Ctx) -> ApiResult<Thing> {
+        self.id
+            .as_ref()
+            .ok_or(ApiError {
+                req_id: ctx.req_id(),
+                error: Error::Generic {
+                    description: "Can't get thing. Get none instead".to_string(),
+                },
+            })?
+            .thing(ctx)
+    }
+}
+
+pub struct StockMovementRepository {}
+
+impl StockMovementRepository {
+    pub async fn list(
+        offset: usize,
+        limit: usize,
+        material: Option<ThingDerived>,
+        db: &Db,
+        ctx: &dyn Ctx,
+    ) -> ApiResult<Vec<StockMovement>> {
+        let query = db
+            .query(format!(
+                "SELECT * FROM {RESOURCE} WHERE $material = NONE OR material = $material \
+                 ORDER BY date DESC LIMIT {limit} START {offset};"
+            ))
+            .bind((
+                "material",
+                material.map(|material| material.thing(ctx)).transpose()?,
+            ));
+        Unwrapper::unwrapper_vec(query, 0, ctx).await
+    }
+
+    pub async fn count(db: &Db, ctx: &dyn Ctx) -> ApiResult<usize> {
+        let query = db.query(format!("(SELECT count() FROM {RESOURCE} GROUP ALL).count"));
+        Unwrapper::unwrapper_option_without_error(query, 0, ctx)
+            .await
+            .map(|count| count.unwrap_or(0))
+    }
+
+    pub async fn select_by_id(
+        id: &dyn ObjectWithThing,
+        db: &Db,
+        ctx: &dyn Ctx,
+    ) -> ApiResult<StockMovement> {
+        let thing_id = id.thing(ctx)?;
+        if thing_id.tb != RESOURCE {
+            return Err(ApiError {
+                error: Error::Generic {
+                    description: "Wrong table name in select_by_id".to_string(),
+                },
+                req_id: ctx.req_id(),
+            });
+        }
+        let query = db
+            .query("SELECT * FROM $thing_id".to_string())
+            .bind(("thing_id", thing_id));
+
+        Unwrapper::unwrapper_option(query, 0, "Can't get stock movement by id", ctx).await
+    }
+
+    /// Movements of the material before `to`, oldest first
+    pub async fn select_by_material(
+        material: &dyn ObjectWithThing,
+        to: DateTimeDerived,
+        db: &Db,
+        ctx: &dyn Ctx,
+    ) -> ApiResult<Vec<StockMovement>> {
+        let query = db
+            .query(format!(
+                "SELECT * FROM {RESOURCE} WHERE material = $material AND date < $to \
+                 ORDER BY date ASC;"
+            ))
+            .bind(("material", material.thing(ctx)?))
+            .bind(("to", to.0));
+        Unwrapper::unwrapper_vec(query, 0, ctx).await
+    }
+
+    pub async fn create(
+        movement: StockMovement,
+        db: &Db,
+        ctx: &dyn Ctx,
+    ) -> ApiResult<StockMovement> {
+        db.create(RESOURCE)
+            .content(Audited::new(movement, ctx))
+            .await
+            .map_err(ApiError::from(ctx))
+            .map(|v: Vec<StockMovement>| {
+                v.into_iter().next().ok_or(ApiError {
+                    req_id: ctx.req_id(),
+                    error: Error::SurrealDbNoResult {
+                        source: "internal".to_string(),
+                        id: "Error while creating ".to_string(),
+                    },
+                })
+            })?
+    }
+
+    pub async fn update(
+        movement: StockMovement,
+        id: Thing,
+        db: &Db,
+        ctx: &dyn Ctx,
+    ) -> ApiResult<StockMovement> {
+        db.update((RESOURCE, id.id.to_string()))
+            .content(Audited::new(
+                StockMovement {
+                    id: None,
+                    ..movement
+                },
+                ctx,
+            ))
+            .await
+            .map_err(ApiError::from(ctx))?
+            .ok_or(ApiError {
+                req_id: ctx.req_id(),
+                error: Error::SurrealDbNoResult {
+                    source: "internal".to_string(),
+                    id: id.to_string(),
+                },
+            })
+    }
+
+    pub async fn delete(id: Thing, db: &Db, ctx: &dyn Ctx) -> ApiResult<StockMovement> {
+        AuditLogRepository::delete(RESOURCE, id.id, db, ctx)
+            .await?
+            .ok_or(ApiError {
+                req_id: ctx.req_id(),
+                error: Error::SurrealDbNoResult {
+                    source: "internal".to_string(),
+                    id: "Error while deleting ".to_string(),
+                },
+            })
+    }
+}
+
+#[derive(Deserialize, InputObject, Clone, Serialize, Debug)]
+pub struct CreateStockMovementInput {
+    pub material: ThingDerived,
+    pub date: DateTimeDerived,
+    pub kind: StockMovementKind,
+    /// Positive for receipts, signed for adjustments
+    pub amount: Decimal,
+    pub units: ThingDerived,
+    pub comment: Option<String>,
+}
+
+pub struct StockMovementUseCases {}
+
+impl StockMovementUseCases {
+    fn movement(ct_input: CreateStockMovementInput, ctx: &dyn Ctx) -> ApiResult<StockMovement> {
+        let valid = match ct_input.kind {
+            StockMovementKind::Receipt => ct_input.amount > Decimal::ZERO,
+            StockMovementKind::Adjustment => !ct_input.amount.is_zero(),
+        };
+        if !valid {
+            return Err(ApiError {
+                req_id: ctx.req_id(),
+                error: Error::Generic {
+                    description: format!(
+                        "Stock movement: wrong amount {} for {:?}",
+                        ct_input.amount, ct_input.kind
+                    ),
+                },
+            });
+        }
+        Ok(StockMovement {
+            id: None,
+            material: ct_input.material,
+            date: ct_input.date,
+            kind: ct_input.kind,
+            amount: ct_input.amount,
+            units: ct_input.units,
+            comment: ct_input.comment,
+            created_by: ctx.user_id_thing().ok().map(Into::into),
+        })
+    }
+
+    pub async fn create(
+        ct_input: CreateStockMovementInput,
+        db: &Db,
+        ctx: &dyn Ctx,
+    ) -> ApiResult<StockMovement> {
+        StockMovementRepository::create(Self::movement(ct_input, ctx)?, db, ctx).await
+    }
+
+    pub async fn update(
+        ct_input: CreateStockMovementInput,
+        id: &dyn ObjectWithThing,
+        db: &Db,
+        ctx: &dyn Ctx,
+    ) -> ApiResult<StockMovement> {
+        StockMovementRepository::update(Self::movement(ct_input, ctx)?, id.thing(ctx)?, db, ctx)
+            .await
+    }
+
+    pub async fn delete(
+        id: &dyn ObjectWithThing,
+        db: &Db,
+        ctx: &dyn Ctx,
+    ) -> ApiResult<StockMovement> {
+        StockMovementRepository::delete(id.thing(ctx)?, db, ctx).await
+    }
+
+    pub async fn select_by_id(
+        id: &dyn ObjectWithThing,
+        db: &Db,
+        ctx: &dyn Ctx,
+    ) -> ApiResult<StockMovement> {
+        StockMovementRepository::select_by_id(id, db, ctx).await
+    }
+
+    pub async fn select_by_material(
+        material: &dyn ObjectWithThing,
+        to: DateTimeDerived,
+        db: &Db,
+        ctx: &dyn Ctx,
+    ) -> ApiResult<Vec<StockMovement>> {
+        StockMovementRepository::select_by_material(material, to, db, ctx).await
+    }
+
+    pub async fn count(db: &Db, ctx: &dyn Ctx) -> ApiResult<usize> {
+        StockMovementRepository::count(db, ctx).await
+    }
+
+    pub async fn list(
+        offset: Option<usize>,
+        limit: Option<usize>,
+        material: Option<ThingDerived>,
+        db: &Db,
+        ctx: &dyn Ctx,
+    ) -> ApiResult<Vec<StockMovement>> {
+        let limit = limit.unwrap_or(10);
+        let offset = offset.unwrap_or(0);
+
+        StockMovementRepository::list(offset, limit, material, db, ctx).await
+    }
+}